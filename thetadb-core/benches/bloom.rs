@@ -0,0 +1,65 @@
+//! Compares looking up absent keys with and without the Bloom filters in the leaf pages, and
+//! reports the leaf space the filters cost.
+//!
+//! The absent keys fall between the present ones, so every lookup reaches a leaf, where the
+//! filter can skip searching it.
+//!
+//! Run with `cargo bench --bench bloom`.
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use thetadb::{keys, Options, Result};
+
+const RECORDS: u64 = 100_000;
+const LOOKUPS: u64 = 1_000_000;
+const ROUNDS: u32 = 3;
+
+fn main() -> Result<()> {
+    let path = "target/bench_bloom.theta";
+    let mut leaf_pages = None;
+
+    for bits in [0, 10] {
+        _ = fs::remove_file(path);
+        let db = Options::new().bloom_bits_per_key(bits).open(path)?;
+        // The even keys are present, the odd ones are absent.
+        db.extend((0..RECORDS).map(|i| (keys::encode_u64(i * 2), i.to_le_bytes())))?;
+        let stats = db.debugger()?.stats()?;
+
+        let tx = db.begin_tx()?;
+        let absent = |i: u64| keys::encode_u64(i % RECORDS * 2 + 1);
+        let (mut get, mut contains) = (Duration::MAX, Duration::MAX);
+        for _ in 0..ROUNDS {
+            let start = Instant::now();
+            for i in 0..LOOKUPS {
+                assert!(tx.get(absent(i))?.is_none());
+            }
+            get = get.min(start.elapsed());
+
+            let start = Instant::now();
+            for i in 0..LOOKUPS {
+                assert!(!tx.contains(absent(i))?);
+            }
+            contains = contains.min(start.elapsed());
+        }
+        drop(tx);
+
+        // The leaves without filters are the baseline of the space cost.
+        let base = *leaf_pages.get_or_insert(stats.leaf_pages);
+        let cost = (stats.leaf_pages as f64 / base as f64 - 1.) * 100.;
+        let per_lookup = |elapsed: Duration| elapsed.as_nanos() as f64 / LOOKUPS as f64;
+        println!(
+            "{bits:>2} bits per key: {:>6.1} ns per get, {:>6.1} ns per contains, \
+             {:>5} leaves (+{cost:.1}%)",
+            per_lookup(get),
+            per_lookup(contains),
+            stats.leaf_pages,
+        );
+        drop(db);
+    }
+
+    _ = fs::remove_file(path);
+    Ok(())
+}
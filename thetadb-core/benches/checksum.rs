@@ -0,0 +1,46 @@
+//! Compares the throughput of validating the metadata with each checksum algorithm, by checking
+//! the file without verifying the tree, which reads and validates both meta pages.
+//!
+//! The metadata is only a few dozen bytes, so the difference between the algorithms is small
+//! next to reading the file.
+//!
+//! Run with `cargo bench --bench checksum`.
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use thetadb::{keys, ChecksumKind, Options, Result, ThetaDB};
+
+const CHECKS: u32 = 20_000;
+const ROUNDS: u32 = 5;
+
+fn main() -> Result<()> {
+    let path = "target/bench_checksum.theta";
+
+    for kind in [ChecksumKind::Crc32, ChecksumKind::XxHash64] {
+        _ = fs::remove_file(path);
+        let db = Options::new().checksum(kind).open(path)?;
+        db.extend((0..1_000u64).map(|i| (keys::encode_u64(i), i.to_le_bytes())))?;
+        drop(db);
+
+        let mut best = Duration::MAX;
+        for _ in 0..ROUNDS {
+            let start = Instant::now();
+            for _ in 0..CHECKS {
+                assert!(ThetaDB::check(path, false)?.is_healthy());
+            }
+            best = best.min(start.elapsed());
+        }
+
+        let per_check = best.as_nanos() as f64 / CHECKS as f64;
+        println!(
+            "{:>8}: {per_check:>8.1} ns per validation",
+            format!("{kind:?}")
+        );
+    }
+
+    _ = fs::remove_file(path);
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Compares iterating the records by stepping a cursor, which keeps its track from the root
+//! warm, with seeking every record from the root.
+//!
+//! Run with `cargo bench --bench cursor`.
+
+use std::{
+    fs,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use thetadb::{keys, Result, ThetaDB};
+
+const RECORDS: u64 = 200_000;
+const ROUNDS: u32 = 5;
+
+fn main() -> Result<()> {
+    let path = "target/bench_cursor.theta";
+    _ = fs::remove_file(path);
+    let db = ThetaDB::open(path)?;
+    db.extend((0..RECORDS).map(|i| (keys::encode_u64(i), i.to_le_bytes())))?;
+
+    let warm = measure(|| {
+        let mut cursor = db.first_cursor()?;
+        let mut count = 0;
+        while let Some(value) = cursor.value_ref(|v| v.len())? {
+            count += black_box(value);
+            cursor.next()?;
+        }
+        Ok(count)
+    })?;
+
+    let cold = measure(|| {
+        let mut count = 0;
+        for i in 0..RECORDS {
+            let cursor = db.cursor_from_key(&keys::encode_u64(i))?;
+            count += black_box(cursor.value_ref(|v| v.len())?.unwrap_or_default());
+        }
+        Ok(count)
+    })?;
+
+    report("step with the warm track", warm);
+    report("seek from the root", cold);
+
+    drop(db);
+    _ = fs::remove_file(path);
+    Ok(())
+}
+
+/// Runs the iteration several times, returns the best elapsed time.
+fn measure(mut iterate: impl FnMut() -> Result<usize>) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        assert_eq!(iterate()?, RECORDS as usize * 8);
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let per_record = elapsed.as_nanos() as f64 / RECORDS as f64;
+    println!("{name:>24}: {elapsed:>12.3?} in total, {per_record:>8.1} ns per record");
+}
@@ -0,0 +1,69 @@
+//! Compares overwriting values with ones of the same length, which are set in place in the
+//! leaf, with overwriting them with ones of another length, which go through the general path
+//! of putting a record.
+//!
+//! Run with `cargo bench --bench overwrite`.
+
+use std::{fs, time::Instant};
+
+use thetadb::{keys, Result, Stats, ThetaDB};
+
+const RECORDS: u64 = 10_000;
+const OVERWRITES: u64 = 1_000_000;
+const VALUE_LEN: usize = 64;
+
+fn main() -> Result<()> {
+    let path = "target/bench_overwrite.theta";
+
+    for (name, other_len) in [
+        ("same length", VALUE_LEN),
+        ("another length", VALUE_LEN - 1),
+    ] {
+        _ = fs::remove_file(path);
+        let db = ThetaDB::open(path)?;
+        db.extend((0..RECORDS).map(|i| (keys::encode_u64(i), [0; VALUE_LEN])))?;
+        let before = db.debugger()?.stats()?;
+
+        let start = Instant::now();
+        db.update(|tx| {
+            for i in 0..OVERWRITES {
+                // Every other round writes the original length back.
+                let len = if (i / RECORDS).is_multiple_of(2) {
+                    other_len
+                } else {
+                    VALUE_LEN
+                };
+                tx.put(keys::encode_u64(i % RECORDS), &[i as u8; VALUE_LEN][..len])?;
+            }
+            Ok(())
+        })?;
+        let elapsed = start.elapsed();
+
+        let after = db.debugger()?.stats()?;
+        if other_len == VALUE_LEN {
+            // No node is split or merged for the overwrites, the pages of the tree are only
+            // shadowed once by the transaction.
+            assert_eq!(shape(&after), shape(&before));
+        }
+
+        let per_overwrite = elapsed.as_nanos() as f64 / OVERWRITES as f64;
+        println!(
+            "{name:>14}: {per_overwrite:>8.1} ns per overwrite, {:>4} leaves before, {:>4} after",
+            before.leaf_pages, after.leaf_pages,
+        );
+        drop(db);
+    }
+
+    _ = fs::remove_file(path);
+    Ok(())
+}
+
+/// The height of the tree and the numbers of its nodes and records.
+fn shape(stats: &Stats) -> (u32, u32, u32, u64) {
+    (
+        stats.height,
+        stats.branch_pages,
+        stats.leaf_pages,
+        stats.entry_count,
+    )
+}
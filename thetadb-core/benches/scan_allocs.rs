@@ -0,0 +1,75 @@
+//! Counts the allocations made while scanning all the records with a cursor, which reuses its
+//! track as it steps, so the count stays flat however many records are scanned.
+//!
+//! Run with `cargo bench --bench scan_allocs`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use thetadb::{keys, Result, ThetaDB};
+
+const RECORDS: u64 = 200_000;
+
+/// Counts the allocations made through the global allocator.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() -> Result<()> {
+    let path = "target/bench_scan_allocs.theta";
+    _ = fs::remove_file(path);
+    let db = ThetaDB::open(path)?;
+    db.extend((0..RECORDS).map(|i| (keys::encode_u64(i), i.to_le_bytes())))?;
+
+    for records in [RECORDS / 100, RECORDS / 10, RECORDS] {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+
+        let mut cursor = db.first_cursor()?;
+        let mut count = 0;
+        while count < records {
+            let Some(len) = cursor.value_ref(|v| v.len())? else {
+                break;
+            };
+            black_box(len);
+            count += 1;
+            cursor.next()?;
+        }
+        drop(cursor);
+
+        let elapsed = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("{count:>8} records: {allocations:>6} allocations in {elapsed:>10.3?}");
+    }
+
+    drop(db);
+    _ = fs::remove_file(path);
+    Ok(())
+}
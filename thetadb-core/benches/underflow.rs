@@ -0,0 +1,52 @@
+//! Compares deleting records at different underflow ratios, by the throughput of the deletes and
+//! how full the leaves are left.
+//!
+//! Run with `cargo bench --bench underflow`.
+
+use std::{fs, time::Instant};
+
+use thetadb::{keys, Options, Result};
+
+const RECORDS: u64 = 100_000;
+const BATCH: u64 = 1_000;
+
+fn main() -> Result<()> {
+    let path = "target/bench_underflow.theta";
+
+    for ratio in [0.0, 0.15, 0.25, 0.35, 0.45] {
+        _ = fs::remove_file(path);
+        let db = Options::new()
+            .page_size(Some(4096))
+            .underflow_ratio(ratio)
+            .open(path)?;
+        db.extend((0..RECORDS).map(|i| (keys::encode_u64(i), [0; 64])))?;
+
+        // Deletes three of every four records in batches, leaving the leaves sparse.
+        let start = Instant::now();
+        for batch in (0..RECORDS).step_by(BATCH as usize) {
+            db.update(|tx| {
+                for i in (batch..batch + BATCH).filter(|i| i % 4 != 0) {
+                    tx.delete(keys::encode_u64(i))?;
+                }
+                Ok(())
+            })?;
+        }
+        let elapsed = start.elapsed();
+
+        let (stats, histogram) = {
+            let debugger = db.debugger()?;
+            (debugger.stats()?, debugger.leaf_fill_histogram()?)
+        };
+        let below_half = histogram[..5].iter().sum::<usize>();
+        let per_delete = elapsed.as_nanos() as f64 / (RECORDS * 3 / 4) as f64;
+        println!(
+            "ratio {ratio:.2}: {per_delete:>8.1} ns per delete, {:>5} leaves, \
+             {below_half:>5} of them less than half full, {:>5} free pages",
+            stats.leaf_pages, stats.free_pages,
+        );
+        drop(db);
+    }
+
+    _ = fs::remove_file(path);
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! The async wrapper of ThetaDB, enabled by the `async` feature.
+//!
+//! ThetaDB blocks the calling thread on page faults, IO and lock waits, which would stall the
+//! executor if it were called from a future directly. The wrapper runs each operation on a pool
+//! of blocking threads instead, and returns a future resolving to its result. It works with any
+//! executor, as the futures are woken by the pool threads.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    pin::Pin,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crate::{Options, Result, ThetaDB, Tx, TxMut};
+
+/// A ThetaDB whose operations run on a pool of blocking threads, returning futures.
+///
+/// The transactions are not sendable, so each of them is performed within a single blocking
+/// task by the closure-based [`view`](Self::view) and [`update`](Self::update).
+#[derive(Clone)]
+pub struct AsyncThetaDB {
+    db: Arc<ThetaDB>,
+}
+
+impl AsyncThetaDB {
+    /// Wraps an opened ThetaDB.
+    #[inline]
+    pub fn new(db: ThetaDB) -> Self {
+        Self { db: Arc::new(db) }
+    }
+
+    /// Open a ThetaDB instance at the given file path with the provided options.
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Blocking<Result<Self>> {
+        let path = path.as_ref().to_path_buf();
+        spawn(move || options.open(path).map(Self::new))
+    }
+
+    /// Get the value associated with a given key.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Blocking<Result<Option<Vec<u8>>>> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.get(key))
+    }
+
+    /// Put a key-value pair into the ThetaDB.
+    pub fn put(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Blocking<Result<()>> {
+        let (key, value) = (key.as_ref().to_vec(), value.as_ref().to_vec());
+        self.run(move |db| db.put(key, value))
+    }
+
+    /// Delete a key-value pair from the ThetaDB.
+    pub fn delete(&self, key: impl AsRef<[u8]>) -> Blocking<Result<()>> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.delete(key))
+    }
+
+    /// Perform a read-only transaction using closure on the ThetaDB, see [`ThetaDB::view`].
+    pub fn view<T, F>(&self, f: F) -> Blocking<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Tx) -> Result<T> + Send + 'static,
+    {
+        self.run(move |db| db.view(f))
+    }
+
+    /// Perform a read-write transaction using closure on the ThetaDB, see [`ThetaDB::update`].
+    pub fn update<T, F>(&self, f: F) -> Blocking<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut TxMut) -> Result<T> + Send + 'static,
+    {
+        self.run(move |db| db.update(f))
+    }
+
+    /// Run the closure with the ThetaDB on a blocking thread, e.g., to call the APIs not
+    /// wrapped above.
+    pub fn run<T, F>(&self, f: F) -> Blocking<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ThetaDB) -> T + Send + 'static,
+    {
+        let db = self.db.clone();
+        spawn(move || f(&db))
+    }
+}
+
+/// The future of an operation running on a blocking thread, see [`AsyncThetaDB`].
+///
+/// The operation runs even if the future is dropped. If it panics, the panic is resumed when
+/// the future is polled.
+pub struct Blocking<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        match slot.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the closure on the pool of blocking threads.
+fn spawn<T, F>(f: F) -> Blocking<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+
+    let job_slot = slot.clone();
+    let job: Job = Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let waker = {
+            let mut slot = job_slot.lock().unwrap_or_else(PoisonError::into_inner);
+            slot.result = Some(result);
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+
+    pool()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .send(job)
+        .expect("the blocking threads never exit");
+    Blocking { slot }
+}
+
+/// The sender of jobs to the blocking threads, which are started on first use and live as long
+/// as the process. The transactions never outlive their jobs, so a job waiting for a lock only
+/// waits for the ones running on the other threads, never for the queued ones.
+fn pool() -> &'static Mutex<Sender<Job>> {
+    static POOL: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+
+    POOL.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = thread::available_parallelism().map_or(2, |n| n.get().max(2));
+
+        for idx in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("thetadb-blocking-{idx}"))
+                .spawn(move || loop {
+                    // The jobs catch their panics, so the threads keep running.
+                    let job = receiver
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                })
+                .expect("failed to spawn the blocking threads of ThetaDB");
+        }
+        Mutex::new(sender)
+    })
+}
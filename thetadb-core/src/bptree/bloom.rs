@@ -0,0 +1,143 @@
+use crate::medium::{Bytes, BytesMut};
+
+/// A Bloom filter stored at the end of a leaf page, which tells that a key is definitely
+/// absent from the leaf without searching it.
+///
+/// Bits are never cleared when keys are deleted, so the filter may report deleted keys as
+/// present, which only costs a search. It is rebuilt when the records of the leaf are moved
+/// (i.e., splitting and merging). An empty filter reports every key as present.
+pub(crate) struct Bloom<B> {
+    bits: B,
+    hashes: u8,
+}
+
+/// The shape of the Bloom filters in new leaves, see `Options::bloom_bits_per_key`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct BloomShape {
+    /// The number of bytes of the filter, 0 if there is no filter.
+    pub(crate) len: u16,
+    /// The number of hash functions.
+    pub(crate) hashes: u8,
+}
+
+impl BloomShape {
+    /// The average length of records that the filters are sized for.
+    const RECORD_LEN: u32 = 32;
+
+    /// The maximum fraction of a page that a filter takes up, which keeps leaves with and
+    /// without filters mergeable.
+    const MAX_PAGE_FRACTION: u32 = 8;
+
+    pub(crate) fn new(bits_per_key: u8, page_size: u32) -> Self {
+        if bits_per_key == 0 {
+            return Self::default();
+        }
+
+        let bits = page_size / Self::RECORD_LEN * bits_per_key as u32;
+        let len = (bits / u8::BITS).min(page_size / Self::MAX_PAGE_FRACTION);
+        // The optimal number of hash functions is `bits_per_key * ln(2)`.
+        let hashes = (bits_per_key as f64 * std::f64::consts::LN_2).round() as u8;
+
+        Self {
+            len: len.try_into().unwrap_or(u16::MAX),
+            hashes: hashes.max(1),
+        }
+    }
+}
+
+impl<B> Bloom<B>
+where
+    B: Bytes,
+{
+    #[inline]
+    pub(crate) fn new(bits: B, hashes: u8) -> Self {
+        Self { bits, hashes }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// Checks if the key may be in the leaf, false means it is definitely absent.
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        bit_indexes(key, self.bits.len(), self.hashes)
+            .all(|idx| self.bits[idx / 8] & (1 << (idx % 8)) != 0)
+    }
+}
+
+impl<B> Bloom<B>
+where
+    B: BytesMut,
+{
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+
+    pub(crate) fn insert(&mut self, key: &[u8]) {
+        for idx in bit_indexes(key, self.bits.len(), self.hashes) {
+            self.bits[idx / 8] |= 1 << (idx % 8);
+        }
+    }
+}
+
+/// The indexes of the bits of a key, using double hashing. The hashes must be stable as the
+/// filter is persisted.
+fn bit_indexes(key: &[u8], bytes_len: usize, hashes: u8) -> impl Iterator<Item = usize> {
+    let bits_len = bytes_len as u64 * 8;
+
+    let h1 = crc32fast::hash(key) as u64;
+    let mut hasher = crc32fast::Hasher::new_with_initial(h1 as u32 ^ 0x9E37_79B9);
+    hasher.update(key);
+    let h2 = hasher.finalize() as u64 | 1;
+
+    let count = if bits_len == 0 { 0 } else { hashes as u64 };
+    (0..count).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits_len) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bloom, BloomShape};
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(BloomShape::new(0, 4096), BloomShape::default());
+        assert_eq!(
+            BloomShape::new(10, 4096),
+            BloomShape {
+                len: 160,
+                hashes: 7
+            }
+        );
+        assert_eq!(
+            BloomShape::new(255, 4096),
+            BloomShape {
+                len: 512,
+                hashes: 177
+            }
+        );
+    }
+
+    #[test]
+    fn test_may_contain() {
+        let mut bytes = [0; 160];
+        let mut bloom = Bloom::new(bytes.as_mut_slice(), 7);
+
+        let keys = (0..128u32).map(|i| i.to_be_bytes()).collect::<Vec<_>>();
+        keys.iter().for_each(|k| bloom.insert(k));
+        assert!(keys.iter().all(|k| bloom.may_contain(k)));
+
+        let false_positives = (128..1128u32)
+            .filter(|i| bloom.may_contain(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 50, "{false_positives}");
+
+        bloom.clear();
+        assert!(!bloom.may_contain(&keys[0]));
+
+        // An empty filter reports every key as present.
+        let empty = Bloom::new([].as_slice(), 7);
+        assert!(empty.may_contain(b"foo"));
+    }
+}
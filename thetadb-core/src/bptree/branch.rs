@@ -138,6 +138,8 @@ where
         left: PageId,
         right: PageId,
     ) -> mapping::Result<()> {
+        // The key of the first record is never compared by `search`, so it does not collide
+        // with the empty key put by users.
         self.put(0, &[], left)?;
         self.put(1, key, right)?;
         Ok(())
@@ -1,15 +1,59 @@
+use std::{borrow::Cow, ops::Bound};
+
 use crate::{
     bptree::{
         branch::Branch,
+        cursor::Track,
         entry::Value,
         index::{TreeIndex, TreeIndexExt, TreeIndexMut, TreeIndexMutExt},
         node::Node,
         BPTree,
     },
     medium::mapping,
+    meta::MIN_PAGE_SIZE,
     storage::PageId,
+    MAX_KEY_LEN,
 };
 
+/// The default fill rate at or below which a node is merged with one of its siblings, see
+/// `Options::underflow_ratio`.
+pub(crate) const NODE_UNDERFLOW_RATIO: f64 = 0.35;
+
+/// The longest key with which merging two underflowed nodes always succeeds in pages of the
+/// given size.
+///
+/// The merged node takes up at most `2 * NODE_UNDERFLOW_RATIO` of the page, and merging branches
+/// also moves the separator key down from the parent. So the rest of the page must hold the
+/// longest key, with 64 bytes to spare for the page headers and a slot. Pages of 2 KB or larger
+/// hold keys up to [`MAX_KEY_LEN`].
+pub(crate) const fn max_key_len(page_size: u32) -> usize {
+    let len = merge_room(page_size, NODE_UNDERFLOW_RATIO);
+    if len < MAX_KEY_LEN {
+        len
+    } else {
+        MAX_KEY_LEN
+    }
+}
+
+/// Checks if merging two nodes underflowed at the given ratio always succeeds in pages of the
+/// given size. The keys are limited by the default ratio, see [`max_key_len`], so a higher
+/// ratio is only supported if the rest of the merged page still holds the longest of them.
+pub(crate) fn supports_underflow_ratio(page_size: u32, underflow_ratio: f64) -> bool {
+    merge_room(page_size, underflow_ratio) >= max_key_len(page_size)
+}
+
+/// The room left for a key in pages of the given size after merging two nodes underflowed at
+/// the given ratio.
+const fn merge_room(page_size: u32, underflow_ratio: f64) -> usize {
+    let spare = (page_size as f64 * (1.0 - 2.0 * underflow_ratio)) as usize;
+    spare.saturating_sub(64)
+}
+
+// The page size is never smaller than the minimum one, see `Options::validate` and
+// `Header::validate`, so even the smallest pages hold reasonable keys.
+const _: () = assert!(max_key_len(MIN_PAGE_SIZE) >= 64);
+const _: () = assert!(max_key_len(2 * 1024) == MAX_KEY_LEN);
+
 impl<Index> BPTree<Index>
 where
     Index: TreeIndex,
@@ -22,67 +66,177 @@ where
                     let index = branch.search(key)?;
                     node = self.index.child(&branch, index)?;
                 }
+                Node::Leaf(leaf) if !leaf.may_contain(key) => break Ok(false),
                 Node::Leaf(leaf) => break leaf.search(key).map(|i| i.is_ok()),
             }
         }
     }
 
+    #[inline]
     pub(crate) fn get(&self, key: &[u8]) -> mapping::Result<Option<Vec<u8>>> {
-        let mut node = self.index.root_node()?;
+        self.search_value(key, |value| self.index.value(value))
+    }
+
+    /// Reads the value associated with the key into the buffer, which is cleared first, returns
+    /// whether the key exists.
+    #[inline]
+    pub(crate) fn get_into(&self, key: &[u8], buf: &mut Vec<u8>) -> mapping::Result<bool> {
+        buf.clear();
+        self.search_value(key, |value| self.index.value_into(value, buf))
+            .map(|found| found.is_some())
+    }
+
+    fn search_value<T>(
+        &self,
+        key: &[u8],
+        read: impl FnOnce(Value<&[u8]>) -> mapping::Result<T>,
+    ) -> mapping::Result<Option<T>> {
+        let mut page_id = self.index.root_id();
+        let mut node = self.index.node(page_id)?;
         loop {
             match node {
                 Node::Branch(branch) => {
-                    let index = branch.search(key)?;
-                    node = self.index.child(&branch, index)?;
+                    page_id = branch
+                        .search(key)
+                        .and_then(|index| branch.page_id(index))
+                        .map_err(|err| err.context(format_args!("branch page {}", page_id.raw())))?;
+                    node = self.index.node(page_id)?;
                 }
+                Node::Leaf(leaf) if !leaf.may_contain(key) => break Ok(None),
                 Node::Leaf(leaf) => {
-                    let Ok(index) = leaf.search(key)? else {
-                        break Ok(None);
+                    let entry = match leaf.search(key) {
+                        Ok(Ok(index)) => leaf.entry(index),
+                        Ok(Err(_)) => break Ok(None),
+                        Err(err) => Err(err),
                     };
-                    break leaf
-                        .entry(index)
-                        .and_then(|e| self.index.value(e.value))
-                        .map(Some);
+                    let entry = entry
+                        .map_err(|err| err.context(format_args!("leaf page {}", page_id.raw())))?;
+                    break read(entry.value).map(Some);
                 }
             }
         }
     }
+
+    /// Visits all the branch pages, so that they are loaded into memory.
+    pub(crate) fn visit_branches(&self) -> mapping::Result<()> {
+        let mut branches = Vec::new();
+        if let Node::Branch(root) = self.index.root_node()? {
+            branches.push(root);
+        }
+
+        while let Some(branch) = branches.pop() {
+            for index in 0..branch.count() {
+                if let Node::Branch(child) = self.index.child(&branch, index)? {
+                    branches.push(child);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `put_inner` does with the value of the key.
+#[derive(Clone, Copy)]
+enum Put<'v> {
+    /// Replaces the value.
+    Value(&'v [u8]),
+    /// Appends to the value, see `BPTree::append`.
+    Append(&'v [u8]),
+    /// Puts the value only if the key is absent, see `BPTree::put_if_absent`.
+    IfAbsent(&'v [u8]),
 }
 
+/// The new sibling and its first key, when a node is split by putting into it.
+type Split = Option<(PageId, Vec<u8>)>;
+
 impl<Index> BPTree<Index>
 where
     Index: TreeIndexMut,
 {
     const VALUE_OVERFLOW_RATIO: f64 = 0.25;
 
+    /// The longest key that can be put into the tree, see [`max_key_len`].
+    #[inline]
+    pub(crate) fn max_key_len(&self) -> usize {
+        max_key_len(self.index.page_size())
+    }
+
+    #[inline]
     pub(crate) fn put(&self, key: &[u8], value: &[u8]) -> mapping::Result<()> {
+        self.put_record(key, Put::Value(value)).map(|_| ())
+    }
+
+    /// Appends the suffix to the value of the key, an absent key is put with the suffix.
+    ///
+    /// An overflowed value is extended without being read and rewritten, see
+    /// `TreeIndexMutExt::append_chunk`. An inline value is put again with the suffix, which
+    /// overflows it if it becomes too long.
+    #[inline]
+    pub(crate) fn append(&self, key: &[u8], suffix: &[u8]) -> mapping::Result<()> {
+        self.put_record(key, Put::Append(suffix)).map(|_| ())
+    }
+
+    /// Puts the record only if the key is absent, returns whether it is put.
+    #[inline]
+    pub(crate) fn put_if_absent(&self, key: &[u8], value: &[u8]) -> mapping::Result<bool> {
+        self.put_record(key, Put::IfAbsent(value))
+    }
+
+    /// Puts the record, returns whether it is put.
+    fn put_record(&self, key: &[u8], put: Put) -> mapping::Result<bool> {
         let (root_id, root) = self.index.shadow_root()?;
 
-        if let Some(mid) = self.put_inner(root, key, value)? {
+        let Some(split) = self.put_inner(root, key, put)? else {
+            return Ok(false);
+        };
+        if let Some(mid) = split {
             let mut new_root = self.index.alloc_branch_root()?;
             new_root.init_root(&mid.1, root_id, mid.0)?;
         }
 
-        Ok(())
+        Ok(true)
     }
 
+    /// Puts the record into the subtree of the node, returns `None` if nothing is put, i.e.,
+    /// the key of `Put::IfAbsent` is present.
     fn put_inner<'a>(
         &'a self,
         mut node: Node<&'a mut [u8]>,
         key: &[u8],
-        value: &[u8],
-    ) -> mapping::Result<Option<(PageId, Vec<u8>)>> {
+        put: Put,
+    ) -> mapping::Result<Option<Split>> {
         match &mut node {
             Node::Leaf(leaf) => {
                 let index = leaf.search(key)?;
 
+                let value = match (put, index) {
+                    (Put::IfAbsent(_), Ok(_)) => return Ok(None),
+                    (Put::Value(value) | Put::IfAbsent(value), _) => Cow::Borrowed(value),
+                    (Put::Append(suffix), Ok(idx)) => match leaf.entry(idx)?.value {
+                        Value::Bytes(bytes) => Cow::Owned([bytes, suffix].concat()),
+                        Value::Overflowed { page_id } => {
+                            let page_id = self.index.append_chunk(page_id, suffix)?;
+                            // The record keeps its length, so it is always set in place.
+                            leaf.put(index, key, Value::Overflowed { page_id })?;
+                            return Ok(Some(None));
+                        }
+                    },
+                    (Put::Append(suffix), Err(_)) => Cow::Borrowed(suffix),
+                };
+                let value = value.as_ref();
+
+                // Overwrite the inline value of the same length in place.
+                if let Ok(idx) = index && !self.is_value_overflow(key, value) && leaf.overwrite(idx, value)? {
+                    return Ok(Some(None));
+                }
+
                 // Delete overflow chunk of key.
                 if let Ok(idx) = index && let Value::Overflowed { page_id } = leaf.entry(idx)?.value {
                     self.index.delete_chunk(page_id)?;
                 }
 
                 // If the value is overflow, then store it in new overflow pages (Chunk pages).
-                let value = if self.is_value_overflow(value) {
+                let value = if self.is_value_overflow(key, value) {
                     let page_id = self.index.set_chunk(value)?;
                     Value::Overflowed { page_id }
                 } else {
@@ -91,7 +245,7 @@ where
 
                 // Try inserting data to see if there is enough space.
                 if leaf.put(index, key, value)? {
-                    return Ok(None);
+                    return Ok(Some(None));
                 }
 
                 // Obtain a new page.
@@ -99,7 +253,7 @@ where
                 // Split then put data
                 let mid_key = leaf.split_put(&mut new, index, key, value)?;
 
-                Ok(Some((new_id, mid_key)))
+                Ok(Some(Some((new_id, mid_key))))
             }
 
             Node::Branch(branch) => {
@@ -112,13 +266,14 @@ where
                 branch.set_page_id(index, child_id)?;
 
                 // Recursively add data to the following child nodes.
-                let Some(mid) = self.put_inner(child, key, value)? else {
-                    return Ok(None);
+                let mid = match self.put_inner(child, key, put)? {
+                    Some(Some(mid)) => mid,
+                    unsplit => return Ok(unsplit),
                 };
 
                 // Try inserting data to see if there is enough space.
                 if branch.put(index + 1, &mid.1, mid.0)? {
-                    return Ok(None);
+                    return Ok(Some(None));
                 }
 
                 // Obtain a new page
@@ -126,14 +281,153 @@ where
                 // Split then put data
                 let mid_key = branch.split_put(&mut new, index + 1, &mid.1, mid.0)?;
 
-                Ok(Some((new_id, mid_key)))
+                Ok(Some(Some((new_id, mid_key))))
             }
         }
     }
 
+    /// The longest value that can be stored inline with the key. The key is counted in, so that
+    /// the halves of a split leaf always have room for the new record, even in small pages.
     #[inline]
-    fn is_value_overflow(&self, value: &[u8]) -> bool {
-        (value.len() as f64 / self.index.page_size() as f64) > Self::VALUE_OVERFLOW_RATIO
+    pub(crate) fn max_inline_value_len(&self, key_len: usize) -> usize {
+        let record_len = (self.index.page_size() as f64 * Self::VALUE_OVERFLOW_RATIO) as usize;
+        record_len.saturating_sub(key_len)
+    }
+
+    #[inline]
+    fn is_value_overflow(&self, key: &[u8], value: &[u8]) -> bool {
+        value.len() > self.max_inline_value_len(key.len())
+    }
+}
+
+impl<Index> BPTree<Index>
+where
+    Index: TreeIndexMut,
+{
+    /// Deletes all records for which `f` returns false, returns the keys of deleted records.
+    ///
+    /// Records are visited in ascending order by key. The keys to be deleted are collected first
+    /// and then deleted, so the traversal never observes a restructured tree.
+    pub(crate) fn retain<F>(&self, mut f: F) -> mapping::Result<Vec<Vec<u8>>>
+    where
+        F: FnMut(&[u8], &[u8]) -> bool,
+    {
+        let mut deleted_keys = Vec::new();
+        let mut track = Track::new();
+        let mut found = self.edge_track(true, &mut track)?;
+
+        while found {
+            let Some(location) = track.last() else {
+                break;
+            };
+            let Node::Leaf(leaf) = self.index.node(location.page_id)? else {
+                break;
+            };
+
+            let entry = leaf.entry(location.index)?;
+            let value = self.index.value(entry.value)?;
+            if !f(&entry.key, &value) {
+                deleted_keys.push(entry.key.to_vec());
+            }
+
+            found = self.step_track(&mut track, true)?;
+        }
+
+        for key in &deleted_keys {
+            self.delete(key)?;
+        }
+        Ok(deleted_keys)
+    }
+
+    /// Deletes all records whose keys fall within the given bounds, returns the keys of deleted
+    /// records.
+    pub(crate) fn delete_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> mapping::Result<Vec<Vec<u8>>> {
+        let mut deleted_keys = Vec::new();
+        let mut track = Track::new();
+        let mut found = match start {
+            Bound::Included(key) => self.lower_bound_track(key, true, &mut track)?,
+            Bound::Excluded(key) => self.lower_bound_track(key, false, &mut track)?,
+            Bound::Unbounded => self.edge_track(true, &mut track)?,
+        };
+
+        while found {
+            let Some(location) = track.last() else {
+                break;
+            };
+            let Node::Leaf(leaf) = self.index.node(location.page_id)? else {
+                break;
+            };
+
+            let key = leaf.entry(location.index)?.key;
+            let in_range = match end {
+                Bound::Included(end) => key <= *end,
+                Bound::Excluded(end) => key < *end,
+                Bound::Unbounded => true,
+            };
+            if !in_range {
+                break;
+            }
+            deleted_keys.push(key.to_vec());
+
+            found = self.step_track(&mut track, true)?;
+        }
+
+        for key in &deleted_keys {
+            self.delete(key)?;
+        }
+        Ok(deleted_keys)
+    }
+
+    /// Deletes all records by freeing every page of the tree, overflow pages included, and
+    /// replacing the root with a new empty leaf. Returns the number of deleted records, whose
+    /// keys are passed to `on_deleted` in order.
+    ///
+    /// Unlike deleting the records one by one, no node is rewritten, merged or rebalanced.
+    pub(crate) fn clear(&self, mut on_deleted: impl FnMut(&[u8])) -> mapping::Result<usize> {
+        let root_id = self.index.root_id();
+        if matches!(self.index.node(root_id)?, Node::Leaf(leaf) if leaf.count() == 0) {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        let mut pending = vec![root_id];
+
+        while let Some(id) = pending.pop() {
+            // A page is freed only after reading it, as freeing a page written by the
+            // transaction gives its memory back.
+            let mut chunk_ids = Vec::new();
+            match self.index.node(id)? {
+                // The children are pushed in reverse, so the keys are collected in order.
+                Node::Branch(branch) => {
+                    for index in (0..branch.count()).rev() {
+                        pending.push(branch.page_id(index)?);
+                    }
+                }
+                Node::Leaf(leaf) => {
+                    for index in 0..leaf.count() {
+                        let entry = leaf.entry(index)?;
+                        if let Value::Overflowed { page_id } = entry.value {
+                            chunk_ids.push(page_id);
+                        }
+                        on_deleted(&entry.key);
+                    }
+                    count += leaf.count();
+                }
+            }
+
+            for chunk_id in chunk_ids {
+                self.index.delete_chunk(chunk_id)?;
+            }
+            self.index.delete(id);
+        }
+
+        let (id, _) = self.index.alloc_leaf()?;
+        self.index.set_root_id(id);
+        Ok(count)
     }
 }
 
@@ -147,30 +441,30 @@ impl<Index> BPTree<Index>
 where
     Index: TreeIndexMut,
 {
-    const NODE_UNDERFLOW_RATIO: f64 = 0.35;
-
-    pub(crate) fn delete(&self, key: &[u8]) -> mapping::Result<()> {
+    /// Deletes the key-value pair with the given key, returns whether the key existed.
+    pub(crate) fn delete(&self, key: &[u8]) -> mapping::Result<bool> {
         let (root_id, mut root) = self.index.shadow_root()?;
 
-        self.delete_inner(&mut root, key)?;
+        let deleted = self.delete_inner(&mut root, key)?;
 
         if let Node::Branch(root) = root && root.count() == 1 {
             self.index.set_root_id(root.page_id(0)?);
             self.index.delete(root_id);
         }
 
-        Ok(())
+        Ok(deleted)
     }
 
-    fn delete_inner(&self, node: &mut Node<&mut [u8]>, key: &[u8]) -> mapping::Result<()> {
+    fn delete_inner(&self, node: &mut Node<&mut [u8]>, key: &[u8]) -> mapping::Result<bool> {
         match node {
             Node::Leaf(leaf) => {
-                if let Ok(index) = leaf.search(key)? {
-                    if let Value::Overflowed { page_id } = leaf.entry(index)?.value {
-                        self.index.delete_chunk(page_id)?;
-                    }
-                    leaf.delete(index)?;
+                let Ok(index) = leaf.search(key)? else {
+                    return Ok(false);
+                };
+                if let Value::Overflowed { page_id } = leaf.entry(index)?.value {
+                    self.index.delete_chunk(page_id)?;
                 }
+                leaf.delete(index)?;
             }
 
             Node::Branch(branch) => {
@@ -182,26 +476,29 @@ where
                 // Update page id for shadow child page.
                 branch.set_page_id(index, child_id)?;
 
-                self.delete_inner(&mut child, key)?;
+                let deleted = self.delete_inner(&mut child, key)?;
+
+                if child.fill_rate() > self.index.underflow_ratio() {
+                    return Ok(deleted);
+                }
 
-                if child.fill_rate() > Self::NODE_UNDERFLOW_RATIO {
-                    return Ok(());
+                // An empty node is deleted instead of being merged, as an empty branch has no
+                // record to hold the separator key moved down from the parent.
+                if child.is_empty() {
+                    self.index.delete(child_id);
+                    branch.delete(index)?;
+                    return Ok(deleted);
                 }
 
                 // Only merge with siblings and don't borrow records from them.
                 let Some((sibling_id, sibling, is_next)) = self.underflow_sibling(index, branch)?
                 else {
-                    if child.is_empty() {
-                        self.index.delete(child_id);
-                        branch.delete(index)?;
-                    }
-                    return Ok(());
+                    return Ok(deleted);
                 };
                 let deleted_index = if is_next { index + 1 } else { index };
 
-                // We assume that the key len won't exceed (2 * NODE_UNDERFLOW_RATIO * page size).
-                // In fact, the key is already limited to a maximum len of 255,
-                // so we just need to make sure the page size doesn't get smaller than 510.
+                // Both nodes are underflowed, so there is always enough space for merging,
+                // see `max_key_len` and `supports_underflow_ratio`.
                 match &mut child {
                     Node::Leaf(child) => {
                         if let Some(sibling) = sibling.leaf() {
@@ -223,9 +520,10 @@ where
                 if !is_next {
                     branch.set_page_id(index - 1, child_id)?;
                 }
+                return Ok(deleted);
             }
         }
-        Ok(())
+        Ok(true)
     }
 
     fn underflow_sibling(
@@ -237,7 +535,7 @@ where
             if let Some(id) = parent.sibling(index, with_next)? {
                 let sibling = self.index.node(id)?;
 
-                if sibling.fill_rate() <= Self::NODE_UNDERFLOW_RATIO {
+                if sibling.fill_rate() <= self.index.underflow_ratio() {
                     return Ok(Some((id, sibling, with_next)));
                 }
             }
@@ -1,5 +1,8 @@
+use std::{mem, ops::Bound};
+
 use crate::{
     bptree::{
+        entry::Value,
         index::{TreeIndex, TreeIndexExt},
         node::Node,
         BPTree,
@@ -10,12 +13,14 @@ use crate::{
 
 pub(crate) struct Cursor<Index> {
     bptree: BPTree<Index>,
-    track: Option<Track>,
+    /// The path from the root to the current record, empty if the cursor points to nothing.
+    /// It is reused as the cursor moves, rather than being allocated on each move.
+    track: Track,
 }
 
 pub(crate) type Track = Vec<Location>;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) struct Location {
     pub(crate) page_id: PageId,
     pub(crate) index: usize,
@@ -29,12 +34,16 @@ impl Location {
 }
 
 impl<Index> Cursor<Index> {
+    /// Creates a cursor pointing to where the track leads, so that the position can be kept
+    /// without the B+ tree in between.
     #[inline]
-    pub(crate) fn new(bptree: BPTree<Index>) -> Self {
-        Self {
-            bptree,
-            track: None,
-        }
+    pub(crate) fn new(bptree: BPTree<Index>, track: Track) -> Self {
+        Self { bptree, track }
+    }
+
+    #[inline]
+    pub(crate) fn into_track(self) -> Track {
+        self.track
     }
 }
 
@@ -63,6 +72,39 @@ where
         Ok(Some(value))
     }
 
+    /// Reads the value of the current record into the buffer, which is cleared first, returns
+    /// whether the cursor points to a record.
+    pub(crate) fn value_into(&self, buf: &mut Vec<u8>) -> mapping::Result<bool> {
+        buf.clear();
+        let Some(location) = self.entry_location() else {
+            return Ok(false);
+        };
+        let Node::Leaf(leaf) = self.bptree.index.node(location.page_id)? else {
+            return Ok(false);
+        };
+        self.bptree
+            .index
+            .value_into(leaf.entry(location.index)?.value, buf)?;
+        Ok(true)
+    }
+
+    /// Passes the value of the current record to the closure, borrowing the inline value from
+    /// the page without copying it.
+    pub(crate) fn value_ref<T>(&self, f: impl FnOnce(&[u8]) -> T) -> mapping::Result<Option<T>> {
+        let Some(location) = self.entry_location() else {
+            return Ok(None);
+        };
+        let Node::Leaf(leaf) = self.bptree.index.node(location.page_id)? else {
+            return Ok(None);
+        };
+        let res = match leaf.entry(location.index)?.value {
+            Value::Bytes(bytes) => f(bytes),
+            // The overflowed value is scattered across the chunk pages, so it is assembled.
+            Value::Overflowed { page_id } => f(&self.bptree.index.chunk(page_id)?),
+        };
+        Ok(Some(res))
+    }
+
     pub(crate) fn key_value(&self) -> mapping::Result<Option<(Vec<u8>, Vec<u8>)>> {
         let Some(location) = self.entry_location() else {
             return Ok(None);
@@ -77,36 +119,81 @@ where
         Ok(Some((key, value)))
     }
 
+    /// Estimates the position of the current record among all records, from 0.0 at the first
+    /// one towards 1.0 at the last one.
+    ///
+    /// Each level of the track narrows the position down to a fraction of its parent, as if
+    /// the subtrees of a node were equally sized. So the estimate is cheap, but not exact.
+    pub(crate) fn approx_rank(&self) -> mapping::Result<Option<f64>> {
+        if self.track.is_empty() {
+            return Ok(None);
+        }
+
+        let (mut rank, mut width) = (0.0, 1.0);
+        for location in &self.track {
+            let count = self.bptree.index.node(location.page_id)?.count().max(1) as f64;
+            width /= count;
+            rank += location.index as f64 * width;
+        }
+        Ok(Some(rank))
+    }
+
     #[inline]
     pub(crate) fn first(&mut self) -> mapping::Result<bool> {
-        self.track = self.bptree.edge_track(true)?;
-        Ok(self.track.is_some())
+        self.move_track(|bptree, track| bptree.edge_track(true, track))
     }
 
     #[inline]
     pub(crate) fn last(&mut self) -> mapping::Result<bool> {
-        self.track = self.bptree.edge_track(false)?;
-        Ok(self.track.is_some())
+        self.move_track(|bptree, track| bptree.edge_track(false, track))
     }
 
     #[inline]
     pub(crate) fn seek(&mut self, key: &[u8]) -> mapping::Result<bool> {
-        self.track = self.bptree.track(key)?;
-        Ok(self.track.is_some())
+        self.move_track(|bptree, track| bptree.track(key, track))
+    }
+
+    /// Moves the cursor to the first record satisfying the start bound if `forward`, otherwise
+    /// to the last record satisfying the end bound.
+    pub(crate) fn seek_bound(
+        &mut self,
+        bound: Bound<&[u8]>,
+        forward: bool,
+    ) -> mapping::Result<bool> {
+        self.move_track(|bptree, track| match bound {
+            Bound::Included(key) if forward => bptree.lower_bound_track(key, true, track),
+            Bound::Excluded(key) if forward => bptree.lower_bound_track(key, false, track),
+            Bound::Included(key) => bptree.upper_bound_track(key, true, track),
+            Bound::Excluded(key) => bptree.upper_bound_track(key, false, track),
+            Bound::Unbounded => bptree.edge_track(forward, track),
+        })
     }
 
     #[inline]
     pub(crate) fn step(&mut self, forward: bool) -> mapping::Result<bool> {
-        let Some(track) = self.track.take() else {
+        if self.track.is_empty() {
             return Ok(false);
-        };
-        self.track = self.bptree.step_track(track, forward)?;
-        Ok(self.track.is_some())
+        }
+        self.move_track(|bptree, track| bptree.step_track(track, forward))
+    }
+
+    #[inline]
+    pub(crate) fn entry_location(&self) -> Option<Location> {
+        self.track.last().cloned()
     }
 
+    /// Moves the track in place, the cursor points to nothing if no record is found or an error
+    /// occurs.
     #[inline]
-    fn entry_location(&self) -> Option<Location> {
-        self.track.as_ref().and_then(|t| t.last().cloned())
+    fn move_track<F>(&mut self, f: F) -> mapping::Result<bool>
+    where
+        F: FnOnce(&BPTree<Index>, &mut Track) -> mapping::Result<bool>,
+    {
+        let res = f(&self.bptree, &mut self.track);
+        if !matches!(res, Ok(true)) {
+            self.track.clear();
+        }
+        res
     }
 }
 
@@ -114,9 +201,138 @@ impl<Index> BPTree<Index>
 where
     Index: TreeIndex,
 {
+    /// Obtains the key of the first (or last) record in the tree.
+    pub(crate) fn edge_key(&self, first: bool) -> mapping::Result<Option<Vec<u8>>> {
+        let Some(location) = self.edge_location(first)? else {
+            return Ok(None);
+        };
+        let Node::Leaf(leaf) = self.index.node(location.page_id)? else {
+            return Ok(None);
+        };
+        Ok(Some(leaf.entry(location.index)?.key.to_vec()))
+    }
+
+    /// Obtains the key-value pair of the first (or last) record in the tree.
+    pub(crate) fn edge_key_value(
+        &self,
+        first: bool,
+    ) -> mapping::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let Some(location) = self.edge_location(first)? else {
+            return Ok(None);
+        };
+        let Node::Leaf(leaf) = self.index.node(location.page_id)? else {
+            return Ok(None);
+        };
+        let entry = leaf.entry(location.index)?;
+
+        let key = entry.key.to_vec();
+        let value = self.index.value(entry.value)?;
+        Ok(Some((key, value)))
+    }
+
+    /// Counts the records whose keys fall within the given bounds.
+    ///
+    /// Records are counted leaf by leaf, so only the boundary leaves need to be searched.
+    pub(crate) fn count_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> mapping::Result<usize> {
+        let mut track = Track::new();
+        let mut found = match start {
+            Bound::Included(key) => self.lower_bound_track(key, true, &mut track)?,
+            Bound::Excluded(key) => self.lower_bound_track(key, false, &mut track)?,
+            Bound::Unbounded => self.edge_track(true, &mut track)?,
+        };
+
+        let mut count = 0;
+        while found {
+            let Some(location) = track.last_mut() else {
+                break;
+            };
+            let Node::Leaf(leaf) = self.index.node(location.page_id)? else {
+                break;
+            };
+
+            // The index (exclusive) where records of this leaf stop satisfying the end bound.
+            let end_index = match end {
+                Bound::Included(key) => leaf.search(key)?.map_or_else(|i| i, |i| i + 1),
+                Bound::Excluded(key) => leaf.search(key)?.unwrap_or_else(|i| i),
+                Bound::Unbounded => leaf.count(),
+            };
+            count += end_index.saturating_sub(location.index);
+
+            if end_index < leaf.count() {
+                break;
+            }
+
+            // Jump to the first record of the next leaf.
+            location.index = leaf.count() - 1;
+            found = self.step_track(&mut track, true)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Picks up to `parts - 1` keys in ascending order, which split the tree into roughly
+    /// equal parts.
+    ///
+    /// The levels are descended until one has enough pages, so the separator keys of the
+    /// branches are used as the split points. The keys of the records are used instead if
+    /// there are too few leaves.
+    pub(crate) fn split_keys(&self, parts: usize) -> mapping::Result<Vec<Vec<u8>>> {
+        // The pages of the current level, and the keys between each two adjacent of them.
+        let (mut ids, mut keys) = (vec![self.index.root_id()], Vec::<Vec<u8>>::new());
+
+        while keys.len() + 1 < parts && !ids.is_empty() {
+            let (mut next_ids, mut next_keys) = (Vec::new(), Vec::new());
+            for (i, id) in ids.into_iter().enumerate() {
+                if i > 0 {
+                    next_keys.push(mem::take(&mut keys[i - 1]));
+                }
+                match self.index.node(id)? {
+                    Node::Branch(branch) => {
+                        for index in 0..branch.count() {
+                            // The first key of a branch is never used for searching.
+                            if index > 0 {
+                                next_keys.push(branch.key(index)?.to_vec());
+                            }
+                            next_ids.push(branch.page_id(index)?);
+                        }
+                    }
+                    Node::Leaf(leaf) => {
+                        for index in 1..leaf.count() {
+                            next_keys.push(leaf.entry(index)?.key.to_vec());
+                        }
+                    }
+                }
+            }
+            (ids, keys) = (next_ids, next_keys);
+        }
+        keys.dedup();
+
+        // Picks the keys evenly if there are more than needed.
+        let count = keys.len() + 1;
+        if count <= parts {
+            return Ok(keys);
+        }
+        Ok((1..parts)
+            .map(|i| mem::take(&mut keys[i * count / parts - 1]))
+            .collect())
+    }
+
+    /// Obtains the location of the first (or last) record in the tree.
     #[inline]
-    fn track(&self, key: &[u8]) -> mapping::Result<Option<Track>> {
-        let mut track = Vec::new();
+    fn edge_location(&self, first: bool) -> mapping::Result<Option<Location>> {
+        let mut track = Track::new();
+        let found = self.edge_track(first, &mut track)?;
+        Ok(track.last().cloned().filter(|_| found))
+    }
+
+    /// Fills the track of the record with the given key, returns false if there is no such
+    /// record.
+    fn track(&self, key: &[u8], track: &mut Track) -> mapping::Result<bool> {
+        track.clear();
         let (mut page_id, mut node) = (self.index.root_id(), self.index.root_node()?);
 
         loop {
@@ -130,17 +346,77 @@ where
                 }
                 Node::Leaf(leaf) => {
                     let Ok(index) = leaf.search(key)? else {
-                        break Ok(None);
+                        break Ok(false);
                     };
                     track.push(Location::new(page_id, index));
-                    break Ok(Some(track));
+                    break Ok(true);
                 }
             }
         }
     }
 
-    fn edge_track(&self, first: bool) -> mapping::Result<Option<Track>> {
-        let mut track = Vec::new();
+    /// Fills the track of the first record whose key is greater than or equal to (or strictly
+    /// greater than, if `inclusive` is false) the given key, returns false if there is none.
+    pub(super) fn lower_bound_track(
+        &self,
+        key: &[u8],
+        inclusive: bool,
+        track: &mut Track,
+    ) -> mapping::Result<bool> {
+        track.clear();
+        let (mut page_id, mut node) = (self.index.root_id(), self.index.root_node()?);
+
+        loop {
+            match &node {
+                Node::Branch(branch) => {
+                    let index = branch.search(key)?;
+                    track.push(Location::new(page_id, index));
+
+                    page_id = branch.page_id(index)?;
+                    node = self.index.node(page_id)?;
+                }
+                Node::Leaf(leaf) => {
+                    let index = match leaf.search(key)? {
+                        Ok(index) if !inclusive => index + 1,
+                        Ok(index) | Err(index) => index,
+                    };
+                    if index < leaf.count() {
+                        track.push(Location::new(page_id, index));
+                        break Ok(true);
+                    }
+
+                    // All keys of this leaf are smaller, so the record is the first one of the
+                    // next leaf.
+                    let Some(last_index) = leaf.count().checked_sub(1) else {
+                        break Ok(false);
+                    };
+                    track.push(Location::new(page_id, last_index));
+                    break self.step_track(track, true);
+                }
+            }
+        }
+    }
+
+    /// Fills the track of the last record whose key is less than or equal to (or strictly less
+    /// than, if `inclusive` is false) the given key, returns false if there is none.
+    pub(super) fn upper_bound_track(
+        &self,
+        key: &[u8],
+        inclusive: bool,
+        track: &mut Track,
+    ) -> mapping::Result<bool> {
+        // The record is right before the first one beyond the bound.
+        if self.lower_bound_track(key, !inclusive, track)? {
+            self.step_track(track, false)
+        } else {
+            self.edge_track(false, track)
+        }
+    }
+
+    /// Fills the track of the first (or last) record in the tree, returns false if the tree is
+    /// empty.
+    pub(super) fn edge_track(&self, first: bool, track: &mut Track) -> mapping::Result<bool> {
+        track.clear();
         let (mut page_id, mut node) = (self.index.root_id(), self.index.root_node()?);
 
         loop {
@@ -150,7 +426,7 @@ where
                 node.last_index()
             };
             let Some(index) = index else {
-                break Ok(None);
+                break Ok(false);
             };
 
             track.push(Location::new(page_id, index));
@@ -160,35 +436,38 @@ where
                     page_id = branch.page_id(index)?;
                     node = self.index.node(page_id)?;
                 }
-                Node::Leaf(_) => break Ok(Some(track)),
+                Node::Leaf(_) => break Ok(true),
             }
         }
     }
 
-    fn step_track(&self, mut track: Track, forward: bool) -> mapping::Result<Option<Track>> {
+    /// Moves the track to the next (or previous) record in place, returns false if there is
+    /// none, in which case the track is left in an unspecified state.
+    ///
+    /// Only the nodes whose edge is passed are left and replaced by their siblings, the rest of
+    /// the track stays, so a step is amortized to a constant number of node reads.
+    pub(super) fn step_track(&self, track: &mut Track, forward: bool) -> mapping::Result<bool> {
         let Some(mut location) = track.pop() else {
-            return Ok(None);
+            return Ok(false);
         };
 
         let node = self.index.node(location.page_id)?;
         if let Some(next_index) = node.step_index(location.index, forward) {
             location.index = next_index;
             track.push(location);
-            return Ok(Some(track));
+            return Ok(true);
         }
 
-        track = if let Some(track) = self.step_track(track, forward)? {
-            track
-        } else {
-            return Ok(None);
-        };
+        if !self.step_track(track, forward)? {
+            return Ok(false);
+        }
 
         let parent = track.last().unwrap();
 
         location.page_id = match &self.index.node(parent.page_id)? {
             Node::Branch(branch) => branch.page_id(parent.index)?,
             // Parent node should not be leaf, so this code branch should not be reachable.
-            Node::Leaf(_) => return Ok(None),
+            Node::Leaf(_) => return Ok(false),
         };
 
         let node = self.index.node(location.page_id)?;
@@ -201,10 +480,10 @@ where
         location.index = if let Some(index) = index {
             index
         } else {
-            return Ok(None);
+            return Ok(false);
         };
 
         track.push(location);
-        Ok(Some(track))
+        Ok(true)
     }
 }
@@ -1,16 +1,33 @@
-use std::fmt::Formatter;
+use std::fmt::{self, Write};
 
 use crate::{
-    bptree::{branch::Branch, index::TreeIndexExt, leaf::Leaf, node::Node, BPTree, TreeIndex},
+    bptree::{
+        branch::Branch, entry::Value, index::TreeIndexExt, leaf::Leaf, node::Node, BPTree,
+        TreeIndex,
+    },
+    chunk::Chunk,
     medium::mapping,
+    storage::PageId,
+    tx::{PageKind, ValueStorage},
 };
 
+/// The statistics of the B+ tree collected by traversing it.
+#[derive(Default)]
+pub(crate) struct TreeStats {
+    pub(crate) height: u32,
+    pub(crate) branch_pages: u32,
+    pub(crate) leaf_pages: u32,
+    pub(crate) overflow_pages: u32,
+    pub(crate) entry_count: u64,
+}
+
 impl<Index> BPTree<Index>
 where
     Index: TreeIndex,
 {
+    /// Dumps the tree as an ASCII diagram, writing to the sink as it goes.
     #[inline]
-    pub(crate) fn dump(&self, f: &mut Formatter) -> std::fmt::Result {
+    pub(crate) fn dump(&self, f: &mut impl Write) -> Result<(), DumpError> {
         writeln!(f, "┓")?;
         self.dump_node(self.index.root_node()?, String::new(), f)
     }
@@ -20,8 +37,8 @@ where
         &self,
         node: Node<&[u8]>,
         prefix: String,
-        f: &mut Formatter<'_>,
-    ) -> std::fmt::Result {
+        f: &mut impl Write,
+    ) -> Result<(), DumpError> {
         match node {
             Node::Branch(branch) => self.dump_branch(branch, prefix, f),
             Node::Leaf(leaf) => self.dump_leaf(leaf, prefix, f),
@@ -32,8 +49,8 @@ where
         &self,
         branch: Branch<&[u8]>,
         prefix: String,
-        f: &mut Formatter,
-    ) -> std::fmt::Result {
+        f: &mut impl Write,
+    ) -> Result<(), DumpError> {
         for index in 0..branch.count() {
             let is_last = index == branch.count() - 1;
             let page_id = branch.page_id(index)?;
@@ -57,7 +74,12 @@ where
         Ok(())
     }
 
-    fn dump_leaf(&self, leaf: Leaf<&[u8]>, prefix: String, f: &mut Formatter) -> std::fmt::Result {
+    fn dump_leaf(
+        &self,
+        leaf: Leaf<&[u8]>,
+        prefix: String,
+        f: &mut impl Write,
+    ) -> Result<(), DumpError> {
         for index in 0..leaf.count() {
             let is_last = index == leaf.count() - 1;
             let weld = if is_last { "┗" } else { "┣" };
@@ -69,9 +91,480 @@ where
     }
 }
 
-impl From<mapping::Error> for std::fmt::Error {
+impl<Index> BPTree<Index>
+where
+    Index: TreeIndex,
+{
+    /// Dumps the tree as JSON, the values are dumped only if `include_values` is set, otherwise
+    /// just their lengths.
+    #[inline]
+    pub(crate) fn dump_json(&self, include_values: bool) -> mapping::Result<String> {
+        let mut json = String::new();
+        let root_id = self.index.root_id();
+        self.dump_node_json(
+            root_id,
+            self.index.node(root_id)?,
+            include_values,
+            &mut json,
+        )?;
+        Ok(json)
+    }
+
+    fn dump_node_json(
+        &self,
+        page_id: PageId,
+        node: Node<&[u8]>,
+        include_values: bool,
+        json: &mut String,
+    ) -> mapping::Result<()> {
+        let kind = match node {
+            Node::Branch(_) => "branch",
+            Node::Leaf(_) => "leaf",
+        };
+        _ = write!(
+            json,
+            r#"{{"type":"{kind}","page_id":{},"count":{},"fill_rate":{}"#,
+            page_id.raw(),
+            node.count(),
+            node.fill_rate()
+        );
+
+        match node {
+            Node::Branch(branch) => self.dump_branch_json(branch, include_values, json)?,
+            Node::Leaf(leaf) => self.dump_leaf_json(leaf, include_values, json)?,
+        }
+
+        json.push('}');
+        Ok(())
+    }
+
+    fn dump_branch_json(
+        &self,
+        branch: Branch<&[u8]>,
+        include_values: bool,
+        json: &mut String,
+    ) -> mapping::Result<()> {
+        // The first key of a branch is never used for searching, just like `dump`.
+        json.push_str(r#","keys":["#);
+        for index in 1..branch.count() {
+            if index > 1 {
+                json.push(',');
+            }
+            push_json_str(&branch.key(index)?, json);
+        }
+
+        json.push_str(r#"],"children":["#);
+        for index in 0..branch.count() {
+            if index > 0 {
+                json.push(',');
+            }
+            let page_id = branch.page_id(index)?;
+            self.dump_node_json(page_id, self.index.node(page_id)?, include_values, json)?;
+        }
+
+        json.push(']');
+        Ok(())
+    }
+
+    fn dump_leaf_json(
+        &self,
+        leaf: Leaf<&[u8]>,
+        include_values: bool,
+        json: &mut String,
+    ) -> mapping::Result<()> {
+        json.push_str(r#","entries":["#);
+        for index in 0..leaf.count() {
+            if index > 0 {
+                json.push(',');
+            }
+
+            let entry = leaf.entry(index)?;
+            let overflowed = matches!(entry.value, Value::Overflowed { .. });
+            let value = self.index.value(entry.value)?;
+
+            json.push_str(r#"{"key":"#);
+            push_json_str(&entry.key, json);
+            _ = write!(
+                json,
+                r#","value_len":{},"overflowed":{overflowed}"#,
+                value.len()
+            );
+            if include_values {
+                json.push_str(r#","value":"#);
+                push_json_str(&value, json);
+            }
+            json.push('}');
+        }
+
+        json.push(']');
+        Ok(())
+    }
+}
+
+/// Pushes the bytes as a JSON string if they are valid UTF-8, otherwise as an object holding
+/// their hex encoding, e.g. `{"hex":"6b65ff"}`, so that no byte is lost.
+fn push_json_str(bytes: &[u8], json: &mut String) {
+    let Ok(str) = std::str::from_utf8(bytes) else {
+        json.push_str(r#"{"hex":""#);
+        for byte in bytes {
+            _ = write!(json, "{byte:02x}");
+        }
+        json.push_str(r#""}"#);
+        return;
+    };
+
+    json.push('"');
+    for char in str.chars() {
+        match char {
+            '"' => json.push_str(r#"\""#),
+            '\\' => json.push_str(r"\\"),
+            '\n' => json.push_str(r"\n"),
+            '\r' => json.push_str(r"\r"),
+            '\t' => json.push_str(r"\t"),
+            char if char.is_control() => _ = write!(json, r"\u{:04x}", char as u32),
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+}
+
+impl<Index> BPTree<Index>
+where
+    Index: TreeIndex,
+{
+    /// Collects the statistics by visiting every page of the tree.
+    #[inline]
+    pub(crate) fn stats(&self) -> mapping::Result<TreeStats> {
+        let mut stats = TreeStats::default();
+        self.stats_node(self.index.root_node()?, 1, &mut stats)?;
+        Ok(stats)
+    }
+
+    fn stats_node(
+        &self,
+        node: Node<&[u8]>,
+        depth: u32,
+        stats: &mut TreeStats,
+    ) -> mapping::Result<()> {
+        stats.height = stats.height.max(depth);
+
+        match node {
+            Node::Branch(branch) => {
+                stats.branch_pages += 1;
+                for index in 0..branch.count() {
+                    self.stats_node(self.index.child(&branch, index)?, depth + 1, stats)?;
+                }
+            }
+            Node::Leaf(leaf) => {
+                stats.leaf_pages += 1;
+                stats.entry_count += leaf.count() as u64;
+                for index in 0..leaf.count() {
+                    if let Value::Overflowed { page_id } = leaf.entry(index)?.value {
+                        stats.overflow_pages +=
+                            Chunk::chain_len(page_id, |id| self.index.page(id))?.0;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Visits every page of the tree, including the overflow pages, passing their ids and kinds.
+    pub(crate) fn visit_pages(
+        &self,
+        mut visit: impl FnMut(PageId, PageKind),
+    ) -> mapping::Result<()> {
+        let mut ids = vec![self.index.root_id()];
+
+        while let Some(id) = ids.pop() {
+            match self.index.node(id)? {
+                Node::Branch(branch) => {
+                    visit(id, PageKind::Branch);
+                    for index in 0..branch.count() {
+                        ids.push(branch.page_id(index)?);
+                    }
+                }
+                Node::Leaf(leaf) => {
+                    visit(id, PageKind::Leaf);
+                    for index in 0..leaf.count() {
+                        let Value::Overflowed { page_id } = leaf.entry(index)?.value else {
+                            continue;
+                        };
+                        let mut prev_id = Some(page_id);
+                        while let Some(id) = prev_id {
+                            visit(id, PageKind::Overflow);
+                            prev_id = self.index.page::<Chunk<_>>(id)?.prev();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the number of entries of the node page, and the ids of its children if it is a
+    /// branch. The page must be known to be a node.
+    pub(crate) fn node_entries(&self, id: PageId) -> mapping::Result<(usize, Vec<PageId>)> {
+        match self.index.node(id)? {
+            Node::Branch(branch) => {
+                let children = (0..branch.count())
+                    .map(|index| branch.page_id(index))
+                    .collect::<mapping::Result<_>>()?;
+                Ok((branch.count(), children))
+            }
+            Node::Leaf(leaf) => Ok((leaf.count(), Vec::new())),
+        }
+    }
+
+    /// Counts the leaves by their fill rates, each bucket covers an equal share of the range.
+    pub(crate) fn leaf_fill_histogram(&self, buckets: usize) -> mapping::Result<Vec<usize>> {
+        let mut histogram = vec![0; buckets];
+        let mut nodes = vec![self.index.root_node()?];
+
+        while let Some(node) = nodes.pop() {
+            match node {
+                Node::Branch(branch) => {
+                    for index in 0..branch.count() {
+                        nodes.push(self.index.child(&branch, index)?);
+                    }
+                }
+                Node::Leaf(leaf) => {
+                    // The full leaves are counted in the last bucket.
+                    let bucket = (leaf.fill_rate() * buckets as f64) as usize;
+                    histogram[bucket.min(buckets - 1)] += 1;
+                }
+            }
+        }
+        Ok(histogram)
+    }
+
+    /// Finds out how the value associated with the key is stored.
+    pub(crate) fn value_storage(&self, key: &[u8]) -> mapping::Result<Option<ValueStorage>> {
+        let mut node = self.index.root_node()?;
+        let leaf = loop {
+            match node {
+                Node::Branch(branch) => {
+                    let index = branch.search(key)?;
+                    node = self.index.child(&branch, index)?;
+                }
+                Node::Leaf(leaf) => break leaf,
+            }
+        };
+
+        let Ok(index) = leaf.search(key)? else {
+            return Ok(None);
+        };
+        let storage = match leaf.entry(index)?.value {
+            Value::Bytes(bytes) => ValueStorage::Inline {
+                len: bytes.len() as u32,
+            },
+            Value::Overflowed { page_id } => {
+                let (chunk_count, len) = Chunk::chain_len(page_id, |id| self.index.page(id))?;
+                ValueStorage::Overflow {
+                    head_page: Chunk::first(page_id, |id| self.index.page(id))?.raw(),
+                    chunk_count,
+                    len,
+                }
+            }
+        };
+        Ok(Some(storage))
+    }
+}
+
+impl<Index> BPTree<Index>
+where
+    Index: TreeIndex,
+{
+    /// Verifies the invariants of the tree by visiting every page of it, i.e., every page can
+    /// be read, the keys are in ascending order and within the bounds of their branches, and
+    /// all the leaves are at the same depth. The pages are claimed by the verifier, which also
+    /// collects the problems found.
+    #[inline]
+    pub(crate) fn verify(&self, verifier: &mut Verifier) {
+        self.verify_node(self.index.root_id(), 1, (None, None), verifier);
+    }
+
+    fn verify_node(
+        &self,
+        id: PageId,
+        depth: u32,
+        bounds: (Option<&[u8]>, Option<&[u8]>),
+        verifier: &mut Verifier,
+    ) {
+        if !verifier.claim(id) {
+            return;
+        }
+        let node = match self.index.node(id) {
+            Ok(node) => node,
+            Err(err) => return verifier.report(id, format!("cannot be read as a node ({err})")),
+        };
+
+        match node {
+            Node::Branch(branch) => {
+                let keys = (1..branch.count())
+                    .map(|index| branch.key(index))
+                    .collect::<mapping::Result<Vec<_>>>();
+                let keys = match keys {
+                    Ok(keys) => keys,
+                    Err(err) => return verifier.report(id, format!("has unreadable keys ({err})")),
+                };
+
+                for index in 0..branch.count() {
+                    // The child covers the keys from its key (inclusive) to the next one.
+                    let lower = index.checked_sub(1).map(|i| keys[i].as_slice());
+                    let upper = keys.get(index).map(Vec::as_slice);
+                    match branch.page_id(index) {
+                        Ok(child) => self.verify_node(
+                            child,
+                            depth + 1,
+                            (lower.or(bounds.0), upper.or(bounds.1)),
+                            verifier,
+                        ),
+                        Err(err) => verifier.report(id, format!("has an unreadable child ({err})")),
+                    }
+                }
+            }
+            Node::Leaf(leaf) => {
+                match verifier.leaf_depth {
+                    Some(leaf_depth) if leaf_depth != depth => verifier.report(
+                        id,
+                        format!("is a leaf at depth {depth}, while others are at {leaf_depth}"),
+                    ),
+                    _ => verifier.leaf_depth = Some(depth),
+                }
+
+                for index in 0..leaf.count() {
+                    let entry = match leaf.entry(index) {
+                        Ok(entry) => entry,
+                        Err(err) => {
+                            verifier.report(id, format!("has an unreadable entry ({err})"));
+                            continue;
+                        }
+                    };
+
+                    let key: &[u8] = &entry.key;
+                    if verifier.last_key.as_deref().is_some_and(|last| key <= last) {
+                        verifier.report(id, "has keys out of order".to_string());
+                    }
+                    if bounds.0.is_some_and(|lower| key < lower)
+                        || bounds.1.is_some_and(|upper| key >= upper)
+                    {
+                        verifier.report(id, "has keys out of the bounds of its branch".to_string());
+                    }
+                    verifier.last_key = Some(key.to_vec());
+
+                    if let Value::Overflowed { page_id } = entry.value {
+                        verifier.read_chain(page_id, |id| self.index.page(id));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Verifies the pages of a ThetaDB file, every page should be claimed exactly once, either
+/// as in use or as free.
+pub(crate) struct Verifier {
+    claimed: Vec<bool>,
+    problems: Vec<String>,
+    leaf_depth: Option<u32>,
+    last_key: Option<Vec<u8>>,
+}
+
+impl Verifier {
+    #[inline]
+    pub(crate) fn new(page_count: u32) -> Self {
+        Self {
+            claimed: vec![false; page_count as usize],
+            problems: Vec::new(),
+            leaf_depth: None,
+            last_key: None,
+        }
+    }
+
+    /// Claims a page, false if it is out of the file or has been claimed, which is reported.
+    pub(crate) fn claim(&mut self, id: PageId) -> bool {
+        match self.claimed.get_mut(id.raw() as usize) {
+            Some(false) => {
+                self.claimed[id.raw() as usize] = true;
+                true
+            }
+            Some(true) => {
+                self.report(id, "is referenced more than once".to_string());
+                false
+            }
+            None => {
+                let count = self.claimed.len();
+                self.report(id, format!("is out of the {count} pages"));
+                false
+            }
+        }
+    }
+
+    /// Reads a page chain and claims its pages, `None` if it is broken. A chain looping back
+    /// stops at the page claimed already.
+    pub(crate) fn read_chain<'a>(
+        &mut self,
+        id: PageId,
+        mut obtain: impl FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+    ) -> Option<Vec<u8>> {
+        let (mut bodies, mut prev_id) = (Vec::new(), Some(id));
+
+        while let Some(id) = prev_id {
+            if !self.claim(id) {
+                return None;
+            }
+            let read = obtain(id).and_then(|chunk| {
+                bodies.push(chunk.page_body()?);
+                Ok(chunk.prev())
+            });
+            match read {
+                Ok(prev) => prev_id = prev,
+                Err(err) => {
+                    self.report(id, format!("cannot be read as a chunk ({err})"));
+                    return None;
+                }
+            }
+        }
+
+        // The chain is read from its end.
+        Some(bodies.into_iter().rev().flatten().copied().collect())
+    }
+
+    #[inline]
+    pub(crate) fn report(&mut self, id: PageId, problem: String) {
+        self.problems.push(format!("page {} {problem}", id.raw()));
+    }
+
+    /// Finishes the verification, the pages never claimed are reported as leaked.
+    pub(crate) fn finish(mut self) -> Vec<String> {
+        let leaked = self.claimed.iter().filter(|&&claimed| !claimed).count();
+        if leaked > 0 {
+            self.problems
+                .push(format!("{leaked} pages are neither in use nor free"));
+        }
+        self.problems
+    }
+}
+
+/// The failure of dumping the tree.
+pub(crate) enum DumpError {
+    /// A page of the tree cannot be read.
+    Mapping(mapping::Error),
+    /// The sink fails to be written.
+    Write,
+}
+
+impl From<mapping::Error> for DumpError {
+    #[inline]
+    fn from(value: mapping::Error) -> Self {
+        Self::Mapping(value)
+    }
+}
+
+impl From<fmt::Error> for DumpError {
     #[inline]
-    fn from(_: mapping::Error) -> Self {
-        Self
+    fn from(_: fmt::Error) -> Self {
+        Self::Write
     }
 }
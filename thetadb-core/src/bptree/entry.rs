@@ -3,8 +3,15 @@ use std::{borrow::Cow, cmp::Ordering, mem, ops::Deref};
 use crate::{
     medium::{mapping, Bytes, BytesMut, Mapping},
     storage::PageId,
+    MAX_KEY_LEN,
 };
 
+/// The prefix storing the length of a key, which must be able to represent [`MAX_KEY_LEN`].
+/// Raising the limit requires widening the prefix, which changes the file format.
+pub(crate) type KeyLen = u8;
+
+const _: () = assert!(MAX_KEY_LEN <= KeyLen::MAX as usize);
+
 #[repr(transparent)]
 pub(crate) struct Key<B>(B);
 
@@ -24,27 +31,27 @@ where
 
     #[inline]
     pub(crate) fn split_from_bytes(bytes: B) -> mapping::Result<(Self, B)> {
-        let (len, remaining) = unsafe { Mapping::<B, u8>::split(bytes)? };
+        let (len, remaining) = unsafe { Mapping::<B, KeyLen>::split(bytes)? };
         let (body, remaining) = remaining.split_at(*len as usize);
         Ok((Self(body), remaining))
     }
 
     #[inline]
     pub(crate) fn len(&self) -> u32 {
-        (self.0.len() + mem::size_of::<u8>()) as u32
+        (self.0.len() + mem::size_of::<KeyLen>()) as u32
     }
 
     pub(crate) fn split_assign_to<T>(&self, bytes: T) -> mapping::Result<T>
     where
         T: BytesMut,
     {
-        let (mut len, remaining) = unsafe { Mapping::<T, u8>::split(bytes)? };
+        let (mut len, remaining) = unsafe { Mapping::<T, KeyLen>::split(bytes)? };
 
         *len = self
             .0
             .len()
             .try_into()
-            .expect("the key length cannot exceed 255");
+            .expect("the key length cannot exceed `MAX_KEY_LEN`");
 
         let (mut body, remaining) = remaining.split_at(*len as usize);
         body.copy_from_slice(&self.0);
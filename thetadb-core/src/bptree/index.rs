@@ -1,5 +1,5 @@
 use crate::{
-    bptree::{branch::Branch, entry::Value, leaf::Leaf, node::Node, NodePage},
+    bptree::{bloom::BloomShape, branch::Branch, entry::Value, leaf::Leaf, node::Node, NodePage},
     chunk::Chunk,
     medium::mapping,
     storage::{Page, PageId},
@@ -14,10 +14,36 @@ pub(crate) trait TreeIndex {
         P: Page<&'a [u8]>;
 }
 
+/// A borrowed index reads like the index itself, e.g., for a cursor over a read-write
+/// transaction that cannot give its index away.
+impl<T> TreeIndex for &T
+where
+    T: TreeIndex,
+{
+    #[inline]
+    fn root_id(&self) -> PageId {
+        (**self).root_id()
+    }
+
+    #[inline]
+    fn page<'a, P>(&'a self, id: PageId) -> mapping::Result<P>
+    where
+        P: Page<&'a [u8]>,
+    {
+        (**self).page(id)
+    }
+}
+
 /// Defines how the B+ Tree writes data from the underlying storage, it is also a TreeIndex.
 pub(crate) trait TreeIndexMut: TreeIndex {
     fn page_size(&self) -> u32;
 
+    /// The shape of the Bloom filters in new leaves.
+    fn bloom_shape(&self) -> BloomShape;
+
+    /// The fill rate at or below which a node is merged with one of its siblings on deletion.
+    fn underflow_ratio(&self) -> f64;
+
     fn set_root_id(&self, id: PageId);
 
     fn alloc<'a, P>(&'a self) -> mapping::Result<(PageId, P)>
@@ -46,6 +72,18 @@ pub(crate) trait TreeIndexExt: TreeIndex {
         }
     }
 
+    /// Reads the value by appending it to the buffer.
+    #[inline]
+    fn value_into(&self, value: Value<&[u8]>, buf: &mut Vec<u8>) -> mapping::Result<()> {
+        match value {
+            Value::Bytes(bytes) => {
+                buf.extend_from_slice(bytes);
+                Ok(())
+            }
+            Value::Overflowed { page_id } => Chunk::read_into(page_id, |id| self.page(id), buf),
+        }
+    }
+
     #[inline]
     fn root_node(&self) -> mapping::Result<Node<&[u8]>> {
         self.node(self.root_id())
@@ -53,7 +91,10 @@ pub(crate) trait TreeIndexExt: TreeIndex {
 
     #[inline]
     fn node(&self, id: PageId) -> mapping::Result<Node<&[u8]>> {
-        self.page::<NodePage<_>>(id).and_then(|p| p.into_node())
+        self.page::<NodePage<_>>(id).and_then(|p| {
+            p.into_node()
+                .map_err(|err| err.context(format_args!("node page {}", id.raw())))
+        })
     }
 
     #[inline]
@@ -82,7 +123,7 @@ pub(crate) trait TreeIndexMutExt: TreeIndexMut {
     #[inline]
     fn alloc_leaf(&self) -> mapping::Result<(PageId, Leaf<&mut [u8]>)> {
         let (id, page) = self.alloc::<NodePage<_>>()?;
-        let leaf = page.init_leaf()?;
+        let leaf = page.init_leaf(self.bloom_shape())?;
         Ok((id, leaf))
     }
 
@@ -105,6 +146,13 @@ pub(crate) trait TreeIndexMutExt: TreeIndexMut {
         Chunk::write(slice, || self.alloc())
     }
 
+    /// Appends the slice to a chunk chain, returns the new id of its last chunk. Only the
+    /// last chunk is shadowed, and the pages needed for the rest are allocated.
+    #[inline]
+    fn append_chunk(&self, id: PageId, slice: &[u8]) -> mapping::Result<PageId> {
+        Chunk::append(id, slice, |id| self.shadow(id), || self.alloc())
+    }
+
     #[inline]
     fn delete_chunk(&self, id: PageId) -> mapping::Result<()> {
         Chunk::delete(id, |id| self.page(id), |id| Ok(self.delete(id)))
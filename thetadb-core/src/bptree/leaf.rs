@@ -1,5 +1,6 @@
 use crate::{
     bptree::{
+        bloom::{Bloom, BloomShape},
         entry::{Entry, Key, Value},
         search::SearchIndex,
         slotted::Slotted,
@@ -7,8 +8,8 @@ use crate::{
     medium::{mapping, Bytes, BytesMut},
 };
 
-#[repr(transparent)]
-pub(crate) struct Leaf<B>(Slotted<B>);
+/// A leaf node, whose records are followed by an optional Bloom filter of their keys.
+pub(crate) struct Leaf<B>(Slotted<B>, Bloom<B>);
 
 struct Record<B> {
     key: Key<B>,
@@ -59,8 +60,15 @@ where
     B: Bytes,
 {
     #[inline]
-    pub(crate) fn new(bytes: B) -> mapping::Result<Self> {
-        Slotted::new(bytes).map(Self)
+    pub(crate) fn new(bytes: B, bloom: BloomShape) -> mapping::Result<Self> {
+        let records_len = bytes.len().checked_sub(bloom.len as usize).ok_or_else(|| {
+            mapping::Error::Size.context(format_args!(
+                "the Bloom filter of {} bytes is longer than the page",
+                bloom.len
+            ))
+        })?;
+        let (records, bits) = bytes.split_at(records_len);
+        Ok(Self(Slotted::new(records)?, Bloom::new(bits, bloom.hashes)))
     }
 
     #[inline]
@@ -75,6 +83,13 @@ where
         Ok(index)
     }
 
+    /// Checks if the key may be in the leaf, false means it is definitely absent, so that
+    /// searching can be skipped.
+    #[inline]
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        self.1.may_contain(key)
+    }
+
     #[inline]
     pub(crate) fn is_empty(&self) -> bool {
         self.count() == 0
@@ -103,6 +118,7 @@ where
     #[inline]
     pub(crate) fn init(&mut self) {
         self.0.init();
+        self.1.clear();
     }
 
     pub(crate) fn put(
@@ -120,6 +136,7 @@ where
 
         if let Some(bytes) = bytes {
             record.assign_to(bytes)?;
+            self.1.insert(key);
             Ok(true)
         } else {
             Ok(false)
@@ -143,11 +160,35 @@ where
 
         record.assign_to(bytes)?;
 
+        // The records are moved, so the filters are rebuilt.
+        self.rebuild_bloom()?;
+        new.rebuild_bloom()?;
+
         // Extract middle key.
         let mid_key = new.record(0)?.key.to_vec();
         Ok(mid_key)
     }
 
+    /// Overwrites the inline value of the record at the given index in place, only if the new
+    /// value has the same length, returns whether it is overwritten.
+    ///
+    /// The layout of the page is left untouched, which is common for counters and fixed-size
+    /// records.
+    pub(crate) fn overwrite(&mut self, index: usize, value: &[u8]) -> mapping::Result<bool> {
+        let record = self.record(index)?;
+        let Value::Bytes(old_value) = record.value else {
+            return Ok(false);
+        };
+        if old_value.len() != value.len() {
+            return Ok(false);
+        }
+
+        let key_len = record.key.len() as usize;
+        let bytes = self.0.get_mut(index)?;
+        Value::Bytes(value).assign_to(&mut bytes[key_len..])?;
+        Ok(true)
+    }
+
     #[inline]
     pub(crate) fn delete(&mut self, index: usize) -> mapping::Result<()> {
         self.0.remove(index)
@@ -158,6 +199,57 @@ where
     where
         T: Bytes,
     {
-        self.0.merge(&other.0, with_next)
+        if !self.0.merge(&other.0, with_next)? {
+            return Ok(false);
+        }
+        for idx in 0..other.count() {
+            self.1.insert(&other.record(idx)?.key);
+        }
+        Ok(true)
+    }
+
+    fn rebuild_bloom(&mut self) -> mapping::Result<()> {
+        if self.1.is_empty() {
+            return Ok(());
+        }
+        self.1.clear();
+        for idx in 0..self.count() {
+            self.1.insert(&Key::from_bytes(self.0.get(idx)?)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Leaf;
+    use crate::{
+        bptree::{bloom::BloomShape, entry::Value},
+        medium::mapping::Result,
+    };
+
+    #[test]
+    fn test_overwrite() -> Result<()> {
+        fn put_records<'a>(bytes: &'a mut [u8], value: &[u8]) -> Result<Leaf<&'a mut [u8]>> {
+            let mut leaf = Leaf::new(bytes, BloomShape::default())?;
+            leaf.init();
+            leaf.put(Err(0), b"a", Value::Bytes(value))?;
+            leaf.put(Err(1), b"b", Value::Overflowed { page_id: 9.into() })?;
+            leaf.put(Err(2), b"c", Value::Bytes(b"456"))?;
+            Ok(leaf)
+        }
+
+        let mut bytes = [0; 256];
+        let mut leaf = put_records(&mut bytes, b"123")?;
+        assert!(leaf.overwrite(0, b"789")?);
+        assert!(!leaf.overwrite(1, b"78")?);
+        assert!(!leaf.overwrite(2, b"78")?);
+
+        // The layout of the page is the same as putting the new value from the start.
+        let mut expected = [0; 256];
+        put_records(&mut expected, b"789")?;
+        assert_eq!(bytes, expected);
+        Ok(())
     }
 }
@@ -1,10 +1,15 @@
-pub(crate) use cursor::Cursor;
+pub(crate) use bloom::BloomShape;
+pub(crate) use crud::{max_key_len, supports_underflow_ratio, NODE_UNDERFLOW_RATIO};
+pub(crate) use cursor::{Cursor, Location, Track};
+pub(crate) use debug::{DumpError, Verifier};
+pub(crate) use entry::Value;
 pub(crate) use index::{TreeIndex, TreeIndexMut};
-pub(crate) use node::NodePage;
+pub(crate) use node::{Node, NodePage};
 
 #[macro_use]
 mod search;
 
+mod bloom;
 mod branch;
 mod crud;
 mod cursor;
@@ -1,8 +1,8 @@
 use std::{mem, ops::Range};
 
 use crate::{
-    bptree::{branch::Branch, leaf::Leaf},
-    medium::{mapping, Bytes, BytesMut, Mapping, Padding},
+    bptree::{bloom::BloomShape, branch::Branch, leaf::Leaf},
+    medium::{mapping, Bytes, BytesMut, Mapping},
     storage::Page,
 };
 
@@ -84,8 +84,8 @@ where
     }
 
     #[inline]
-    fn new_leaf(bytes: B) -> mapping::Result<Self> {
-        Leaf::new(bytes).map(Self::Leaf)
+    fn new_leaf(bytes: B, bloom: BloomShape) -> mapping::Result<Self> {
+        Leaf::new(bytes, bloom).map(Self::Leaf)
     }
 
     #[inline]
@@ -106,26 +106,48 @@ enum NodeType {
     Leaf,
 }
 
-const NODE_ALIGN: usize = mem::align_of::<u32>();
-const PAGE_HEADER_PADDING: usize = NODE_ALIGN - mem::size_of::<NodeType>();
-
 #[repr(C)]
 struct PageHeader {
     node_type: NodeType,
-    _padding: Padding<PAGE_HEADER_PADDING>,
+    /// The number of hash functions of the Bloom filter in a leaf page.
+    bloom_hashes: u8,
+    /// The length of the Bloom filter at the end of a leaf page, 0 if there is no filter.
+    bloom_len: u16,
 }
 
+// The node is aligned to u32 after the header.
+const _: () = assert!(mem::size_of::<PageHeader>() == mem::align_of::<u32>());
+
 impl<B> NodePage<B>
 where
     B: Bytes,
 {
     #[inline]
     pub(crate) fn into_node(self) -> mapping::Result<Node<B>> {
+        let bloom = self.bloom_shape();
+        match self.header.node_type {
+            NodeType::Leaf => Node::new_leaf(self.body, bloom),
+            NodeType::Branch => Node::new_branch(self.body),
+        }
+    }
+
+    /// Like `into_node`, but a leaf is read without its Bloom filter, e.g., in the files written
+    /// before the filters, whose page header has padding in place of the shape.
+    #[inline]
+    pub(crate) fn into_node_without_bloom(self) -> mapping::Result<Node<B>> {
         match self.header.node_type {
-            NodeType::Leaf => Node::new_leaf(self.body),
+            NodeType::Leaf => Node::new_leaf(self.body, BloomShape::default()),
             NodeType::Branch => Node::new_branch(self.body),
         }
     }
+
+    #[inline]
+    fn bloom_shape(&self) -> BloomShape {
+        BloomShape {
+            len: self.header.bloom_len,
+            hashes: self.header.bloom_hashes,
+        }
+    }
 }
 
 impl<B> NodePage<B>
@@ -135,18 +157,26 @@ where
     #[inline]
     pub(crate) fn init_branch(mut self) -> mapping::Result<Branch<B>> {
         self.header.node_type = NodeType::Branch;
+        self.set_bloom_shape(BloomShape::default());
         let mut branch = Branch::new(self.body)?;
         branch.init();
         Ok(branch)
     }
 
     #[inline]
-    pub(crate) fn init_leaf(mut self) -> mapping::Result<Leaf<B>> {
+    pub(crate) fn init_leaf(mut self, bloom: BloomShape) -> mapping::Result<Leaf<B>> {
         self.header.node_type = NodeType::Leaf;
-        let mut leaf = Leaf::new(self.body)?;
+        self.set_bloom_shape(bloom);
+        let mut leaf = Leaf::new(self.body, bloom)?;
         leaf.init();
         Ok(leaf)
     }
+
+    #[inline]
+    fn set_bloom_shape(&mut self, bloom: BloomShape) {
+        self.header.bloom_len = bloom.len;
+        self.header.bloom_hashes = bloom.hashes;
+    }
 }
 
 unsafe impl<B> Page<B> for NodePage<B>
@@ -163,7 +193,7 @@ where
 mod tests {
 
     use crate::{
-        bptree::{entry::Value, node::Node, NodePage},
+        bptree::{bloom::BloomShape, entry::Value, node::Node, NodePage},
         medium::mapping::Result,
         storage::Page,
     };
@@ -182,7 +212,7 @@ mod tests {
         };
         assert_eq!(branch.page_id(0)?, 123.into());
 
-        let mut leaf = NodePage::from_bytes(bytes.as_mut())?.init_leaf()?;
+        let mut leaf = NodePage::from_bytes(bytes.as_mut())?.init_leaf(BloomShape::default())?;
         leaf.put(Err(0), b"abc", Value::Bytes(b"123"))?;
         assert!(matches!(leaf.entry(0)?.value, Value::Bytes(b"123")));
 
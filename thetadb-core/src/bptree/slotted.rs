@@ -1,4 +1,9 @@
-use std::{iter::zip, mem, ops::Range, u32};
+use std::{
+    iter::zip,
+    mem,
+    ops::{Range, RangeTo},
+    u32,
+};
 
 use crate::medium::{mapping, Bytes, BytesMut, Mapping};
 
@@ -94,17 +99,37 @@ where
         let pointer = &self.pointers()?[index];
         let range = pointer.range();
 
-        mapping::check_range(&range, &self.body)?;
+        self.check_record_range(index, &range)?;
         Ok(&self.body[range])
     }
 
     #[inline]
     fn pointers(&self) -> mapping::Result<Pointers<&[u8]>> {
         let range = ..self.pointers_len() as usize;
-        mapping::check_range(&range, &self.body)?;
+        self.check_pointers_range(&range)?;
         unsafe { Mapping::new_slice(&self.body[range]) }
     }
 
+    #[inline]
+    fn check_record_range(&self, index: usize, range: &Range<usize>) -> mapping::Result<()> {
+        mapping::check_range(range, &self.body).map_err(|err| {
+            err.context(format_args!(
+                "slot {index} has the offset {} out of range",
+                range.start
+            ))
+        })
+    }
+
+    #[inline]
+    fn check_pointers_range(&self, range: &RangeTo<usize>) -> mapping::Result<()> {
+        mapping::check_range(range, &self.body).map_err(|err| {
+            err.context(format_args!(
+                "the {} slots are out of range",
+                self.header.num_slots
+            ))
+        })
+    }
+
     #[inline]
     fn pointers_len(&self) -> u32 {
         self.header.num_slots * Pointer::SIZE
@@ -135,7 +160,7 @@ where
         let pointer = &self.pointers()?[index];
         let range = pointer.range();
 
-        mapping::check_range(&range, &self.body)?;
+        self.check_record_range(index, &range)?;
         Ok(&mut self.body[range])
     }
 
@@ -341,7 +366,7 @@ where
     #[inline]
     fn pointers_mut(&mut self) -> mapping::Result<Pointers<&mut [u8]>> {
         let range = ..self.pointers_len() as usize;
-        mapping::check_range(&range, &self.body)?;
+        self.check_pointers_range(&range)?;
         unsafe { Mapping::new_slice(&mut self.body[range]) }
     }
 }
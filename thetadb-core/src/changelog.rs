@@ -0,0 +1,529 @@
+use std::{
+    fs,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{medium::file, meta::ValidationError, trace, tx::ChangeEvent, Result};
+
+/// A special sequence of bytes that is used at the beginning of the change log file for validation.
+const MAGIC: u32 = 0xC4A4_6E10;
+
+/// The current format version of the change log file.
+const VERSION: u32 = 2;
+
+/// The length of the header: magic (u32), version (u32) and truncated txid (u64).
+const HEADER_LEN: usize = 16;
+
+/// The length of the record header: txid (u64), generation (u64), payload length (u32) and
+/// checksum (u32).
+const RECORD_HEADER_LEN: usize = 24;
+
+const TAG_PUT: u8 = 0;
+const TAG_DELETE: u8 = 1;
+
+/// Represents the changes of a committed read-write transaction recorded in the change log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeLogEntry {
+    /// The id of the transaction, which is increasing monotonically.
+    pub txid: u64,
+    /// The changes made by the transaction, in the order they were made.
+    pub changes: Vec<ChangeEvent>,
+}
+
+/// An append-only log file that durably records the changes of each committed read-write
+/// transaction, so that they can be replayed elsewhere (e.g., synced to a backend).
+///
+/// # Change Log File
+///
+/// ```plain
+/// ┌───────┬─────────┬───────────────┬──────────┬──────────┬─────┐
+/// │ Magic │ Version │ Truncated TxId│ Record 1 │ Record 2 │ ••• │
+/// └───────┴─────────┴───────────────┴──────────┴──────────┴─────┘
+///
+/// ┌──────┬────────────┬─────┬──────────┬──────────────────────────────────────┐
+/// │ TxId │ Generation │ Len │ Checksum │ Payload (Tag, Key Len, Key, [Value]) │
+/// └──────┴────────────┴─────┴──────────┴──────────────────────────────────────┘
+/// ```
+///
+/// A record is appended and synced before its transaction switches the meta page, and is
+/// tagged with the generation of the ThetaDB the transaction commits. So the log never misses
+/// a committed transaction. The record of a transaction failing to switch the meta page is
+/// discarded at once, or when the log is opened if the process crashed in between, as it is
+/// the last one and its generation is right after the committed one.
+///
+/// A record that is incomplete or fails its checksum (e.g., torn by a crash) ends the log,
+/// and will be discarded when the log is opened.
+///
+/// The log written in version 1, whose records have no generation, is upgraded when opened.
+pub(crate) struct ChangeLog {
+    path: PathBuf,
+    file: fs::File,
+    len: u64,
+    next_txid: u64,
+    /// The offset of the record appended last, until the next append or truncation.
+    last_offset: Option<u64>,
+}
+
+impl ChangeLog {
+    /// Opens the change log of the ThetaDB whose latest committed generation is `generation`.
+    pub(crate) fn open(path: impl AsRef<Path>, generation: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        // Create all necessary intermediate directories.
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir).map_err(file::Error::from)?;
+        }
+
+        let mut file = Self::open_file(&path)?;
+        let mut bytes = Self::read_file(&file)?;
+
+        if bytes.is_empty() {
+            file.write_all_at(&header(0), 0)
+                .map_err(file::Error::from)?;
+            return Ok(Self {
+                path,
+                file,
+                len: HEADER_LEN as u64,
+                next_txid: 1,
+                last_offset: None,
+            });
+        }
+
+        let (version, truncated_txid) = parse_header(&bytes)?;
+        if version < VERSION {
+            bytes = upgrade(&bytes, truncated_txid);
+            replace_file(&path, &bytes)?;
+            file = Self::open_file(&path)?;
+        }
+
+        let mut records = Records::new(&bytes, VERSION);
+        let last = records.by_ref().last();
+        let mut valid_len = records.offset;
+        let mut next_txid = last
+            .as_ref()
+            .map_or(0, |r| r.entry.txid)
+            .max(truncated_txid)
+            + 1;
+
+        // Discard the record of the transaction that crashed before switching the meta page.
+        if let Some(record) = last && record.generation == generation + 1 {
+            trace::event!(
+                WARN,
+                "discard uncommitted change log record",
+                txid = record.entry.txid
+            );
+            valid_len = record.offset;
+            next_txid = record.entry.txid.max(truncated_txid + 1);
+        }
+
+        // Discard the torn tail.
+        if valid_len < bytes.len() {
+            file.set_len(valid_len as u64).map_err(file::Error::from)?;
+        }
+
+        Ok(Self {
+            path,
+            file,
+            len: valid_len as u64,
+            next_txid,
+            last_offset: None,
+        })
+    }
+
+    /// Appends the changes of a transaction about to commit the given generation and syncs
+    /// them, returns the id of the transaction.
+    ///
+    /// If the transaction fails to commit, the record must be discarded by `discard_last`.
+    pub(crate) fn append(&mut self, changes: &[ChangeEvent], generation: u64) -> Result<u64> {
+        let txid = self.next_txid;
+        let record = encode_record(txid, generation, changes);
+
+        let res = self
+            .file
+            .write_all_at(&record, self.len)
+            .and_then(|_| self.file.sync_data());
+        if let Err(err) = res {
+            // The partial record would be overwritten by the next one anyway.
+            _ = self.file.set_len(self.len);
+            return Err(file::Error::from(err).into());
+        }
+
+        self.last_offset = Some(self.len);
+        self.len += record.len() as u64;
+        self.next_txid += 1;
+        Ok(txid)
+    }
+
+    /// Discards the record appended last, as its transaction failed to commit.
+    pub(crate) fn discard_last(&mut self) {
+        let Some(offset) = self.last_offset.take() else {
+            return;
+        };
+        // If the record fails to be cut off, it is overwritten by the next one, or discarded
+        // when the log is opened as its generation is right after the committed one.
+        _ = self.file.set_len(offset);
+        self.len = offset;
+        self.next_txid -= 1;
+    }
+
+    /// Reads all the entries remaining in the log, in ascending order by txid.
+    pub(crate) fn entries(&self) -> Result<Vec<ChangeLogEntry>> {
+        let bytes = Self::read_file(&self.file)?;
+        Ok(Records::new(&bytes, VERSION).map(|r| r.entry).collect())
+    }
+
+    /// Removes the entries whose txid is not greater than the given one.
+    ///
+    /// The log is rewritten into a temporary file first, and then replaces the original file,
+    /// so that a crash during truncation never leaves the log half-truncated.
+    pub(crate) fn truncate(&mut self, txid: u64) -> Result<()> {
+        let truncated_txid = txid.min(self.next_txid - 1);
+        let bytes = Self::read_file(&self.file)?;
+
+        let tail_offset = Records::new(&bytes, VERSION)
+            .find(|r| r.entry.txid > truncated_txid)
+            .map_or(self.len as usize, |r| r.offset);
+
+        let mut truncated = header(truncated_txid).to_vec();
+        truncated.extend_from_slice(&bytes[tail_offset..self.len as usize]);
+        replace_file(&self.path, &truncated)?;
+
+        self.file = Self::open_file(&self.path)?;
+        self.len = truncated.len() as u64;
+        self.last_offset = None;
+        Ok(())
+    }
+
+    #[inline]
+    fn open_file(path: &Path) -> Result<fs::File> {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| file::Error::from(e).into())
+    }
+
+    fn read_file(file: &fs::File) -> Result<Vec<u8>> {
+        let len = file.metadata().map_err(file::Error::from)?.len() as usize;
+        let mut bytes = vec![0; len];
+        file.read_exact_at(&mut bytes, 0)
+            .map_err(file::Error::from)?;
+        Ok(bytes)
+    }
+}
+
+/// Replaces the file with the bytes, which are written into a temporary file first and then
+/// renamed to the path, so that a crash never leaves the file half-written.
+fn replace_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes)
+        .and_then(|_| fs::File::open(&tmp_path)?.sync_all())
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .and_then(|_| file::sync_parent_dir(path))
+        .map_err(|err| file::Error::from(err).into())
+}
+
+#[inline]
+fn header(truncated_txid: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..].copy_from_slice(&truncated_txid.to_le_bytes());
+    header
+}
+
+/// Validates the header, returns the version and the truncated txid.
+fn parse_header(bytes: &[u8]) -> Result<(u32, u64)> {
+    let mut reader = Reader(bytes);
+    let (Some(magic), Some(version), Some(truncated_txid)) =
+        (reader.u32(), reader.u32(), reader.u64())
+    else {
+        return Err(ValidationError::FileInvalid.into());
+    };
+
+    if magic != MAGIC {
+        Err(ValidationError::FileInvalid.into())
+    } else if version == 0 || version > VERSION {
+        Err(ValidationError::VersionMismatched.into())
+    } else {
+        Ok((version, truncated_txid))
+    }
+}
+
+/// Rewrites the log of an older version in the current one. Its records were appended after
+/// their transactions had committed, so they are tagged with generation 0, which is never
+/// discarded as uncommitted.
+fn upgrade(bytes: &[u8], truncated_txid: u64) -> Vec<u8> {
+    let mut upgraded = header(truncated_txid).to_vec();
+    for record in Records::new(bytes, 1) {
+        upgraded.extend(encode_record(record.entry.txid, 0, &record.entry.changes));
+    }
+    upgraded
+}
+
+fn encode_record(txid: u64, generation: u64, changes: &[ChangeEvent]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for change in changes {
+        let (tag, key, value) = match change {
+            ChangeEvent::Put { key, value } => (TAG_PUT, key, Some(value)),
+            ChangeEvent::Delete { key } => (TAG_DELETE, key, None),
+        };
+
+        payload.push(tag);
+        for bytes in Some(key).into_iter().chain(value) {
+            payload.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            payload.extend_from_slice(bytes);
+        }
+    }
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    record.extend_from_slice(&txid.to_le_bytes());
+    record.extend_from_slice(&generation.to_le_bytes());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&checksum(txid, Some(generation), &payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+fn decode_payload(payload: &[u8]) -> Option<Vec<ChangeEvent>> {
+    let mut reader = Reader(payload);
+    let mut changes = Vec::new();
+
+    while let Some(tag) = reader.u8() {
+        let key = reader.bytes()?.to_vec();
+        changes.push(match tag {
+            TAG_PUT => ChangeEvent::Put {
+                key,
+                value: reader.bytes()?.to_vec(),
+            },
+            TAG_DELETE => ChangeEvent::Delete { key },
+            _ => return None,
+        });
+    }
+
+    Some(changes)
+}
+
+/// The checksum of a record, the generation is absent in version 1.
+#[inline]
+fn checksum(txid: u64, generation: Option<u64>, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&txid.to_le_bytes());
+    if let Some(generation) = generation {
+        hasher.update(&generation.to_le_bytes());
+    }
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// A valid record of the log.
+struct Record {
+    offset: usize,
+    generation: u64,
+    entry: ChangeLogEntry,
+}
+
+/// Iterates over the valid records of the log written in the given version.
+struct Records<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    version: u32,
+}
+
+impl<'a> Records<'a> {
+    #[inline]
+    fn new(bytes: &'a [u8], version: u32) -> Self {
+        Self {
+            bytes,
+            offset: HEADER_LEN.min(bytes.len()),
+            version,
+        }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut reader = Reader(&self.bytes[self.offset..]);
+
+        let txid = reader.u64()?;
+        let generation = if self.version > 1 {
+            Some(reader.u64()?)
+        } else {
+            None
+        };
+        let len = reader.u32()? as usize;
+        let checksum_value = reader.u32()?;
+        let payload = reader.take(len)?;
+
+        if checksum(txid, generation, payload) != checksum_value {
+            return None;
+        }
+        let changes = decode_payload(payload)?;
+
+        let offset = self.offset;
+        self.offset = self.bytes.len() - reader.0.len();
+        Some(Record {
+            offset,
+            generation: generation.unwrap_or(0),
+            entry: ChangeLogEntry { txid, changes },
+        })
+    }
+}
+
+/// A little-endian reader over a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        (self.0.len() >= len).then(|| {
+            let (bytes, remaining) = self.0.split_at(len);
+            self.0 = remaining;
+            bytes
+        })
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    #[inline]
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    #[inline]
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{checksum, encode_record, header, ChangeLog, ChangeLogEntry};
+    use crate::tx::ChangeEvent;
+
+    fn changes() -> Vec<ChangeEvent> {
+        vec![
+            ChangeEvent::Put {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            },
+            ChangeEvent::Delete {
+                key: b"baz".to_vec(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let path = "target/test_changelog_torn_tail.log";
+        _ = fs::remove_file(path);
+        let changes = changes();
+
+        let mut changelog = ChangeLog::open(path, 0).unwrap();
+        assert_eq!(changelog.append(&changes, 1).unwrap(), 1);
+        assert_eq!(changelog.append(&changes, 2).unwrap(), 2);
+        let len = changelog.len;
+        drop(changelog);
+
+        // Simulate a crash while appending the third record.
+        let mut bytes = fs::read(path).unwrap();
+        bytes.extend_from_slice(&3u64.to_le_bytes());
+        bytes.extend_from_slice(&[0xFF; 7]);
+        fs::write(path, bytes).unwrap();
+
+        let mut changelog = ChangeLog::open(path, 2).unwrap();
+        assert_eq!(changelog.len, len);
+        assert_eq!(changelog.entries().unwrap().len(), 2);
+        assert_eq!(changelog.append(&changes, 3).unwrap(), 3);
+        assert_eq!(
+            changelog.entries().unwrap().last(),
+            Some(&ChangeLogEntry { txid: 3, changes })
+        );
+
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_uncommitted_record() {
+        let path = "target/test_changelog_uncommitted_record.log";
+        _ = fs::remove_file(path);
+        let changes = changes();
+
+        // The record of a transaction failing to commit is discarded at once.
+        let mut changelog = ChangeLog::open(path, 0).unwrap();
+        assert_eq!(changelog.append(&changes, 1).unwrap(), 1);
+        assert_eq!(changelog.append(&changes, 2).unwrap(), 2);
+        changelog.discard_last();
+        assert_eq!(changelog.entries().unwrap().len(), 1);
+        assert_eq!(changelog.append(&changes, 2).unwrap(), 2);
+        drop(changelog);
+
+        // The last record is kept if its generation is committed.
+        let changelog = ChangeLog::open(path, 2).unwrap();
+        assert_eq!(changelog.entries().unwrap().len(), 2);
+        drop(changelog);
+
+        // Otherwise, the process crashed before its transaction switched the meta page.
+        let mut changelog = ChangeLog::open(path, 1).unwrap();
+        assert_eq!(changelog.entries().unwrap().len(), 1);
+        assert_eq!(changelog.append(&changes, 2).unwrap(), 2);
+        drop(changelog);
+
+        // The records are never discarded for a generation far behind, e.g., of a new file.
+        let changelog = ChangeLog::open(path, 0).unwrap();
+        assert_eq!(changelog.entries().unwrap().len(), 2);
+
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_upgrade_v1() {
+        let path = "target/test_changelog_upgrade_v1.log";
+        let changes = changes();
+
+        // The log of version 1, whose records have no generation.
+        let mut bytes = header(1).to_vec();
+        bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+        for txid in 2..4u64 {
+            let record = encode_record(txid, 0, &changes);
+            let payload = &record[24..];
+            bytes.extend_from_slice(&txid.to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&checksum(txid, None, payload).to_le_bytes());
+            bytes.extend_from_slice(payload);
+        }
+        fs::write(path, bytes).unwrap();
+
+        let mut changelog = ChangeLog::open(path, 0).unwrap();
+        let entry = |txid| ChangeLogEntry {
+            txid,
+            changes: changes.clone(),
+        };
+        assert_eq!(changelog.entries().unwrap(), [entry(2), entry(3)]);
+        assert_eq!(changelog.append(&changes, 1).unwrap(), 4);
+        drop(changelog);
+
+        let changelog = ChangeLog::open(path, 1).unwrap();
+        assert_eq!(changelog.entries().unwrap(), [entry(2), entry(3), entry(4)]);
+
+        _ = fs::remove_file(path);
+    }
+}
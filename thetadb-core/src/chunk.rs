@@ -13,23 +13,42 @@ use crate::{
 /// # Chunk Page Chain
 ///
 /// ```plain
-///           ┌────────────────────────┐           ┌────────────────────────┐        
-/// ┌─────┬───┴──┬───────────────────┐ │ ┌─────┬───┴──┬───────────────────┐ │ ┌─────┐
-/// │ Len │ Next │      Payload      │ └▶│ Len │ Next │      Payload      │ └▶│ ••• │
-/// └─────┴──────┴───────────────────┘   └─────┴──────┴───────────────────┘   └─────┘
+/// ┌─────┐   ┌─────┬──────┬───────┬─────┬──────────┬─────────────┐
+/// │ ••• │◀┐ │ Len │ Prev │ Chain │ Seq │ Checksum │   Payload   │
+/// └─────┘ │ └─────┴──┬───┴───────┴─────┴──────────┴─────────────┘
+///         └──────────┘
 /// ```
+///
+/// A chain is referred to by its last chunk, and each chunk is linked to the previous one, so
+/// appending to a chain shadows only the last chunk, while the ones before it stay in place.
+/// All but the last chunk are full.
+///
+/// Each chunk records the chain it belongs to (the id of the page the chain was first written
+/// to) and its position in the chain, which are checked along with the checksum when the chain
+/// is walked. So a corrupted `prev` pointer that leads to garbage, to another chain or back into
+/// the same chain is detected, instead of returning wrong data or looping forever.
 pub(crate) struct Chunk<B> {
     len: Mapping<B, Len>,
-    next: Mapping<B, PageId>,
+    prev: Mapping<B, PageId>,
+    chain: Mapping<B, PageId>,
+    seq: Mapping<B, u32>,
+    checksum: Mapping<B, Checksum>,
     body: B,
 }
 
 type Len = u32;
 
-/// Indicates that a chunk a overflowed, i.e., the length of the data exceeds the capacity
-/// of the chunk. When a chunk is overflowed, the `len` field is set to `LEN_OVERFLOW_FLAG`,
-/// and the remaining data is stored in the next chunk in the chain.
-const LEN_OVERFLOW_FLAG: Len = Len::MAX;
+type Checksum = u32;
+
+/// The length of the fields before the payload of a chunk.
+const HEADER_LEN: u32 = (mem::size_of::<Len>()
+    + mem::size_of::<PageId>() * 2
+    + mem::size_of::<u32>()
+    + mem::size_of::<Checksum>()) as u32;
+
+/// The maximum length of the data stored in a page chain. It is below `Len::MAX`, so the total
+/// length measured by `chain_len` saturates beyond any valid one.
+pub(crate) const MAX_DATA_LEN: usize = Len::MAX as usize - 1;
 
 unsafe impl<B> Page<B> for Chunk<B>
 where
@@ -37,14 +56,21 @@ where
 {
     #[inline]
     fn from_bytes(bytes: B) -> mapping::Result<Self> {
-        assert!(
-            bytes.len() < LEN_OVERFLOW_FLAG as usize,
-            "page size is too large"
-        );
+        assert!(bytes.len() <= MAX_DATA_LEN, "page size is too large");
 
         let (len, remaining) = unsafe { Mapping::split(bytes)? };
-        let (next, body) = unsafe { Mapping::split(remaining)? };
-        Ok(Self { len, next, body })
+        let (prev, remaining) = unsafe { Mapping::split(remaining)? };
+        let (chain, remaining) = unsafe { Mapping::split(remaining)? };
+        let (seq, remaining) = unsafe { Mapping::split(remaining)? };
+        let (checksum, body) = unsafe { Mapping::split(remaining)? };
+        Ok(Self {
+            len,
+            prev,
+            chain,
+            seq,
+            checksum,
+            body,
+        })
     }
 }
 
@@ -55,17 +81,13 @@ where
     /// The length of the chunk.
     #[inline]
     pub(crate) fn len(&self) -> u32 {
-        if self.is_overflow() {
-            self.body.len() as u32
-        } else {
-            *self.len
-        }
+        *self.len
     }
 
-    /// The id of the next chunk page in the chain.
+    /// The id of the previous chunk page in the chain, `None` for the first chunk.
     #[inline]
-    pub(crate) fn next(&self) -> Option<PageId> {
-        self.is_overflow().then(|| *self.next)
+    pub(crate) fn prev(&self) -> Option<PageId> {
+        (*self.seq > 0).then(|| *self.prev)
     }
 
     /// The data of the chunk.
@@ -76,10 +98,41 @@ where
         Ok(&self.body[range])
     }
 
-    /// Checks if the chunk is overflowed.
+    /// Validates the chunk stored in the page `id`, which is expected to belong to the chain
+    /// identified by `chain`, and to be the `seq`-th one of it if `seq` is given.
+    fn validate(&self, id: PageId, chain: PageId, seq: Option<u32>) -> mapping::Result<()> {
+        let res = match self.calc_checksum() {
+            Ok(checksum) if checksum != *self.checksum => Err(mapping::Error::Checksum),
+            Ok(_) if *self.chain != chain || seq.is_some_and(|seq| *self.seq != seq) => {
+                Err(mapping::Error::ChainBroken)
+            }
+            res => res.map(|_| ()),
+        };
+        res.map_err(|err| err.context(format_args!("chunk page {}", id.raw())))
+    }
+
+    /// Calculates the checksum of the chunk, which covers all the fields but itself. A chunk
+    /// is never relinked after being written, as the chunk before it stays in place.
+    fn calc_checksum(&self) -> mapping::Result<Checksum> {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.len.to_le_bytes());
+        hasher.update(&self.prev.raw().to_le_bytes());
+        hasher.update(&self.chain.raw().to_le_bytes());
+        hasher.update(&self.seq.to_le_bytes());
+        hasher.update(self.body()?);
+        Ok(hasher.finalize())
+    }
+}
+
+impl<'a> Chunk<&'a [u8]> {
+    /// The data of the chunk, which borrows the page rather than the chunk, so that it can be
+    /// kept while the chain is walked.
     #[inline]
-    fn is_overflow(&self) -> bool {
-        *self.len == LEN_OVERFLOW_FLAG
+    pub(crate) fn page_body(&self) -> mapping::Result<&'a [u8]> {
+        let body: &'a [u8] = self.body;
+        let range = ..self.len() as usize;
+        mapping::check_range(&range, &body)?;
+        Ok(&body[range])
     }
 }
 
@@ -87,98 +140,249 @@ impl<B> Chunk<B>
 where
     B: BytesMut,
 {
-    /// Assign a slice of bytes to the chunk.
-    pub(crate) fn assign<'a>(&mut self, slice: &'a [u8]) -> Option<(&'a [u8], &mut PageId)> {
-        if slice.len() > self.body.len() {
-            *self.len = LEN_OVERFLOW_FLAG;
+    /// Assign a slice of bytes to the chunk, which is the `seq`-th one of the chain identified
+    /// by `chain`, and follows the chunk in the page `prev`. The rest that does not fit is
+    /// returned.
+    pub(crate) fn assign<'a>(
+        &mut self,
+        slice: &'a [u8],
+        chain: PageId,
+        seq: u32,
+        prev: PageId,
+    ) -> Option<&'a [u8]> {
+        *self.prev = prev;
+        *self.chain = chain;
+        *self.seq = seq;
+
+        let (body, remaining) = slice.split_at(slice.len().min(self.body.len()));
+        *self.len = body.len() as u32;
+        self.body[..body.len()].copy_from_slice(body);
+
+        self.update_checksum();
+        (!remaining.is_empty()).then_some(remaining)
+    }
 
-            let (body, remaining) = slice.split_at(self.body.len());
-            self.body.copy_from_slice(body);
+    /// Appends a slice of bytes to the data of the last chunk in a chain. Like `assign`, the
+    /// rest that does not fit is returned.
+    pub(crate) fn extend<'a>(&mut self, slice: &'a [u8]) -> Option<&'a [u8]> {
+        let len = *self.len as usize;
+        let room = self.body.len() - len;
 
-            Some((remaining, &mut self.next))
-        } else {
-            *self.len = slice.len() as u32;
+        let (body, remaining) = slice.split_at(slice.len().min(room));
+        *self.len += body.len() as u32;
+        self.body[len..len + body.len()].copy_from_slice(body);
 
-            self.body[..slice.len()].copy_from_slice(slice);
-            None
-        }
+        self.update_checksum();
+        (!remaining.is_empty()).then_some(remaining)
+    }
+
+    #[inline]
+    fn update_checksum(&mut self) {
+        *self.checksum = self
+            .calc_checksum()
+            .expect("the length of a written chunk is within its body");
     }
 }
 
 impl Chunk<()> {
     /// Reads a page chain into a byte vector.
-    pub(crate) fn read<'a, F>(id: PageId, mut obtain: F) -> mapping::Result<Vec<u8>>
+    #[inline]
+    pub(crate) fn read<'a, F>(id: PageId, obtain: F) -> mapping::Result<Vec<u8>>
     where
         F: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
     {
-        let (mut res, mut next_id) = (Vec::new(), Some(id));
+        let mut res = Vec::new();
+        Self::read_into(id, obtain, &mut res)?;
+        Ok(res)
+    }
 
-        while let Some(id) = next_id {
-            let chunk = obtain(id)?;
-            res.extend_from_slice(chunk.body()?);
-            next_id = chunk.next();
-        }
+    /// Reads a page chain by appending it to the buffer, so that the capacity of the buffer
+    /// can be reused.
+    pub(crate) fn read_into<'a, F>(id: PageId, obtain: F, buf: &mut Vec<u8>) -> mapping::Result<()>
+    where
+        F: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+    {
+        // The chain is walked from its end, so the bodies are collected before being copied
+        // in order.
+        let mut bodies = Vec::new();
+        Self::walk(id, obtain, |_, chunk| {
+            bodies.push(chunk.page_body()?);
+            Ok(())
+        })?;
 
-        Ok(res)
+        buf.reserve(bodies.iter().map(|body| body.len()).sum());
+        for body in bodies.into_iter().rev() {
+            buf.extend_from_slice(body);
+        }
+        Ok(())
     }
 
-    /// Writes a byte slice into a page chain.
-    pub(crate) fn write<'a, F>(mut slice: &[u8], mut alloc: F) -> mapping::Result<PageId>
+    /// Writes a byte slice into a page chain, returns the id of its last chunk.
+    pub(crate) fn write<'a, F>(slice: &[u8], mut alloc: F) -> mapping::Result<PageId>
     where
         F: FnMut() -> mapping::Result<(PageId, Chunk<&'a mut [u8]>)>,
     {
-        let (id, mut chunk) = alloc()?;
+        debug_assert!(slice.len() <= MAX_DATA_LEN, "the data is too long");
+        let (id, chunk) = alloc()?;
+        Self::fill((id, chunk), slice, id, 0, id, alloc)
+    }
 
-        while let Some((remaining, next_id)) = chunk.assign(slice) {
-            (*next_id, chunk) = alloc()?;
-            slice = remaining;
-        }
+    /// Appends a byte slice to a page chain, filling up its last chunk before writing the rest
+    /// into new chunks. Returns the new id of the last chunk.
+    ///
+    /// Only the last chunk is obtained for writing by `shadow`, which may move it to another
+    /// page. The chunks before it are neither read nor written.
+    pub(crate) fn append<'a, S, A>(
+        id: PageId,
+        slice: &[u8],
+        mut shadow: S,
+        mut alloc: A,
+    ) -> mapping::Result<PageId>
+    where
+        S: FnMut(PageId) -> mapping::Result<(PageId, Chunk<&'a mut [u8]>)>,
+        A: FnMut() -> mapping::Result<(PageId, Chunk<&'a mut [u8]>)>,
+    {
+        let (tail_id, mut chunk) = shadow(id)?;
+        chunk.validate(id, *chunk.chain, None)?;
 
-        Ok(id)
+        let (chain, seq) = (*chunk.chain, *chunk.seq + 1);
+        match chunk.extend(slice) {
+            Some(remaining) => Self::fill(alloc()?, remaining, chain, seq, tail_id, alloc),
+            None => Ok(tail_id),
+        }
     }
 
     /// Deletes a page chain.
-    pub(crate) fn delete<'a, O, D>(id: PageId, mut obtain: O, mut delete: D) -> mapping::Result<()>
+    pub(crate) fn delete<'a, O, D>(id: PageId, obtain: O, mut delete: D) -> mapping::Result<()>
     where
         O: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
         D: FnMut(PageId) -> mapping::Result<()>,
     {
-        let mut next_id = Some(id);
+        Self::walk(id, obtain, |id, _| delete(id))
+    }
 
-        while let Some(id) = next_id {
-            let chunk = obtain(id)?;
-            next_id = chunk.next();
-            delete(id)?;
-        }
+    /// Counts the number of pages in a page chain, and the length of the data stored in it.
+    pub(crate) fn chain_len<'a, F>(id: PageId, obtain: F) -> mapping::Result<(u32, u32)>
+    where
+        F: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+    {
+        let (mut pages, mut len) = (0, 0u32);
 
-        Ok(())
+        Self::walk(id, obtain, |_, chunk| {
+            len = len.saturating_add(chunk.len());
+            pages += 1;
+            Ok(())
+        })?;
+
+        Ok((pages, len))
+    }
+
+    /// Finds the first chunk of a page chain, which is the last one walked to.
+    pub(crate) fn first<'a, F>(id: PageId, obtain: F) -> mapping::Result<PageId>
+    where
+        F: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+    {
+        let mut first = id;
+        Self::walk(id, obtain, |id, _| {
+            first = id;
+            Ok(())
+        })?;
+        Ok(first)
+    }
+
+    /// Finds the chunk of a page chain with the largest page id, i.e., the one closest to the
+    /// end of the file.
+    pub(crate) fn last_page<'a, F>(id: PageId, obtain: F) -> mapping::Result<PageId>
+    where
+        F: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+    {
+        let mut last = id;
+        Self::walk(id, obtain, |id, _| {
+            last = last.max(id);
+            Ok(())
+        })?;
+        Ok(last)
     }
 
     /// Counts the number of chunks needed to store a given length of data.
     #[inline]
     pub(crate) fn count(len: u32, page_size: u32) -> u32 {
-        let capacity = page_size - mem::size_of::<Len>() as u32 - mem::size_of::<PageId>() as u32;
+        let capacity = page_size - HEADER_LEN;
         (len - 1) / capacity + 1
     }
+
+    /// Assigns a byte slice to the chunk, which is the `seq`-th one of the chain and follows
+    /// the chunk in the page `prev`, and to the chunks allocated for the rest. Returns the id
+    /// of the last chunk.
+    fn fill<'a, F>(
+        (mut id, mut chunk): (PageId, Chunk<&'a mut [u8]>),
+        mut slice: &[u8],
+        chain: PageId,
+        mut seq: u32,
+        mut prev: PageId,
+        mut alloc: F,
+    ) -> mapping::Result<PageId>
+    where
+        F: FnMut() -> mapping::Result<(PageId, Chunk<&'a mut [u8]>)>,
+    {
+        while let Some(remaining) = chunk.assign(slice, chain, seq, prev) {
+            prev = id;
+            (id, chunk) = alloc()?;
+            slice = remaining;
+            seq += 1;
+        }
+
+        Ok(id)
+    }
+
+    /// Walks through a page chain backward from its last chunk, passing each chunk to `visit`
+    /// after validating it, so that a corrupted chain is reported with the page where it
+    /// breaks. The position of every chunk is one before the last visited, so the walk ends
+    /// at the first chunk even if the chain is looping.
+    fn walk<'a, O, V>(id: PageId, mut obtain: O, mut visit: V) -> mapping::Result<()>
+    where
+        O: FnMut(PageId) -> mapping::Result<Chunk<&'a [u8]>>,
+        V: FnMut(PageId, &Chunk<&'a [u8]>) -> mapping::Result<()>,
+    {
+        let (mut prev_id, mut expected) = (Some(id), None);
+
+        while let Some(id) = prev_id {
+            let chunk = obtain(id)?;
+            let (chain, seq) = expected.unwrap_or((*chunk.chain, *chunk.seq));
+            chunk.validate(id, chain, Some(seq))?;
+            // The chunk is linked before visiting it, which may free its page.
+            prev_id = chunk.prev();
+            visit(id, &chunk)?;
+            expected = seq.checked_sub(1).map(|seq| (chain, seq));
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::{HashMap, HashSet};
+    use std::{
+        cell::{Cell, RefCell},
+        collections::{HashMap, HashSet},
+    };
 
-    use super::Chunk;
+    use super::{Chunk, HEADER_LEN};
     use crate::{
-        medium::{mapping::Result, mempool::MemoryPool},
+        medium::{
+            mapping::{Error, Result},
+            mempool::MemoryPool,
+        },
         storage::{Page, PageId},
     };
 
     #[test]
     fn test_chunk() -> Result<()> {
-        const PAGE_SIZE: usize = 20;
+        const PAGE_SIZE: usize = 40;
 
         let mut id = PageId::from_raw(0);
         let mut pages = HashMap::new();
-        let pool = MemoryPool::new(PAGE_SIZE, 0);
+        let pool = MemoryPool::new(PAGE_SIZE, 8, 0);
 
         let bytes = b"ThetaDB is suitable for use on mobile clients with \"High-Read, Low-Write\" demands, it uses B+ Tree as the foundational layer for index management.";
 
@@ -203,6 +407,14 @@ mod tests {
 
         assert_eq!(bytes, res.as_slice());
 
+        // Measure
+        let (count, len) = Chunk::chain_len(id, |id| {
+            let cell = pages.get(&id).unwrap();
+            Chunk::from_bytes(unsafe { cell.as_slice() })
+        })?;
+        assert_eq!(count, pages.len() as u32);
+        assert_eq!(len, bytes.len() as u32);
+
         // Delete
         let mut page_ids = HashSet::new();
 
@@ -222,4 +434,123 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_chunk_corrupted() -> Result<()> {
+        const PAGE_SIZE: usize = 40;
+
+        let mut id = PageId::from_raw(0);
+        let mut pages = HashMap::new();
+        let pool = MemoryPool::new(PAGE_SIZE, 8, 0);
+
+        let mut write = |bytes: &[u8]| {
+            Chunk::write(bytes, || {
+                let id = id.incr();
+                let cell = pages.entry(id).or_insert(pool.obtain_cell());
+                let chunk = Chunk::from_bytes(unsafe { cell.as_mut_slice() })?;
+                Ok((id, chunk))
+            })
+        };
+        let a = write(&[1; 50])?;
+        let b = write(&[2; 50])?;
+
+        let read = |id| {
+            Chunk::read(id, |id| {
+                let cell = pages.get(&id).unwrap();
+                Chunk::from_bytes(unsafe { cell.as_slice() })
+            })
+        };
+        let page = |id: u32| unsafe { pages[&PageId::from_raw(id)].as_mut_slice() };
+        let root_of = |res: Result<Vec<u8>>| match res.unwrap_err() {
+            Error::Context { context, inner } => (context, *inner),
+            err => panic!("unexpected error: {err}"),
+        };
+        assert_eq!(read(a)?, [1; 50]);
+        assert_eq!(read(b)?, [2; 50]);
+
+        // A flipped byte in the payload.
+        page(1)[HEADER_LEN as usize] ^= 0xFF;
+        let (context, err) = root_of(read(a));
+        assert_eq!(context, "chunk page 1");
+        assert!(matches!(err, Error::Checksum));
+        page(1)[HEADER_LEN as usize] ^= 0xFF;
+
+        let relink = |id: u32, prev: u32| -> Result<()> {
+            let mut chunk = Chunk::from_bytes(page(id))?;
+            *chunk.prev = PageId::from_raw(prev);
+            chunk.update_checksum();
+            Ok(())
+        };
+
+        // The last chunk of `a` is cross-linked to the second one of `b`.
+        relink(2, 4)?;
+        let (context, err) = root_of(read(a));
+        assert_eq!(context, "chunk page 4");
+        assert!(matches!(err, Error::ChainBroken));
+
+        // The last chunk of `a` is linked back to itself.
+        relink(2, 2)?;
+        let (context, err) = root_of(read(a));
+        assert_eq!(context, "chunk page 2");
+        assert!(matches!(err, Error::ChainBroken));
+
+        relink(2, 1)?;
+        assert_eq!(read(a)?, [1; 50]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunk_append() -> Result<()> {
+        const PAGE_SIZE: usize = 40;
+
+        let next_id = Cell::new(0);
+        let pages = RefCell::new(HashMap::new());
+        let pool = MemoryPool::new(PAGE_SIZE, 8, 0);
+
+        let alloc = || {
+            let id = PageId::from_raw(next_id.replace(next_id.get() + 1));
+            let cell = pool.obtain_cell();
+            let chunk = Chunk::from_bytes(unsafe { cell.as_mut_slice() })?;
+            pages.borrow_mut().insert(id, cell);
+            Ok((id, chunk))
+        };
+        let mut shadowed = Vec::new();
+        let mut shadow = |id| {
+            let (new_id, chunk) = alloc()?;
+            let pages = pages.borrow();
+            unsafe { pages[&new_id].as_mut_slice() }
+                .copy_from_slice(unsafe { pages[&id].as_slice() });
+            shadowed.push(id);
+            Ok((new_id, chunk))
+        };
+        let read = |id| {
+            Chunk::read(id, |id| {
+                Chunk::from_bytes(unsafe { pages.borrow()[&id].as_slice() })
+            })
+        };
+
+        // Three chunks, the last of which has room for 10 bytes.
+        let id = Chunk::write(&[1; 50], alloc)?;
+        assert_eq!(id, PageId::from_raw(2));
+
+        // Fits in the last chunk.
+        let id = Chunk::append(id, &[2; 5], &mut shadow, alloc)?;
+        assert_eq!(id, PageId::from_raw(3));
+        // Fills up the last chunk and goes on with a new one.
+        let id = Chunk::append(id, &[3; 20], &mut shadow, alloc)?;
+        assert_eq!(id, PageId::from_raw(5));
+
+        // Only the last chunk is shadowed each time.
+        assert_eq!(shadowed, [PageId::from_raw(2), PageId::from_raw(3)]);
+        let expected = [[1; 50].as_slice(), &[2; 5], &[3; 20]].concat();
+        assert_eq!(read(id)?, expected);
+
+        let (count, len) = Chunk::chain_len(id, |id| {
+            Chunk::from_bytes(unsafe { pages.borrow()[&id].as_slice() })
+        })?;
+        assert_eq!((count, len), (4, expected.len() as u32));
+
+        Ok(())
+    }
 }
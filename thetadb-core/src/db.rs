@@ -1,25 +1,60 @@
 use std::{
-    path::Path,
-    sync::{Mutex, RwLock},
+    fmt, fs,
+    io::{self, Read},
+    mem,
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+    result,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use thiserror::Error;
+
 use crate::{
-    bptree::NodePage,
-    chunk::Chunk,
+    bptree::{self, BPTree, BloomShape, NodePage, TreeIndex, Verifier},
+    changelog::{ChangeLog, ChangeLogEntry},
+    chunk::{self, Chunk},
     error::Result,
     freelist::Freelist,
-    medium::{mempool::MemoryPool, File},
-    meta::{Meta, MetaPage, PageIndex, ValidationError},
-    storage::{Page, Storage},
-    tx::{CursorTx, Debugger, Tx, TxMut},
+    medium::{self, mempool::MemoryPool, File, IoMode},
+    meta::{ChecksumKind, Header, Meta, MetaPage, PageIndex, ValidationError, MIN_PAGE_SIZE},
+    metrics::{Counters, Metrics},
+    storage::{Page, SharedStorage, Storage, StorageLock},
+    trace,
+    tx::{
+        ChangeEvent, CursorIter, CursorTx, Debugger, DryRunReport, RangeIter, ReadSession,
+        Readonly, ScopedTx, Snapshot, Snapshots, Tx, TxMut, TxToken,
+    },
+    upgrade, MAX_KEY_LEN, MAX_VALUE_LEN,
 };
 
 /// The options for configuring a ThetaDB instance.
 #[derive(Debug, Clone)]
 pub struct Options {
     pub(crate) page_size: Option<u32>,
+    pub(crate) checksum: ChecksumKind,
     pub(crate) force_sync: bool,
     pub(crate) mempool_capacity: usize,
+    pub(crate) initial_capacity_bytes: usize,
+    pub(crate) io_mode: IoMode,
+    pub(crate) page_cache_capacity: usize,
+    pub(crate) create_dirs: bool,
+    pub(crate) preload: Preload,
+    pub(crate) shrink_on_close: bool,
+    pub(crate) bloom_bits_per_key: u8,
+    pub(crate) underflow_ratio: f64,
+    pub(crate) auto_compact: Option<f64>,
+    pub(crate) max_key_len: usize,
+    pub(crate) max_value_len: usize,
+    pub(crate) disable_overflow: bool,
+    pub(crate) on_commit: Option<CommitObserver>,
+    pub(crate) changelog_path: Option<PathBuf>,
+    pub(crate) auto_sync_interval: Option<Duration>,
 }
 
 impl Options {
@@ -29,16 +64,36 @@ impl Options {
         Default::default()
     }
 
-    /// Set the size of a page in the ThetaDB.
+    /// Set the size of a page in the ThetaDB, which should be a power of two.
+    ///
+    /// The page size is fixed when the ThetaDB is created, opening an existing ThetaDB with a
+    /// different page size fails with [`ErrorCode::FileUnexpected`](crate::ErrorCode).
+    ///
+    /// Small pages suit tiny databases, but they limit the length of keys, which is 89 bytes for
+    /// 512-byte pages and 243 bytes for 1 KB pages. Pages of 2 KB or larger hold keys up to
+    /// [`MAX_KEY_LEN`].
     ///
     /// By default, it is the operating system's memory page size. And the minimum
-    /// page size is 4 KB.
+    /// page size is 512 bytes.
     #[inline]
     pub fn page_size(&mut self, page_size: Option<u32>) -> &mut Self {
         self.page_size = page_size;
         self
     }
 
+    /// Set the algorithm used to calculate the checksum of the metadata.
+    ///
+    /// Like the page size, it is fixed when the ThetaDB is created, and the stored one is used
+    /// when the ThetaDB is opened again. By default, it is `ChecksumKind::Crc32`.
+    ///
+    /// It does not affect the chunks of page chains (e.g., the overflow values and the
+    /// freelist), which are always checksummed with CRC32.
+    #[inline]
+    pub fn checksum(&mut self, kind: ChecksumKind) -> &mut Self {
+        self.checksum = kind;
+        self
+    }
+
     /// Decide whether to force synchronization on every commit of the read write transaction.
     ///
     /// If it is true, every commit of the read write transaction will be immediately followed
@@ -50,7 +105,8 @@ impl Options {
         self
     }
 
-    /// Set the capacity of the memory pool. Represents the number of pages that can be reused.
+    /// Set the capacity of the memory pool. Represents the number of pages that can be reused,
+    /// which should not be 0.
     ///
     /// By default, it is 4.
     #[inline]
@@ -59,6 +115,185 @@ impl Options {
         self
     }
 
+    /// Set the number of bytes that the ThetaDB file is grown to when it is opened.
+    ///
+    /// Growing the file requires remapping it, so reserving the expected size up front
+    /// (e.g., before a bulk load) avoids repeated remaps. By default, it is 0, which means
+    /// the file only grows on demand.
+    #[inline]
+    pub fn initial_capacity_bytes(&mut self, capacity: usize) -> &mut Self {
+        self.initial_capacity_bytes = capacity;
+        self
+    }
+
+    /// Set the way ThetaDB reads and writes the database file.
+    ///
+    /// By default, it is `IoMode::Mmap`. Use `IoMode::Syscall` on the file systems where mmap
+    /// misbehaves.
+    #[inline]
+    pub fn io_mode(&mut self, io_mode: IoMode) -> &mut Self {
+        self.io_mode = io_mode;
+        self
+    }
+
+    /// Set the number of pages kept in memory by the page cache of `IoMode::Syscall`, beyond
+    /// which the least recently used ones are evicted.
+    ///
+    /// The pages are evicted only once no read is in progress, and the pages written by a
+    /// transaction are kept until it is committed, so the cache may hold more pages meanwhile.
+    /// It has no effect on `IoMode::Mmap`. By default, it is 256.
+    #[inline]
+    pub fn page_cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.page_cache_capacity = capacity;
+        self
+    }
+
+    /// Decide whether to create the missing parent directories of the ThetaDB file when
+    /// opening it.
+    ///
+    /// If it is false, opening a file whose parent directory does not exist fails with
+    /// [`ErrorCode::IO`](crate::ErrorCode), which keeps the file system untouched in sandboxed
+    /// environments. By default, it is true.
+    #[inline]
+    pub fn create_dirs(&mut self, flag: bool) -> &mut Self {
+        self.create_dirs = flag;
+        self
+    }
+
+    /// Set which pages are loaded into memory right after the ThetaDB is opened, so that the
+    /// first reads do not stall on page faults.
+    ///
+    /// By default, it is `Preload::None`.
+    #[inline]
+    pub fn preload(&mut self, preload: Preload) -> &mut Self {
+        self.preload = preload;
+        self
+    }
+
+    /// Decide whether to shrink the ThetaDB file when the ThetaDB is dropped, see
+    /// [`ThetaDB::shrink`].
+    ///
+    /// By default, it is false.
+    #[inline]
+    pub fn shrink_on_close(&mut self, flag: bool) -> &mut Self {
+        self.shrink_on_close = flag;
+        self
+    }
+
+    /// Set the number of bits per key of the Bloom filters in leaf pages, which let `get` and
+    /// `contains` skip searching the leaves that definitely do not contain the key.
+    ///
+    /// It only applies to the leaf pages created afterwards, and the filters take up to an
+    /// eighth of each leaf page. By default, it is 0, which means there are no filters.
+    #[inline]
+    pub fn bloom_bits_per_key(&mut self, bits: u8) -> &mut Self {
+        self.bloom_bits_per_key = bits;
+        self
+    }
+
+    /// Set the fill rate at or below which a node is merged with one of its siblings when
+    /// records are deleted, which should be at least 0 and below 0.5.
+    ///
+    /// A higher ratio reclaims the space of the deleted records sooner at the cost of merging
+    /// more often, while a lower one avoids merging and splitting the same nodes repeatedly when
+    /// records are put and deleted in turn. It only affects the rebalancing, so it can be changed
+    /// every time the ThetaDB is opened. The merged node must still hold the longest key, so a
+    /// ratio above the default is rejected if it is too high for the page size, e.g., above
+    /// 0.46 for 4 KB pages.
+    ///
+    /// By default, it is 0.35.
+    #[inline]
+    pub fn underflow_ratio(&mut self, ratio: f64) -> &mut Self {
+        self.underflow_ratio = ratio;
+        self
+    }
+
+    /// Set the ratio of free pages to all pages, above which the ThetaDB is considered to
+    /// need compaction, see [`ThetaDB::needs_compaction`]. The ratio should be between 0 and 1.
+    ///
+    /// The ratio is checked cheaply on every commit, but nothing is reclaimed inline since it
+    /// would block the ThetaDB. By default, it is `None`, which means the ratio is not checked.
+    #[inline]
+    pub fn auto_compact(&mut self, free_ratio: Option<f64>) -> &mut Self {
+        self.auto_compact = free_ratio;
+        self
+    }
+
+    /// Set the maximum length of the keys that can be put into the ThetaDB, which should not
+    /// exceed [`MAX_KEY_LEN`] as the length of a key is stored in a byte.
+    ///
+    /// By default, it is [`MAX_KEY_LEN`].
+    #[inline]
+    pub fn max_key_len(&mut self, max_key_len: usize) -> &mut Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Set the maximum length of the values that can be put into the ThetaDB, which should be
+    /// below `u32::MAX`. Large values are stored in chains of pages, so a higher limit only
+    /// costs memory when they are read and written.
+    ///
+    /// It is not persisted, so it can be changed every time the ThetaDB is opened.
+    /// By default, it is [`MAX_VALUE_LEN`].
+    #[inline]
+    pub fn max_value_len(&mut self, max_value_len: usize) -> &mut Self {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    /// Decide whether to store all values inline in the leaf pages, without overflow pages.
+    ///
+    /// If it is true, every value is read from a single page, and the values too long to be
+    /// stored inline are rejected with [`ErrorCode::InputInvalid`](crate::ErrorCode). A key and
+    /// its inline value take up at most a quarter of a page together, e.g., 1 KB for 4 KB pages.
+    ///
+    /// By default, it is false.
+    #[inline]
+    pub fn disable_overflow(&mut self, flag: bool) -> &mut Self {
+        self.disable_overflow = flag;
+        self
+    }
+
+    /// Set an observer that is invoked with the changes of each committed read-write transaction.
+    ///
+    /// The observer is called on the committing thread after the commit is completed and all
+    /// locks are released. Transactions without any change do not trigger it, and changes made
+    /// within the observer will trigger it again.
+    #[inline]
+    pub fn on_commit(&mut self, observer: Arc<ObserverFn>) -> &mut Self {
+        self.on_commit = Some(CommitObserver(observer));
+        self
+    }
+
+    /// Set the path of the change log file.
+    ///
+    /// If it is set, the changes of each committed read-write transaction will also be appended
+    /// to the change log, tagged with an increasing transaction id. The change log survives
+    /// restarts, so it can be used to replicate the ThetaDB (e.g., sync it to a backend).
+    ///
+    /// The changes are synced to the change log before the transaction takes effect, even if
+    /// `force_sync` is not set, so the change log never misses a committed transaction.
+    ///
+    /// By default, it is `None`, which means no change log is kept.
+    #[inline]
+    pub fn changelog_path(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.changelog_path = path;
+        self
+    }
+
+    /// Set the interval at which a background thread syncs the ThetaDB to the disk.
+    ///
+    /// It bounds how long the committed data can stay in the page cache when `force_sync` is
+    /// false, without syncing on every commit. The thread blocks the transactions only while
+    /// syncing, and it is stopped when the ThetaDB is dropped. The interval should not be zero.
+    ///
+    /// By default, it is `None`, which means no background thread is spawned.
+    #[inline]
+    pub fn auto_sync_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.auto_sync_interval = interval;
+        self
+    }
+
     /// Open a ThetaDB instance with the current options.
     #[inline]
     pub fn open(&self, path: impl AsRef<Path>) -> Result<ThetaDB> {
@@ -66,26 +301,363 @@ impl Options {
     }
 }
 
+/// Generates the by-value counterparts of the setters of `Options`.
+macro_rules! by_value_setters {
+    ($($with:ident => $setter:ident($ty:ty);)+) => {
+        impl Options {
+            $(
+                #[doc = concat!(
+                    "Like [`", stringify!($setter), "`](Self::", stringify!($setter), "), but takes ",
+                    "and returns the options by value, e.g., to derive a variant of existing ",
+                    "options with `base.clone().", stringify!($with), "(...)`."
+                )]
+                #[inline]
+                pub fn $with(mut self, value: $ty) -> Self {
+                    self.$setter(value);
+                    self
+                }
+            )+
+        }
+    };
+}
+
+by_value_setters! {
+    with_page_size => page_size(Option<u32>);
+    with_checksum => checksum(ChecksumKind);
+    with_force_sync => force_sync(bool);
+    with_mempool_capacity => mempool_capacity(usize);
+    with_initial_capacity_bytes => initial_capacity_bytes(usize);
+    with_io_mode => io_mode(IoMode);
+    with_page_cache_capacity => page_cache_capacity(usize);
+    with_create_dirs => create_dirs(bool);
+    with_preload => preload(Preload);
+    with_shrink_on_close => shrink_on_close(bool);
+    with_bloom_bits_per_key => bloom_bits_per_key(u8);
+    with_underflow_ratio => underflow_ratio(f64);
+    with_auto_compact => auto_compact(Option<f64>);
+    with_max_key_len => max_key_len(usize);
+    with_max_value_len => max_value_len(usize);
+    with_disable_overflow => disable_overflow(bool);
+    with_on_commit => on_commit(Arc<ObserverFn>);
+    with_changelog_path => changelog_path(Option<PathBuf>);
+    with_auto_sync_interval => auto_sync_interval(Option<Duration>);
+}
+
+/// Decides which pages are loaded into memory when the ThetaDB is opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preload {
+    /// Load the pages on demand.
+    #[default]
+    None,
+    /// Load the branch pages of the B+ tree, so that navigating to any key is fast.
+    TreeSpine,
+    /// Advise the operating system to read the whole file ahead, only takes effect in
+    /// `IoMode::Mmap`.
+    All,
+}
+
+/// Decides whether the transaction of [`ThetaDB::transact`] is committed, and tells which
+/// happened afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOutcome<T> {
+    /// Commit the changes of the transaction.
+    Committed(T),
+    /// Discard the changes of the transaction.
+    Aborted(T),
+}
+
+impl<T> TxOutcome<T> {
+    /// Checks if the transaction is committed.
+    #[inline]
+    pub fn is_committed(&self) -> bool {
+        matches!(self, Self::Committed(_))
+    }
+
+    /// Takes the value out of the outcome, regardless of whether it is committed.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Committed(value) | Self::Aborted(value) => value,
+        }
+    }
+}
+
+/// The number of pages of the ThetaDB file by usage, see [`ThetaDB::page_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageUsage {
+    /// The number of pages in use or free, including the meta and freelist pages.
+    pub total_pages: u32,
+    /// The number of free pages that can be reused.
+    pub free_pages: u32,
+    /// The number of pages in use, i.e., `total_pages - free_pages`.
+    pub live_pages: u32,
+}
+
+/// An adjustment made to the options when the ThetaDB is opened, which does not fail the open
+/// but may surprise the app, see [`ThetaDB::warnings`].
+///
+/// The options that cannot be honored at all fail the open instead, so a requested page size
+/// is never adjusted: one differing from the page size stored in the file fails with
+/// [`ErrorCode::FileUnexpected`](crate::ErrorCode), and one too small to be supported fails
+/// with [`ErrorCode::InputInvalid`](crate::ErrorCode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenWarning {
+    /// `Options::max_key_len` is lowered to the limit of the page size, see
+    /// [`ThetaDB::max_key_len`].
+    MaxKeyLenLimited { requested: usize, actual: usize },
+    /// The file is grown beyond `Options::initial_capacity_bytes`, as it is grown in multiples
+    /// of the OS page size and holds at least the pages in use.
+    InitialCapacityRounded { requested: usize, actual: usize },
+}
+
+/// The result of checking the health of a ThetaDB file, see [`ThetaDB::check`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Whether the latest metadata is damaged, in which case the ThetaDB is opened with the
+    /// older one and the last committed transaction may be lost.
+    pub recovered_from_older_meta: bool,
+    /// The problems found in the file, each described in a sentence.
+    pub problems: Vec<String>,
+}
+
+impl HealthReport {
+    /// Check if no problem is found. Falling back to the older metadata is not a problem, as
+    /// the ThetaDB can still be opened consistently.
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Options {
+    /// Whether read-write transactions need to record their changes.
+    #[inline]
+    pub(crate) fn tracks_changes(&self) -> bool {
+        self.on_commit.is_some() || self.changelog_path.is_some()
+    }
+
+    /// Checks that all the options are valid, before anything is done with them.
+    pub(crate) fn validate(&self) -> result::Result<(), OptionsInvalid> {
+        if let Some(page_size) = self.page_size && (page_size < MIN_PAGE_SIZE || !page_size.is_power_of_two()) {
+            return Err(OptionsInvalid::PageSize(page_size));
+        }
+        if self.mempool_capacity == 0 {
+            return Err(OptionsInvalid::MempoolCapacity);
+        }
+        if self.max_key_len > MAX_KEY_LEN {
+            return Err(OptionsInvalid::MaxKeyLen(self.max_key_len));
+        }
+        if self.max_value_len > chunk::MAX_DATA_LEN {
+            return Err(OptionsInvalid::MaxValueLen(self.max_value_len));
+        }
+        if !(0.0..0.5).contains(&self.underflow_ratio) {
+            return Err(OptionsInvalid::UnderflowRatio(self.underflow_ratio));
+        }
+        // The page size of an existing ThetaDB is checked again when it is bound.
+        self.validate_underflow_ratio(self.page_size.unwrap_or_else(Meta::default_page_size))?;
+        if let Some(free_ratio) = self.auto_compact && !(0.0..=1.0).contains(&free_ratio) {
+            return Err(OptionsInvalid::FreeRatio(free_ratio));
+        }
+        if self.auto_sync_interval == Some(Duration::ZERO) {
+            return Err(OptionsInvalid::AutoSyncInterval);
+        }
+        Ok(())
+    }
+
+    /// Checks that the nodes merged at the underflow ratio hold the longest keys in pages of
+    /// the given size, see `bptree::supports_underflow_ratio`.
+    pub(crate) fn validate_underflow_ratio(
+        &self,
+        page_size: u32,
+    ) -> result::Result<(), OptionsInvalid> {
+        if bptree::supports_underflow_ratio(page_size, self.underflow_ratio) {
+            Ok(())
+        } else {
+            Err(OptionsInvalid::UnderflowRatioForPageSize {
+                ratio: self.underflow_ratio,
+                page_size,
+            })
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum OptionsInvalid {
+    #[error("the page size ({0}) should be a power of two and not smaller than {MIN_PAGE_SIZE}")]
+    PageSize(u32),
+    #[error("the capacity of the memory pool should not be 0")]
+    MempoolCapacity,
+    #[error("the maximum key length ({0}) should not exceed {MAX_KEY_LEN}")]
+    MaxKeyLen(usize),
+    #[error(
+        "the maximum value length ({0}) should not exceed {}",
+        chunk::MAX_DATA_LEN
+    )]
+    MaxValueLen(usize),
+    #[error("the underflow ratio ({0}) should be at least 0 and below 0.5")]
+    UnderflowRatio(f64),
+    #[error("the underflow ratio ({ratio}) is too high for the page size ({page_size})")]
+    UnderflowRatioForPageSize { ratio: f64, page_size: u32 },
+    #[error("the free ratio ({0}) should be between 0 and 1")]
+    FreeRatio(f64),
+    #[error("the auto sync interval should not be zero")]
+    AutoSyncInterval,
+}
+
 impl Default for Options {
     #[inline]
     fn default() -> Self {
         Self {
             page_size: None,
+            checksum: ChecksumKind::Crc32,
             force_sync: false,
             mempool_capacity: 4,
+            initial_capacity_bytes: 0,
+            io_mode: IoMode::Mmap,
+            page_cache_capacity: medium::file::DEFAULT_CACHE_CAPACITY,
+            create_dirs: true,
+            preload: Preload::None,
+            shrink_on_close: false,
+            bloom_bits_per_key: 0,
+            underflow_ratio: bptree::NODE_UNDERFLOW_RATIO,
+            auto_compact: None,
+            max_key_len: MAX_KEY_LEN,
+            max_value_len: MAX_VALUE_LEN,
+            disable_overflow: false,
+            on_commit: None,
+            changelog_path: None,
+            auto_sync_interval: None,
         }
     }
 }
 
+type KeyValue = (Vec<u8>, Vec<u8>);
+
+/// The function observing the changes of committed transactions, see [`Options::on_commit`].
+pub type ObserverFn = dyn Fn(&[ChangeEvent]) + Send + Sync;
+
+/// The observer of committed changes, see [`Options::on_commit`].
+#[derive(Clone)]
+pub(crate) struct CommitObserver(Arc<ObserverFn>);
+
+impl CommitObserver {
+    #[inline]
+    pub(crate) fn notify(&self, changes: &[ChangeEvent]) {
+        (self.0)(changes)
+    }
+}
+
+impl fmt::Debug for CommitObserver {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CommitObserver")
+    }
+}
+
 /// The main database struct, all entry points are here.
 pub struct ThetaDB {
+    path: PathBuf,
     pub(crate) options: Options,
-    pub(crate) storage: RwLock<Storage>,
-    pub(crate) rw_coordinator: Mutex<TxCoordinator>,
+    /// The page size of the file, which is fixed once created.
+    page_size: u32,
+    pub(crate) storage: Arc<StorageLock>,
+    pub(crate) rw_coordinator: Arc<parking_lot::Mutex<TxCoordinator>>,
+    pub(crate) snapshots: Mutex<Snapshots>,
+    pub(crate) metrics: Counters,
+    pub(crate) needs_compaction: AtomicBool,
+    recovered_from_older_meta: bool,
+    was_created: bool,
+    warnings: Vec<OpenWarning>,
+    auto_sync: Option<AutoSync>,
 }
 
 pub(crate) struct TxCoordinator {
     pub(crate) mempool: MemoryPool,
+    pub(crate) changelog: Option<ChangeLog>,
+}
+
+impl TxCoordinator {
+    /// Creates the coordinator of the ThetaDB whose latest committed generation is
+    /// `generation`, opening the change log if any.
+    #[inline]
+    fn new(mempool: MemoryPool, options: &Options, generation: u64) -> Result<Self> {
+        let changelog = options
+            .changelog_path
+            .as_ref()
+            .map(|path| ChangeLog::open(path, generation))
+            .transpose()?;
+        Ok(Self { mempool, changelog })
+    }
+}
+
+/// The background thread that periodically syncs the storage, see
+/// [`Options::auto_sync_interval`]. Dropping it stops the thread and waits for it to exit.
+struct AutoSync {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AutoSync {
+    fn spawn(db: &ThetaDB, interval: Duration) -> Result<Self> {
+        let (storage, coordinator) = (db.storage.clone(), db.rw_coordinator.clone());
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let handle = thread::Builder::new()
+            .name("thetadb-auto-sync".into())
+            .spawn({
+                let stopped = stopped.clone();
+                move || Self::run(&storage, &coordinator, interval, &stopped)
+            })
+            .map_err(medium::file::Error::from)?;
+
+        Ok(Self {
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    fn run(
+        storage: &StorageLock,
+        coordinator: &parking_lot::Mutex<TxCoordinator>,
+        interval: Duration,
+        stopped: &(Mutex<bool>, Condvar),
+    ) {
+        let (lock, condvar) = stopped;
+        loop {
+            // Waits for the interval, or returns as soon as it is stopped.
+            let Ok(guard) = lock.lock() else { return };
+            let Ok((guard, _)) = condvar.wait_timeout_while(guard, interval, |stopped| !*stopped)
+            else {
+                return;
+            };
+            if *guard {
+                return;
+            }
+            drop(guard);
+
+            // Like `ThetaDB::sync`, the storage is held exclusively only under the coordinator,
+            // and only while syncing. The errors are retried on the next tick.
+            let _coordinator = coordinator.lock();
+            let Ok(mut storage) = storage.write() else {
+                return;
+            };
+            if let Err(_err) = storage.sync() {
+                trace::event!(WARN, "auto sync failed", error = %_err);
+            }
+        }
+    }
+}
+
+impl Drop for AutoSync {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.stopped;
+        if let Ok(mut stopped) = lock.lock() {
+            *stopped = true;
+            condvar.notify_all();
+        }
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
 }
 
 impl ThetaDB {
@@ -96,13 +668,146 @@ impl ThetaDB {
     }
 
     /// Open a ThetaDB instance at the given file path with the provided options.
+    ///
+    /// A file written in an older format version is upgraded first: its data is copied into a
+    /// new file in the current format, which then replaces it. The page size, the checksum
+    /// algorithm and the user version of the file are kept.
     pub fn open_with_options(path: impl AsRef<Path>, options: Options) -> Result<Self> {
-        let file = File::open(path)?;
-        if file.is_empty() {
-            Self::init(options, file)
+        options.validate()?;
+        let path = path.as_ref();
+
+        // Reject a file that cannot even hold the metadata before it is extended by mapping.
+        if let Ok(metadata) = fs::metadata(path)
+            && metadata.is_file()
+            && (1..Meta::SIZE as u64).contains(&metadata.len())
+        {
+            return Err(ValidationError::FileTooSmall.into());
+        }
+        upgrade::upgrade(path, &options)?;
+
+        let file = File::open(
+            path,
+            options.io_mode,
+            options.page_cache_capacity,
+            options.create_dirs,
+        )?;
+        trace::event!(DEBUG, "open", len = file.len(), io_mode = ?options.io_mode);
+        let opened_len = file.len();
+        let mut db = if file.is_empty() {
+            Self::init(path, options, file)?
         } else {
-            Self::bind(options, file)
+            let db = Self::bind(path, options, file)?;
+            db.preload()?;
+            db
+        };
+        db.check_adjustments(opened_len);
+
+        if let Some(interval) = db.options.auto_sync_interval {
+            db.auto_sync = Some(AutoSync::spawn(&db, interval)?);
+        }
+        Ok(db)
+    }
+
+    /// Open a ThetaDB instance at the given file path with the provided options, and seed it
+    /// with the default data in a single transaction if it is newly created.
+    ///
+    /// The seed runs only while no transaction has ever been committed, so the ThetaDB has
+    /// either no data or all of the seed. A seed interrupted by a crash or an error is run
+    /// again on the next open, and it is never run on a ThetaDB that has been written.
+    pub fn create_seeded(
+        path: impl AsRef<Path>,
+        options: Options,
+        seed: impl FnOnce(&mut TxMut) -> Result<()>,
+    ) -> Result<Self> {
+        let db = Self::open_with_options(path, options)?;
+        if db.generation()? == 0 {
+            db.update(seed)?;
+        }
+        Ok(db)
+    }
+
+    /// Check the health of an existing ThetaDB file without opening it, e.g., to decide at
+    /// launch whether to restore it from a backup.
+    ///
+    /// The metadata is always validated. If `verify_tree` is set, the freelist and the whole
+    /// B+ tree are walked as well, checking that every page can be read, the keys are in order,
+    /// the leaves are at the same depth, and every page is either in use or free exactly once.
+    ///
+    /// The file is only read, it is neither created nor changed. Fails only if the file cannot
+    /// be read, the problems found in it are described in the report instead.
+    pub fn check(path: impl AsRef<Path>, verify_tree: bool) -> Result<HealthReport> {
+        let file = File::open_readonly(path)?;
+        let mut report = HealthReport::default();
+
+        let (meta, recovered_from_older_meta) = match Self::load_meta(&file) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                report.problems.push(err.to_string());
+                return Ok(report);
+            }
+        };
+        report.recovered_from_older_meta = recovered_from_older_meta;
+
+        let page_count = meta.page_index().page_count();
+        let expected_len = page_count as usize * meta.page_size() as usize;
+        if file.len() < expected_len {
+            report.problems.push(format!(
+                "the file is truncated to {} bytes, while its {page_count} pages take {expected_len}",
+                file.len()
+            ));
+        } else if verify_tree {
+            report.problems = Self::verify(file, &meta);
         }
+        Ok(report)
+    }
+
+    /// Walks the freelist and the B+ tree of the file, returns the problems found.
+    fn verify(file: File, meta: &Meta) -> Vec<String> {
+        let page_index = meta.page_index().clone();
+        let storage = StorageLock::new(Storage::new(
+            file,
+            meta.page_size(),
+            PageIndex::meta_id(meta.txid()),
+        ));
+        let bptree = BPTree::new(Readonly::pinned(
+            storage.read().unwrap(),
+            page_index.clone(),
+        ));
+
+        let mut verifier = Verifier::new(page_index.page_count());
+        for id in PageIndex::METAS {
+            verifier.claim(id);
+        }
+
+        let freelist = verifier.read_chain(page_index.freelist, |id| bptree.as_index().page(id));
+        for (id, count) in freelist
+            .map(|bytes| Freelist::from_bytes(&bytes).runs())
+            .unwrap_or_default()
+        {
+            (id.raw()..id.raw() + count).for_each(|id| _ = verifier.claim(id.into()));
+        }
+
+        bptree.verify(&mut verifier);
+        verifier.finish()
+    }
+
+    /// Get the format version of an existing ThetaDB file, which only reads the header of the
+    /// file without opening the ThetaDB.
+    pub fn file_format_version(path: impl AsRef<Path>) -> Result<u32> {
+        let mut bytes = [0; Header::VERSION_END];
+        match fs::File::open(path).and_then(|mut file| file.read_exact(&mut bytes)) {
+            Ok(_) => Header::read_version(&bytes).map_err(Into::into),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(ValidationError::FileTooSmall.into())
+            }
+            Err(err) => Err(medium::file::Error::from(err).into()),
+        }
+    }
+
+    /// The path of the ThetaDB file, as it was given when opening the ThetaDB.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     /// Options used to configure the ThetaDB.
@@ -111,6 +816,125 @@ impl ThetaDB {
         self.options.clone()
     }
 
+    /// Get the size of a page in the ThetaDB, which is fixed when the ThetaDB is created.
+    #[inline]
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Get the user version of the ThetaDB, which is 0 for a new one.
+    ///
+    /// Like SQLite's `user_version`, it is a number stored in the metadata for the application,
+    /// e.g., to record the schema of the data for migrations, and it is never used by ThetaDB.
+    #[inline]
+    pub fn user_version(&self) -> Result<u32> {
+        let storage = self.read_storage()?;
+        Ok(storage.meta()?.page_index().user_version)
+    }
+
+    /// Set the user version of the ThetaDB within a single read-write transaction. Use
+    /// [`TxMut::set_user_version`] to set it atomically along with the migrated data.
+    #[inline]
+    pub fn set_user_version(&self, version: u32) -> Result<()> {
+        self.update(|tx| Ok(tx.set_user_version(version)))
+    }
+
+    /// Get the maximum length of the keys that can be put into the ThetaDB, which is the lower of
+    /// `Options::max_key_len` and the limit of the page size, see [`MAX_KEY_LEN`].
+    #[inline]
+    pub fn max_key_len(&self) -> usize {
+        self.options
+            .max_key_len
+            .min(bptree::max_key_len(self.page_size()))
+    }
+
+    /// Get the maximum length of the values that can be put into the ThetaDB, see
+    /// `Options::max_value_len`.
+    #[inline]
+    pub fn max_value_len(&self) -> usize {
+        self.options.max_value_len
+    }
+
+    /// Get the current length of the ThetaDB file.
+    #[inline]
+    pub fn file_len(&self) -> Result<usize> {
+        self.read_storage().map(|storage| storage.file_len())
+    }
+
+    /// Get the number of pages of the ThetaDB by usage, which tells how much of the file is
+    /// taken up by the live data.
+    ///
+    /// It only reads the metadata and the freelist, so it is much cheaper than walking the
+    /// whole B+ tree with [`Debugger::stats`].
+    pub fn page_usage(&self) -> Result<PageUsage> {
+        let readonly = Readonly::new(self.read_storage()?)?;
+        let page_index = readonly.meta()?.page_index().clone();
+        let freelist = Chunk::read(page_index.freelist, |id| readonly.page(id))
+            .map(|bytes| Freelist::from_bytes(&bytes))?;
+
+        let total_pages = page_index.page_count();
+        let free_pages = freelist.len() as u32;
+        Ok(PageUsage {
+            total_pages,
+            free_pages,
+            live_pages: total_pages - free_pages,
+        })
+    }
+
+    /// Get the generation of the ThetaDB, i.e., the id of the latest committed transaction.
+    ///
+    /// It advances whenever a read-write transaction commits changes, so it can be recorded and
+    /// compared later to tell whether the data has changed since then.
+    #[inline]
+    pub fn generation(&self) -> Result<u64> {
+        let storage = self.read_storage()?;
+        storage.meta().map(|m| m.txid()).map_err(Into::into)
+    }
+
+    /// Check if the ThetaDB was opened with the older metadata because the latest one was
+    /// damaged, in which case the last committed transaction may be lost.
+    #[inline]
+    pub fn recovered_from_older_meta(&self) -> bool {
+        self.recovered_from_older_meta
+    }
+
+    /// Check if the ThetaDB was created by this open, rather than bound to an existing file, e.g.,
+    /// to decide whether to seed the default data without checking the file before opening it.
+    ///
+    /// An existing empty file counts as created, since it is initialized by the open.
+    #[inline]
+    pub fn was_created(&self) -> bool {
+        self.was_created
+    }
+
+    /// Get the adjustments made to the options when the ThetaDB was opened, e.g., to log them.
+    /// It is empty if all options are honored as they are.
+    #[inline]
+    pub fn warnings(&self) -> &[OpenWarning] {
+        &self.warnings
+    }
+
+    /// Check if the free pages exceeded the ratio set by [`Options::auto_compact`] as of the
+    /// last commit, in which case the app may reclaim the space at an idle moment, e.g., with
+    /// [`ThetaDB::shrink`].
+    #[inline]
+    pub fn needs_compaction(&self) -> bool {
+        self.needs_compaction.load(Ordering::Relaxed)
+    }
+
+    /// Get the counters describing how the ThetaDB has been used since it was opened or the
+    /// metrics were last reset.
+    #[inline]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    /// Reset all the counters of the metrics to zero.
+    #[inline]
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
     /// Check if the ThetaDB contains a given key.
     #[inline]
     pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
@@ -123,7 +947,75 @@ impl ThetaDB {
         self.begin_tx()?.get(key)
     }
 
+    /// Get the value associated with a given key into the buffer, which is cleared first.
+    /// Returns whether the key exists.
+    ///
+    /// Reusing the buffer across calls saves the allocations of reading large values.
+    #[inline]
+    pub fn get_into(&self, key: impl AsRef<[u8]>, buf: &mut Vec<u8>) -> Result<bool> {
+        self.begin_tx()?.get_into(key, buf)
+    }
+
+    /// Get the values associated with the given keys within a single read-only transaction.
+    ///
+    /// The returned values are aligned to the input order.
+    #[inline]
+    pub fn get_many(&self, keys: &[impl AsRef<[u8]>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.begin_tx()?.get_many(keys)
+    }
+
+    /// Get the smallest key in the ThetaDB, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn first_key(&self) -> Result<Option<Vec<u8>>> {
+        self.begin_tx()?.first_key()
+    }
+
+    /// Get the largest key in the ThetaDB, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn last_key(&self) -> Result<Option<Vec<u8>>> {
+        self.begin_tx()?.last_key()
+    }
+
+    /// Get the key-value pair with the smallest key, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn first(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.begin_tx()?.first()
+    }
+
+    /// Get the key-value pair with the largest key, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn last(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.begin_tx()?.last()
+    }
+
+    /// Count the records whose keys fall within the given range.
+    #[inline]
+    pub fn count_range(&self, range: impl RangeBounds<[u8]>) -> Result<usize> {
+        self.begin_tx()?.count_range(range)
+    }
+
+    /// Count all the records, see [`Tx::len`].
+    #[inline]
+    pub fn len(&self) -> Result<usize> {
+        self.begin_tx()?.len()
+    }
+
+    /// Check if there is no record in the ThetaDB.
+    #[inline]
+    pub fn is_empty(&self) -> Result<bool> {
+        self.begin_tx()?.is_empty()
+    }
+
+    /// Split the key space into at most `k` ranges holding roughly equal numbers of records,
+    /// see [`Tx::split_ranges`].
+    #[inline]
+    pub fn split_ranges(&self, k: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.begin_tx()?.split_ranges(k)
+    }
+
     /// Insert or update a key-value pair into the ThetaDB.
+    ///
+    /// The key can be empty, which is the smallest of all keys.
     #[inline]
     pub fn put(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
         let mut tx = self.begin_tx_mut()?;
@@ -131,6 +1023,28 @@ impl ThetaDB {
         tx.commit()
     }
 
+    /// Append the suffix to the value associated with a given key, see [`TxMut::append`].
+    #[inline]
+    pub fn append(&self, key: impl AsRef<[u8]>, suffix: impl AsRef<[u8]>) -> Result<()> {
+        let mut tx = self.begin_tx_mut()?;
+        tx.append(key, suffix)?;
+        tx.commit()
+    }
+
+    /// Insert a key-value pair into the ThetaDB only if the key is absent, returns whether it
+    /// is inserted.
+    #[inline]
+    pub fn put_if_absent(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<bool> {
+        self.update(|tx| tx.put_if_absent(key, value))
+    }
+
+    /// Add the delta to the counter associated with a given key within a single read-write
+    /// transaction, returns the new value of it, see [`TxMut::increment`].
+    #[inline]
+    pub fn increment(&self, key: impl AsRef<[u8]>, delta: i64) -> Result<i64> {
+        self.update(|tx| tx.increment(key, delta))
+    }
+
     /// Delete a key-value pair from the ThetaDB.
     #[inline]
     pub fn delete(&self, key: impl AsRef<[u8]>) -> Result<()> {
@@ -139,6 +1053,157 @@ impl ThetaDB {
         tx.commit()
     }
 
+    /// Delete all key-value pairs whose keys fall within the given range within a single
+    /// read-write transaction, returns the number of deleted key-value pairs.
+    #[inline]
+    pub fn delete_range(&self, range: impl RangeBounds<[u8]>) -> Result<usize> {
+        self.update(|tx| tx.delete_range(range))
+    }
+
+    /// Delete all key-value pairs for which `f` returns false within a single read-write
+    /// transaction, returns the number of deleted key-value pairs.
+    ///
+    /// The key-value pairs are visited in ascending order by key.
+    #[inline]
+    pub fn retain(&self, f: impl FnMut(&[u8], &[u8]) -> bool) -> Result<usize> {
+        self.update(|tx| tx.retain(f))
+    }
+
+    /// Delete all key-value pairs within a single read-write transaction while keeping the
+    /// file, returns the number of deleted key-value pairs, see [`TxMut::clear`].
+    #[inline]
+    pub fn clear(&self) -> Result<usize> {
+        self.update(|tx| tx.clear())
+    }
+
+    /// Insert or update all the key-value pairs from an iterator in a single read-write
+    /// transaction, nothing is inserted if any of them fails.
+    ///
+    /// The records are written into new pages until the commit, so the readers keep seeing the
+    /// records as before the load throughout it, and all the loaded ones after it.
+    #[inline]
+    pub fn extend<K, V>(&self, iter: impl IntoIterator<Item = (K, V)>) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.update(|tx| {
+            iter.into_iter()
+                .try_for_each(|(key, value)| tx.put(key, value))
+        })
+    }
+
+    /// Get the entries remaining in the change log, in ascending order by transaction id.
+    ///
+    /// Returns an empty list if no change log is set by [`Options::changelog_path`].
+    #[inline]
+    pub fn changelog(&self) -> Result<Vec<ChangeLogEntry>> {
+        let coordinator = self.rw_coordinator.lock();
+        coordinator
+            .changelog
+            .as_ref()
+            .map_or(Ok(Vec::new()), ChangeLog::entries)
+    }
+
+    /// Remove the entries up to and including the given transaction id from the change log,
+    /// typically called once they are confirmed to be synced.
+    #[inline]
+    pub fn truncate_changelog(&self, txid: u64) -> Result<()> {
+        let mut coordinator = self.rw_coordinator.lock();
+        coordinator
+            .changelog
+            .as_mut()
+            .map_or(Ok(()), |changelog| changelog.truncate(txid))
+    }
+
+    /// Grow the ThetaDB file up front, so that the given number of pages can be added
+    /// without remapping the file.
+    pub fn reserve(&self, pages: u32) -> Result<()> {
+        // Waits for all transactions like a read-write one. The storage is only held
+        // exclusively under the coordinator, see `sync`.
+        let _token = TxToken::writable(self)?;
+        let _coordinator = self.rw_coordinator.lock();
+        let mut storage = self.storage.write().unwrap();
+        let page_count = storage.meta()?.page_index().page_count();
+
+        let page_count = page_count
+            .checked_add(pages)
+            .ok_or(medium::file::Error::SizeOverflow)?;
+        storage.allocate(page_count).map_err(Into::into)
+    }
+
+    /// Shrink the ThetaDB file by cutting off the free pages at the end of it, e.g., after most
+    /// of the data has been deleted.
+    ///
+    /// Only the trailing free pages are cut off, so the file may stay large if there are pages
+    /// in use near the end of it. If there is nothing to cut off, nothing is committed, so the
+    /// [`generation`](Self::generation) stays unchanged.
+    pub fn shrink(&self) -> Result<()> {
+        // The freelist is rewritten on every commit, so it may sit at the end of the file. The
+        // first pass moves it towards the start, then the second one can cut off its old pages.
+        let mut committed = false;
+        for _ in 0..2 {
+            let mut tx = self.begin_tx_mut()?;
+            if tx.take_trailing_free_pages() == 0 && !tx.can_move_freelist()? {
+                break;
+            }
+            tx.commit()?;
+            committed = true;
+        }
+        if !committed {
+            return Ok(());
+        }
+
+        // The file always holds the pages of the latest committed state. The coordinator keeps
+        // the pages written by a prepared transaction from being cut off.
+        let _coordinator = self.rw_coordinator.lock();
+        let mut storage = self.storage.write().unwrap();
+        let page_count = storage.meta()?.page_index().page_count();
+        trace::event!(DEBUG, "shrink", page_count);
+        storage.truncate(page_count).map_err(Into::into)
+    }
+
+    /// Release the memory cached by the ThetaDB, e.g., after large operations or when the app
+    /// enters the background.
+    ///
+    /// It drops the recycled pages in the memory pool, and returns the physical memory of the
+    /// mapped file to the operating system after syncing it to the disk.
+    pub fn trim_memory(&self) -> Result<()> {
+        // Waits for all transactions like a read-write one.
+        let _token = TxToken::writable(self)?;
+        let coordinator = self.rw_coordinator.lock();
+        coordinator.mempool.shrink_to(0);
+
+        self.storage.write().unwrap().trim().map_err(Into::into)
+    }
+
+    /// Close the ThetaDB after syncing it to the disk, returning the errors that dropping it
+    /// would silently ignore, e.g., to confirm durability before uploading the file.
+    ///
+    /// The file is shrunk first if `Options::shrink_on_close` is set. Whether it succeeds or
+    /// not, the file is unmapped and closed afterwards.
+    pub fn close(mut self) -> Result<()> {
+        self.auto_sync = None;
+        // Dropping shrinks the file on a best-effort basis, which is done here instead.
+        if mem::take(&mut self.options.shrink_on_close) {
+            self.shrink()?;
+        }
+        self.sync()
+    }
+
+    /// Sync the committed data of the ThetaDB to the disk, e.g., at the points where durability
+    /// matters when `Options::force_sync` is false.
+    ///
+    /// Fails with [`ErrorCode::TxOverlapped`](crate::ErrorCode) if a transaction is alive on the
+    /// same thread.
+    pub fn sync(&self) -> Result<()> {
+        // The storage is only held exclusively under the coordinator, so no writer is queued
+        // while a read-write transaction is alive, and the reads made during it never wait.
+        let _token = TxToken::writable(self)?;
+        let _coordinator = self.rw_coordinator.lock();
+        self.storage.write().unwrap().sync().map_err(Into::into)
+    }
+
     /// Perform a read-only transaction using closure on the ThetaDB.
     #[inline]
     pub fn view<T>(&self, f: impl FnOnce(&Tx) -> Result<T>) -> Result<T> {
@@ -154,18 +1219,145 @@ impl ThetaDB {
         tx.commit().map(|_| value)
     }
 
+    /// Perform a read-write transaction using closure on the ThetaDB, which the closure can
+    /// commit early with [`ScopedTx::commit_now`] and keep reading afterwards.
+    ///
+    /// The transaction is committed after the closure returns if it has not been. The changes
+    /// committed early stay committed even if the closure fails afterwards.
+    #[inline]
+    pub fn update_scoped<T>(&self, f: impl FnOnce(&mut ScopedTx) -> Result<T>) -> Result<T> {
+        let mut tx = ScopedTx::new(self)?;
+        let value = f(&mut tx)?;
+        tx.commit_now().map(|_| value)
+    }
+
+    /// Perform a read-write transaction using closure on the ThetaDB, the closure decides
+    /// whether the transaction is committed or rolled back.
+    ///
+    /// Unlike [`update`](Self::update), aborting is not an error, the changes are simply
+    /// discarded and the [`TxOutcome::Aborted`] is returned. The transaction is rolled back
+    /// as well if the closure fails, and the error is returned.
+    pub fn transact<T>(
+        &self,
+        f: impl FnOnce(&mut TxMut) -> Result<TxOutcome<T>>,
+    ) -> Result<TxOutcome<T>> {
+        let mut tx = self.begin_tx_mut()?;
+        match f(&mut tx)? {
+            TxOutcome::Committed(value) => tx.commit().map(|_| TxOutcome::Committed(value)),
+            // Dropping the transaction discards its changes.
+            aborted => Ok(aborted),
+        }
+    }
+
+    /// Perform a read-write transaction using closure on the ThetaDB, then always roll it back
+    /// and report what it would have done if it were committed, e.g., to preview the impact of
+    /// a migration.
+    ///
+    /// The report is worked out the same way as the commit, without writing anything, so it
+    /// matches what committing the same changes right away would do. The closure reads its own
+    /// changes as usual, and an error from it is returned as is.
+    pub fn dry_run(&self, f: impl FnOnce(&mut TxMut) -> Result<()>) -> Result<DryRunReport> {
+        let mut tx = self.begin_tx_mut()?;
+        tx.track_changes();
+        f(&mut tx)?;
+        tx.dry_run()
+    }
+
     /// Start a read-only transaction.
     #[inline]
     pub fn begin_tx(&self) -> Result<Tx> {
         Tx::new(self)
     }
 
+    /// Start a read session, which holds the storage to serve a burst of point lookups and
+    /// cursors without taking the lock for each of them.
+    ///
+    /// Read-write transactions cannot commit until the session is dropped, and the other reads
+    /// on the same thread fail meanwhile, see [`ReadSession`].
+    #[inline]
+    pub fn read_session(&self) -> Result<ReadSession> {
+        ReadSession::new(self)
+    }
+
     /// Start a read-write transaction.
     #[inline]
     pub fn begin_tx_mut(&self) -> Result<TxMut> {
         TxMut::new(self)
     }
 
+    /// Start a read-write transaction, waiting at most the given timeout for the one in progress
+    /// to finish. Returns `None` on timeout, e.g., to keep the UI thread responsive.
+    #[inline]
+    pub fn begin_tx_mut_timeout(&self, timeout: Duration) -> Result<Option<TxMut>> {
+        TxMut::with_timeout(self, timeout)
+    }
+
+    /// Create a snapshot of the ThetaDB, which keeps a consistent view for many discrete reads
+    /// without blocking read-write transactions, like a read-only transaction.
+    #[inline]
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::new(self)
+    }
+
+    /// Iterate over the key-value pairs whose keys fall within the given range, from either end.
+    ///
+    /// The range can be a pair of `Bound<&[u8]>` or `..`, e.g.
+    /// `(Bound::Included(b"a".as_slice()), Bound::Excluded(b"c".as_slice()))`.
+    /// Like the cursor, it iterates over the view of the ThetaDB at the moment it was created.
+    #[inline]
+    pub fn range(&self, range: impl RangeBounds<[u8]>) -> RangeIter<'_> {
+        RangeIter::new(self, range.start_bound(), range.end_bound())
+    }
+
+    /// Iterate over the key-value pairs whose keys start with the given prefix, from either end,
+    /// e.g., all the records under a namespace like `user:123:`.
+    ///
+    /// It is a [`range`](Self::range) from the prefix up to the first key after all the ones
+    /// starting with it, so the iteration ends at the boundary of the prefix.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> RangeIter<'_> {
+        // Increments the last byte below 0xFF, there is no such key if none, e.g., the prefix
+        // is empty, then the range is unbounded.
+        let end = prefix.iter().rposition(|&b| b != u8::MAX).map(|idx| {
+            let mut end = prefix[..=idx].to_vec();
+            end[idx] += 1;
+            end
+        });
+        let end = end.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        RangeIter::new(self, Bound::Included(prefix), end)
+    }
+
+    /// Get a page of up to `limit` key-value pairs whose keys are strictly after `after`, or
+    /// from the first record if it is `None`.
+    ///
+    /// Returns the page along with the key to resume from, which is the last key of the page,
+    /// or `None` if there are no more records. Passing it as `after` fetches the next page,
+    /// so the pagination needs no cursor kept alive in between. A `limit` of 0 returns an
+    /// empty page and `None`.
+    pub fn scan_page(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(Vec<KeyValue>, Option<Vec<u8>>)> {
+        if limit == 0 {
+            return Ok((Vec::new(), None));
+        }
+
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        // Reads one more record to tell whether there are more records after the page.
+        let mut page = self
+            .range((start, Bound::Unbounded))
+            .take(limit.saturating_add(1))
+            .collect::<Result<Vec<_>>>()?;
+
+        let resume = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|(key, _)| key.clone())
+        } else {
+            None
+        };
+        Ok((page, resume))
+    }
+
     /// Get the cursor pointing to the first record in the ThetaDB.
     #[inline]
     pub fn first_cursor(&self) -> Result<CursorTx> {
@@ -187,57 +1379,231 @@ impl ThetaDB {
         cursor.seek(key).map(|_| cursor)
     }
 
+    /// Get the cursor pointing to the record in the ThetaDB with the largest key not greater
+    /// than the given one, which can then walk backward with [`CursorTx::prev`], e.g., to page
+    /// through the records in descending order.
+    #[inline]
+    pub fn cursor_before_key(&self, key: &[u8]) -> Result<CursorTx> {
+        let mut cursor = CursorTx::new(self)?;
+        cursor.seek_for_prev(key).map(|_| cursor)
+    }
+
+    /// Get the cursor pointing to the first record in the ThetaDB whose key starts with the given
+    /// prefix, or pointing to nothing if there is no such record.
+    ///
+    /// The cursor is not bounded by the prefix, stepping it may pass the last key starting with
+    /// it. Use [`scan_prefix`](Self::scan_prefix) to stop at the boundary of the prefix.
+    #[inline]
+    pub fn prefix_cursor(&self, prefix: &[u8]) -> Result<CursorTx> {
+        let mut cursor = CursorTx::new(self)?;
+        cursor.seek_prefix(prefix).map(|_| cursor)
+    }
+
     /// Initialize a new ThetaDB file with the given options.
-    fn init(options: Options, file: File) -> Result<Self> {
-        let meta = options.page_size.map(Meta::new).unwrap_or_default();
+    fn init(path: &Path, options: Options, file: File) -> Result<Self> {
+        let page_size = options.page_size.unwrap_or_else(Meta::default_page_size);
+        let meta = Meta::new(page_size, options.checksum);
 
-        let mempool = MemoryPool::new(meta.page_size() as usize, 4);
+        let mempool = MemoryPool::new(page_size as usize, page_size as usize, 4);
 
-        let mut storage = Storage::new(file, meta.page_size());
+        let meta_id = PageIndex::meta_id(meta.txid());
+        let mut storage = Storage::new(file, meta.page_size(), meta_id);
+        storage.reserve(options.initial_capacity_bytes)?;
         storage.allocate(2 * meta.page_index().page_count())?;
 
         // Initialize root node page.
         storage
             .page_mut::<NodePage<_>>(meta.page_index().root)?
-            .init_leaf()?;
+            .init_leaf(BloomShape::new(
+                options.bloom_bits_per_key,
+                meta.page_size(),
+            ))?;
 
         // Initialize freelist page.
-        storage
-            .page_mut::<Chunk<_>>(meta.page_index().freelist)?
-            .assign(&Freelist::new().into_bytes());
+        let freelist_id = meta.page_index().freelist;
+        storage.page_mut::<Chunk<_>>(freelist_id)?.assign(
+            &Freelist::new().into_bytes(),
+            freelist_id,
+            0,
+            freelist_id,
+        );
+
+        // Initialize both meta pages.
+        for id in PageIndex::METAS {
+            *storage.page_mut::<MetaPage<_>>(id)? = meta.clone();
+        }
+        storage.flush()?;
 
-        // Initialize meta page.
-        *storage.page_mut::<MetaPage<_>>(PageIndex::META)? = meta;
+        let coordinator = TxCoordinator::new(mempool, &options, meta.txid())?;
 
         Ok(Self {
+            path: path.to_path_buf(),
             options,
-            storage: storage.into(),
-            rw_coordinator: TxCoordinator { mempool }.into(),
+            page_size: meta.page_size(),
+            storage: Arc::new(StorageLock::new(storage)),
+            rw_coordinator: Arc::new(coordinator.into()),
+            snapshots: Default::default(),
+            metrics: Default::default(),
+            needs_compaction: Default::default(),
+            recovered_from_older_meta: false,
+            was_created: true,
+            warnings: Vec::new(),
+            auto_sync: None,
         })
     }
 
     /// Bind to an existing ThetaDB file with the given options.
-    fn bind(options: Options, file: File) -> Result<Self> {
-        let meta = MetaPage::from_bytes(file.as_ref())
-            .map_err(|_| ValidationError::FileInvalid)
-            .and_then(|m| m.validate().and(Ok(m)))?
-            .clone();
+    fn bind(path: &Path, options: Options, file: File) -> Result<Self> {
+        let (meta, recovered_from_older_meta) = Self::load_meta(&file)?;
+        if let Some(configured) = options.page_size && configured != meta.page_size() {
+            return Err(ValidationError::PageSizeMismatched {
+                stored: meta.page_size(),
+                configured,
+            }
+            .into());
+        }
+        options.validate_underflow_ratio(meta.page_size())?;
 
-        let storage = Storage::new(file, meta.page_size());
-        let mempool = MemoryPool::new(meta.page_size() as usize, options.mempool_capacity);
+        let meta_id = PageIndex::meta_id(meta.txid());
+        let mut storage = Storage::new(file, meta.page_size(), meta_id);
+        storage.reserve(options.initial_capacity_bytes)?;
+
+        let page_size = meta.page_size() as usize;
+        let mempool = MemoryPool::new(page_size, page_size, options.mempool_capacity);
+        let coordinator = TxCoordinator::new(mempool, &options, meta.txid())?;
 
         Ok(Self {
+            path: path.to_path_buf(),
             options,
-            storage: storage.into(),
-            rw_coordinator: TxCoordinator { mempool }.into(),
+            page_size: meta.page_size(),
+            storage: Arc::new(StorageLock::new(storage)),
+            rw_coordinator: Arc::new(coordinator.into()),
+            snapshots: Default::default(),
+            metrics: Default::default(),
+            needs_compaction: Default::default(),
+            recovered_from_older_meta,
+            was_created: false,
+            warnings: Vec::new(),
+            auto_sync: None,
         })
     }
+
+    /// Collects the adjustments made to the options that are known once the ThetaDB is opened,
+    /// given the length of the file before the open.
+    fn check_adjustments(&mut self, opened_len: usize) {
+        let (requested, actual) = (self.options.max_key_len, self.max_key_len());
+        if actual < requested {
+            self.warnings
+                .push(OpenWarning::MaxKeyLenLimited { requested, actual });
+        }
+
+        let file_len = self.storage.read().unwrap().file_len();
+        let (requested, actual) = (self.options.initial_capacity_bytes, file_len);
+        if requested > opened_len && actual != requested {
+            self.warnings
+                .push(OpenWarning::InitialCapacityRounded { requested, actual });
+        }
+
+        for _warning in &self.warnings {
+            trace::event!(WARN, "options adjusted on open", warning = ?_warning);
+        }
+    }
+
+    /// Load the pages according to `Options::preload`.
+    fn preload(&self) -> Result<()> {
+        match self.options.preload {
+            Preload::None => Ok(()),
+            Preload::TreeSpine => Tx::visit_branches(self),
+            Preload::All => self.read_storage()?.preload().map_err(Into::into),
+        }
+    }
+
+    /// Read the storage shared for a single operation.
+    ///
+    /// Fails if a read session or a debugger is alive on the same thread, as a commit may be
+    /// queued for the storage held by it, which blocks reading the storage again forever.
+    pub(crate) fn read_storage(&self) -> Result<SharedStorage<'_>> {
+        TxToken::check_read(self)?;
+        Ok(self.storage.read().unwrap())
+    }
+
+    /// Load the latest valid metadata from the meta pages, returns whether it falls back to
+    /// the older one because the other is damaged.
+    pub(crate) fn load_meta(file: &File) -> Result<(Meta, bool)> {
+        let read = |offset: usize| {
+            let end = offset + Meta::SIZE;
+            if end > file.len() {
+                return Err(ValidationError::FileInvalid);
+            }
+            file.bytes(offset..end)
+                .map_err(medium::mapping::Error::from)
+                .and_then(MetaPage::from_bytes)
+                .map(|m| m.clone())
+                .map_err(|_| ValidationError::FileInvalid)
+        };
+
+        // The page size is only known from the first meta page, so its header must be valid.
+        let first = read(0)?;
+        first.validate_header()?;
+        let page_size = first.page_size();
+
+        let second = read(page_size as usize).and_then(|m| {
+            if m.page_size() == page_size {
+                Ok(m)
+            } else {
+                Err(ValidationError::FileInvalid)
+            }
+        });
+        let first = first.validate().map(|_| first);
+        let second = second.and_then(|m| m.validate().map(|_| m));
+
+        match (first, second) {
+            (Ok(first), Ok(second)) if second.txid() > first.txid() => Ok((second, false)),
+            (Ok(first), Ok(_)) => Ok((first, false)),
+            (Ok(meta), Err(_)) | (Err(_), Ok(meta)) => Ok((meta, true)),
+            (Err(err), Err(_)) => Err(err.into()),
+        }
+    }
+}
+
+impl Drop for ThetaDB {
+    fn drop(&mut self) {
+        // Stops the background thread before the storage is shrunk and released.
+        self.auto_sync = None;
+        if self.options.shrink_on_close {
+            _ = self.shrink();
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ThetaDB {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+    type IntoIter = CursorIter<'a>;
+
+    /// Iterate over all the key-value pairs in ascending order by key.
+    ///
+    /// Like the cursor, it iterates over the view of the ThetaDB at the moment it was created.
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        CursorIter::new(self.first_cursor())
+    }
 }
 
 impl ThetaDB {
     /// Get a debugger for the ThetaDB.
+    ///
+    /// Unlike the read-only transactions, it blocks read-write transactions from committing
+    /// until it is dropped. Like [`ReadSession`], the other reads on the same thread fail with
+    /// [`ErrorCode::TxOverlapped`](crate::ErrorCode) while it is alive.
     #[inline]
     pub fn debugger(&self) -> Result<Debugger> {
         Debugger::new(self)
     }
+
+    /// Dumps the B+ tree as an ASCII diagram to the writer (e.g., a file or stdout), see
+    /// [`Debugger::dump`].
+    #[inline]
+    pub fn dump(&self, writer: &mut impl io::Write) -> Result<()> {
+        self.debugger()?.dump(writer)
+    }
 }
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 
-use crate::{medium, meta, tx};
+use crate::{db, medium, meta, tx};
 
 /// A `Result` type that all API calls in ThetaDB will return.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -16,6 +16,13 @@ pub enum ErrorCode {
     FileUnexpected,
     /// The database is corrupted.
     DatabaseCorrupted,
+    /// The database file cannot grow any more, e.g., it has reached the size limit.
+    DatabaseFull,
+    /// The transaction overlaps with another one alive on the same thread, which would deadlock.
+    TxOverlapped,
+    /// There is no address space to map the database file into memory, e.g., on 32-bit devices.
+    /// Opening it with `IoMode::Syscall` avoids mapping it.
+    AddressSpaceExhausted,
 }
 
 #[derive(Debug)]
@@ -32,6 +39,9 @@ impl Display for ErrorCode {
             Self::InputInvalid => "invalid input argument",
             Self::FileUnexpected => "unexpected database file",
             Self::DatabaseCorrupted => "database is corrupted",
+            Self::DatabaseFull => "database is full",
+            Self::TxOverlapped => "transactions overlapped",
+            Self::AddressSpaceExhausted => "address space exhausted",
         })
     }
 }
@@ -41,6 +51,18 @@ impl Error {
     pub fn code(&self) -> ErrorCode {
         self.code
     }
+
+    /// Check if the error is caused by an I/O operation.
+    #[inline]
+    pub fn is_io(&self) -> bool {
+        self.code == ErrorCode::IO
+    }
+
+    /// Check if the error is caused by a corrupted database.
+    #[inline]
+    pub fn is_corrupted(&self) -> bool {
+        self.code == ErrorCode::DatabaseCorrupted
+    }
 }
 
 impl Display for Error {
@@ -60,8 +82,13 @@ impl std::error::Error for Error {
 impl From<medium::file::Error> for Error {
     #[inline]
     fn from(value: medium::file::Error) -> Self {
+        let code = match value {
+            medium::file::Error::SizeOverflow => ErrorCode::DatabaseFull,
+            medium::file::Error::AddressSpaceExhausted { .. } => ErrorCode::AddressSpaceExhausted,
+            medium::file::Error::NotRegularFile | medium::file::Error::IO(_) => ErrorCode::IO,
+        };
         Self {
-            code: ErrorCode::IO,
+            code,
             source: Box::new(value),
         }
     }
@@ -77,6 +104,26 @@ impl From<tx::InputInvalid> for Error {
     }
 }
 
+impl From<db::OptionsInvalid> for Error {
+    #[inline]
+    fn from(value: db::OptionsInvalid) -> Self {
+        Self {
+            code: ErrorCode::InputInvalid,
+            source: Box::new(value),
+        }
+    }
+}
+
+impl From<tx::TxOverlapped> for Error {
+    #[inline]
+    fn from(value: tx::TxOverlapped) -> Self {
+        Self {
+            code: ErrorCode::TxOverlapped,
+            source: Box::new(value),
+        }
+    }
+}
+
 impl From<meta::ValidationError> for Error {
     #[inline]
     fn from(value: meta::ValidationError) -> Self {
@@ -90,8 +137,12 @@ impl From<meta::ValidationError> for Error {
 impl From<medium::mapping::Error> for Error {
     #[inline]
     fn from(value: medium::mapping::Error) -> Self {
+        let code = match value.root() {
+            medium::mapping::Error::IO(_) => ErrorCode::IO,
+            _ => ErrorCode::DatabaseCorrupted,
+        };
         Self {
-            code: ErrorCode::DatabaseCorrupted,
+            code,
             source: Box::new(value),
         }
     }
@@ -30,6 +30,31 @@ impl Freelist {
             .fold(0, |acc, word| acc + word.count_ones() as usize)
     }
 
+    /// The runs of consecutive free pages, each is the id of the first page and the number of
+    /// pages, in ascending order by page id.
+    pub(crate) fn runs(&self) -> Vec<(PageId, u32)> {
+        let mut runs: Vec<(PageId, u32)> = Vec::new();
+
+        for (word_idx, &word) in self.bitmap.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                let idx = word.trailing_zeros();
+                let count = (word >> idx).trailing_ones();
+                let start = word_idx as u32 * BitmapWord::BITS + idx;
+
+                // A run may span across words.
+                match runs.last_mut() {
+                    Some((id, len)) if id.raw() + *len == start => *len += count,
+                    _ => runs.push((start.into(), count)),
+                }
+
+                word &= BitmapWord::MAX.checked_shl(idx + count).unwrap_or(0);
+            }
+        }
+
+        runs
+    }
+
     /// Marks the specified pages as free in the free list.
     pub(crate) fn free(&mut self, page_id: PageId, count: u32) {
         if count == 0 {
@@ -48,8 +73,42 @@ impl Freelist {
             let last_idx = last_idx.min((word_idx + 1) * BitmapWord::BITS - 1);
             let count = last_idx - index + 1;
 
-            self.bitmap[(word_idx) as usize] |= ((1 << count) - 1) << (index % BitmapWord::BITS);
+            // The count is at most the bits of a word, at which `1 << count` would overflow, so
+            // the mask is shifted down from a full word instead.
+            let mask = BitmapWord::MAX >> (BitmapWord::BITS - count);
+            self.bitmap[word_idx as usize] |= mask << (index % BitmapWord::BITS);
+        }
+    }
+
+    /// Removes the specified page from the free list, so that it will not be taken.
+    ///
+    /// Returns whether the page was free before.
+    pub(crate) fn remove(&mut self, page_id: PageId) -> bool {
+        let (word_idx, idx) = (
+            (page_id.raw() / BitmapWord::BITS) as usize,
+            page_id.raw() % BitmapWord::BITS,
+        );
+        let Some(word) = self.bitmap.get_mut(word_idx) else {
+            return false;
+        };
+
+        let was_free = *word & (1 << idx) != 0;
+        *word &= !(1 << idx);
+        was_free
+    }
+
+    /// Removes the run of free pages that ends right before the given page id, returns the
+    /// number of removed pages.
+    pub(crate) fn take_trailing(&mut self, end: PageId) -> u32 {
+        let mut count = 0;
+        while count < end.raw() && self.remove((end.raw() - count - 1).into()) {
+            count += 1;
+        }
+
+        while let Some(last) = self.bitmap.last() && *last == 0 {
+            self.bitmap.pop();
         }
+        count
     }
 
     /// Takes a certain number of consecutive pages from the freelist.
@@ -83,6 +142,24 @@ impl Freelist {
         Some((word_idx as u32 * BitmapWord::BITS + idx).into())
     }
 
+    /// Takes the free page with the smallest id, so that the pages at the end stay free.
+    pub(crate) fn take_first(&mut self) -> Option<PageId> {
+        let (word_idx, word) = self
+            .bitmap
+            .iter_mut()
+            .enumerate()
+            .find(|(_, w)| **w != 0)?;
+
+        let idx = word.trailing_zeros();
+        *word &= !(1 << idx);
+
+        while let Some(last) = self.bitmap.last() && *last == 0 {
+            self.bitmap.pop();
+        }
+
+        Some((word_idx as u32 * BitmapWord::BITS + idx).into())
+    }
+
     /// Takes multiple consecutive pages from the free list.
     fn take_mult_slowpath(&mut self, page_count: u32) -> Option<PageId> {
         struct Window {
@@ -245,6 +322,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_set_full_word() {
+        let mut freelist = Freelist::new();
+        freelist.free(64.into(), 64);
+        assert_eq!(freelist.bitmap, [0, u64::MAX]);
+        assert_eq!(freelist.len(), 64);
+        assert_eq!(freelist.runs(), [(64.into(), 64)]);
+
+        freelist.free(0.into(), 64);
+        assert_eq!(freelist.bitmap, [u64::MAX, u64::MAX]);
+        assert_eq!(freelist.runs(), [(0.into(), 128)]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut freelist = Freelist::new();
+        freelist.free(62.into(), 4);
+
+        assert!(freelist.remove(63.into()));
+        assert!(!freelist.remove(63.into()));
+        assert!(!freelist.remove(200.into()));
+        assert_eq!(
+            freelist.bitmap,
+            [
+                0b01000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
+                0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000011,
+            ]
+        );
+
+        assert_eq!(freelist.take(1), Some(65.into()));
+        assert_eq!(freelist.take(1), Some(64.into()));
+        assert_eq!(freelist.take(1), Some(62.into()));
+        assert_eq!(freelist.take(1), None);
+    }
+
+    #[test]
+    fn test_runs() {
+        let mut freelist = Freelist::new();
+        assert_eq!(freelist.runs(), []);
+
+        freelist.free(3.into(), 2);
+        freelist.free(9.into(), 1);
+        freelist.free(60.into(), 10);
+        freelist.free(191.into(), 1);
+        assert_eq!(
+            freelist.runs(),
+            [
+                (3.into(), 2),
+                (9.into(), 1),
+                (60.into(), 10),
+                (191.into(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_take_trailing() {
+        let mut freelist = Freelist::new();
+        freelist.free(3.into(), 2);
+        freelist.free(60.into(), 10);
+        assert_eq!(freelist.take_trailing(71.into()), 0);
+        assert_eq!(freelist.take_trailing(70.into()), 10);
+        assert_eq!(freelist.bitmap, [0b11000]);
+        assert_eq!(freelist.take_trailing(5.into()), 2);
+        assert_eq!(freelist.bitmap, []);
+        assert_eq!(freelist.take_trailing(5.into()), 0);
+    }
+
     #[test]
     fn test_take_one() {
         let mut freelist = Freelist::from([
@@ -271,6 +416,19 @@ mod tests {
         assert_eq!(freelist.bitmap, []);
     }
 
+    #[test]
+    fn test_take_first() {
+        let mut freelist = Freelist::new();
+        freelist.free(19.into(), 1);
+        freelist.free(65.into(), 1);
+
+        assert_eq!(freelist.take_first(), Some(19.into()));
+        assert_eq!(freelist.bitmap, [0, 0b10]);
+        assert_eq!(freelist.take_first(), Some(65.into()));
+        assert_eq!(freelist.bitmap, []);
+        assert_eq!(freelist.take_first(), None);
+    }
+
     #[test]
     fn test_take_mult() {
         let mut freelist = Freelist::from([
@@ -0,0 +1,103 @@
+//! Helpers for encoding integers into keys that sort in numeric order.
+//!
+//! ThetaDB compares keys byte by byte, so integers encoded in little-endian or native-endian
+//! bytes do not keep their numeric order, e.g., `256u64` would sort before `1u64`. The
+//! integers encoded by the following helpers are big-endian, and the signed ones have their
+//! sign bit flipped so that negative numbers sort before positive ones. Therefore, traversing
+//! the keys with a cursor yields them in numeric order.
+//!
+//! ```
+//! use thetadb::keys;
+//!
+//! assert!(keys::encode_u64(1) < keys::encode_u64(256));
+//! assert!(keys::encode_i64(-1) < keys::encode_i64(0));
+//! assert_eq!(keys::decode_i64(&keys::encode_i64(-42)), Some(-42));
+//! ```
+
+macro_rules! unsigned {
+    ($ty:ty, $encode:ident, $decode:ident) => {
+        #[doc = concat!("Encodes a `", stringify!($ty), "` into big-endian bytes.")]
+        #[inline]
+        pub fn $encode(n: $ty) -> [u8; <$ty>::BITS as usize / 8] {
+            n.to_be_bytes()
+        }
+
+        #[doc = concat!("Decodes a `", stringify!($ty), "` encoded by [`", stringify!($encode), "`], ")]
+        /// `None` if the length of the bytes does not match.
+        #[inline]
+        pub fn $decode(bytes: &[u8]) -> Option<$ty> {
+            bytes.try_into().ok().map(<$ty>::from_be_bytes)
+        }
+    };
+}
+
+macro_rules! signed {
+    ($ty:ty, $unsigned:ty, $encode:ident, $decode:ident) => {
+        #[doc = concat!("Encodes an `", stringify!($ty), "` into big-endian bytes with the sign bit flipped.")]
+        #[inline]
+        pub fn $encode(n: $ty) -> [u8; <$ty>::BITS as usize / 8] {
+            ((n as $unsigned) ^ (1 << (<$ty>::BITS - 1))).to_be_bytes()
+        }
+
+        #[doc = concat!("Decodes an `", stringify!($ty), "` encoded by [`", stringify!($encode), "`], ")]
+        /// `None` if the length of the bytes does not match.
+        #[inline]
+        pub fn $decode(bytes: &[u8]) -> Option<$ty> {
+            bytes
+                .try_into()
+                .ok()
+                .map(|b| (<$unsigned>::from_be_bytes(b) ^ (1 << (<$ty>::BITS - 1))) as $ty)
+        }
+    };
+}
+
+unsigned!(u32, encode_u32, decode_u32);
+unsigned!(u64, encode_u64, decode_u64);
+signed!(i32, u32, encode_i32, decode_i32);
+signed!(i64, u64, encode_i64, decode_i64);
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_i32, decode_i64, decode_u32, decode_u64, encode_i32, encode_i64, encode_u32,
+        encode_u64,
+    };
+
+    #[test]
+    fn test_order() {
+        let unsigned = [0, 1, 255, 256, u64::MAX - 1, u64::MAX];
+        assert!(unsigned
+            .windows(2)
+            .all(|w| encode_u64(w[0]) < encode_u64(w[1])));
+
+        let signed = [i64::MIN, -256, -1, 0, 1, 256, i64::MAX];
+        assert!(signed
+            .windows(2)
+            .all(|w| encode_i64(w[0]) < encode_i64(w[1])));
+
+        let signed = [i32::MIN, -1, 0, 1, i32::MAX];
+        assert!(signed
+            .windows(2)
+            .all(|w| encode_i32(w[0]) < encode_i32(w[1])));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for n in [0, 1, u32::MAX] {
+            assert_eq!(decode_u32(&encode_u32(n)), Some(n));
+        }
+        for n in [0, 1, u64::MAX] {
+            assert_eq!(decode_u64(&encode_u64(n)), Some(n));
+        }
+        for n in [i32::MIN, -1, 0, i32::MAX] {
+            assert_eq!(decode_i32(&encode_i32(n)), Some(n));
+        }
+        for n in [i64::MIN, -1, 0, i64::MAX] {
+            assert_eq!(decode_i64(&encode_i64(n)), Some(n));
+        }
+
+        assert_eq!(encode_i64(0), [0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(decode_u64(&[0; 7]), None);
+        assert_eq!(decode_i32(&[0; 8]), None);
+    }
+}
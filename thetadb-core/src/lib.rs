@@ -33,7 +33,8 @@
 //!
 //! # fn main() { try_main().unwrap(); }
 //! ```
-//! ThetaDB will automatically close when the database instance is destroyed.
+//! ThetaDB will automatically close when the database instance is destroyed. To handle the
+//! errors of the final sync, close it explicitly with `ThetaDB::close`.
 //!
 //! # Get, Insert, Update, Delete
 //!
@@ -84,10 +85,10 @@
 //! modification.
 //!
 //! ThetaDB allows a number of read-only transactions at a time but allows at most one read-write
-//! transaction at a time. When a read-write transaction is committing, it has exclusive access
-//! to the database until the commit is completed, at which point other transactions trying to
-//! access the database will be blocked. You can think of this situation as `shared access` and
-//! `exclusive access` to reader-writer lock.
+//! transaction at a time. A read-only transaction reads a consistent view of the database at the
+//! moment it was started, which is kept by shadow paging, so it never blocks read-write
+//! transactions from committing. Only the brief moment of a commit has exclusive access to the
+//! database, during which the reads of other transactions wait.
 //!
 //! ## Read-Only Transaction
 //!
@@ -155,8 +156,9 @@
 //! thread. Rust leverages `Ownership` system and the `Send` and `Sync` traits to enforce
 //! requirements automatically, whereas Swift requires us to manually ensure these guarantees.
 //!
-//! ❗️ Read-only transactions and read-write transaction must not overlap, otherwise a deadlock
-//! will be occurred.
+//! ❗️ Read-write transactions must not overlap with each other on the same thread, otherwise a
+//! deadlock will be occurred. The overlapping ones are rejected with `ErrorCode::TxOverlapped`
+//! instead. So are the reads made on the thread holding a read session or a debugger.
 //!
 //! 😺 So ThetaDB recommends that if you want to use transactions, use the APIs with closure
 //! parameter (i.e., `view`, `update`).
@@ -189,6 +191,22 @@
 //! # fn main() { try_main().unwrap(); }
 //! ```
 //!
+//! Or convert the cursor into an iterator, which yields the errors (e.g., a corrupted page is
+//! met midway) as items and then ends:
+//!
+//! ```
+//! # use thetadb::{ThetaDB, Result};
+//! # fn try_main() -> Result<()> {
+//! # let db = ThetaDB::open("target/db.theta")?;
+//! for pair in db.first_cursor()?.iter() {
+//!     let (key, value) = pair?;
+//!     println!("{:?} => {:?}", key, value);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { try_main().unwrap(); }
+//! ```
+//!
 //! Or we can perform range queries on ThetaDB in this way:
 //!
 //! ```
@@ -217,23 +235,52 @@
 #![feature(let_chains)]
 #![allow(clippy::unit_arg)]
 
+#[cfg(feature = "async")]
+mod async_db;
 mod bptree;
+mod changelog;
 mod chunk;
 mod db;
 mod error;
 mod freelist;
+pub mod keys;
 mod medium;
 mod meta;
+mod metrics;
 mod storage;
+mod trace;
 mod tx;
+mod upgrade;
 
+#[cfg(feature = "async")]
+pub use crate::async_db::{AsyncThetaDB, Blocking};
 pub use crate::{
-    db::{Options, ThetaDB},
+    changelog::ChangeLogEntry,
+    db::{HealthReport, ObserverFn, OpenWarning, Options, PageUsage, Preload, ThetaDB, TxOutcome},
     error::{Error, ErrorCode, Result},
-    tx::{CursorTx, Debugger, Tx, TxMut},
+    medium::IoMode,
+    meta::ChecksumKind,
+    metrics::Metrics,
+    tx::{
+        ChangeEvent, CursorIter, CursorMut, CursorTx, Debugger, DryRunReport, PageInfo, PageKind,
+        PreparedTx, RangeIter, ReadSession, ScopedTx, SeekResult, SessionCursor, Snapshot, Stats,
+        Tx, TxMut, ValueStorage,
+    },
 };
 
-/// The maximum length of a key that can be put into the database.
+/// The version of the ThetaDB library.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The maximum length of a key that can be put into the database, a lower limit can be set
+/// with [`Options::max_key_len`]. Pages smaller than 2 KB lower it as well, see
+/// [`Options::page_size`]. The effective limit of an opened database is given by
+/// [`ThetaDB::max_key_len`].
+///
+/// The length of a key is stored in a byte, so it cannot exceed 255 without changing the
+/// file format.
+///
+/// There is no minimum length, the empty key is valid and is the smallest of all keys.
 pub const MAX_KEY_LEN: usize = 255;
-/// The maximum length of a value that can be put into the database.
+/// The default maximum length of a value that can be put into the database, see
+/// [`Options::max_value_len`].
 pub const MAX_VALUE_LEN: usize = 10 * 1024 * 1024;
@@ -1,15 +1,21 @@
 use std::{
+    collections::{hash_map, BTreeSet, HashMap},
     fs, io,
-    ops::{Deref, DerefMut},
-    os::fd::{AsRawFd, RawFd},
+    ops::Range,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::FileExt,
+    },
     path::Path,
     ptr::{self, NonNull},
     result, slice,
+    sync::Mutex,
 };
 
 use thiserror::Error;
 
 use super::align_to_page_size;
+use crate::trace;
 
 pub(crate) type Result<T> = result::Result<T, Error>;
 
@@ -17,29 +23,70 @@ pub(crate) type Result<T> = result::Result<T, Error>;
 pub(crate) enum Error {
     #[error("the file size exceeded the limit")]
     SizeOverflow,
+    #[error("the path is not a regular file (e.g., it is a directory or a device)")]
+    NotRegularFile,
+    #[error("no address space to map {len} bytes of the file, consider using `IoMode::Syscall`")]
+    AddressSpaceExhausted { len: usize },
     #[error(transparent)]
     IO(#[from] io::Error),
 }
 
+/// The number of pages kept by the page cache by default.
+pub(crate) const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// The way ThetaDB reads and writes the database file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// Maps the entire file into memory using mmap.
+    #[default]
+    Mmap,
+    /// Reads and writes pages using pread/pwrite, through a page cache bounded by
+    /// [`Options::page_cache_capacity`](crate::Options::page_cache_capacity).
+    ///
+    /// It is slower than mmap, but is robust on the file systems where mmap misbehaves
+    /// (e.g., network-backed or FUSE file systems, on which a fault can SIGBUS the process).
+    Syscall,
+}
+
 /// A handle to a file stored on disk.
 ///
 /// It allows us to read and write disk file as easily as memory,
-/// using mmap internally for mapping.
+/// using mmap or pread/pwrite internally according to the `IoMode`.
 pub(crate) struct File {
     inner: fs::File,
-    mmap: Option<Mmap>,
+    medium: Medium,
     len: usize,
 }
 
+enum Medium {
+    Mmap(Option<Mmap>),
+    Syscall(PageCache),
+}
+
 impl File {
-    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self> {
+    /// Opens the file, creating it if it does not exist. The missing parent directories are
+    /// created only if `create_dirs` is set, otherwise opening fails with an IO error.
+    ///
+    /// In the syscall mode, the page cache keeps at most `cache_capacity` pages once they are
+    /// no longer read.
+    pub(crate) fn open(
+        path: impl AsRef<Path>,
+        io_mode: IoMode,
+        cache_capacity: usize,
+        create_dirs: bool,
+    ) -> Result<Self> {
         let path = path.as_ref();
 
         // Create all necessary intermediate directories.
-        if let Some(parent_dir) = path.parent() {
+        if create_dirs && let Some(parent_dir) = path.parent() {
             fs::create_dir_all(parent_dir)?;
         }
 
+        // Directories and special files would fail obscurely later, e.g., when mapping them.
+        if let Ok(metadata) = fs::metadata(path) && !metadata.is_file() {
+            return Err(Error::NotRegularFile);
+        }
+
         // Open the database file.
         let inner = fs::OpenOptions::new()
             .read(true)
@@ -48,11 +95,11 @@ impl File {
             .open(path)?;
 
         let len = inner.metadata()?.len() as usize;
-        let mut file = Self {
-            inner,
-            len,
-            mmap: None,
+        let medium = match io_mode {
+            IoMode::Mmap => Medium::Mmap(None),
+            IoMode::Syscall => Medium::Syscall(PageCache::new(cache_capacity)),
         };
+        let mut file = Self { inner, len, medium };
 
         // If the file is not mapped, we consider the current file to be empty.
         if len > 0 {
@@ -62,6 +109,23 @@ impl File {
         Ok(file)
     }
 
+    /// Opens an existing file for reading only. The pages are read with pread, so the file is
+    /// neither created, mapped, nor changed in any way.
+    pub(crate) fn open_readonly(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Ok(metadata) = fs::metadata(path) && !metadata.is_file() {
+            return Err(Error::NotRegularFile);
+        }
+
+        let inner = fs::File::open(path)?;
+        let len = inner.metadata()?.len() as usize;
+        Ok(Self {
+            inner,
+            len,
+            medium: Medium::Syscall(PageCache::new(DEFAULT_CACHE_CAPACITY)),
+        })
+    }
+
     #[inline]
     pub(crate) fn is_empty(&self) -> bool {
         self.len() == 0
@@ -71,6 +135,55 @@ impl File {
     pub(crate) fn len(&self) -> usize {
         self.len
     }
+
+    /// Marks the start of reading the file shared, the bytes read are kept valid until
+    /// `end_read` is called.
+    #[inline]
+    pub(crate) fn begin_read(&self) {
+        if let Medium::Syscall(cache) = &self.medium {
+            cache.begin_read();
+        }
+    }
+
+    /// Marks the end of reading the file shared, after which the page cache may evict the
+    /// bytes read if no one else is reading.
+    ///
+    /// # Safety
+    ///
+    /// It must pair with a `begin_read`, and none of the bytes read since then may be used
+    /// afterwards.
+    #[inline]
+    pub(crate) unsafe fn end_read(&self) {
+        if let Medium::Syscall(cache) = &self.medium {
+            cache.end_read();
+        }
+    }
+
+    /// Reads the bytes in the given range, which should be within the file.
+    pub(crate) fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        debug_assert!(range.end <= self.len, "out of the file");
+        match &self.medium {
+            Medium::Mmap(mmap) => Ok(mmap
+                .as_ref()
+                .map(|m| unsafe { &slice::from_raw_parts(m.as_ptr(), m.len())[range] })
+                .unwrap_or_default()),
+            Medium::Syscall(cache) => cache.read(&self.inner, range),
+        }
+    }
+
+    /// Obtains the bytes in the given range for writing, which should be within the file.
+    ///
+    /// The written bytes may not reach the disk until the file is flushed.
+    pub(crate) fn bytes_mut(&mut self, range: Range<usize>) -> io::Result<&mut [u8]> {
+        debug_assert!(range.end <= self.len, "out of the file");
+        match &mut self.medium {
+            Medium::Mmap(mmap) => Ok(mmap
+                .as_mut()
+                .map(|m| unsafe { &mut slice::from_raw_parts_mut(m.as_mut_ptr(), m.len())[range] })
+                .unwrap_or_default()),
+            Medium::Syscall(cache) => cache.write(&self.inner, range),
+        }
+    }
 }
 
 impl File {
@@ -82,11 +195,16 @@ impl File {
 
     pub(crate) fn allocate(&mut self, len: usize) -> Result<()> {
         debug_assert_ne!(len, 0, "len is meaningless");
-        if self.len >= len && self.mmap.is_some() {
+        let mapped = match &self.medium {
+            Medium::Mmap(mmap) => mmap.is_some(),
+            Medium::Syscall(_) => true,
+        };
+        if self.len >= len && mapped {
             return Ok(());
         }
 
         let len = Self::adjust_length(self.len, len)?;
+        let _span = trace::span!(DEBUG, "allocate file", from_len = self.len, to_len = len);
 
         // Truncate the file.
         if self.len != len {
@@ -94,23 +212,126 @@ impl File {
             self.len = len;
         }
 
-        // Unmap the previous mmap.
-        drop(self.mmap.take());
-        // mmap the file.
-        self.mmap = Some(Mmap::new(self.inner.as_raw_fd(), self.len)?);
+        if let Medium::Mmap(mmap) = &mut self.medium {
+            // Unmap the previous mmap.
+            drop(mmap.take());
+            // mmap the file.
+            *mmap = Some(Mmap::new(self.inner.as_raw_fd(), self.len)?);
+            trace::event!(DEBUG, "remap file", len = self.len);
+        }
+
+        Ok(())
+    }
+
+    /// Shrinks the file to the given length aligned to the OS page size, does nothing if the
+    /// file is not longer than that.
+    pub(crate) fn truncate(&mut self, len: usize) -> Result<()> {
+        let len = align_to_page_size(len, true);
+        if len == 0 || len >= self.len {
+            return Ok(());
+        }
+
+        let _span = trace::span!(DEBUG, "truncate file", from_len = self.len, to_len = len);
+        // The pending writes must not extend the file again after truncating.
+        self.flush()?;
 
+        if let Medium::Mmap(mmap) = &mut self.medium {
+            drop(mmap.take());
+        }
+        self.inner.set_len(len as u64)?;
+        self.len = len;
+
+        if let Medium::Mmap(mmap) = &mut self.medium {
+            *mmap = Some(Mmap::new(self.inner.as_raw_fd(), self.len)?);
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes written since the last flush to the file, without waiting for
+    /// them to reach the disk.
+    #[inline]
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        match &mut self.medium {
+            Medium::Mmap(_) => Ok(()),
+            Medium::Syscall(cache) => cache.flush(&self.inner).map_err(Into::into),
+        }
+    }
+
+    /// Reads the whole mapped file ahead in the background, does nothing in the syscall mode.
+    #[inline]
+    pub(crate) fn preload(&self) -> Result<()> {
+        match &self.medium {
+            Medium::Mmap(Some(mmap)) => mmap.advise_willneed().map_err(Into::into),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the physical memory held for the file to the operating system, after writing
+    /// everything to the disk.
+    pub(crate) fn trim(&mut self) -> Result<()> {
+        self.sync()?;
+        if let Medium::Mmap(Some(mmap)) = &mut self.medium {
+            mmap.advise_dontneed()?;
+        }
         Ok(())
     }
 
     #[inline]
     pub(crate) fn sync(&mut self) -> Result<()> {
-        self.mmap
-            .as_mut()
-            .map_or(Ok(()), |m| m.sync())
-            .map_err(Into::into)
+        let _span = trace::span!(DEBUG, "sync file", len = self.len);
+        match &mut self.medium {
+            Medium::Mmap(mmap) => mmap.as_mut().map_or(Ok(()), |m| m.sync()),
+            Medium::Syscall(cache) => cache
+                .flush(&self.inner)
+                .and_then(|_| self.inner.sync_data()),
+        }
+        .map_err(Into::into)
+    }
+
+    /// Writes the given range of the file to the disk, and waits for it to complete. Only the
+    /// range is synced in the mmap mode, while the whole file is synced in the syscall mode.
+    #[inline]
+    pub(crate) fn sync_range(&mut self, range: Range<usize>) -> Result<()> {
+        self.sync_ranges([range])
+    }
+
+    /// Writes the given ranges of the file to the disk, and waits for them to complete. In the
+    /// mmap mode, the ranges are coalesced into runs of memory pages and each run is synced once,
+    /// while the whole file is synced once in the syscall mode.
+    pub(crate) fn sync_ranges(
+        &mut self,
+        ranges: impl IntoIterator<Item = Range<usize>>,
+    ) -> Result<()> {
+        match &mut self.medium {
+            Medium::Mmap(mmap) => {
+                let Some(mmap) = mmap.as_mut() else {
+                    return Ok(());
+                };
+                coalesce_ranges(ranges).into_iter().try_for_each(|range| {
+                    let _span = trace::span!(
+                        DEBUG,
+                        "sync file range",
+                        offset = range.start,
+                        len = range.len()
+                    );
+                    mmap.sync_range(range)
+                })
+            }
+            Medium::Syscall(cache) => {
+                let _span = trace::span!(DEBUG, "sync file", len = self.len);
+                cache
+                    .flush(&self.inner)
+                    .and_then(|_| self.inner.sync_data())
+            }
+        }
+        .map_err(Into::into)
     }
 
     fn adjust_length(mut len: usize, expected: usize) -> Result<usize> {
+        if expected > Self::MAX_LENGTH {
+            return Err(Error::SizeOverflow);
+        }
+
         // When the len is 0, we use the expected len as the default.
         if len == 0 {
             len = expected;
@@ -136,23 +357,134 @@ impl File {
     }
 }
 
-impl Deref for File {
-    type Target = [u8];
+/// A cache of the file ranges read or written with pread/pwrite.
+///
+/// The borrowed bytes must stay valid, so cached ranges are never evicted or moved while they
+/// may be read. Once the last reader is done, or the cache is written exclusively, the least
+/// recently used ranges beyond the capacity are evicted. The written ranges are kept until
+/// they are flushed, which empties the cache.
+struct PageCache {
+    ranges: Mutex<Buffers>,
+    dirty: BTreeSet<(usize, usize)>,
+    capacity: usize,
+}
+
+/// The buffers of cached ranges, keyed by the offset and the length of each range.
+#[derive(Default)]
+struct Buffers {
+    buffers: HashMap<(usize, usize), Buffer>,
+    /// The number of readers that may borrow the buffers.
+    readers: usize,
+    /// Ticks on every access, to find the least recently used buffers.
+    clock: u64,
+}
+
+struct Buffer {
+    bytes: Box<[u64]>,
+    used: u64,
+}
+
+impl PageCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ranges: Default::default(),
+            dirty: BTreeSet::new(),
+            capacity,
+        }
+    }
+
+    fn read(&self, file: &fs::File, range: Range<usize>) -> io::Result<&[u8]> {
+        let mut ranges = self.ranges.lock().unwrap();
+        let buf = ranges.get_or_load(file, &range)?;
+
+        // The buffer is heap allocated and kept until there is no reader of the cache, so it
+        // outlives the shared borrow of the cache.
+        let bytes = unsafe { slice::from_raw_parts(buf.as_ptr() as *const u8, range.len()) };
+        Ok(bytes)
+    }
+
+    fn write(&mut self, file: &fs::File, range: Range<usize>) -> io::Result<&mut [u8]> {
+        let ranges = self.ranges.get_mut().unwrap();
+        let key = (range.start, range.len());
+
+        // Drops the overlapping ranges that would otherwise be stale.
+        ranges.buffers.retain(|&(start, len), _| {
+            (start, len) == key || start + len <= range.start || start >= range.end
+        });
+        ranges.evict(self.capacity, &self.dirty);
 
-    fn deref(&self) -> &Self::Target {
-        self.mmap
-            .as_ref()
-            .map(|m| unsafe { slice::from_raw_parts(m.as_ptr(), m.len()) })
-            .unwrap_or_default()
+        let buf = ranges.get_or_load(file, &range)?;
+        self.dirty.insert(key);
+
+        let bytes = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, range.len()) };
+        Ok(bytes)
+    }
+
+    fn flush(&mut self, file: &fs::File) -> io::Result<()> {
+        let ranges = self.ranges.get_mut().unwrap();
+        for key @ (start, len) in &self.dirty {
+            let buf = &ranges.buffers[key].bytes;
+            let bytes = unsafe { slice::from_raw_parts(buf.as_ptr() as *const u8, *len) };
+            file.write_all_at(bytes, *start as u64)?;
+        }
+
+        self.dirty.clear();
+        ranges.buffers.clear();
+        Ok(())
+    }
+
+    fn begin_read(&self) {
+        self.ranges.lock().unwrap().readers += 1;
+    }
+
+    /// Evicts the ranges beyond the capacity once the last reader is done.
+    fn end_read(&self) {
+        let mut ranges = self.ranges.lock().unwrap();
+        ranges.readers -= 1;
+        if ranges.readers == 0 {
+            ranges.evict(self.capacity, &self.dirty);
+        }
+    }
+
+    /// Reads the range into a new buffer, which is aligned for mapping structs.
+    fn load(file: &fs::File, range: &Range<usize>) -> io::Result<Box<[u64]>> {
+        let mut buf = vec![0u64; range.len().div_ceil(8)].into_boxed_slice();
+        let bytes = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, range.len()) };
+        file.read_exact_at(bytes, range.start as u64)?;
+        Ok(buf)
     }
 }
 
-impl DerefMut for File {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.mmap
-            .as_mut()
-            .map(|m| unsafe { slice::from_raw_parts_mut(m.as_mut_ptr(), m.len()) })
-            .unwrap_or_default()
+impl Buffers {
+    fn get_or_load(&mut self, file: &fs::File, range: &Range<usize>) -> io::Result<&mut [u64]> {
+        self.clock += 1;
+        let buf = match self.buffers.entry((range.start, range.len())) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => entry.insert(Buffer {
+                bytes: PageCache::load(file, range)?,
+                used: 0,
+            }),
+        };
+        buf.used = self.clock;
+        Ok(&mut buf.bytes)
+    }
+
+    /// Evicts the least recently used ranges beyond the capacity, except the written ones that
+    /// are not flushed yet. The caller must ensure that none of the buffers is borrowed.
+    fn evict(&mut self, capacity: usize, dirty: &BTreeSet<(usize, usize)>) {
+        let Some(excess) = self.buffers.len().checked_sub(capacity) else {
+            return;
+        };
+        let mut clean: Vec<_> = self
+            .buffers
+            .iter()
+            .filter(|(key, _)| !dirty.contains(key))
+            .map(|(&key, buf)| (buf.used, key))
+            .collect();
+        clean.sort_unstable();
+        for (_, key) in clean.into_iter().take(excess) {
+            self.buffers.remove(&key);
+        }
     }
 }
 
@@ -162,6 +494,13 @@ struct Mmap {
     len: usize,
 }
 
+// SAFETY: The mapping is plain memory owned exclusively by the handle until it is unmapped on
+// drop, so it can be moved to another thread. It is only written through `&mut self`, which the
+// lock of the storage hands out exclusively, so sharing `&Mmap` across threads only allows the
+// concurrent reads, like sharing a `&[u8]`.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
 impl Mmap {
     #[inline]
     fn len(&self) -> usize {
@@ -180,7 +519,7 @@ impl Mmap {
 }
 
 impl Mmap {
-    fn new(file: RawFd, len: usize) -> io::Result<Self> {
+    fn new(file: RawFd, len: usize) -> Result<Self> {
         unsafe {
             // The entire file will be mapped.
             let raw_ptr = libc::mmap(
@@ -192,12 +531,20 @@ impl Mmap {
                 0,
             );
             if raw_ptr == libc::MAP_FAILED {
-                return Err(io::Error::last_os_error());
+                let err = io::Error::last_os_error();
+                // The address space cannot hold the entire file, e.g., on 32-bit devices.
+                return Err(if err.raw_os_error() == Some(libc::ENOMEM) {
+                    Error::AddressSpaceExhausted { len }
+                } else {
+                    err.into()
+                });
             }
 
             // Expects to access the mmap randomly.
             if libc::madvise(raw_ptr, len, libc::MADV_RANDOM) != 0 {
-                return Err(io::Error::last_os_error());
+                let err = io::Error::last_os_error();
+                _ = libc::munmap(raw_ptr, len);
+                return Err(err.into());
             }
 
             let ptr = NonNull::new_unchecked(raw_ptr as *mut u8);
@@ -205,10 +552,49 @@ impl Mmap {
         }
     }
 
-    fn sync(&mut self) -> io::Result<()> {
+    /// Tells the operating system that the mapped pages will be accessed soon, so that they can
+    /// be read ahead.
+    fn advise_willneed(&self) -> io::Result<()> {
+        let ptr = self.as_ptr() as *mut libc::c_void;
+        unsafe {
+            if libc::madvise(ptr, self.len, libc::MADV_WILLNEED) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
+
+    /// Tells the operating system that the mapped pages are not needed for now, they will be
+    /// reloaded from the file when accessed again.
+    fn advise_dontneed(&mut self) -> io::Result<()> {
         let ptr = self.as_mut_ptr() as *mut libc::c_void;
         unsafe {
-            if libc::msync(ptr, self.len, libc::MS_SYNC) == 0 {
+            if libc::madvise(ptr, self.len, libc::MADV_DONTNEED) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_range(0..self.len)
+    }
+
+    /// Writes the mapped pages covering the range to the file, and waits for them to reach the
+    /// disk.
+    fn sync_range(&mut self, range: Range<usize>) -> io::Result<()> {
+        // msync requires the start to be aligned to the memory page size.
+        let start = align_to_page_size(range.start, false);
+        let end = range.end.min(self.len);
+        if start >= end {
+            return Ok(());
+        }
+
+        let ptr = unsafe { self.as_mut_ptr().add(start) } as *mut libc::c_void;
+        unsafe {
+            if libc::msync(ptr, end - start, libc::MS_SYNC) == 0 {
                 Ok(())
             } else {
                 Err(io::Error::last_os_error())
@@ -217,6 +603,36 @@ impl Mmap {
     }
 }
 
+/// Sorts the ranges, widens them to the memory pages covering them, and merges the overlapping
+/// or adjacent ones, so that every memory page is synced at most once.
+fn coalesce_ranges(ranges: impl IntoIterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut ranges = ranges
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .map(|range| align_to_page_size(range.start, false)..align_to_page_size(range.end, true))
+        .collect::<Vec<_>>();
+    ranges.sort_unstable_by_key(|range| range.start);
+
+    let mut coalesced: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+/// Syncs the directory containing the path, so that a file renamed to the path survives a
+/// crash.
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent_dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent_dir)?.sync_all()
+}
+
 impl Drop for Mmap {
     fn drop(&mut self) {
         let ptr = self.as_mut_ptr() as *mut libc::c_void;
@@ -225,3 +641,118 @@ impl Drop for Mmap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::fd::AsRawFd};
+
+    use super::{coalesce_ranges, Error, File, Medium, Mmap};
+    use crate::medium::{os_page_size, IoMode};
+
+    #[test]
+    fn test_mmap_address_space_exhausted() {
+        let path = "target/test_mmap_address_space_exhausted.theta";
+        let file = fs::File::create(path).unwrap();
+
+        // No address space can hold it, regardless of the length of the file.
+        let len = 1 << (usize::BITS - 4);
+        let res = Mmap::new(file.as_raw_fd(), len);
+        assert!(matches!(res, Err(Error::AddressSpaceExhausted { len: l }) if l == len));
+
+        drop(file);
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_mmap_sync_range() {
+        let path = "target/test_mmap_sync_range.theta";
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        let page_size = os_page_size();
+        file.set_len(3 * page_size as u64).unwrap();
+
+        let mut mmap = Mmap::new(file.as_raw_fd(), 3 * page_size).unwrap();
+        unsafe { mmap.as_mut_ptr().add(page_size + 100).write(7) };
+
+        // The unaligned start is aligned down, and the range beyond the end is ignored.
+        mmap.sync_range(page_size + 100..page_size + 612).unwrap();
+        mmap.sync_range(2 * page_size..4 * page_size).unwrap();
+        mmap.sync_range(3 * page_size..4 * page_size).unwrap();
+        assert_eq!(fs::read(path).unwrap()[page_size + 100], 7);
+
+        drop(mmap);
+        drop(file);
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        let page_size = os_page_size();
+        let ranges = coalesce_ranges([
+            5 * page_size..6 * page_size,
+            100..200,
+            page_size..page_size + 1,
+            300..400,
+            2 * page_size..3 * page_size,
+            7 * page_size..7 * page_size,
+            4 * page_size + 10..5 * page_size - 10,
+        ]);
+        assert_eq!(ranges, [0..3 * page_size, 4 * page_size..6 * page_size]);
+        assert!(coalesce_ranges([]).is_empty());
+    }
+
+    #[test]
+    fn test_page_cache_capacity() {
+        let path = "target/test_page_cache_capacity.theta";
+        let page_size = os_page_size();
+        let pages: Vec<u8> = (0..16).flat_map(|i| vec![i as u8; page_size]).collect();
+        fs::write(path, &pages).unwrap();
+
+        let mut file = File::open(path, IoMode::Syscall, 4, false).unwrap();
+        let cached = |file: &File| match &file.medium {
+            Medium::Syscall(cache) => {
+                let ranges = cache.ranges.lock().unwrap();
+                let mut pages: Vec<_> = ranges
+                    .buffers
+                    .keys()
+                    .map(|(start, _)| start / page_size)
+                    .collect();
+                pages.sort_unstable();
+                pages
+            }
+            Medium::Mmap(_) => unreachable!(),
+        };
+        let read =
+            |file: &File, i: usize| file.bytes(i * page_size..(i + 1) * page_size).unwrap()[0];
+
+        // The pages being read are kept until the last reader is done, then only the least
+        // recently used ones beyond the capacity are evicted.
+        file.begin_read();
+        file.begin_read();
+        for i in 0..16 {
+            assert_eq!(read(&file, i), i as u8);
+        }
+        assert_eq!(read(&file, 0), 0);
+        unsafe { file.end_read() };
+        assert_eq!(cached(&file).len(), 16);
+        unsafe { file.end_read() };
+        assert_eq!(cached(&file), [0, 13, 14, 15]);
+
+        // The written pages are kept until they are flushed.
+        for i in 0..8 {
+            file.bytes_mut(i * page_size..(i + 1) * page_size).unwrap()[0] = 0xff;
+        }
+        assert_eq!(cached(&file), [0, 1, 2, 3, 4, 5, 6, 7]);
+        file.flush().unwrap();
+        assert!(cached(&file).is_empty());
+        assert_eq!(read(&file, 7), 0xff);
+
+        drop(file);
+        _ = fs::remove_file(path);
+    }
+}
@@ -1,4 +1,6 @@
 use std::{
+    fmt::Display,
+    io,
     marker::PhantomData,
     mem,
     ops::{Bound, Deref, DerefMut, RangeBounds},
@@ -7,12 +9,6 @@ use std::{
 
 use thiserror::Error;
 
-/// A struct that is used to align memory when it is directly mapped to a struct.
-///
-/// By adjusting the value of `N`, you can control the alignment of the struct in memory.
-#[repr(transparent)]
-pub(crate) struct Padding<const N: usize>([u8; N]);
-
 /// Represents an immutable byte slice.
 pub(crate) trait Bytes: Deref<Target = [u8]> + Sized {
     fn split_at(self, mid: usize) -> (Self, Self);
@@ -45,6 +41,35 @@ pub(crate) enum Error {
     Size,
     #[error("bytes are not aligned")]
     Alignment,
+    #[error("checksum is mismatched")]
+    Checksum,
+    #[error("page chain is broken")]
+    ChainBroken,
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    /// Tells where the error occurred, e.g., in which page.
+    #[error("{context}: {inner}")]
+    Context { context: String, inner: Box<Error> },
+}
+
+impl Error {
+    /// Wraps the error with the context of where it occurred.
+    #[inline]
+    pub(crate) fn context(self, context: impl Display) -> Self {
+        Self::Context {
+            context: context.to_string(),
+            inner: Box::new(self),
+        }
+    }
+
+    /// The error without the contexts wrapping it.
+    #[inline]
+    pub(crate) fn root(&self) -> &Self {
+        match self {
+            Self::Context { inner, .. } => inner.root(),
+            err => err,
+        }
+    }
 }
 
 #[inline]
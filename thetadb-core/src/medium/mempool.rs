@@ -2,12 +2,10 @@ use core::slice;
 use std::{
     alloc,
     alloc::Layout,
-    cell::RefCell,
-    mem,
     mem::ManuallyDrop,
     ops::{Deref, DerefMut},
     ptr::NonNull,
-    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
 /// A struct representing a memory pool that manages memory cells.
@@ -15,9 +13,12 @@ use std::{
 /// It recycles memory of cell when cell is dropped and reuses it
 /// when new cell is requested. This can be more efficient than constantly
 /// allocating and deallocating memory.
+///
+/// The pool is shared by the read-write transactions on different threads, so it is
+/// synchronized, while each cell is owned by a single transaction.
 #[derive(Clone)]
 pub(crate) struct MemoryPool {
-    inner: Rc<Inner>,
+    inner: Arc<Inner>,
 }
 
 /// A struct representing a memory cell.
@@ -31,18 +32,21 @@ pub(crate) struct MemoryCell {
 struct Inner {
     capacity: usize,
     cell_layout: Layout,
-    recycle_chain: RefCell<RecycleChain>,
+    recycle_chain: Mutex<RecycleChain>,
 }
 
 impl MemoryPool {
-    const CELL_ALIGN: usize = mem::align_of::<usize>();
-
-    /// Constructor. It takes the size of each memory cell
+    /// Constructor. It takes the size and the alignment of each memory cell
     /// and the maximum capacity of the pool.
-    pub(crate) fn new(cell_len: usize, capacity: usize) -> Self {
-        let cell_layout = Layout::from_size_align(cell_len, Self::CELL_ALIGN).expect("impossible");
+    ///
+    /// The alignment should be a power of two. The cells holding pages are aligned to the page
+    /// size like the pages in the file, so that whatever is mapped onto a page is also aligned
+    /// in a cell.
+    pub(crate) fn new(cell_len: usize, cell_align: usize, capacity: usize) -> Self {
+        let cell_layout = Layout::from_size_align(cell_len, cell_align)
+            .expect("the alignment should be a power of two");
 
-        let recycle_chain = RefCell::new(RecycleChain::new());
+        let recycle_chain = Mutex::new(RecycleChain::new());
 
         let inner = Inner {
             capacity,
@@ -51,7 +55,7 @@ impl MemoryPool {
         };
 
         Self {
-            inner: Rc::new(inner),
+            inner: Arc::new(inner),
         }
     }
 
@@ -69,17 +73,27 @@ impl MemoryPool {
     fn obtain_raw_cell(&self) -> RawCell {
         self.inner
             .recycle_chain
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .pop_cell()
             .unwrap_or_else(|| RawCell::new(self.inner.cell_layout))
     }
 
+    /// Drops the recycled cells until at most `len` cells are left in the pool.
+    #[inline]
+    pub(crate) fn shrink_to(&self, len: usize) {
+        let mut recycle_chain = self.inner.recycle_chain.lock().unwrap();
+        while recycle_chain.len > len {
+            drop(recycle_chain.pop_cell());
+        }
+    }
+
     /// Recycles a raw cell back into the pool.
     ///
     /// If the pool is already at capacity, the cell is dropped.
     #[inline]
     fn recycle_raw_cell(&self, raw_cell: RawCell) {
-        let mut recycle_chain = self.inner.recycle_chain.borrow_mut();
+        let mut recycle_chain = self.inner.recycle_chain.lock().unwrap();
         if recycle_chain.len < self.inner.capacity {
             recycle_chain.push_cell(raw_cell);
         }
@@ -156,6 +170,10 @@ struct RawCell {
     layout: Layout,
 }
 
+// SAFETY: The cell exclusively owns the memory it points to, like a `Box<[u8]>`, so it can be
+// moved to another thread.
+unsafe impl Send for RawCell {}
+
 impl RawCell {
     fn new(layout: Layout) -> Self {
         // Allocates memory for the cell.
@@ -227,31 +245,53 @@ mod tests {
     #[test]
     fn test_mempool() {
         const CAPACITY: usize = 4;
-        let pool = MemoryPool::new(20, CAPACITY);
-        assert_eq!(pool.inner.recycle_chain.borrow().len, 0);
+        let pool = MemoryPool::new(20, 8, CAPACITY);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 0);
 
         let cell_0 = pool.obtain_cell();
         let cell_1 = pool.obtain_cell();
-        assert_eq!(pool.inner.recycle_chain.borrow().len, 0);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 0);
 
         drop(cell_0);
         drop(cell_1);
-        assert_eq!(pool.inner.recycle_chain.borrow().len, 2);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 2);
 
         let cell_0 = pool.obtain_cell();
-        assert_eq!(pool.inner.recycle_chain.borrow().len, 1);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 1);
 
         let cell_1 = pool.obtain_cell();
         let cell_2 = pool.obtain_cell();
         let cell_3 = pool.obtain_cell();
         let cell_4 = pool.obtain_cell();
-        assert_eq!(pool.inner.recycle_chain.borrow().len, 0);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 0);
 
         drop(cell_0);
         drop(cell_1);
         drop(cell_2);
         drop(cell_3);
         drop(cell_4);
-        assert_eq!(pool.inner.recycle_chain.borrow().len, CAPACITY);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, CAPACITY);
+
+        pool.shrink_to(CAPACITY + 1);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, CAPACITY);
+
+        pool.shrink_to(1);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 1);
+
+        pool.shrink_to(0);
+        assert_eq!(pool.inner.recycle_chain.lock().unwrap().len, 0);
+    }
+
+    #[test]
+    fn test_mempool_align() {
+        for align in [8, 512, 4096] {
+            let pool = MemoryPool::new(align, align, 1);
+            let cells = (0..4).map(|_| pool.obtain_cell()).collect::<Vec<_>>();
+            assert!(cells.iter().all(|c| c.as_ptr().align_offset(align) == 0));
+            drop(cells);
+
+            // The recycled cells keep the alignment.
+            assert_eq!(pool.obtain_cell().as_ptr().align_offset(align), 0);
+        }
     }
 }
@@ -1,7 +1,8 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub(crate) use file::File;
-pub(crate) use mapping::{Bytes, BytesMut, Mapping, Padding};
+pub use file::IoMode;
+pub(crate) use mapping::{Bytes, BytesMut, Mapping};
 
 pub(crate) mod file;
 pub(crate) mod mapping;
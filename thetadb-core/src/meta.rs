@@ -12,13 +12,13 @@ use crate::{
 };
 
 /// The current format version of the ThetaDB file.
-const VERSION: u32 = 1;
+pub(crate) const VERSION: u32 = 7;
 
 /// A special sequence of bytes that is used at the beginning of the ThetaDB file for validation.
 const MAGIC: u32 = 0xDB314159;
 
-/// The minimum page size (4 KB) of ThetaDB.
-const MIN_PAGE_SIZE: u32 = 4 * 1024;
+/// The minimum page size (512 bytes) of ThetaDB.
+pub(crate) const MIN_PAGE_SIZE: u32 = 512;
 
 /// Represents the header of a ThetaDB file.
 #[repr(C)]
@@ -27,6 +27,27 @@ pub(crate) struct Header {
     magic: u32,
     version: u32,
     page_size: u32,
+    /// The raw value of the [`ChecksumKind`], which is validated along with the checksum.
+    checksum_kind: u32,
+}
+
+/// The algorithm used to calculate the checksum of the metadata.
+///
+/// It is chosen when the ThetaDB file is created and stored in the file, so the same
+/// algorithm is used when the file is opened again.
+///
+/// The metadata is validated with it when the ThetaDB is opened or checked. The chunks of page
+/// chains (e.g., the overflow values and the freelist) are always checksummed with CRC32, and
+/// the node pages of the B+ tree are not checksummed.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// The CRC32 algorithm.
+    #[default]
+    Crc32 = 0,
+    /// The XxHash64 algorithm, which is faster on large inputs, though the metadata is only a few
+    /// dozen bytes. The hash is truncated to 32 bits.
+    XxHash64 = 1,
 }
 
 /// Represents the index of pages in a ThetaDB file.
@@ -39,15 +60,24 @@ pub(crate) struct PageIndex {
     pub(crate) freelist: PageId,
     /// The id of the next available page.
     pub(crate) next: PageId,
+    /// The version number of the application data, which is controlled by the application
+    /// (e.g., for migrations), see [`ThetaDB::user_version`](crate::ThetaDB::user_version).
+    pub(crate) user_version: u32,
 }
 
 type Checksum = u32;
 
 /// Represents the metadata of the ThetaDB file.
+///
+/// There are two copies of the metadata at the beginning of the file, the commits write
+/// them alternately. If the latest one is damaged (e.g., torn by a crash), the older one
+/// is still available.
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Meta {
     header: Header,
+    /// The id of the last committed transaction, increasing on every commit.
+    txid: u64,
     page_index: PageIndex,
     checksum: Checksum,
 }
@@ -56,20 +86,45 @@ pub(crate) struct Meta {
 pub(crate) enum ValidationError {
     #[error("the ThetaDB file is invalid")]
     FileInvalid,
+    #[error("the file is too small to be a ThetaDB file")]
+    FileTooSmall,
     #[error("the file format version is mismatched")]
     VersionMismatched,
     #[error("the metadata checksum is mismatched")]
     ChecksumMismatched,
+    #[error("the page size of the file ({stored}) differs from the configured one ({configured})")]
+    PageSizeMismatched { stored: u32, configured: u32 },
+    #[error("the page size of the file ({0}) is smaller than the minimum {MIN_PAGE_SIZE}")]
+    PageSizeUnsupported(u32),
 }
 
 impl Header {
     #[inline]
-    fn new(page_size: u32) -> Self {
+    fn new(page_size: u32, checksum_kind: ChecksumKind) -> Self {
         Self {
             magic: MAGIC,
             version: VERSION,
             page_size: page_size.max(MIN_PAGE_SIZE),
+            checksum_kind: checksum_kind as u32,
+        }
+    }
+
+    /// The number of leading bytes of the file needed to read the format version.
+    pub(crate) const VERSION_END: usize = mem::offset_of!(Self, page_size);
+
+    /// Reads the format version from the leading bytes of the file, without validating it.
+    pub(crate) fn read_version(bytes: &[u8]) -> Result<u32, ValidationError> {
+        let field = |offset: usize| {
+            bytes
+                .get(offset..offset + mem::size_of::<u32>())
+                .map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+                .ok_or(ValidationError::FileInvalid)
+        };
+
+        if field(mem::offset_of!(Self, magic))? != MAGIC {
+            return Err(ValidationError::FileInvalid);
         }
+        field(mem::offset_of!(Self, version))
     }
 
     /// Validates the header by checking the magic number and version.
@@ -78,17 +133,45 @@ impl Header {
             Err(ValidationError::FileInvalid)
         } else if self.version != VERSION {
             Err(ValidationError::VersionMismatched)
+        } else if self.page_size < MIN_PAGE_SIZE {
+            // B+ tree nodes cannot be merged reliably within smaller pages.
+            Err(ValidationError::PageSizeUnsupported(self.page_size))
         } else {
             Ok(())
         }
     }
 }
 
+impl ChecksumKind {
+    #[inline]
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(Self::Crc32),
+            1 => Some(Self::XxHash64),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn checksum(self, bytes: &[u8]) -> Checksum {
+        match self {
+            Self::Crc32 => crc32fast::hash(bytes),
+            Self::XxHash64 => xxhash_rust::xxh64::xxh64(bytes, 0) as Checksum,
+        }
+    }
+}
+
 impl PageIndex {
-    pub(crate) const META: PageId = PageId::from_raw(0);
-    pub(crate) const DEFAULT_ROOT: PageId = PageId::from_raw(1);
-    pub(crate) const DEFAULT_FREELIST: PageId = PageId::from_raw(2);
-    pub(crate) const DEFAULT_NEXT: PageId = PageId::from_raw(3);
+    pub(crate) const METAS: [PageId; 2] = [PageId::from_raw(0), PageId::from_raw(1)];
+    pub(crate) const DEFAULT_ROOT: PageId = PageId::from_raw(2);
+    pub(crate) const DEFAULT_FREELIST: PageId = PageId::from_raw(3);
+    pub(crate) const DEFAULT_NEXT: PageId = PageId::from_raw(4);
+
+    /// The id of the meta page that stores the metadata of the given transaction.
+    #[inline]
+    pub(crate) fn meta_id(txid: u64) -> PageId {
+        Self::METAS[(txid % 2) as usize]
+    }
 
     #[inline]
     pub(crate) fn page_count(&self) -> u32 {
@@ -103,6 +186,7 @@ impl Default for PageIndex {
             root: Self::DEFAULT_ROOT,
             freelist: Self::DEFAULT_FREELIST,
             next: Self::DEFAULT_NEXT,
+            user_version: 0,
         }
     }
 }
@@ -110,18 +194,18 @@ impl Default for PageIndex {
 impl Meta {
     pub(crate) const SIZE: usize = mem::size_of::<Self>();
 
-    pub(crate) fn new(page_size: u32) -> Self {
-        let header = Header::new(page_size);
+    pub(crate) fn new(page_size: u32, checksum_kind: ChecksumKind) -> Self {
+        let header = Header::new(page_size, checksum_kind);
         let page_index = PageIndex::default();
         let checksum = Checksum::default();
 
         let mut meta = Self {
             header,
+            txid: 0,
             page_index,
             checksum,
         };
 
-        meta.check_page_size();
         meta.update_checksum();
         meta
     }
@@ -131,22 +215,52 @@ impl Meta {
         self.header.page_size
     }
 
+    /// The default page size, which is the operating system's memory page size.
+    #[inline]
+    pub(crate) fn default_page_size() -> u32 {
+        os_page_size()
+            .try_into()
+            .expect("the page size is too large")
+    }
+
     #[inline]
     pub(crate) fn page_index(&self) -> &PageIndex {
         &self.page_index
     }
 
     #[inline]
-    pub(crate) fn set_page_index(&mut self, page_index: PageIndex) {
+    pub(crate) fn txid(&self) -> u64 {
+        self.txid
+    }
+
+    /// Advances the metadata to the next transaction with the given page index.
+    #[inline]
+    pub(crate) fn advance(&mut self, page_index: PageIndex) {
         self.page_index = page_index;
+        self.txid += 1;
+        self.update_checksum();
+    }
+
+    /// Continues from the given transaction id, e.g., in a file rewritten from another one.
+    #[inline]
+    pub(crate) fn set_txid(&mut self, txid: u64) {
+        self.txid = txid;
         self.update_checksum();
     }
 
+    /// Validates the header only, which is enough to trust the page size.
+    #[inline]
+    pub(crate) fn validate_header(&self) -> Result<(), ValidationError> {
+        self.header.validate()
+    }
+
     /// Validates the metadata by validating the header and checking the checksum.
     pub(crate) fn validate(&self) -> Result<(), ValidationError> {
         self.header.validate()?;
-        // Validate checksum.
-        if self.checksum != self.calc_checksum() {
+        // Validate checksum, an unknown algorithm means the metadata is damaged.
+        if ChecksumKind::from_raw(self.header.checksum_kind).is_none()
+            || self.checksum != self.calc_checksum()
+        {
             Err(ValidationError::ChecksumMismatched)
         } else {
             Ok(())
@@ -159,36 +273,28 @@ impl Meta {
         self.checksum = self.calc_checksum();
     }
 
-    /// Calculates a checksum of the metadata using the CRC32 algorithm.
-    fn calc_checksum(&self) -> u32 {
+    /// Calculates a checksum of the metadata using the algorithm in the header, falls back to
+    /// CRC32 if the algorithm is unknown.
+    fn calc_checksum(&self) -> Checksum {
         // The byte slice that points to the metadata (without the checksum field).
         let bytes = unsafe {
-            // The length of the metadata without the checksum field.
-            let len = mem::size_of_val(self) - mem::size_of_val(&self.checksum);
+            // The checksum field is the last one, and there is no padding before it.
+            let len = mem::offset_of!(Self, checksum);
             slice::from_raw_parts(self as *const _ as *const u8, len)
         };
-        crc32fast::hash(bytes)
-    }
-
-    /// Check page size, prevent data damage in ThetaDB.
-    #[inline]
-    fn check_page_size(&self) {
-        assert!(
-            self.header.page_size as usize >= Self::SIZE,
-            "page size is too small, should not be smaller than than metadata size"
-        );
+        ChecksumKind::from_raw(self.header.checksum_kind)
+            .unwrap_or_default()
+            .checksum(bytes)
     }
 }
 
+// Pages are never smaller than the minimum page size, so the metadata always fits in a page.
+const _: () = assert!(MIN_PAGE_SIZE as usize >= Meta::SIZE);
+
 impl Default for Meta {
     #[inline]
     fn default() -> Self {
-        // Uses os page size as default.
-        Self::new(
-            os_page_size()
-                .try_into()
-                .expect("the page size is too large"),
-        )
+        Self::new(Self::default_page_size(), ChecksumKind::default())
     }
 }
 
@@ -229,7 +335,9 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{Header, Meta, PageIndex, VERSION};
+    use std::slice;
+
+    use super::{ChecksumKind, Header, Meta, PageIndex, ValidationError, MIN_PAGE_SIZE, VERSION};
     use crate::medium::os_page_size;
 
     #[test]
@@ -238,18 +346,83 @@ mod tests {
             magic: 0xDB314159,
             version: VERSION,
             page_size: os_page_size() as u32,
+            checksum_kind: 0,
         };
         let page_index = PageIndex {
-            root: 1.into(),
-            freelist: 2.into(),
-            next: 3.into(),
+            root: 2.into(),
+            freelist: 3.into(),
+            next: 4.into(),
+            user_version: 0,
         };
         let mut meta = Meta {
             header,
+            txid: 0,
             page_index,
             checksum: 0,
         };
         meta.update_checksum();
         assert_eq!(Meta::default(), meta);
     }
+
+    #[test]
+    fn test_read_version() {
+        let meta = Meta::default();
+        let bytes = unsafe {
+            slice::from_raw_parts(&meta as *const Meta as *const u8, Header::VERSION_END)
+        };
+        assert_eq!(Header::read_version(bytes).unwrap(), VERSION);
+        assert!(Header::read_version(&bytes[..4]).is_err());
+        assert!(Header::read_version(&[0; Header::VERSION_END]).is_err());
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut meta = Meta::default();
+        assert_eq!(PageIndex::meta_id(meta.txid()), PageIndex::METAS[0]);
+
+        let page_index = PageIndex {
+            root: 5.into(),
+            freelist: 6.into(),
+            next: 7.into(),
+            user_version: 9,
+        };
+        meta.advance(page_index.clone());
+        assert_eq!(meta.txid(), 1);
+        assert_eq!(meta.page_index(), &page_index);
+        assert_eq!(PageIndex::meta_id(meta.txid()), PageIndex::METAS[1]);
+        assert!(meta.validate().is_ok());
+
+        meta.page_index.root = 8.into();
+        assert!(meta.validate().is_err());
+
+        // The user version is covered by the checksum as well.
+        meta.page_index.root = 5.into();
+        meta.page_index.user_version = 10;
+        assert!(meta.validate().is_err());
+    }
+
+    #[test]
+    fn test_checksum_kind() {
+        let mut meta = Meta::new(Meta::default_page_size(), ChecksumKind::XxHash64);
+        assert!(meta.validate().is_ok());
+        assert_ne!(meta.checksum, Meta::default().checksum);
+
+        meta.header.checksum_kind = ChecksumKind::Crc32 as u32;
+        assert!(meta.validate().is_err());
+
+        meta.header.checksum_kind = 2;
+        meta.update_checksum();
+        assert!(meta.validate().is_err());
+    }
+
+    #[test]
+    fn test_page_size_unsupported() {
+        let mut meta = Meta::default();
+        meta.header.page_size = MIN_PAGE_SIZE / 2;
+        meta.update_checksum();
+        assert!(matches!(
+            meta.validate_header(),
+            Err(ValidationError::PageSizeUnsupported(_))
+        ));
+    }
 }
@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the counters describing how a ThetaDB instance has been used since it was
+/// opened or the metrics were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The number of read-only transactions started, including cursors.
+    pub read_txs: u64,
+    /// The number of read-write transactions started, whether committed or not.
+    pub write_txs: u64,
+    /// The number of keys looked up by `get` and `contains`.
+    pub keys_read: u64,
+    /// The number of keys put by committed read-write transactions.
+    pub keys_written: u64,
+    /// The number of keys deleted by committed read-write transactions.
+    pub keys_deleted: u64,
+    /// The number of pages allocated by committed read-write transactions.
+    pub pages_allocated: u64,
+    /// The number of pages freed by committed read-write transactions. Only the pages committed
+    /// before each transaction are counted, the ones it allocated and freed itself, e.g., a
+    /// page shadowed twice, are counted in `pages_allocated` only.
+    pub pages_freed: u64,
+    /// The number of bytes written by the commits that were synced to the disk.
+    pub bytes_synced: u64,
+}
+
+/// The atomic counters behind [`Metrics`], updated with relaxed ordering as they are only
+/// statistics.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) read_txs: AtomicU64,
+    pub(crate) write_txs: AtomicU64,
+    pub(crate) keys_read: AtomicU64,
+    pub(crate) keys_written: AtomicU64,
+    pub(crate) keys_deleted: AtomicU64,
+    pub(crate) pages_allocated: AtomicU64,
+    pub(crate) pages_freed: AtomicU64,
+    pub(crate) bytes_synced: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            read_txs: self.read_txs.load(Ordering::Relaxed),
+            write_txs: self.write_txs.load(Ordering::Relaxed),
+            keys_read: self.keys_read.load(Ordering::Relaxed),
+            keys_written: self.keys_written.load(Ordering::Relaxed),
+            keys_deleted: self.keys_deleted.load(Ordering::Relaxed),
+            pages_allocated: self.pages_allocated.load(Ordering::Relaxed),
+            pages_freed: self.pages_freed.load(Ordering::Relaxed),
+            bytes_synced: self.bytes_synced.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [
+            &self.read_txs,
+            &self.write_txs,
+            &self.keys_read,
+            &self.keys_written,
+            &self.keys_deleted,
+            &self.pages_allocated,
+            &self.pages_freed,
+            &self.bytes_synced,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) trait Counter {
+    fn add(&self, count: u64);
+}
+
+impl Counter for AtomicU64 {
+    #[inline]
+    fn add(&self, count: u64) {
+        if count > 0 {
+            self.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+}
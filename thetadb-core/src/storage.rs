@@ -1,6 +1,12 @@
-use std::ops::Range;
+use std::{
+    ops::{Deref, Range},
+    sync::{LockResult, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
-use crate::medium::{file, mapping, Bytes, BytesMut, File};
+use crate::{
+    medium::{file, mapping, Bytes, BytesMut, File},
+    meta::{Meta, MetaPage, PageIndex},
+};
 
 /// Represents the fundamental unit of data storage in ThetaDB.
 ///
@@ -61,17 +67,92 @@ impl From<u32> for PageId {
     }
 }
 
+/// The lock of the storage.
+///
+/// The pages read through the storage held shared are cached in the syscall mode, so holding
+/// it marks a reader of the file, and the cache evicts the pages only once there is no reader.
+pub(crate) struct StorageLock(RwLock<Storage>);
+
+impl StorageLock {
+    #[inline]
+    pub(crate) fn new(storage: Storage) -> Self {
+        Self(RwLock::new(storage))
+    }
+
+    #[inline]
+    pub(crate) fn read(&self) -> LockResult<SharedStorage<'_>> {
+        match self.0.read() {
+            Ok(guard) => Ok(SharedStorage::new(guard)),
+            Err(err) => Err(PoisonError::new(SharedStorage::new(err.into_inner()))),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn write(&self) -> LockResult<RwLockWriteGuard<'_, Storage>> {
+        self.0.write()
+    }
+}
+
+/// The storage held shared, see [`StorageLock`].
+pub(crate) struct SharedStorage<'a>(RwLockReadGuard<'a, Storage>);
+
+impl<'a> SharedStorage<'a> {
+    #[inline]
+    fn new(guard: RwLockReadGuard<'a, Storage>) -> Self {
+        guard.file.begin_read();
+        Self(guard)
+    }
+}
+
+impl Deref for SharedStorage<'_> {
+    type Target = Storage;
+
+    #[inline]
+    fn deref(&self) -> &Storage {
+        &self.0
+    }
+}
+
+impl Drop for SharedStorage<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // The pages read are borrowed from the guard, so none of them is used any more.
+        unsafe { self.0.file.end_read() }
+    }
+}
+
 /// Represents the storage system of the ThetaDB and is responsible for managing the data
 /// storage file.
 pub(crate) struct Storage {
     file: File,
     page_size: u32,
+    /// The id of the meta page that stores the latest metadata.
+    meta_id: PageId,
 }
 
 impl Storage {
     #[inline]
-    pub(crate) fn new(file: File, page_size: u32) -> Self {
-        Self { file, page_size }
+    pub(crate) fn new(file: File, page_size: u32, meta_id: PageId) -> Self {
+        Self {
+            file,
+            page_size,
+            meta_id,
+        }
+    }
+
+    /// The latest metadata.
+    #[inline]
+    pub(crate) fn meta(&self) -> mapping::Result<MetaPage<&[u8]>> {
+        self.page(self.meta_id)
+    }
+
+    /// Writes the metadata into its meta page, which becomes the latest one.
+    #[inline]
+    pub(crate) fn set_meta(&mut self, meta: Meta) -> mapping::Result<()> {
+        let meta_id = PageIndex::meta_id(meta.txid());
+        *self.page_mut::<MetaPage<_>>(meta_id)? = meta;
+        self.meta_id = meta_id;
+        Ok(())
     }
 
     #[inline]
@@ -79,10 +160,38 @@ impl Storage {
         self.page_size
     }
 
+    /// The current length of the mapped file.
+    #[inline]
+    pub(crate) fn file_len(&self) -> usize {
+        self.file.len()
+    }
+
     #[inline]
     pub(crate) fn allocate(&mut self, page_count: u32) -> file::Result<()> {
-        let len = page_count * self.page_size;
-        self.file.allocate(len as usize).map_err(Into::into)
+        let len = page_count as usize * self.page_size as usize;
+        self.file.allocate(len).map_err(Into::into)
+    }
+
+    /// Grows the file to at least the given length up front, to avoid repeated remaps.
+    #[inline]
+    pub(crate) fn reserve(&mut self, len: usize) -> file::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.file.allocate(len)
+    }
+
+    /// Shrinks the file to hold only the given number of pages.
+    #[inline]
+    pub(crate) fn truncate(&mut self, page_count: u32) -> file::Result<()> {
+        let len = page_count as usize * self.page_size as usize;
+        self.file.truncate(len)
+    }
+
+    /// Writes the pages written since the last flush to the file, see [`File::flush`].
+    #[inline]
+    pub(crate) fn flush(&mut self) -> file::Result<()> {
+        self.file.flush()
     }
 
     #[inline]
@@ -90,6 +199,34 @@ impl Storage {
         self.file.sync()
     }
 
+    /// Syncs only the given page to the disk, see [`File::sync_range`].
+    #[inline]
+    pub(crate) fn sync_page(&mut self, id: PageId) -> file::Result<()> {
+        let offset = id.raw() as usize * self.page_size as usize;
+        self.file
+            .sync_range(offset..offset + self.page_size as usize)
+    }
+
+    /// Syncs only the given pages to the disk, the consecutive ones are synced together, see
+    /// [`File::sync_ranges`].
+    pub(crate) fn sync_pages(&mut self, ids: impl IntoIterator<Item = PageId>) -> file::Result<()> {
+        let page_size = self.page_size as usize;
+        self.file.sync_ranges(ids.into_iter().map(|id| {
+            let offset = id.raw() as usize * page_size;
+            offset..offset + page_size
+        }))
+    }
+
+    #[inline]
+    pub(crate) fn preload(&self) -> file::Result<()> {
+        self.file.preload()
+    }
+
+    #[inline]
+    pub(crate) fn trim(&mut self) -> file::Result<()> {
+        self.file.trim()
+    }
+
     #[inline]
     pub(crate) fn page<'a, T>(&'a self, id: PageId) -> mapping::Result<T>
     where
@@ -127,20 +264,27 @@ impl Storage {
     #[inline]
     fn page_raw(&self, id: PageId) -> mapping::Result<&[u8]> {
         let range = self.range(id)?;
-        Ok(&self.file[range])
+        self.file.bytes(range).map_err(Into::into)
     }
 
     #[inline]
     fn page_raw_mut(&mut self, id: PageId) -> mapping::Result<&mut [u8]> {
         let range = self.range(id)?;
-        Ok(&mut self.file[range])
+        self.file.bytes_mut(range).map_err(Into::into)
     }
 
     #[inline]
     fn range(&self, id: PageId) -> mapping::Result<Range<usize>> {
         let offset = id.raw() as usize * self.page_size as usize;
         let range = offset..offset + self.page_size as usize;
-        mapping::check_range(&range, &self.file.as_ref())?;
-        Ok(range)
+        if range.end <= self.file.len() {
+            Ok(range)
+        } else {
+            let len = self.file.len();
+            Err(mapping::Error::Size.context(format_args!(
+                "page {} is beyond the end of the file ({len} bytes)",
+                id.raw()
+            )))
+        }
     }
 }
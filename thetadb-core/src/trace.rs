@@ -0,0 +1,44 @@
+//! The instrumentation with [`tracing`](https://docs.rs/tracing), enabled by the `tracing`
+//! feature. Without it, the macros expand to nothing and their fields are never evaluated.
+
+/// Emits an event at the given level, e.g., `event!(DEBUG, "sync", bytes = len)`.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, $name:literal $(, $($field:tt)+)?) => {
+        ::tracing::event!(
+            target: "thetadb",
+            ::tracing::Level::$level,
+            $($($field)+,)?
+            $name
+        )
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($($arg:tt)+) => {};
+}
+
+/// Enters a span at the given level, which is exited as the returned guard is dropped.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, $name:literal $(, $($field:tt)+)?) => {
+        ::tracing::span!(
+            target: "thetadb",
+            ::tracing::Level::$level,
+            $name
+            $(, $($field)+)?
+        )
+        .entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($arg:tt)+) => {
+        ()
+    };
+}
+
+pub(crate) use event;
+pub(crate) use span;
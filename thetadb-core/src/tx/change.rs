@@ -0,0 +1,8 @@
+/// Represents a logical change made by a committed read-write transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// A key-value pair was inserted or updated.
+    Put { key: Vec<u8>, value: Vec<u8> },
+    /// A key-value pair was deleted.
+    Delete { key: Vec<u8> },
+}
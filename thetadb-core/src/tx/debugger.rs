@@ -1,24 +1,111 @@
-use std::fmt::{Debug, Formatter};
+use std::{
+    cell::OnceCell,
+    fmt::{self, Debug, Formatter},
+    io,
+};
 
 use crate::{
-    bptree::{BPTree, TreeIndex},
+    bptree::{BPTree, DumpError, TreeIndex},
     chunk::Chunk,
     freelist::Freelist,
-    meta::{Meta, MetaPage, PageIndex},
-    tx::readonly::Readonly,
+    medium,
+    meta::{Meta, PageIndex},
+    storage::PageId,
+    tx::{readonly::Readonly, InputInvalid, TxToken},
     Result, ThetaDB,
 };
 
+/// The statistics of the pages and entries in the ThetaDB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of levels of the B+ tree.
+    pub height: u32,
+    /// The number of branch pages of the B+ tree.
+    pub branch_pages: u32,
+    /// The number of leaf pages of the B+ tree.
+    pub leaf_pages: u32,
+    /// The number of pages storing the overflowed values.
+    pub overflow_pages: u32,
+    /// The number of free pages that can be reused.
+    pub free_pages: u32,
+    /// The number of pages in use or free, including the meta and freelist pages.
+    pub total_pages: u32,
+    /// The number of key-value pairs.
+    pub entry_count: u64,
+}
+
+/// How the value of a key is stored in the ThetaDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueStorage {
+    /// The value is stored in the leaf page along with the key.
+    Inline {
+        /// The length of the value.
+        len: u32,
+    },
+    /// The value is too large to be stored in the leaf page, so it is stored in a chain of
+    /// overflow pages.
+    Overflow {
+        /// The id of the first page of the chain.
+        head_page: u32,
+        /// The number of pages of the chain.
+        chunk_count: u32,
+        /// The length of the value.
+        len: u32,
+    },
+}
+
+/// The kind of a page in the ThetaDB, see [`Debugger::page_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    /// One of the two pages storing the metadata.
+    Meta,
+    /// A branch page of the B+ tree.
+    Branch,
+    /// A leaf page of the B+ tree.
+    Leaf,
+    /// A page of the chain storing an overflowed value.
+    Overflow,
+    /// A page of the chain storing the freelist.
+    Freelist,
+    /// A free page that can be reused.
+    Free,
+    /// A page that is neither in use nor free, i.e., it is leaked.
+    Unreferenced,
+}
+
+/// The information of a page in the ThetaDB, see [`Debugger::page_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageInfo {
+    /// The kind of the page.
+    pub kind: PageKind,
+    /// The number of entries of a branch or leaf page, 0 for the other pages.
+    pub entry_count: u32,
+    /// The ids of the child pages of a branch page in key order, empty for the other pages.
+    pub children: Vec<u32>,
+    /// The id of the previous page in the chain of an overflow or freelist page, `None` for the
+    /// first page of a chain and the other pages. A chain is linked backward from its last page.
+    pub prev_page: Option<u32>,
+}
+
 pub struct Debugger<'a> {
     _db: &'a ThetaDB,
     bptree: BPTree<Readonly<'a>>,
+    /// The kinds of all pages, collected on the first call of `page_info`.
+    page_kinds: OnceCell<Vec<PageKind>>,
+    _token: TxToken,
 }
 
 impl<'a> Debugger<'a> {
     pub fn new(db: &'a ThetaDB) -> Result<Self> {
+        let token = TxToken::shared(db)?;
         let storage = db.storage.read().unwrap();
         let bptree = Readonly::new(storage).map(BPTree::new)?;
-        Ok(Self { _db: db, bptree })
+        Ok(Self {
+            _db: db,
+            bptree,
+            page_kinds: OnceCell::new(),
+            _token: token,
+        })
     }
 
     #[inline]
@@ -26,11 +113,130 @@ impl<'a> Debugger<'a> {
         self.freelist().map(|f| f.len())
     }
 
+    /// The runs of consecutive free pages, each is the id of the first page and the number of
+    /// pages, in ascending order by page id.
+    #[inline]
+    pub fn freelist_map(&self) -> Result<Vec<(u32, u32)>> {
+        self.freelist().map(|f| {
+            f.runs()
+                .into_iter()
+                .map(|(id, len)| (id.raw(), len))
+                .collect()
+        })
+    }
+
+    /// Collects the statistics by visiting every page of the B+ tree.
+    pub fn stats(&self) -> Result<Stats> {
+        let tree = self.bptree.stats()?;
+        Ok(Stats {
+            height: tree.height,
+            branch_pages: tree.branch_pages,
+            leaf_pages: tree.leaf_pages,
+            overflow_pages: tree.overflow_pages,
+            free_pages: self.freelist_len()? as u32,
+            total_pages: self.meta()?.page_index().page_count(),
+            entry_count: tree.entry_count,
+        })
+    }
+
+    /// Counts the leaf pages by how full they are, in 10 buckets of 10% each. E.g., the first
+    /// bucket counts the leaves less than 10% full, and the last one counts the leaves at least
+    /// 90% full.
+    ///
+    /// Leaves that are consistently half empty suggest that a different page size or a
+    /// compaction would help.
+    #[inline]
+    pub fn leaf_fill_histogram(&self) -> Result<Vec<usize>> {
+        self.bptree.leaf_fill_histogram(10).map_err(Into::into)
+    }
+
+    /// Finds out how the value associated with the given key is stored, `None` if the key
+    /// does not exist.
+    #[inline]
+    pub fn value_storage(&self, key: impl AsRef<[u8]>) -> Result<Option<ValueStorage>> {
+        self.bptree.value_storage(key.as_ref()).map_err(Into::into)
+    }
+
+    /// Inspects the page with the given id, which must be less than the number of pages, see
+    /// [`Stats::total_pages`].
+    ///
+    /// The kinds of all pages are found out by visiting the whole B+ tree on the first call, so
+    /// the pages can be inspected in any order afterwards.
+    pub fn page_info(&self, id: u32) -> Result<PageInfo> {
+        let page_count = self.meta()?.page_index().page_count();
+        if id >= page_count {
+            return Err(InputInvalid::PageOutOfRange { id, page_count }.into());
+        }
+
+        let kinds = match self.page_kinds.get() {
+            Some(kinds) => kinds,
+            None => {
+                let kinds = self.collect_page_kinds(page_count)?;
+                self.page_kinds.get_or_init(|| kinds)
+            }
+        };
+
+        let mut info = PageInfo {
+            kind: kinds[id as usize],
+            entry_count: 0,
+            children: Vec::new(),
+            prev_page: None,
+        };
+        let id = PageId::from(id);
+        match info.kind {
+            PageKind::Branch | PageKind::Leaf => {
+                let (count, children) = self.bptree.node_entries(id)?;
+                info.entry_count = count as u32;
+                info.children = children.into_iter().map(|id| id.raw()).collect();
+            }
+            PageKind::Overflow | PageKind::Freelist => {
+                let chunk = self.bptree.as_index().page::<Chunk<_>>(id)?;
+                info.prev_page = chunk.prev().map(|id| id.raw());
+            }
+            PageKind::Meta | PageKind::Free | PageKind::Unreferenced => (),
+        }
+        Ok(info)
+    }
+
     #[inline]
     pub fn page_size(&self) -> Result<u32> {
         self.meta().map(|m| m.page_size())
     }
 
+    /// Dumps the B+ tree as the ASCII diagram of its `Debug` format to the writer, rendering it
+    /// as it goes rather than buffering the whole diagram.
+    pub fn dump(&self, writer: &mut impl io::Write) -> Result<()> {
+        let mut sink = IoSink {
+            writer,
+            error: None,
+        };
+        match self.bptree.dump(&mut sink) {
+            Ok(()) => Ok(()),
+            Err(DumpError::Mapping(err)) => Err(err.into()),
+            Err(DumpError::Write) => {
+                let err = sink
+                    .error
+                    .unwrap_or_else(|| io::Error::other("formatter error"));
+                Err(medium::file::Error::from(err).into())
+            }
+        }
+    }
+
+    /// Dumps the B+ tree as JSON, with the lengths of the values but not the values themselves.
+    ///
+    /// Keys and values that are valid UTF-8 are dumped as strings, others as objects holding
+    /// their hex encoding, e.g. `{"hex":"6b65ff"}`.
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        self.bptree.dump_json(false).map_err(Into::into)
+    }
+
+    /// Dumps the B+ tree as JSON, including the values.
+    #[inline]
+    pub fn to_json_with_values(&self) -> Result<String> {
+        self.bptree.dump_json(true).map_err(Into::into)
+    }
+
     #[inline]
     fn freelist(&self) -> Result<Freelist> {
         Chunk::read(self.meta()?.page_index().freelist, |id| {
@@ -40,19 +246,63 @@ impl<'a> Debugger<'a> {
         .map_err(Into::into)
     }
 
+    fn collect_page_kinds(&self, page_count: u32) -> Result<Vec<PageKind>> {
+        let mut kinds = vec![PageKind::Unreferenced; page_count as usize];
+        let mut mark = |id: PageId, kind| {
+            if let Some(slot) = kinds.get_mut(id.raw() as usize) {
+                *slot = kind;
+            }
+        };
+
+        for id in PageIndex::METAS {
+            mark(id, PageKind::Meta);
+        }
+
+        let mut prev_id = Some(self.meta()?.page_index().freelist);
+        while let Some(id) = prev_id {
+            mark(id, PageKind::Freelist);
+            prev_id = self.bptree.as_index().page::<Chunk<_>>(id)?.prev();
+        }
+
+        for (id, len) in self.freelist()?.runs() {
+            for offset in 0..len {
+                mark((id.raw() + offset).into(), PageKind::Free);
+            }
+        }
+
+        self.bptree.visit_pages(mark)?;
+        Ok(kinds)
+    }
+
     #[inline]
     fn meta(&self) -> Result<Meta> {
-        self.bptree
-            .as_index()
-            .page::<MetaPage<_>>(PageIndex::META)
-            .map(|m| m.clone())
-            .map_err(Into::into)
+        self.bptree.as_index().meta().map_err(Into::into)
     }
 }
 
 impl Debug for Debugger<'_> {
     #[inline]
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.bptree.dump(f)
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.bptree.dump(f).map_err(|_| fmt::Error)
+    }
+}
+
+/// Writes the formatted text to an `io::Write`, keeping the I/O error that `fmt::Error` cannot
+/// carry.
+struct IoSink<'w, W> {
+    writer: &'w mut W,
+    error: Option<io::Error>,
+}
+
+impl<W> fmt::Write for IoSink<'_, W>
+where
+    W: io::Write,
+{
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
     }
 }
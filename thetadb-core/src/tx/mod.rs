@@ -1,170 +1,1316 @@
-use std::fmt::Debug;
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    iter::{self, FusedIterator},
+    mem,
+    ops::{Bound, RangeBounds},
+    sync::atomic::Ordering,
+    time::Duration,
+};
 
+use parking_lot::MutexGuard;
 use thiserror::Error;
 
 use crate::{
-    bptree::{BPTree, Cursor},
-    tx::{readonly::Readonly, readwrite::ReadWrite},
-    Result, ThetaDB, MAX_KEY_LEN, MAX_VALUE_LEN,
+    bptree::{BPTree, Cursor, Location, Track},
+    db::TxCoordinator,
+    medium::mapping,
+    metrics::Counter,
+    tx::readwrite::{PagePreview, Prepared, ReadWrite},
+    Error, Result, ThetaDB,
 };
 
+mod change;
 mod debugger;
+mod overlap;
 mod readonly;
 mod readwrite;
+mod session;
+mod snapshot;
 
-pub use debugger::Debugger;
+pub use change::ChangeEvent;
+pub use debugger::{Debugger, PageInfo, PageKind, Stats, ValueStorage};
+pub(crate) use overlap::{TxOverlapped, TxToken};
+pub(crate) use readonly::Readonly;
+pub use session::{ReadSession, SessionCursor};
+pub use snapshot::Snapshot;
+pub(crate) use snapshot::Snapshots;
 
 /// Represents the read-only transaction in ThetaDB.
-pub struct Tx<'a>(BPTree<Readonly<'a>>);
+///
+/// It reads a consistent view of the ThetaDB at the moment it was started, and does not block
+/// read-write transactions from committing, see [`Snapshot`].
+pub struct Tx<'a> {
+    snapshot: Snapshot<'a>,
+}
 
 impl<'a> Tx<'a> {
     /// Start a read-only transaction.
     pub fn new(db: &'a ThetaDB) -> Result<Self> {
-        let storage = db.storage.read().unwrap();
-        let bptree = Readonly::new(storage).map(BPTree::new)?;
-        Ok(Self(bptree))
+        let snapshot = Snapshot::new(db)?;
+        db.metrics.read_txs.add(1);
+        Ok(Self { snapshot })
     }
 
     /// Check if the ThetaDB contains a given key.
     #[inline]
     pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
-        self.0.contains(key.as_ref()).map_err(Into::into)
+        self.snapshot.contains(key)
     }
 
     /// Get the value associated with a given key.
     #[inline]
     pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
-        self.0.get(key.as_ref()).map_err(Into::into)
+        self.snapshot.get(key)
+    }
+
+    /// Get the value associated with a given key into the buffer, which is cleared first.
+    /// Returns whether the key exists.
+    ///
+    /// Reusing the buffer across calls saves the allocations of reading large values.
+    #[inline]
+    pub fn get_into(&self, key: impl AsRef<[u8]>, buf: &mut Vec<u8>) -> Result<bool> {
+        self.snapshot.get_into(key, buf)
+    }
+
+    /// Get the smallest key in the ThetaDB, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn first_key(&self) -> Result<Option<Vec<u8>>> {
+        self.snapshot.bptree()?.edge_key(true).map_err(Into::into)
+    }
+
+    /// Get the largest key in the ThetaDB, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn last_key(&self) -> Result<Option<Vec<u8>>> {
+        self.snapshot.bptree()?.edge_key(false).map_err(Into::into)
+    }
+
+    /// Get the key-value pair with the smallest key, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn first(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.snapshot
+            .bptree()?
+            .edge_key_value(true)
+            .map_err(Into::into)
+    }
+
+    /// Get the key-value pair with the largest key, `None` if the ThetaDB is empty.
+    #[inline]
+    pub fn last(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.snapshot
+            .bptree()?
+            .edge_key_value(false)
+            .map_err(Into::into)
+    }
+
+    /// Get the cursor pointing to the first record, which reads the same view as the
+    /// transaction and may outlive it.
+    #[inline]
+    pub fn first_cursor(&self) -> Result<CursorTx<'a>> {
+        self.snapshot.first_cursor()
+    }
+
+    /// Get the cursor pointing to the last record, which reads the same view as the
+    /// transaction and may outlive it.
+    #[inline]
+    pub fn last_cursor(&self) -> Result<CursorTx<'a>> {
+        self.snapshot.last_cursor()
+    }
+
+    /// Get the cursor pointing to the specific record with the given key, which reads the same
+    /// view as the transaction and may outlive it.
+    #[inline]
+    pub fn cursor_from_key(&self, key: &[u8]) -> Result<CursorTx<'a>> {
+        self.snapshot.cursor_from_key(key)
+    }
+
+    /// Get the cursor pointing to the record with the largest key not greater than the given
+    /// one, which reads the same view as the transaction and may outlive it.
+    #[inline]
+    pub fn cursor_before_key(&self, key: &[u8]) -> Result<CursorTx<'a>> {
+        self.snapshot.cursor_before_key(key)
+    }
+
+    /// Count the records whose keys fall within the given range.
+    ///
+    /// The range can be a pair of `Bound<&[u8]>` or `..`, e.g.
+    /// `(Bound::Included(b"a".as_slice()), Bound::Excluded(b"c".as_slice()))`.
+    #[inline]
+    pub fn count_range(&self, range: impl RangeBounds<[u8]>) -> Result<usize> {
+        self.snapshot
+            .bptree()?
+            .count_range(range.start_bound(), range.end_bound())
+            .map_err(Into::into)
+    }
+
+    /// Count all the records, which reads every leaf of the B+ tree but not the values, see
+    /// [`count_range`](Self::count_range).
+    #[inline]
+    pub fn len(&self) -> Result<usize> {
+        self.count_range(..)
+    }
+
+    /// Check if there is no record, which reads only the first leaf of the B+ tree.
+    #[inline]
+    pub fn is_empty(&self) -> Result<bool> {
+        self.first_key().map(|key| key.is_none())
+    }
+
+    /// Split the key space into at most `k` ranges holding roughly equal numbers of records,
+    /// e.g., to scan them on separate threads, each with its own read-only transaction.
+    ///
+    /// Each range is a pair of the start key (inclusive) and the end key (exclusive). The
+    /// ranges are adjacent in ascending order, the first one starts with the empty key, and
+    /// the last one ends with the empty key, which means it is unbounded. There are fewer
+    /// ranges if the records are too few, but always at least one.
+    pub fn split_ranges(&self, k: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let keys = self.snapshot.bptree()?.split_keys(k)?;
+        let starts = iter::once(Vec::new()).chain(keys.clone());
+        let ends = keys.into_iter().chain(iter::once(Vec::new()));
+        Ok(starts.zip(ends).collect())
+    }
+
+    /// Visits all the branch pages of the B+ tree, so that they are loaded into memory.
+    ///
+    /// Unlike a started transaction, it is not counted into the metrics.
+    pub(crate) fn visit_branches(db: &ThetaDB) -> Result<()> {
+        let storage = db.read_storage()?;
+        let bptree = Readonly::new(storage).map(BPTree::new)?;
+        bptree.visit_branches().map_err(Into::into)
+    }
+
+    /// Get the values associated with the given keys.
+    ///
+    /// The keys are looked up in ascending order for better locality, while the returned
+    /// values stay aligned to the input order (duplicate keys included).
+    pub fn get_many(&self, keys: &[impl AsRef<[u8]>]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut indices = (0..keys.len()).collect::<Vec<_>>();
+        indices.sort_by_key(|&idx| keys[idx].as_ref());
+
+        let mut values = vec![None; keys.len()];
+        let mut prev: Option<usize> = None;
+
+        for idx in indices {
+            values[idx] = match prev {
+                // Duplicate keys are adjacent after sorting, so reuse the previous lookup.
+                Some(prev) if keys[prev].as_ref() == keys[idx].as_ref() => values[prev].clone(),
+                _ => self.get(&keys[idx])?,
+            };
+            prev = Some(idx);
+        }
+
+        Ok(values)
     }
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum InputInvalid {
-    #[error("the length of the key cannot exceed {}", MAX_KEY_LEN)]
-    KeyInvalid,
-    #[error("the length of the value cannot exceed {}", MAX_VALUE_LEN)]
-    ValueInvalid,
+    #[error("the length of the key ({len}) cannot exceed {max}")]
+    KeyTooLong { len: usize, max: usize },
+    #[error("the length of the value ({len}) cannot exceed {max}")]
+    ValueTooLong { len: usize, max: usize },
+    #[error("the length of the value ({len}) cannot exceed {max} to be stored inline, as overflow pages are disabled")]
+    ValueNotInline { len: usize, max: usize },
+    #[error("the page id ({id}) is out of range, there are {page_count} pages")]
+    PageOutOfRange { id: u32, page_count: u32 },
+    #[error("the value of the counter should be 8 bytes, but it is {len} bytes")]
+    CounterInvalid { len: usize },
+    #[error("adding {delta} to the counter ({value}) overflows")]
+    CounterOverflow { value: i64, delta: i64 },
+    #[error("the transaction has ended without a committed state to read")]
+    TxEnded,
 }
 
 /// Represents the read-write transaction in ThetaDB.
 pub struct TxMut<'a> {
     db: &'a ThetaDB,
     bptree: BPTree<ReadWrite<'a>>,
+    /// The logical changes made by the transaction, only recorded when someone observes them.
+    changes: Option<Vec<ChangeEvent>>,
+    /// The number of keys put and deleted, counted into the metrics once committed.
+    keys_written: u64,
+    keys_deleted: u64,
+    _token: TxToken,
 }
 
 impl<'a> TxMut<'a> {
     /// Start a read-write transaction.
+    ///
+    /// Fails with [`ErrorCode::TxOverlapped`](crate::ErrorCode) if another read-write
+    /// transaction or a debugger is alive on the same thread.
     pub fn new(db: &'a ThetaDB) -> Result<Self> {
-        let coordinator = db.rw_coordinator.lock().unwrap();
+        let token = TxToken::writable(db)?;
+        Self::with_coordinator(db, db.rw_coordinator.lock(), token)
+    }
+
+    /// Start a read-write transaction, `None` if another one is still in progress after the
+    /// timeout.
+    ///
+    /// The storage is only held exclusively under the coordinator, so no writer is queued for
+    /// it once the coordinator is taken, and reading it does not wait. Thus the timeout bounds
+    /// the whole start.
+    pub(crate) fn with_timeout(db: &'a ThetaDB, timeout: Duration) -> Result<Option<Self>> {
+        let token = TxToken::writable(db)?;
+        db.rw_coordinator
+            .try_lock_for(timeout)
+            .map(|coordinator| Self::with_coordinator(db, coordinator, token))
+            .transpose()
+    }
+
+    fn with_coordinator(
+        db: &'a ThetaDB,
+        coordinator: MutexGuard<'a, TxCoordinator>,
+        token: TxToken,
+    ) -> Result<Self> {
         let storage = db.storage.read().unwrap();
-        let bptree = ReadWrite::new(coordinator, storage).map(BPTree::new)?;
-        Ok(Self { db, bptree })
+        let bptree = ReadWrite::new(
+            coordinator,
+            storage,
+            &db.snapshots,
+            &db.metrics,
+            db.options.bloom_bits_per_key,
+            db.options.underflow_ratio,
+        )
+        .map(BPTree::new)?;
+        let changes = db.options.tracks_changes().then(Vec::new);
+        db.metrics.write_txs.add(1);
+        Ok(Self {
+            db,
+            bptree,
+            changes,
+            keys_written: 0,
+            keys_deleted: 0,
+            _token: token,
+        })
     }
 
     /// Check if the ThetaDB contains a given key.
     #[inline]
     pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        self.db.metrics.keys_read.add(1);
         self.bptree.contains(key.as_ref()).map_err(Into::into)
     }
 
     /// Get the value associated with a given key.
     #[inline]
     pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.db.metrics.keys_read.add(1);
         self.bptree.get(key.as_ref()).map_err(Into::into)
     }
 
     /// Insert or update a key-value pair into the ThetaDB.
+    ///
+    /// The key can be empty, which is the smallest of all keys.
     #[inline]
     pub fn put(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
         let (key, value) = (key.as_ref(), value.as_ref());
-        Self::validate_input(key, value)?;
-        self.bptree.put(key, value).map_err(Into::into)
+        self.validate_input(key, value.len())?;
+        self.put_validated(key, value)
+    }
+
+    /// Insert or update all the key-value pairs from an iterator, in the order they are given.
+    ///
+    /// All the pairs are validated before any of them is written, so an invalid one (e.g., with
+    /// a value too long) fails the call with the transaction untouched. A failure while writing
+    /// (e.g., the database is full) may still leave some of them written, after which the
+    /// transaction should be rolled back.
+    pub fn put_many<K, V>(&mut self, pairs: impl IntoIterator<Item = (K, V)>) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let pairs = pairs.into_iter().collect::<Vec<_>>();
+        for (key, value) in &pairs {
+            self.validate_input(key.as_ref(), value.as_ref().len())?;
+        }
+        for (key, value) in &pairs {
+            self.put_validated(key.as_ref(), value.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Insert a key-value pair into the ThetaDB only if the key is absent, returns whether it
+    /// is inserted.
+    ///
+    /// The existing value is left untouched if the key is present.
+    #[inline]
+    pub fn put_if_absent(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<bool> {
+        let (key, value) = (key.as_ref(), value.as_ref());
+        self.validate_input(key, value.len())?;
+        if !self.bptree.put_if_absent(key, value)? {
+            return Ok(false);
+        }
+        self.keys_written += 1;
+
+        self.record(|| ChangeEvent::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        Ok(true)
+    }
+
+    /// Append the suffix to the value associated with a given key, the key is inserted with
+    /// the suffix if it is absent.
+    ///
+    /// A large value stored in overflow pages is extended without being rewritten: only its
+    /// last page is filled up and new pages are allocated for the rest. Its pages are still
+    /// copied once per transaction, as the committed ones are never modified in place.
+    pub fn append(&mut self, key: impl AsRef<[u8]>, suffix: impl AsRef<[u8]>) -> Result<()> {
+        let (key, suffix) = (key.as_ref(), suffix.as_ref());
+        let len = match self.bptree.value_storage(key)? {
+            Some(ValueStorage::Inline { len } | ValueStorage::Overflow { len, .. }) => len,
+            None => 0,
+        };
+        self.validate_input(key, len as usize + suffix.len())?;
+        self.bptree.append(key, suffix)?;
+        self.keys_written += 1;
+
+        if self.changes.is_some() {
+            let value = self.bptree.get(key)?.unwrap_or_default();
+            self.record(|| ChangeEvent::Put {
+                key: key.to_vec(),
+                value,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add the delta to the counter associated with a given key, returns the new value of it.
+    ///
+    /// The counter is stored as a big-endian `i64` (i.e., `i64::to_be_bytes`), and an absent
+    /// one counts as 0. The values that are not 8 bytes long, and the results that overflow,
+    /// are rejected with [`ErrorCode::InputInvalid`](crate::ErrorCode).
+    pub fn increment(&mut self, key: impl AsRef<[u8]>, delta: i64) -> Result<i64> {
+        let key = key.as_ref();
+        let value = match self.bptree.get(key)? {
+            Some(bytes) => bytes
+                .as_slice()
+                .try_into()
+                .map(i64::from_be_bytes)
+                .map_err(|_| InputInvalid::CounterInvalid { len: bytes.len() })?,
+            None => 0,
+        };
+        let value = value
+            .checked_add(delta)
+            .ok_or(InputInvalid::CounterOverflow { value, delta })?;
+        self.put(key, value.to_be_bytes())?;
+        Ok(value)
     }
 
     /// Delete a key-value pair from the ThetaDB.
     #[inline]
     pub fn delete(&mut self, key: impl AsRef<[u8]>) -> Result<()> {
-        self.bptree.delete(key.as_ref()).map_err(Into::into)
+        let key = key.as_ref();
+        // Deleting an absent key is not a change.
+        if self.bptree.delete(key)? {
+            self.keys_deleted += 1;
+            self.record(|| ChangeEvent::Delete { key: key.to_vec() });
+        }
+        Ok(())
+    }
+
+    /// Delete the key-value pairs of all the keys from an iterator, returns the number of
+    /// deleted key-value pairs, which excludes the absent keys.
+    pub fn delete_many<K>(&mut self, keys: impl IntoIterator<Item = K>) -> Result<usize>
+    where
+        K: AsRef<[u8]>,
+    {
+        let deleted = self.keys_deleted;
+        for key in keys {
+            self.delete(key)?;
+        }
+        Ok((self.keys_deleted - deleted) as usize)
+    }
+
+    /// Create a cursor over the records of the transaction, which can delete the records it
+    /// passes. It points to nothing until it is moved, e.g., by [`CursorMut::first`].
+    #[inline]
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, 'a> {
+        CursorMut {
+            tx: self,
+            track: Track::new(),
+            deleted: None,
+        }
+    }
+
+    /// Delete all key-value pairs whose keys fall within the given range, returns the number of
+    /// deleted key-value pairs.
+    #[inline]
+    pub fn delete_range(&mut self, range: impl RangeBounds<[u8]>) -> Result<usize> {
+        let deleted_keys = self
+            .bptree
+            .delete_range(range.start_bound(), range.end_bound())?;
+        Ok(self.record_deletions(deleted_keys))
+    }
+
+    /// Delete all key-value pairs for which `f` returns false, returns the number of deleted
+    /// key-value pairs.
+    ///
+    /// The key-value pairs are visited in ascending order by key.
+    #[inline]
+    pub fn retain(&mut self, f: impl FnMut(&[u8], &[u8]) -> bool) -> Result<usize> {
+        let deleted_keys = self.bptree.retain(f)?;
+        Ok(self.record_deletions(deleted_keys))
+    }
+
+    /// Delete all key-value pairs, returns the number of deleted key-value pairs.
+    ///
+    /// Rather than deleting the records one by one, every page of the B+ tree is freed and the
+    /// root is replaced with an empty leaf, which takes effect atomically on commit.
+    #[inline]
+    pub fn clear(&mut self) -> Result<usize> {
+        // The keys are only copied if the changes are recorded.
+        let changes = &mut self.changes;
+        let count = self.bptree.clear(|key| {
+            if let Some(changes) = changes {
+                changes.push(ChangeEvent::Delete { key: key.to_vec() });
+            }
+        })?;
+        self.keys_deleted += count as u64;
+        Ok(count)
+    }
+
+    /// Get the user version of the ThetaDB, including the change made by this transaction.
+    #[inline]
+    pub fn user_version(&self) -> u32 {
+        self.bptree.as_index().user_version()
+    }
+
+    /// Set the user version of the ThetaDB, which is committed along with the other changes of
+    /// the transaction, see [`ThetaDB::user_version`].
+    #[inline]
+    pub fn set_user_version(&mut self, version: u32) {
+        self.bptree.as_index().set_user_version(version);
+    }
+
+    /// Gives the free pages at the end of the file back, returns the number of them.
+    #[inline]
+    pub(crate) fn take_trailing_free_pages(&mut self) -> u32 {
+        self.bptree.as_index().take_trailing_free_pages()
+    }
+
+    /// Checks if the freelist can be moved away from the end of the file.
+    #[inline]
+    pub(crate) fn can_move_freelist(&self) -> Result<bool> {
+        Ok(self.bptree.as_index().can_move_freelist()?)
     }
 
     /// Commit the read-write transaction, which means it has done all its work.
+    ///
+    /// If an observer is set by [`Options::on_commit`](crate::Options::on_commit), it will be
+    /// invoked with the changes after the commit is completed. If a change log is set by
+    /// [`Options::changelog_path`](crate::Options::changelog_path), the changes will be appended
+    /// to it before the commit returns.
+    ///
+    /// If the commit fails before the meta page is switched, the ThetaDB keeps the last committed
+    /// state, both in the file and in memory, and stays usable.
     #[inline]
     pub fn commit(self) -> Result<()> {
-        self.bptree
+        self.prepare()?.commit()
+    }
+
+    /// Records the changes made afterwards even if nobody observes them, e.g., for a dry run.
+    #[inline]
+    pub(crate) fn track_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// Rolls back the transaction, and reports what it would have done if it were committed,
+    /// see [`ThetaDB::dry_run`](crate::ThetaDB::dry_run).
+    pub(crate) fn dry_run(self) -> Result<DryRunReport> {
+        let PagePreview {
+            written_pages,
+            allocated_pages,
+            freed_pages,
+            page_count,
+            free_pages,
+        } = self.bptree.into_index().preview()?;
+        Ok(DryRunReport {
+            changes: self.changes.unwrap_or_default(),
+            keys_written: self.keys_written,
+            keys_deleted: self.keys_deleted,
+            written_pages,
+            allocated_pages,
+            freed_pages,
+            page_count,
+            free_pages,
+        })
+    }
+
+    /// Prepare to commit the read-write transaction, the first phase of a two-phase commit.
+    ///
+    /// The changed pages are written into the file and synced if `Options::force_sync` is set,
+    /// but the committed state is untouched until [`PreparedTx::commit`] switches the meta page.
+    /// In between, the app can update the external resources that should be changed along with
+    /// the ThetaDB, and abort the transaction if any of them fails.
+    ///
+    /// The prepared transaction does not block readers, but still blocks other read-write
+    /// transactions until it is committed or aborted.
+    pub fn prepare(self) -> Result<PreparedTx<'a>> {
+        let Self {
+            db,
+            bptree,
+            changes,
+            keys_written,
+            keys_deleted,
+            _token: token,
+        } = self;
+
+        let prepared = bptree
             .into_index()
-            .commit(self.db.options.force_sync, || {
-                self.db.storage.write().unwrap()
-            })
+            .prepare(db.options.force_sync, || db.storage.write().unwrap())?;
+        Ok(PreparedTx {
+            db,
+            prepared,
+            changes: changes.unwrap_or_default(),
+            keys_written,
+            keys_deleted,
+            _token: token,
+        })
+    }
+
+    #[inline]
+    fn record(&mut self, change: impl FnOnce() -> ChangeEvent) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(change());
+        }
+    }
+
+    #[inline]
+    fn record_deletions(&mut self, deleted_keys: Vec<Vec<u8>>) -> usize {
+        let count = deleted_keys.len();
+        self.keys_deleted += count as u64;
+        if let Some(changes) = &mut self.changes {
+            changes.extend(
+                deleted_keys
+                    .into_iter()
+                    .map(|key| ChangeEvent::Delete { key }),
+            );
+        }
+        count
+    }
+
+    fn put_validated(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.bptree.put(key, value)?;
+        self.keys_written += 1;
+
+        self.record(|| ChangeEvent::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        Ok(())
     }
 
-    fn validate_input(key: &[u8], value: &[u8]) -> Result<()> {
-        if key.as_ref().len() > MAX_KEY_LEN {
-            return Err(InputInvalid::KeyInvalid.into());
+    fn validate_input(&self, key: &[u8], value_len: usize) -> Result<()> {
+        let options = &self.db.options;
+        // Small pages hold shorter keys, see `ThetaDB::max_key_len`. The storage is not locked
+        // again here, as the transaction already holds it.
+        let max_key_len = options.max_key_len.min(self.bptree.max_key_len());
+        if key.len() > max_key_len {
+            return Err(InputInvalid::KeyTooLong {
+                len: key.len(),
+                max: max_key_len,
+            }
+            .into());
+        }
+        if value_len > options.max_value_len {
+            return Err(InputInvalid::ValueTooLong {
+                len: value_len,
+                max: options.max_value_len,
+            }
+            .into());
         }
-        if value.as_ref().len() > MAX_VALUE_LEN {
-            return Err(InputInvalid::ValueInvalid.into());
+        if options.disable_overflow {
+            let max = self.bptree.max_inline_value_len(key.len());
+            if value_len > max {
+                return Err(InputInvalid::ValueNotInline {
+                    len: value_len,
+                    max,
+                }
+                .into());
+            }
         }
         Ok(())
     }
 }
 
+/// What a read-write transaction would have done if it were committed, reported by
+/// [`ThetaDB::dry_run`](crate::ThetaDB::dry_run).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DryRunReport {
+    /// The logical changes in the order they were made.
+    pub changes: Vec<ChangeEvent>,
+    /// The number of keys put.
+    pub keys_written: u64,
+    /// The number of keys deleted.
+    pub keys_deleted: u64,
+    /// The number of pages that would be written, including the meta page.
+    pub written_pages: u64,
+    /// The number of pages that would be allocated, including the ones of the freelist.
+    pub allocated_pages: u64,
+    /// The number of committed pages that would be freed, including the ones of the old
+    /// freelist, like [`Metrics::pages_freed`](crate::Metrics::pages_freed).
+    pub freed_pages: u64,
+    /// The number of pages in the file after the commit, including the free ones.
+    pub page_count: u32,
+    /// The number of free pages after the commit.
+    pub free_pages: u32,
+}
+
+/// Represents a read-write transaction prepared to commit, see [`TxMut::prepare`].
+///
+/// Dropping it without committing aborts the transaction, the written pages are left free.
+pub struct PreparedTx<'a> {
+    db: &'a ThetaDB,
+    /// `None` if the transaction has nothing to commit.
+    prepared: Option<Prepared<'a>>,
+    changes: Vec<ChangeEvent>,
+    keys_written: u64,
+    keys_deleted: u64,
+    _token: TxToken,
+}
+
+impl<'a> PreparedTx<'a> {
+    /// Commit the prepared transaction by switching the meta page, the second phase of a
+    /// two-phase commit.
+    ///
+    /// Like [`TxMut::commit`], the observer is invoked and the change log is appended.
+    #[inline]
+    pub fn commit(self) -> Result<()> {
+        self.commit_then(|_| Ok(()))
+    }
+
+    /// Commits the prepared transaction, and calls `then` before any other read-write
+    /// transaction can commit, e.g., to take a snapshot of the state right after the commit.
+    pub(crate) fn commit_then<T>(self, then: impl FnOnce(&'a ThetaDB) -> Result<T>) -> Result<T> {
+        let Self {
+            db,
+            prepared,
+            changes,
+            keys_written,
+            keys_deleted,
+            _token: token,
+        } = self;
+
+        let (free_ratio, value) = match prepared {
+            Some(prepared) => {
+                let committed = prepared.commit(db.options.force_sync, &changes, || {
+                    db.storage.write().unwrap()
+                })?;
+                (Some(committed.free_ratio), then(db))
+            }
+            // Nothing is committed, the coordinator is taken again to keep the others away.
+            None => {
+                let _coordinator = db.rw_coordinator.lock();
+                (None, then(db))
+            }
+        };
+        drop(token);
+
+        if let Some(threshold) = db.options.auto_compact && let Some(free_ratio) = free_ratio {
+            db.needs_compaction
+                .store(free_ratio > threshold, Ordering::Relaxed);
+        }
+
+        db.metrics.keys_written.add(keys_written);
+        db.metrics.keys_deleted.add(keys_deleted);
+
+        // All locks have been released at this point.
+        if let Some(observer) = &db.options.on_commit && !changes.is_empty() {
+            observer.notify(&changes);
+        }
+        value
+    }
+
+    /// Abort the prepared transaction, which is the same as dropping it.
+    #[inline]
+    pub fn abort(self) {}
+}
+
+/// Represents a read-write transaction that can be committed before its scope ends, see
+/// [`ThetaDB::update_scoped`](crate::ThetaDB::update_scoped).
+///
+/// Once committed, the write lock is released and the transaction becomes read-only, reading
+/// the state right after the commit. So a quick write followed by lots of reads does not block
+/// the other read-write transactions for longer than needed.
+pub struct ScopedTx<'a> {
+    db: &'a ThetaDB,
+    stage: Stage<'a>,
+}
+
+enum Stage<'a> {
+    Writable(Box<TxMut<'a>>),
+    Committed(Tx<'a>),
+    /// The commit failed, or the snapshot failed to be taken after it. There is nothing to read.
+    Ended,
+}
+
+impl<'a> ScopedTx<'a> {
+    #[inline]
+    pub(crate) fn new(db: &'a ThetaDB) -> Result<Self> {
+        Ok(Self {
+            db,
+            stage: Stage::Writable(Box::new(TxMut::new(db)?)),
+        })
+    }
+
+    /// The read-write transaction, `None` once it has been committed.
+    #[inline]
+    pub fn tx_mut(&mut self) -> Option<&mut TxMut<'a>> {
+        match &mut self.stage {
+            Stage::Writable(tx) => Some(tx),
+            _ => None,
+        }
+    }
+
+    /// The read-only transaction reading the state right after the commit, `None` before
+    /// committing.
+    #[inline]
+    pub fn tx(&self) -> Option<&Tx<'a>> {
+        match &self.stage {
+            Stage::Committed(tx) => Some(tx),
+            _ => None,
+        }
+    }
+
+    /// Check if the transaction has been committed, or has failed to.
+    #[inline]
+    pub fn is_committed(&self) -> bool {
+        !matches!(self.stage, Stage::Writable(_))
+    }
+
+    /// Commit the changes made so far and release the write lock, after which the transaction
+    /// is read-only. It does nothing if the transaction has been committed.
+    ///
+    /// The snapshot is taken before any other read-write transaction can commit, so the reads
+    /// afterwards see exactly the committed changes.
+    ///
+    /// If the commit fails, the changes are discarded and the reads afterwards fail with
+    /// [`ErrorCode::InputInvalid`](crate::ErrorCode).
+    pub fn commit_now(&mut self) -> Result<()> {
+        let Stage::Writable(tx) = mem::replace(&mut self.stage, Stage::Ended) else {
+            return Ok(());
+        };
+        let snapshot = tx.prepare()?.commit_then(Snapshot::new)?;
+        self.db.metrics.read_txs.add(1);
+        self.stage = Stage::Committed(Tx { snapshot });
+        Ok(())
+    }
+
+    /// Check if the ThetaDB contains a given key.
+    #[inline]
+    pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        match &self.stage {
+            Stage::Writable(tx) => tx.contains(key),
+            Stage::Committed(tx) => tx.contains(key),
+            Stage::Ended => Err(InputInvalid::TxEnded.into()),
+        }
+    }
+
+    /// Get the value associated with a given key.
+    #[inline]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        match &self.stage {
+            Stage::Writable(tx) => tx.get(key),
+            Stage::Committed(tx) => tx.get(key),
+            Stage::Ended => Err(InputInvalid::TxEnded.into()),
+        }
+    }
+}
+
 /// Represents a cursor for navigating through the ThetaDB.
-pub struct CursorTx<'a>(Cursor<Readonly<'a>>);
+///
+/// Like the read-only transaction, it reads a consistent view of the ThetaDB and does not block
+/// read-write transactions from committing. Only its position is kept between the moves, the
+/// storage is read shared by each of them.
+///
+/// The position is the path from the root to the current record, so stepping does not descend
+/// from the root again. A step reads the current leaf, and climbs up only when it passes the
+/// edge of a subtree, which costs an amortized constant number of page reads. Therefore, the
+/// records under a subtree (e.g., all keys sharing a prefix) are iterated efficiently by seeking
+/// to the first of them once and then stepping, see `benches/cursor.rs` for the comparison with
+/// seeking every key from the root.
+pub struct CursorTx<'a> {
+    snapshot: Snapshot<'a>,
+    /// The track of the cursor, taken out by each operation and put back afterwards.
+    track: Cell<Track>,
+    /// The buffer the values are read into by `value_ref`, reused across the moves.
+    buf: Cell<Vec<u8>>,
+}
 
 impl<'a> CursorTx<'a> {
     /// Start a cursor transaction.
     pub fn new(db: &'a ThetaDB) -> Result<Self> {
-        let storage = db.storage.read().unwrap();
-        let bptree = Readonly::new(storage).map(BPTree::new).map(Cursor::new)?;
-        Ok(Self(bptree))
+        let snapshot = Snapshot::new(db)?;
+        db.metrics.read_txs.add(1);
+        Ok(Self::with_snapshot(snapshot))
+    }
+
+    /// Creates a cursor reading the snapshot, which points to nothing.
+    #[inline]
+    pub(crate) fn with_snapshot(snapshot: Snapshot<'a>) -> Self {
+        Self {
+            snapshot,
+            track: Cell::new(Track::new()),
+            buf: Cell::default(),
+        }
     }
 
     /// Gets the key of the current record pointed by the cursor.
     #[inline]
     pub fn key(&self) -> Result<Option<Vec<u8>>> {
-        self.0.key().map_err(Into::into)
+        self.with_cursor(|c| c.key())
     }
 
     /// Gets the value of the current record pointed by the cursor.
     #[inline]
     pub fn value(&self) -> Result<Option<Vec<u8>>> {
-        self.0.value().map_err(Into::into)
+        self.with_cursor(|c| c.value())
+    }
+
+    /// Passes the value of the current record pointed by the cursor to the closure, returns
+    /// `None` if the cursor does not point to a record.
+    ///
+    /// The value is read into a buffer kept by the cursor and reused across the moves, which
+    /// makes scans that only inspect the values (e.g., summing or filtering) free of allocations
+    /// once the buffer has grown to fit them. The closure runs after the storage is released,
+    /// so it is free to access the ThetaDB, e.g., to write what it has read.
+    pub fn value_ref<T>(&self, f: impl FnOnce(&[u8]) -> T) -> Result<Option<T>> {
+        let mut buf = self.buf.take();
+        let res = self
+            .with_cursor(|c| c.value_into(&mut buf))
+            .map(|found| found.then(|| f(&buf)));
+        self.buf.set(buf);
+        res
+    }
+
+    /// Gets the value of the current record pointed by the cursor into the buffer, which is
+    /// cleared first. Returns whether the cursor points to a record.
+    ///
+    /// Reusing the buffer while moving the cursor saves the allocations of reading large values.
+    #[inline]
+    pub fn value_into(&self, buf: &mut Vec<u8>) -> Result<bool> {
+        self.with_cursor(|c| c.value_into(buf))
     }
 
     /// Gets the key-value pair of the current record pointed by the cursor.
     #[inline]
     pub fn key_value(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
-        self.0.key_value().map_err(Into::into)
+        self.with_cursor(|c| c.key_value())
+    }
+
+    /// Estimates the position of the current record among all records, from 0.0 at the first
+    /// one towards 1.0 at the last one, e.g., to show the progress of a scan. `None` if the
+    /// cursor points to nothing.
+    ///
+    /// The estimate is derived from the path to the record without counting the records, so it
+    /// is cheap but may be off if the B+ tree is unbalanced in size.
+    #[inline]
+    pub fn approx_rank(&self) -> Result<Option<f64>> {
+        self.with_cursor(|c| c.approx_rank())
     }
 
     /// Moves the cursor to the first record.
     #[inline]
     pub fn first(&mut self) -> Result<bool> {
-        self.0.first().map_err(Into::into)
+        self.with_cursor(|c| c.first())
     }
 
     /// Moves the cursor to the last record.
     #[inline]
     pub fn last(&mut self) -> Result<bool> {
-        self.0.last().map_err(Into::into)
+        self.with_cursor(|c| c.last())
     }
 
     /// Moves the cursor to the specific record with the given key.
     #[inline]
     pub fn seek(&mut self, key: &[u8]) -> Result<bool> {
-        self.0.seek(key).map_err(Into::into)
+        self.with_cursor(|c| c.seek(key))
+    }
+
+    /// Moves the cursor to the record with the given key, or to the first record after it if
+    /// there is no such record, and tells which one it lands on.
+    ///
+    /// Unlike [`seek`](Self::seek), it leaves the cursor pointing to nothing only if all keys are
+    /// smaller than the given one, i.e., [`SeekResult::NotFound`].
+    pub fn seek_result(&mut self, key: &[u8]) -> Result<SeekResult> {
+        self.with_cursor(|c| {
+            if !c.seek_bound(Bound::Included(key), true)? {
+                return Ok(SeekResult::NotFound);
+            }
+            Ok(match c.key()? {
+                Some(found) if found == key => SeekResult::Exact,
+                Some(found) => SeekResult::Positioned(found),
+                None => SeekResult::NotFound,
+            })
+        })
+    }
+
+    /// Moves the cursor to the record with the largest key not greater than the given one, e.g.,
+    /// to walk backward from a key with [`prev`](Self::prev). Returns false if all keys are
+    /// greater than the given one, in which case the cursor points to nothing.
+    #[inline]
+    pub fn seek_for_prev(&mut self, key: &[u8]) -> Result<bool> {
+        self.seek_bound(Bound::Included(key), false)
+    }
+
+    /// Moves the cursor to the first record whose key starts with the given prefix. Returns
+    /// false if there is no such record, in which case the cursor points to nothing.
+    pub(crate) fn seek_prefix(&mut self, prefix: &[u8]) -> Result<bool> {
+        let found = self.with_cursor(|c| {
+            Ok(c.seek_bound(Bound::Included(prefix), true)?
+                && c.key()?.is_some_and(|key| key.starts_with(prefix)))
+        })?;
+        if !found {
+            self.track.set(Track::new());
+        }
+        Ok(found)
     }
 
     /// Moves the cursor to the next record.
     #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn next(&mut self) -> Result<bool> {
-        self.0.step(true).map_err(Into::into)
+        self.with_cursor(|c| c.step(true))
     }
 
     /// Moves the cursor to the previous record.
     #[inline]
     pub fn prev(&mut self) -> Result<bool> {
-        self.0.step(false).map_err(Into::into)
+        self.with_cursor(|c| c.step(false))
+    }
+
+    /// Forks a new cursor pointing to the same record, which then moves independently.
+    ///
+    /// The forked cursor reads the same view of the ThetaDB, so both of them see the same data.
+    #[inline]
+    pub fn fork(&self) -> Result<CursorTx<'a>> {
+        let track = self.track.take();
+        self.track.set(track.clone());
+        Ok(Self {
+            snapshot: self.snapshot.clone(),
+            track: Cell::new(track),
+            buf: Cell::default(),
+        })
+    }
+
+    /// Converts the cursor into an iterator over the key-value pairs, starting from the current
+    /// record and moving forward.
+    #[inline]
+    pub fn iter(self) -> CursorIter<'a> {
+        CursorIter::new(Ok(self))
+    }
+
+    /// Converts the cursor into an iterator over the key-value pairs, starting from the current
+    /// record and moving backward, e.g., from [`ThetaDB::last_cursor`].
+    #[inline]
+    pub fn iter_rev(self) -> CursorIter<'a> {
+        CursorIter::new_rev(Ok(self))
+    }
+
+    /// Moves the cursor to the first record satisfying the start bound if `forward`, otherwise
+    /// to the last record satisfying the end bound.
+    #[inline]
+    fn seek_bound(&mut self, bound: Bound<&[u8]>, forward: bool) -> Result<bool> {
+        self.with_cursor(|c| c.seek_bound(bound, forward))
+    }
+
+    #[inline]
+    fn entry_location(&self) -> Option<Location> {
+        let track = self.track.take();
+        let location = track.last().cloned();
+        self.track.set(track);
+        location
+    }
+
+    /// Rebuilds the cursor from the track to perform an operation, the storage is only read
+    /// shared during it.
+    fn with_cursor<T>(
+        &self,
+        f: impl FnOnce(&mut Cursor<Readonly<'a>>) -> mapping::Result<T>,
+    ) -> Result<T> {
+        let mut cursor = Cursor::new(self.snapshot.bptree()?, self.track.take());
+        let res = f(&mut cursor);
+        self.track.set(cursor.into_track());
+        res.map_err(Into::into)
+    }
+}
+
+/// A cursor over the records of a read-write transaction, created by [`TxMut::cursor_mut`].
+///
+/// It reads the changes made by the transaction so far, and can delete the record it points
+/// to. Deleting restructures the pages (e.g., the leaves are merged and copied), so the next
+/// move finds its record by the deleted key rather than by the position: `next` lands on the
+/// record right after the deleted one, and `prev` on the one right before it.
+pub struct CursorMut<'t, 'a> {
+    tx: &'t mut TxMut<'a>,
+    track: Track,
+    /// The key of the record deleted by the cursor, from which the next move starts.
+    deleted: Option<Vec<u8>>,
+}
+
+impl<'t, 'a> CursorMut<'t, 'a> {
+    /// Gets the key of the current record pointed by the cursor.
+    #[inline]
+    pub fn key(&mut self) -> Result<Option<Vec<u8>>> {
+        self.with_cursor(|c| c.key())
+    }
+
+    /// Gets the value of the current record pointed by the cursor.
+    #[inline]
+    pub fn value(&mut self) -> Result<Option<Vec<u8>>> {
+        self.with_cursor(|c| c.value())
+    }
+
+    /// Gets the key-value pair of the current record pointed by the cursor.
+    #[inline]
+    pub fn key_value(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.with_cursor(|c| c.key_value())
+    }
+
+    /// Moves the cursor to the first record.
+    #[inline]
+    pub fn first(&mut self) -> Result<bool> {
+        self.deleted = None;
+        self.with_cursor(|c| c.first())
+    }
+
+    /// Moves the cursor to the last record.
+    #[inline]
+    pub fn last(&mut self) -> Result<bool> {
+        self.deleted = None;
+        self.with_cursor(|c| c.last())
+    }
+
+    /// Moves the cursor to the specific record with the given key.
+    #[inline]
+    pub fn seek(&mut self, key: &[u8]) -> Result<bool> {
+        self.deleted = None;
+        self.with_cursor(|c| c.seek(key))
+    }
+
+    /// Moves the cursor to the next record, which is the one right after the deleted record if
+    /// the cursor has just deleted one.
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn next(&mut self) -> Result<bool> {
+        self.step(true)
+    }
+
+    /// Moves the cursor to the previous record, which is the one right before the deleted
+    /// record if the cursor has just deleted one.
+    #[inline]
+    pub fn prev(&mut self) -> Result<bool> {
+        self.step(false)
+    }
+
+    /// Deletes the current record, returns false if the cursor points to nothing.
+    ///
+    /// The cursor points to nothing afterwards, until it is moved.
+    pub fn delete_current(&mut self) -> Result<bool> {
+        let Some(key) = self.key()? else {
+            return Ok(false);
+        };
+        self.tx.delete(&key)?;
+        self.track.clear();
+        self.deleted = Some(key);
+        Ok(true)
+    }
+
+    fn step(&mut self, forward: bool) -> Result<bool> {
+        match self.deleted.take() {
+            Some(key) => self.with_cursor(|c| c.seek_bound(Bound::Excluded(&key), forward)),
+            None => self.with_cursor(|c| c.step(forward)),
+        }
+    }
+
+    /// Rebuilds the cursor from the track to perform an operation.
+    fn with_cursor<T>(
+        &mut self,
+        f: impl FnOnce(&mut Cursor<&ReadWrite<'a>>) -> mapping::Result<T>,
+    ) -> Result<T> {
+        let bptree = BPTree::new(self.tx.bptree.as_index());
+        let mut cursor = Cursor::new(bptree, mem::take(&mut self.track));
+        let res = f(&mut cursor);
+        self.track = cursor.into_track();
+        res.map_err(Into::into)
+    }
+}
+
+/// Where the cursor lands on seeking a key, see [`CursorTx::seek_result`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeekResult {
+    /// The cursor points to the record with the given key.
+    Exact,
+    /// The key is absent, and the cursor points to the first record after it, whose key is
+    /// carried.
+    Positioned(Vec<u8>),
+    /// All keys are smaller than the given one, and the cursor points to nothing.
+    NotFound,
+}
+
+/// An iterator over the key-value pairs of a cursor, created by [`CursorTx::iter`] or
+/// [`CursorTx::iter_rev`].
+///
+/// Like the cursor, it does not block read-write transactions from committing. Once an error
+/// is yielded (e.g., a corrupted page is met), the iterator ends and keeps returning `None`.
+pub struct CursorIter<'a> {
+    state: IterState<'a>,
+    forward: bool,
+}
+
+enum IterState<'a> {
+    Cursor(CursorTx<'a>),
+    Failed(Error),
+    Ended,
+}
+
+impl<'a> CursorIter<'a> {
+    /// Creates an iterator moving forward from the cursor.
+    #[inline]
+    pub(crate) fn new(cursor: Result<CursorTx<'a>>) -> Self {
+        Self::with_direction(cursor, true)
+    }
+
+    /// Creates an iterator moving backward from the cursor.
+    #[inline]
+    pub(crate) fn new_rev(cursor: Result<CursorTx<'a>>) -> Self {
+        Self::with_direction(cursor, false)
+    }
+
+    #[inline]
+    fn with_direction(cursor: Result<CursorTx<'a>>, forward: bool) -> Self {
+        let state = match cursor {
+            Ok(cursor) => IterState::Cursor(cursor),
+            Err(err) => IterState::Failed(err),
+        };
+        Self { state, forward }
+    }
+}
+
+impl Iterator for CursorIter<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match mem::replace(&mut self.state, IterState::Ended) {
+            IterState::Cursor(mut cursor) => {
+                let key_value = match cursor.key_value() {
+                    Ok(key_value) => key_value?,
+                    Err(err) => return Some(Err(err)),
+                };
+                // The failure of moving is yielded by the next call.
+                let moved = if self.forward {
+                    cursor.next()
+                } else {
+                    cursor.prev()
+                };
+                self.state = match moved {
+                    Ok(true) => IterState::Cursor(cursor),
+                    Ok(false) => IterState::Ended,
+                    Err(err) => IterState::Failed(err),
+                };
+                Some(Ok(key_value))
+            }
+            IterState::Failed(err) => Some(Err(err)),
+            IterState::Ended => None,
+        }
     }
 }
+
+/// A double-ended iterator over the key-value pairs whose keys fall within a range, created by
+/// [`ThetaDB::range`].
+///
+/// It yields the pairs in ascending order by key from the front, and in descending order from
+/// the back, until both ends meet. Like the cursor, it does not block read-write transactions
+/// from committing. Once an error is yielded, the iterator ends.
+pub struct RangeIter<'a>(RangeState<'a>);
+
+enum RangeState<'a> {
+    /// The cursors pointing to the next records to yield from each end, which read the same
+    /// view of the ThetaDB.
+    Cursors {
+        front: CursorTx<'a>,
+        back: CursorTx<'a>,
+    },
+    Failed(Error),
+    Ended,
+}
+
+impl<'a> RangeIter<'a> {
+    pub(crate) fn new(db: &'a ThetaDB, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Self {
+        let cursors = || -> Result<_> {
+            let mut front = CursorTx::new(db)?;
+            let mut back = front.fork()?;
+            if !front.seek_bound(start, true)? || !back.seek_bound(end, false)? {
+                return Ok(None);
+            }
+
+            // The range is empty if the ends have crossed already.
+            Ok(match (front.key()?, back.key()?) {
+                (Some(first), Some(last)) if first <= last => Some((front, back)),
+                _ => None,
+            })
+        };
+
+        match cursors() {
+            Ok(Some((front, back))) => Self(RangeState::Cursors { front, back }),
+            Ok(None) => Self(RangeState::Ended),
+            Err(err) => Self(RangeState::Failed(err)),
+        }
+    }
+
+    fn step(&mut self, forward: bool) -> Option<Result<(Vec<u8>, Vec<u8>)>> {
+        match mem::replace(&mut self.0, RangeState::Ended) {
+            RangeState::Cursors {
+                mut front,
+                mut back,
+            } => {
+                let cursor = if forward { &mut front } else { &mut back };
+                let key_value = match cursor.key_value() {
+                    Ok(key_value) => key_value?,
+                    Err(err) => return Some(Err(err)),
+                };
+
+                // Both ends meet at the last record to yield.
+                if front.entry_location() == back.entry_location() {
+                    return Some(Ok(key_value));
+                }
+
+                // The failure of moving is yielded by the next call.
+                let moved = if forward { front.next() } else { back.prev() };
+                self.0 = match moved {
+                    Ok(true) => RangeState::Cursors { front, back },
+                    Ok(false) => RangeState::Ended,
+                    Err(err) => RangeState::Failed(err),
+                };
+                Some(Ok(key_value))
+            }
+            RangeState::Failed(err) => Some(Err(err)),
+            RangeState::Ended => None,
+        }
+    }
+}
+
+impl FusedIterator for CursorIter<'_> {}
+
+impl Iterator for RangeIter<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(true)
+    }
+}
+
+impl DoubleEndedIterator for RangeIter<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(false)
+    }
+}
+
+impl FusedIterator for RangeIter<'_> {}
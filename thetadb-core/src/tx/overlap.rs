@@ -0,0 +1,114 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use thiserror::Error;
+
+use crate::ThetaDB;
+
+thread_local! {
+    /// The transactions holding the storage on the current thread, keyed by the address of the
+    /// ThetaDB.
+    static ALIVE_TXS: RefCell<HashMap<usize, TxKind>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TxKind {
+    /// A reader holding the storage shared until it is dropped.
+    Shared,
+    /// A read-write transaction.
+    Writable,
+}
+
+#[derive(Error, Debug)]
+#[error("the transaction overlaps with another one alive on the same thread, which would deadlock")]
+pub(crate) struct TxOverlapped;
+
+/// Registers a transaction holding the storage on the current thread until it is dropped.
+///
+/// A read-write transaction waits for the other read-write transaction to finish when starting,
+/// and for the readers holding the storage (i.e., the read sessions and the debugger) to finish
+/// when committing. Meanwhile, the committing one is queued for the storage, which blocks any
+/// further read of it. So the overlapping ones on the same thread would wait for each other
+/// forever, and at most one of them can be alive on a thread, the others are rejected instead.
+///
+/// For the same reason, the reads of the storage fail while a reader holding it is alive on
+/// the thread, see [`check_read`](Self::check_read). The read-only transactions and cursors
+/// only hold the storage while reading, so they are free to overlap with a read-write
+/// transaction, whose writers never queue for the storage.
+pub(crate) struct TxToken {
+    db: usize,
+}
+
+impl TxToken {
+    /// Registers a reader holding the storage shared, fails if any other transaction is alive.
+    pub(crate) fn shared(db: &ThetaDB) -> Result<Self, TxOverlapped> {
+        Self::register(db, TxKind::Shared)
+    }
+
+    /// Registers a read-write transaction, fails if any other transaction is alive.
+    pub(crate) fn writable(db: &ThetaDB) -> Result<Self, TxOverlapped> {
+        Self::register(db, TxKind::Writable)
+    }
+
+    /// Checks if the storage can be read on the current thread, i.e., no reader holding it is
+    /// alive.
+    pub(crate) fn check_read(db: &ThetaDB) -> Result<(), TxOverlapped> {
+        let db = db as *const ThetaDB as usize;
+        ALIVE_TXS.with(|txs| {
+            let txs = txs.borrow();
+            match txs.get(&db) {
+                Some(TxKind::Shared) => Err(TxOverlapped),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn register(db: &ThetaDB, kind: TxKind) -> Result<Self, TxOverlapped> {
+        let db = db as *const ThetaDB as usize;
+        ALIVE_TXS.with(|txs| match txs.borrow_mut().entry(db) {
+            Entry::Occupied(_) => Err(TxOverlapped),
+            Entry::Vacant(entry) => {
+                entry.insert(kind);
+                Ok(Self { db })
+            }
+        })
+    }
+}
+
+impl Drop for TxToken {
+    fn drop(&mut self) {
+        // The thread-local storage may have been destroyed if the thread is exiting.
+        _ = ALIVE_TXS.try_with(|txs| txs.borrow_mut().remove(&self.db));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TxToken;
+    use crate::ThetaDB;
+
+    #[test]
+    fn test_tx_token() {
+        let path = "target/test_tx_token.theta";
+        let db = ThetaDB::open(path).unwrap();
+
+        let shared = TxToken::shared(&db).unwrap();
+        assert!(TxToken::shared(&db).is_err());
+        assert!(TxToken::writable(&db).is_err());
+        assert!(TxToken::check_read(&db).is_err());
+        drop(shared);
+        assert!(TxToken::check_read(&db).is_ok());
+
+        let writable = TxToken::writable(&db).unwrap();
+        assert!(TxToken::shared(&db).is_err());
+        assert!(TxToken::writable(&db).is_err());
+        assert!(TxToken::check_read(&db).is_ok());
+        drop(writable);
+        assert!(TxToken::shared(&db).is_ok());
+
+        drop(db);
+        _ = std::fs::remove_file(path);
+    }
+}
@@ -1,29 +1,35 @@
-use std::sync::RwLockReadGuard;
-
 use crate::{
     bptree::TreeIndex,
     medium::mapping,
-    meta::{MetaPage, PageIndex},
-    storage::{Page, PageId, Storage},
+    meta::{Meta, PageIndex},
+    storage::{Page, PageId, SharedStorage},
 };
 
 pub(crate) struct Readonly<'a> {
     page_index: PageIndex,
-    storage: RwLockReadGuard<'a, Storage>,
+    storage: SharedStorage<'a>,
 }
 
 impl<'a> Readonly<'a> {
     #[inline]
-    pub(crate) fn new(storage: RwLockReadGuard<'a, Storage>) -> mapping::Result<Self> {
-        let page_index = storage
-            .page::<MetaPage<_>>(PageIndex::META)?
-            .page_index()
-            .clone();
+    pub(crate) fn new(storage: SharedStorage<'a>) -> mapping::Result<Self> {
+        let page_index = storage.meta()?.page_index().clone();
+        Ok(Self::pinned(storage, page_index))
+    }
+
+    /// The latest metadata, which may be newer than the page index being read.
+    #[inline]
+    pub(crate) fn meta(&self) -> mapping::Result<Meta> {
+        self.storage.meta().map(|m| m.clone())
+    }
 
-        Ok(Self {
+    /// Reads the B+ Tree with the given page index rather than the latest committed one.
+    #[inline]
+    pub(crate) fn pinned(storage: SharedStorage<'a>, page_index: PageIndex) -> Self {
+        Self {
             page_index,
             storage,
-        })
+        }
     }
 }
 
@@ -2,24 +2,31 @@ use std::{
     cell::RefCell,
     collections::{hash_map, HashMap},
     iter,
-    sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard},
+    sync::{Mutex, RwLockWriteGuard},
 };
 
 use crate::{
-    bptree::{TreeIndex, TreeIndexMut},
+    bptree::{BloomShape, TreeIndex, TreeIndexMut},
     chunk::Chunk,
     db::TxCoordinator,
     freelist::Freelist,
     medium::{mapping, mempool::MemoryCell},
-    meta::{MetaPage, PageIndex},
-    storage::{Page, PageId, Storage},
+    meta::PageIndex,
+    metrics::{Counter, Counters},
+    storage::{Page, PageId, SharedStorage, Storage},
+    trace,
+    tx::{ChangeEvent, Snapshots},
     Result,
 };
 
 pub(crate) struct ReadWrite<'a> {
     context: RefCell<Context>,
-    coordinator: MutexGuard<'a, TxCoordinator>,
-    storage: RwLockReadGuard<'a, Storage>,
+    coordinator: parking_lot::MutexGuard<'a, TxCoordinator>,
+    storage: SharedStorage<'a>,
+    snapshots: &'a Mutex<Snapshots>,
+    metrics: &'a Counters,
+    bloom_shape: BloomShape,
+    underflow_ratio: f64,
 }
 
 type DirtyPages = HashMap<PageId, DirtyPage>;
@@ -32,16 +39,33 @@ enum DirtyPage {
 struct Context {
     page_index: PageIndex,
     freelist: Freelist,
+    /// The free pages that are still referenced by snapshots, which are taken out of the
+    /// freelist during the transaction and put back on commit.
+    reserved: Vec<PageId>,
     dirty_pages: DirtyPages,
+    /// The number of pages allocated and the number of committed pages deleted, counted into
+    /// the metrics once committed.
+    allocated_count: u64,
+    deleted_count: u64,
+    /// Whether to allocate the free pages with the smallest ids, so that the end of the file
+    /// can be cut off.
+    shrinking: bool,
 }
 
 impl Context {
     #[inline]
-    fn new(page_index: PageIndex, freelist: Freelist) -> Self {
+    fn new(page_index: PageIndex, mut freelist: Freelist, reserved: Vec<PageId>) -> Self {
+        for id in &reserved {
+            freelist.remove(*id);
+        }
         Self {
             page_index,
             freelist,
+            reserved,
             dirty_pages: DirtyPages::new(),
+            allocated_count: 0,
+            deleted_count: 0,
+            shrinking: false,
         }
     }
 
@@ -71,9 +95,20 @@ impl Context {
 
     #[inline]
     fn alloc_id(&mut self) -> PageId {
-        self.freelist
-            .take(1)
-            .unwrap_or_else(|| self.page_index.next.incr())
+        self.allocated_count += 1;
+        let id = if self.shrinking {
+            self.freelist.take_first()
+        } else {
+            self.freelist.take(1)
+        };
+        let id = id.unwrap_or_else(|| self.page_index.next.incr());
+        trace::event!(
+            TRACE,
+            "alloc page",
+            page_id = id.raw(),
+            page_count = self.page_index.page_count()
+        );
+        id
     }
 
     fn alloc(&mut self, memcell: MemoryCell) -> (PageId, &mut MemoryCell) {
@@ -94,19 +129,66 @@ impl Context {
     }
 
     fn delete(&mut self, id: PageId) {
+        trace::event!(TRACE, "delete page", page_id = id.raw());
         if let Some(dirty_page) = self.dirty_pages.remove(&id) {
             match dirty_page {
                 DirtyPage::Allocated { .. } => self.freelist.free(id, 1),
                 DirtyPage::Deleted => panic!("the page has been deleted"),
             }
         } else {
+            // Only the pages committed before are counted, not the ones allocated and deleted
+            // within the transaction, which never take effect.
+            self.deleted_count += 1;
             self.dirty_pages.insert(id, DirtyPage::Deleted);
         }
     }
 
+    /// Gives the free pages at the end of the file back, so that the file can be shrunk.
+    #[inline]
+    fn take_trailing_free_pages(&mut self) -> u32 {
+        self.shrinking = true;
+        let count = self.freelist.take_trailing(self.page_index.next);
+        self.page_index.next = (self.page_index.next.raw() - count).into();
+        count
+    }
+
+    /// Checks if there is nothing to commit, i.e., no page is dirty, the page index is
+    /// untouched, and the freelist is not being moved for shrinking.
+    #[inline]
+    fn is_unchanged(&self, storage: &Storage) -> mapping::Result<bool> {
+        Ok(!self.shrinking
+            && self.dirty_pages.is_empty()
+            && &self.page_index == storage.meta()?.page_index())
+    }
+
+    /// Deletes the old freelist and allocates the pages for the new one, returns the ids of
+    /// them and the length of the freelist to be stored in them.
+    fn realloc_freelist(&mut self, storage: &Storage) -> mapping::Result<(Vec<PageId>, u32)> {
+        // Delete the old freelist first, so that its pages are counted in the new one.
+        Chunk::delete(
+            self.page_index.freelist,
+            |id| storage.page(id),
+            |id| Ok(self.delete(id)),
+        )?;
+
+        // Alloc new pages for freelist.
+        let freelist_len = self.freelist_len();
+        let freelist_ids = iter::repeat_with(|| self.alloc_id())
+            .take(Chunk::count(freelist_len, storage.page_size()) as usize)
+            .collect::<Vec<_>>();
+
+        // Update freelist id, which refers to the last chunk of the chain.
+        self.page_index.freelist = *freelist_ids
+            .last()
+            .expect("should have at least one page to store freelist");
+
+        Ok((freelist_ids, freelist_len))
+    }
+
     #[inline]
     fn freelist_len(&self) -> u32 {
         self.deleted_pages()
+            .chain(self.reserved.iter().cloned())
             .max()
             .map(Freelist::bytes_len_for_storing)
             .unwrap_or(0)
@@ -117,66 +199,88 @@ impl Context {
 impl<'a> ReadWrite<'a> {
     #[inline]
     pub(crate) fn new(
-        coordinator: MutexGuard<'a, TxCoordinator>,
-        storage: RwLockReadGuard<'a, Storage>,
+        coordinator: parking_lot::MutexGuard<'a, TxCoordinator>,
+        storage: SharedStorage<'a>,
+        snapshots: &'a Mutex<Snapshots>,
+        metrics: &'a Counters,
+        bloom_bits_per_key: u8,
+        underflow_ratio: f64,
     ) -> mapping::Result<Self> {
-        let page_index = storage
-            .page::<MetaPage<_>>(PageIndex::META)?
-            .page_index()
-            .clone();
+        let meta = storage.meta()?;
+        let page_index = meta.page_index().clone();
 
         let freelist = Chunk::read(page_index.freelist, |id| storage.page(id))
             .map(|bytes| Freelist::from_bytes(&bytes))?;
 
-        let context = Context::new(page_index, freelist);
+        let reserved = snapshots.lock().unwrap().reserved();
+        trace::event!(
+            DEBUG,
+            "begin read-write transaction",
+            txid = meta.txid() + 1,
+            page_count = page_index.page_count(),
+            free_pages = freelist.len(),
+            reserved_pages = reserved.len()
+        );
+        let context = Context::new(page_index, freelist, reserved);
+        let bloom_shape = BloomShape::new(bloom_bits_per_key, storage.page_size());
 
         Ok(Self {
             context: context.into(),
             coordinator,
             storage,
+            snapshots,
+            metrics,
+            bloom_shape,
+            underflow_ratio,
         })
     }
 
-    #[inline]
-    pub(crate) fn commit<F>(self, force_sync: bool, writable_storage: F) -> Result<()>
+    /// Writes the dirty pages and the freelist into storage, without switching the meta page,
+    /// so the committed state is untouched until the returned transaction is committed.
+    ///
+    /// Returns `None` if there is nothing to commit.
+    pub(crate) fn prepare<F>(
+        self,
+        force_sync: bool,
+        writable_storage: F,
+    ) -> Result<Option<Prepared<'a>>>
     where
         F: FnOnce() -> RwLockWriteGuard<'a, Storage>,
     {
-        // If there are no dirty pages, then nothing is required next.
-        if self.context.borrow().dirty_pages.is_empty() {
-            return Ok(());
+        if self.context.borrow().is_unchanged(&self.storage)? {
+            return Ok(None);
         }
 
         let mut context = self.context.into_inner();
+        let _span = trace::span!(
+            DEBUG,
+            "prepare",
+            dirty_pages = context.dirty_pages.len(),
+            page_count = context.page_index.page_count()
+        );
 
-        // Alloc new pages for freelist.
-        let freelist_len = context.freelist_len();
-        let freelist_ids = iter::repeat_with(|| context.alloc_id())
-            .take(Chunk::count(freelist_len, self.storage.page_size()) as usize)
-            .collect::<Vec<_>>();
-
-        // Update freelist id.
-        Chunk::delete(
-            context.page_index.freelist,
-            |id| self.storage.page(id),
-            |id| Ok(context.delete(id)),
-        )?;
-        context.page_index.freelist = *freelist_ids
-            .first()
-            .expect("should have at least one page to store freelist");
+        let (freelist_ids, freelist_len) = context.realloc_freelist(&self.storage)?;
 
         // Acquires the storage with write access.
         drop(self.storage);
         let mut storage = writable_storage();
 
+        // The deleted pages may still be referenced by snapshots, they are retired along with
+        // switching the meta page.
+        let deleted_pages = context.deleted_pages().collect();
+
         // Allocate enough space for storage.
+        fail_point()?;
         storage.allocate(context.page_index.page_count())?;
 
-        // Write dirty pages into storage.
+        // Write dirty pages into storage, along with the freelist pages.
+        let mut written_ids = freelist_ids.clone();
+        fail_point()?;
         for (id, dirty_page) in context.dirty_pages {
             match dirty_page {
                 DirtyPage::Allocated { memcell } => {
                     storage.copy_page_from_bytes(id, memcell.as_ref())?;
+                    written_ids.push(id);
                 }
                 DirtyPage::Deleted => {
                     context.freelist.free(id, 1);
@@ -185,37 +289,260 @@ impl<'a> ReadWrite<'a> {
         }
 
         // Write freelist into storage.
+        for id in context.reserved {
+            context.freelist.free(id, 1);
+        }
         context.freelist.resize(freelist_len as usize);
+        let free_ratio = context.freelist.len() as f64 / context.page_index.page_count() as f64;
 
         let freelist_bytes = context.freelist.into_bytes();
         let mut freelist_slice = freelist_bytes.as_slice();
+        fail_point()?;
 
         for (idx, id) in freelist_ids.iter().enumerate() {
             let mut chunk = storage.page_mut::<Chunk<_>>(*id)?;
-            let Some((remaining, next)) = chunk.assign(freelist_slice) else {
+            let prev = freelist_ids[idx.saturating_sub(1)];
+            let Some(remaining) = chunk.assign(freelist_slice, freelist_ids[0], idx as u32, prev)
+            else {
                 break;
             };
-
             freelist_slice = remaining;
-            if let Some(next_id) = (idx < freelist_ids.len() - 1).then(|| freelist_ids[idx + 1]) {
-                *next = next_id;
-            }
         }
 
+        // The data pages must reach the disk before the meta page refers to them. msync with
+        // `MS_SYNC` (or fdatasync) returns only after the writes complete, so it is the barrier
+        // between them. Only the written pages are synced, rather than the whole file.
+        fail_point()?;
+        storage.flush()?;
+        let written_pages = written_ids.len() as u64 + 1;
         if force_sync {
-            storage.sync()?;
+            fail_point()?;
+            storage.sync_pages(written_ids)?;
         }
+        trace::event!(
+            DEBUG,
+            "prepared",
+            written_pages,
+            freelist_pages = freelist_ids.len(),
+            allocated_pages = context.allocated_count,
+            deleted_pages = context.deleted_count
+        );
+
+        // The written pages are free in the committed state, which no snapshot references. So
+        // the storage is released for the readers, and the coordinator keeps the writers and
+        // the shrinking away until the meta page is switched.
+        Ok(Some(Prepared {
+            coordinator: self.coordinator,
+            snapshots: self.snapshots,
+            metrics: self.metrics,
+            page_index: context.page_index,
+            txid: storage.meta()?.txid() + 1,
+            deleted_pages,
+            written_pages,
+            allocated_count: context.allocated_count,
+            deleted_count: context.deleted_count,
+            free_ratio,
+        }))
+    }
+}
 
-        // Write meta into storage.
-        storage
-            .page_mut::<MetaPage<_>>(PageIndex::META)?
-            .set_page_index(context.page_index);
+/// How the pages would change if a read-write transaction were committed, see
+/// `ReadWrite::preview`.
+pub(crate) struct PagePreview {
+    /// The number of pages that would be written, including the meta page.
+    pub(crate) written_pages: u64,
+    pub(crate) allocated_pages: u64,
+    pub(crate) freed_pages: u64,
+    pub(crate) page_count: u32,
+    pub(crate) free_pages: u32,
+}
 
-        if force_sync {
-            storage.sync()?;
+impl<'a> ReadWrite<'a> {
+    /// Works out how the pages would change if the transaction were committed, by going
+    /// through the same steps as `prepare` in memory. Nothing is written, and the transaction
+    /// is rolled back as it is consumed.
+    pub(crate) fn preview(self) -> mapping::Result<PagePreview> {
+        let mut context = self.context.into_inner();
+        if context.is_unchanged(&self.storage)? {
+            return Ok(PagePreview {
+                written_pages: 0,
+                allocated_pages: 0,
+                freed_pages: 0,
+                page_count: context.page_index.page_count(),
+                free_pages: (context.freelist.len() + context.reserved.len()) as u32,
+            });
+        }
+
+        let (freelist_ids, freelist_len) = context.realloc_freelist(&self.storage)?;
+        let mut written_pages = freelist_ids.len() as u64 + 1;
+        for (id, dirty_page) in context.dirty_pages {
+            match dirty_page {
+                DirtyPage::Allocated { .. } => written_pages += 1,
+                DirtyPage::Deleted => context.freelist.free(id, 1),
+            }
+        }
+        for id in context.reserved {
+            context.freelist.free(id, 1);
+        }
+        context.freelist.resize(freelist_len as usize);
+
+        Ok(PagePreview {
+            written_pages,
+            allocated_pages: context.allocated_count,
+            freed_pages: context.deleted_count,
+            page_count: context.page_index.page_count(),
+            free_pages: context.freelist.len() as u32,
+        })
+    }
+}
+
+/// The transaction whose pages have been written into storage, waiting for the meta page to be
+/// switched. Dropping it aborts the transaction.
+pub(crate) struct Prepared<'a> {
+    coordinator: parking_lot::MutexGuard<'a, TxCoordinator>,
+    snapshots: &'a Mutex<Snapshots>,
+    metrics: &'a Counters,
+    page_index: PageIndex,
+    /// The id of the transaction, i.e., the generation it commits.
+    txid: u64,
+    deleted_pages: Vec<PageId>,
+    /// The number of pages written, including the meta page.
+    written_pages: u64,
+    allocated_count: u64,
+    deleted_count: u64,
+    free_ratio: f64,
+}
+
+/// The committed transaction, which keeps the other read-write transactions from committing
+/// until it is dropped.
+pub(crate) struct Committed<'a> {
+    _coordinator: parking_lot::MutexGuard<'a, TxCoordinator>,
+    /// The ratio of the free pages to all pages after the commit.
+    pub(crate) free_ratio: f64,
+}
+
+impl<'a> Prepared<'a> {
+    /// Appends the changes to the change log if any, and switches the meta page to the
+    /// written pages.
+    ///
+    /// The commit fails only before the meta page is switched, in which case nothing takes
+    /// effect. Once it is switched, the changes are visible, so failing to flush it afterwards
+    /// does not fail the commit. It is only traced, and the meta page is left to be synced
+    /// along with the whole file.
+    pub(crate) fn commit<F>(
+        self,
+        force_sync: bool,
+        changes: &[ChangeEvent],
+        writable_storage: F,
+    ) -> Result<Committed<'a>>
+    where
+        F: FnOnce() -> RwLockWriteGuard<'a, Storage>,
+    {
+        let mut coordinator = self.coordinator;
+        let _span = trace::span!(DEBUG, "commit", txid = self.txid);
+
+        // The changes are synced to the change log before the meta page is switched, so the
+        // log never misses a committed transaction. The coordinator keeps them in commit order.
+        let mut changelog = coordinator
+            .changelog
+            .as_mut()
+            .filter(|_| !changes.is_empty());
+        if let Some(changelog) = &mut changelog {
+            fail_point()?;
+            changelog.append(changes, self.txid)?;
+        }
+
+        let mut storage = writable_storage();
+        if let Err(err) = switch_meta(&mut storage, self.page_index, self.txid) {
+            if let Some(changelog) = changelog {
+                changelog.discard_last();
+            }
+            return Err(err);
+        }
+
+        // The deleted pages may still be referenced by snapshots. They are retired only once
+        // the meta page is switched, as they are still in use if the commit fails before.
+        self.snapshots.lock().unwrap().retire(self.deleted_pages);
+
+        // The data pages have been synced when preparing, so only the meta page is left.
+        match flush_meta(&mut storage, self.txid, force_sync) {
+            Ok(_) if force_sync => self
+                .metrics
+                .bytes_synced
+                .add(self.written_pages * storage.page_size() as u64),
+            Ok(_) => {}
+            Err(_err) => {
+                trace::event!(WARN, "failed to flush the meta page", error = %_err);
+            }
         }
+        self.metrics.pages_allocated.add(self.allocated_count);
+        self.metrics.pages_freed.add(self.deleted_count);
+        trace::event!(
+            DEBUG,
+            "committed",
+            written_pages = self.written_pages,
+            free_ratio = self.free_ratio
+        );
+
+        Ok(Committed {
+            _coordinator: coordinator,
+            free_ratio: self.free_ratio,
+        })
+    }
+}
+
+/// Writes meta into the older meta page, so the latest one is intact if the commit is torn.
+fn switch_meta(storage: &mut Storage, page_index: PageIndex, txid: u64) -> Result<()> {
+    let mut meta = storage.meta()?.clone();
+    meta.advance(page_index);
+    debug_assert_eq!(meta.txid(), txid, "the meta page is switched by others");
+    fail_point()?;
+    storage.set_meta(meta)?;
+    Ok(())
+}
 
-        Ok(())
+/// Flushes the meta page switched, and syncs it if `force_sync` is set.
+fn flush_meta(storage: &mut Storage, txid: u64, force_sync: bool) -> Result<()> {
+    fail_point()?;
+    storage.flush()?;
+    if force_sync {
+        fail_point()?;
+        storage.sync_page(PageIndex::meta_id(txid))?;
+    }
+    Ok(())
+}
+
+impl<'a> ReadWrite<'a> {
+    /// Gives the free pages at the end of the file back, returns the number of them.
+    ///
+    /// The pages still referenced by snapshots are not free, so they are kept. The pages
+    /// allocated afterwards, including the ones of the freelist, are taken from the start of
+    /// the file.
+    #[inline]
+    pub(crate) fn take_trailing_free_pages(&self) -> u32 {
+        self.context.borrow_mut().take_trailing_free_pages()
+    }
+
+    /// Checks if the freelist holds the last page of the file while there are free pages before
+    /// it. Committing moves the freelist into them then, so that its old pages can be cut off.
+    pub(crate) fn can_move_freelist(&self) -> mapping::Result<bool> {
+        let context = self.context.borrow();
+        if context.freelist.len() == 0 {
+            return Ok(false);
+        }
+        let last = Chunk::last_page(context.page_index.freelist, |id| self.storage.page(id))?;
+        Ok(last.raw() + 1 == context.page_index.next.raw())
+    }
+
+    #[inline]
+    pub(crate) fn user_version(&self) -> u32 {
+        self.context.borrow().page_index.user_version
+    }
+
+    /// Sets the user version, which is written to the meta page along with the page index.
+    #[inline]
+    pub(crate) fn set_user_version(&self, version: u32) {
+        self.context.borrow_mut().page_index.user_version = version;
     }
 }
 
@@ -244,6 +571,16 @@ impl<'a> TreeIndexMut for ReadWrite<'a> {
         self.storage.page_size()
     }
 
+    #[inline]
+    fn bloom_shape(&self) -> BloomShape {
+        self.bloom_shape
+    }
+
+    #[inline]
+    fn underflow_ratio(&self) -> f64 {
+        self.underflow_ratio
+    }
+
     #[inline]
     fn set_root_id(&self, id: PageId) {
         self.context.borrow_mut().page_index.root = id;
@@ -285,3 +622,119 @@ impl<'a> TreeIndexMut for ReadWrite<'a> {
         self.context.borrow_mut().delete(id);
     }
 }
+
+/// Fails the step of committing that follows it in tests, see `tests::FAIL_AFTER`.
+#[inline]
+fn fail_point() -> Result<()> {
+    #[cfg(test)]
+    if tests::fail_point_hit() {
+        let err = std::io::Error::other("injected failure");
+        return Err(crate::medium::file::Error::IO(err).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::BTreeMap, fs};
+
+    use crate::{Options, ThetaDB};
+
+    thread_local! {
+        /// The number of fail points passed before failing, `None` to never fail.
+        static FAIL_AFTER: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    pub(super) fn fail_point_hit() -> bool {
+        FAIL_AFTER.with(|fail_after| match fail_after.get() {
+            Some(0) => {
+                fail_after.set(None);
+                true
+            }
+            count => {
+                fail_after.set(count.map(|n| n - 1));
+                false
+            }
+        })
+    }
+
+    #[test]
+    fn test_commit_failure() {
+        let path = "target/test_commit_failure.theta";
+        let changelog_path = "target/test_commit_failure.changelog";
+        // The fail points before the one of switching the meta page, including the one of
+        // appending to the change log.
+        const STEPS_BEFORE_META: usize = 6;
+        _ = fs::remove_file(path);
+        _ = fs::remove_file(changelog_path);
+
+        let db = Options::new()
+            .force_sync(true)
+            .changelog_path(Some(changelog_path.into()))
+            .open(path)
+            .unwrap();
+        let page_size = db.page_size() as usize;
+
+        let mut expected = BTreeMap::new();
+        for i in 0..100u32 {
+            expected.insert(i.to_be_bytes().to_vec(), vec![i as u8; 100]);
+        }
+        db.extend(expected.clone()).unwrap();
+        let mut logged = 1;
+
+        for step in 0.. {
+            let (key, deleted_key, marker) = (1000 + step as u32, step as u32, 2000 + step as u32);
+            let value = vec![step as u8; page_size * 2];
+
+            // The snapshot makes the deleted pages retired instead of free.
+            let snapshot = db.snapshot().unwrap();
+            FAIL_AFTER.set(Some(step));
+            let res = db.update(|tx| {
+                tx.put(key.to_be_bytes(), &value)?;
+                tx.delete(deleted_key.to_be_bytes())
+            });
+            let failed = FAIL_AFTER.take().is_none();
+
+            // The commit fails only before the meta page is switched, and takes effect
+            // otherwise, even if flushing the meta page fails afterwards.
+            let committed = !failed || step > STEPS_BEFORE_META;
+            assert_eq!(res.is_ok(), committed, "failed at step {step}");
+            if committed {
+                expected.insert(key.to_be_bytes().to_vec(), value);
+                expected.remove(deleted_key.to_be_bytes().as_slice());
+                logged += 1;
+            }
+
+            // The instance stays usable, and the snapshot still reads the earlier state.
+            db.put(marker.to_be_bytes(), marker.to_be_bytes()).unwrap();
+            expected.insert(marker.to_be_bytes().to_vec(), marker.to_be_bytes().to_vec());
+            logged += 1;
+            assert!(snapshot.contains(deleted_key.to_be_bytes()).unwrap());
+            drop(snapshot);
+
+            let records = db.into_iter().collect::<crate::Result<BTreeMap<_, _>>>();
+            assert_eq!(records.unwrap(), expected, "failed at step {step}");
+
+            // The change log records exactly the committed transactions, in order.
+            let entries = db.changelog().unwrap();
+            assert_eq!(entries.len(), logged, "failed at step {step}");
+            assert!(entries.windows(2).all(|w| w[0].txid + 1 == w[1].txid));
+
+            if !failed {
+                assert!(step > STEPS_BEFORE_META + 1, "fail points are not reached");
+                break;
+            }
+        }
+        drop(db);
+
+        let report = ThetaDB::check(path, true).unwrap();
+        assert!(report.is_healthy(), "{:?}", report.problems);
+        let db = ThetaDB::open(path).unwrap();
+        let records = db.into_iter().collect::<crate::Result<BTreeMap<_, _>>>();
+        assert_eq!(records.unwrap(), expected);
+
+        drop(db);
+        _ = fs::remove_file(path);
+        _ = fs::remove_file(changelog_path);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    bptree::{BPTree, Cursor, Track},
+    metrics::Counter,
+    tx::{readonly::Readonly, TxToken},
+    Result, ThetaDB,
+};
+
+/// Represents a burst of reads sharing one acquisition of the storage, created by
+/// [`ThetaDB::read_session`](crate::ThetaDB::read_session).
+///
+/// Unlike the read-only transaction, which reads the storage shared for each operation, the
+/// session holds it until dropped, so the metadata is read once and the point lookups and
+/// cursors made from it take no lock. As a result, read-write transactions cannot commit while
+/// it is alive, so it suits the reads done at once (e.g., filling a screen) rather than being
+/// kept around.
+///
+/// A commit waiting for the session blocks any further read of the storage, so the other reads
+/// of the ThetaDB on the same thread (e.g., `ThetaDB::get`, transactions and cursors) fail with
+/// [`ErrorCode::TxOverlapped`](crate::ErrorCode) while it is alive, rather than waiting forever.
+/// Read through the session instead.
+pub struct ReadSession<'a> {
+    db: &'a ThetaDB,
+    index: Readonly<'a>,
+    _token: TxToken,
+}
+
+impl<'a> ReadSession<'a> {
+    /// Start a read session, fails if any other transaction is alive on the same thread.
+    pub(crate) fn new(db: &'a ThetaDB) -> Result<Self> {
+        let token = TxToken::shared(db)?;
+        let index = Readonly::new(db.storage.read().unwrap())?;
+        db.metrics.read_txs.add(1);
+        Ok(Self {
+            db,
+            index,
+            _token: token,
+        })
+    }
+
+    /// Check if the ThetaDB contains a given key.
+    #[inline]
+    pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree().contains(key.as_ref()).map_err(Into::into)
+    }
+
+    /// Get the value associated with a given key.
+    #[inline]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree().get(key.as_ref()).map_err(Into::into)
+    }
+
+    /// Get the value associated with a given key into the buffer, which is cleared first.
+    /// Returns whether the key exists.
+    #[inline]
+    pub fn get_into(&self, key: impl AsRef<[u8]>, buf: &mut Vec<u8>) -> Result<bool> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree()
+            .get_into(key.as_ref(), buf)
+            .map_err(Into::into)
+    }
+
+    /// Get a cursor of the session, which points to nothing until it is moved.
+    #[inline]
+    pub fn cursor(&self) -> SessionCursor<'_, 'a> {
+        SessionCursor {
+            cursor: Cursor::new(self.bptree(), Track::new()),
+        }
+    }
+
+    /// Get a cursor of the session pointing to the first record.
+    #[inline]
+    pub fn first_cursor(&self) -> Result<SessionCursor<'_, 'a>> {
+        let mut cursor = self.cursor();
+        cursor.first().map(|_| cursor)
+    }
+
+    /// Get a cursor of the session pointing to the last record.
+    #[inline]
+    pub fn last_cursor(&self) -> Result<SessionCursor<'_, 'a>> {
+        let mut cursor = self.cursor();
+        cursor.last().map(|_| cursor)
+    }
+
+    /// Get a cursor of the session pointing to the specific record with the given key.
+    #[inline]
+    pub fn cursor_from_key(&self, key: &[u8]) -> Result<SessionCursor<'_, 'a>> {
+        let mut cursor = self.cursor();
+        cursor.seek(key).map(|_| cursor)
+    }
+
+    #[inline]
+    fn bptree(&self) -> BPTree<&Readonly<'a>> {
+        BPTree::new(&self.index)
+    }
+}
+
+/// A cursor created by [`ReadSession`], which reads the storage held by the session.
+///
+/// It moves like [`CursorTx`](crate::CursorTx), but it is cheaper to create and move as no lock
+/// is taken, and it cannot outlive the session.
+pub struct SessionCursor<'s, 'a> {
+    cursor: Cursor<&'s Readonly<'a>>,
+}
+
+impl SessionCursor<'_, '_> {
+    /// Gets the key of the current record pointed by the cursor.
+    #[inline]
+    pub fn key(&self) -> Result<Option<Vec<u8>>> {
+        self.cursor.key().map_err(Into::into)
+    }
+
+    /// Gets the value of the current record pointed by the cursor.
+    #[inline]
+    pub fn value(&self) -> Result<Option<Vec<u8>>> {
+        self.cursor.value().map_err(Into::into)
+    }
+
+    /// Passes the value of the current record pointed by the cursor to the closure, returns
+    /// `None` if the cursor does not point to a record, see [`CursorTx::value_ref`].
+    ///
+    /// [`CursorTx::value_ref`]: crate::CursorTx::value_ref
+    #[inline]
+    pub fn value_ref<T>(&self, f: impl FnOnce(&[u8]) -> T) -> Result<Option<T>> {
+        self.cursor.value_ref(f).map_err(Into::into)
+    }
+
+    /// Gets the key-value pair of the current record pointed by the cursor.
+    #[inline]
+    pub fn key_value(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.cursor.key_value().map_err(Into::into)
+    }
+
+    /// Moves the cursor to the first record.
+    #[inline]
+    pub fn first(&mut self) -> Result<bool> {
+        self.cursor.first().map_err(Into::into)
+    }
+
+    /// Moves the cursor to the last record.
+    #[inline]
+    pub fn last(&mut self) -> Result<bool> {
+        self.cursor.last().map_err(Into::into)
+    }
+
+    /// Moves the cursor to the specific record with the given key.
+    #[inline]
+    pub fn seek(&mut self, key: &[u8]) -> Result<bool> {
+        self.cursor.seek(key).map_err(Into::into)
+    }
+
+    /// Moves the cursor to the next record.
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn next(&mut self) -> Result<bool> {
+        self.cursor.step(true).map_err(Into::into)
+    }
+
+    /// Moves the cursor to the previous record.
+    #[inline]
+    pub fn prev(&mut self) -> Result<bool> {
+        self.cursor.step(false).map_err(Into::into)
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    bptree::BPTree,
+    meta::PageIndex,
+    metrics::Counter,
+    storage::PageId,
+    tx::{readonly::Readonly, CursorTx},
+    Result, ThetaDB,
+};
+
+/// Represents a consistent view of the ThetaDB at the moment it was created.
+///
+/// A snapshot does not block read-write transactions from committing. The pages it references
+/// will not be reused until it is dropped, so all reads through the snapshot see the same data
+/// even as writers commit. The read-only transactions and cursors are built on it.
+pub struct Snapshot<'a> {
+    db: &'a ThetaDB,
+    page_index: PageIndex,
+    generation: u64,
+}
+
+impl<'a> Snapshot<'a> {
+    /// Create a snapshot of the latest committed state.
+    pub fn new(db: &'a ThetaDB) -> Result<Self> {
+        let storage = db.read_storage()?;
+        let page_index = storage.meta()?.page_index().clone();
+
+        // Commits retire pages while holding the storage exclusively, so the generation is
+        // consistent with the page index.
+        let generation = db.snapshots.lock().unwrap().pin();
+
+        Ok(Self {
+            db,
+            page_index,
+            generation,
+        })
+    }
+
+    /// Check if the snapshot contains a given key.
+    #[inline]
+    pub fn contains(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree()?.contains(key.as_ref()).map_err(Into::into)
+    }
+
+    /// Get the value associated with a given key in the snapshot.
+    #[inline]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree()?.get(key.as_ref()).map_err(Into::into)
+    }
+
+    /// Get the value associated with a given key in the snapshot into the buffer, which is
+    /// cleared first. Returns whether the key exists.
+    #[inline]
+    pub fn get_into(&self, key: impl AsRef<[u8]>, buf: &mut Vec<u8>) -> Result<bool> {
+        self.db.metrics.keys_read.add(1);
+        self.bptree()?
+            .get_into(key.as_ref(), buf)
+            .map_err(Into::into)
+    }
+
+    /// Get the cursor pointing to the first record in the snapshot.
+    #[inline]
+    pub fn first_cursor(&self) -> Result<CursorTx<'a>> {
+        let mut cursor = CursorTx::with_snapshot(self.clone());
+        cursor.first().map(|_| cursor)
+    }
+
+    /// Get the cursor pointing to the last record in the snapshot.
+    #[inline]
+    pub fn last_cursor(&self) -> Result<CursorTx<'a>> {
+        let mut cursor = CursorTx::with_snapshot(self.clone());
+        cursor.last().map(|_| cursor)
+    }
+
+    /// Get the cursor pointing to the specific record in the snapshot with the given key.
+    #[inline]
+    pub fn cursor_from_key(&self, key: &[u8]) -> Result<CursorTx<'a>> {
+        let mut cursor = CursorTx::with_snapshot(self.clone());
+        cursor.seek(key).map(|_| cursor)
+    }
+
+    /// Get the cursor pointing to the record in the snapshot with the largest key not greater
+    /// than the given one.
+    #[inline]
+    pub fn cursor_before_key(&self, key: &[u8]) -> Result<CursorTx<'a>> {
+        let mut cursor = CursorTx::with_snapshot(self.clone());
+        cursor.seek_for_prev(key).map(|_| cursor)
+    }
+
+    /// The B+ tree of the snapshot, which holds the storage shared only while it is alive, so
+    /// it should be dropped as soon as each read is done.
+    #[inline]
+    pub(crate) fn bptree(&self) -> Result<BPTree<Readonly<'a>>> {
+        let storage = self.db.read_storage()?;
+        Ok(BPTree::new(Readonly::pinned(
+            storage,
+            self.page_index.clone(),
+        )))
+    }
+}
+
+impl Clone for Snapshot<'_> {
+    /// Pins the same generation again, so the clone sees the same data.
+    #[inline]
+    fn clone(&self) -> Self {
+        self.db.snapshots.lock().unwrap().repin(self.generation);
+        Self {
+            db: self.db,
+            page_index: self.page_index.clone(),
+            generation: self.generation,
+        }
+    }
+}
+
+impl Drop for Snapshot<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.db.snapshots.lock().unwrap().unpin(self.generation);
+    }
+}
+
+/// Keeps track of the alive snapshots, and the pages that cannot be reused because of them.
+#[derive(Default)]
+pub(crate) struct Snapshots {
+    /// The generation of the latest committed state, increasing on every commit.
+    generation: u64,
+    /// The number of alive snapshots of each generation.
+    pinned: BTreeMap<u64, usize>,
+    /// The pages deleted by each commit, keyed by the generation it produced. They are still
+    /// referenced by the snapshots of the earlier generations.
+    retired: BTreeMap<u64, Vec<PageId>>,
+}
+
+impl Snapshots {
+    #[inline]
+    fn pin(&mut self) -> u64 {
+        self.repin(self.generation);
+        self.generation
+    }
+
+    /// Pins a generation that is already pinned, whose pages have not been released.
+    #[inline]
+    fn repin(&mut self, generation: u64) {
+        *self.pinned.entry(generation).or_default() += 1;
+    }
+
+    fn unpin(&mut self, generation: u64) {
+        if let Some(count) = self.pinned.get_mut(&generation) {
+            *count -= 1;
+            if *count == 0 {
+                self.pinned.remove(&generation);
+            }
+        }
+        self.release();
+    }
+
+    /// Records the pages deleted by a commit, should be called while the commit holds the
+    /// storage exclusively.
+    pub(crate) fn retire(&mut self, pages: impl IntoIterator<Item = PageId>) {
+        self.generation += 1;
+        if !self.pinned.is_empty() {
+            let pages = pages.into_iter().collect::<Vec<_>>();
+            if !pages.is_empty() {
+                self.retired.insert(self.generation, pages);
+            }
+        }
+    }
+
+    /// The pages that are free but must not be reused yet.
+    #[inline]
+    pub(crate) fn reserved(&self) -> Vec<PageId> {
+        self.retired.values().flatten().cloned().collect()
+    }
+
+    /// Releases the retired pages no longer referenced by any alive snapshot.
+    fn release(&mut self) {
+        match self.pinned.keys().next() {
+            Some(&oldest) => self.retired.retain(|&generation, _| generation > oldest),
+            None => self.retired.clear(),
+        }
+    }
+}
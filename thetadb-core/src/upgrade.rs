@@ -0,0 +1,332 @@
+use std::{
+    fs, mem,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bptree::{Node, NodePage, Value},
+    db::{Options, ThetaDB},
+    error::Result,
+    medium::{file, mapping, Bytes, File, IoMode, Mapping},
+    meta::{ChecksumKind, MetaPage, PageIndex, ValidationError, VERSION},
+    storage::{Page, PageId, Storage, StorageLock},
+    trace,
+};
+
+/// The length of the data copied in a single transaction while upgrading, so that a large file
+/// is not held in memory all at once.
+const BATCH_LEN: usize = 4 << 20;
+
+/// Upgrades the file written in an older format version to the current one, does nothing for
+/// any other file, whose problems are reported when it is opened.
+///
+/// The data is copied into a new file in the current format, which then replaces the old one,
+/// so the old file is intact until the upgrade is done. The page size, the checksum algorithm,
+/// the user version and the transaction id of the old file are kept.
+pub(crate) fn upgrade(path: &Path, options: &Options) -> Result<()> {
+    let version = match ThetaDB::file_format_version(path) {
+        Ok(version) if version < VERSION => version,
+        _ => return Ok(()),
+    };
+    let _span = trace::span!(INFO, "upgrade file", version);
+    let meta = LegacyMeta::read(path, version)?;
+
+    let upgrading = upgrading_path(path);
+    _ = fs::remove_file(&upgrading);
+    let res = copy(path, &upgrading, &meta, options).and_then(|_| {
+        fs::rename(&upgrading, path)
+            .and_then(|_| file::sync_parent_dir(path))
+            .map_err(|err| file::Error::from(err).into())
+    });
+    if res.is_err() {
+        _ = fs::remove_file(&upgrading);
+    }
+    res
+}
+
+/// The path of the new file that the old one is upgraded into.
+fn upgrading_path(path: &Path) -> PathBuf {
+    let mut upgrading = path.as_os_str().to_owned();
+    upgrading.push(".upgrading");
+    upgrading.into()
+}
+
+/// Copies all the records of the old file into a new file in the current format.
+fn copy(path: &Path, upgrading: &Path, meta: &LegacyMeta, options: &Options) -> Result<()> {
+    let db = Options::new()
+        .with_page_size(Some(meta.page_size))
+        .with_checksum(meta.checksum.unwrap_or(options.checksum))
+        .with_io_mode(options.io_mode)
+        .with_page_cache_capacity(options.page_cache_capacity)
+        .with_bloom_bits_per_key(options.bloom_bits_per_key)
+        .with_underflow_ratio(options.underflow_ratio)
+        .open(upgrading)?;
+
+    let source = StorageLock::new(Storage::new(
+        File::open_readonly(path)?,
+        meta.page_size,
+        PageIndex::METAS[0],
+    ));
+    let mut batch = Batch::default();
+    copy_node(&source, meta, meta.root, &mut |key, value| {
+        batch.len += key.len() + value.len();
+        batch.records.push((key, value));
+        if batch.len < BATCH_LEN {
+            return Ok(());
+        }
+        db.extend(mem::take(&mut batch).records)
+    })?;
+    db.extend(batch.records)?;
+    if meta.user_version != 0 {
+        db.set_user_version(meta.user_version)?;
+    }
+    drop(db);
+
+    // Continues the transaction ids of the old file, which the change log is reconciled with.
+    let file = File::open(upgrading, IoMode::Syscall, 0, false)?;
+    let (mut new_meta, _) = ThetaDB::load_meta(&file)?;
+    let txid = new_meta.txid().max(meta.txid);
+    new_meta.set_txid(txid);
+
+    let mut storage = Storage::new(file, meta.page_size, PageIndex::meta_id(txid));
+    for id in PageIndex::METAS {
+        *storage.page_mut::<MetaPage<_>>(id)? = new_meta.clone();
+    }
+    storage.sync().map_err(Into::into)
+}
+
+#[derive(Default)]
+struct Batch {
+    records: Vec<(Vec<u8>, Vec<u8>)>,
+    len: usize,
+}
+
+/// Copies the records in the subtree of the node page `id` in order, the storage is held
+/// shared only while reading each page, so the pages read can be evicted from the page cache.
+fn copy_node(
+    source: &StorageLock,
+    meta: &LegacyMeta,
+    id: PageId,
+    put: &mut impl FnMut(Vec<u8>, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let (children, records) = {
+        let storage = source.read().unwrap();
+        let page = storage.page::<NodePage<_>>(id)?;
+        let node = if meta.has_bloom {
+            page.into_node()?
+        } else {
+            page.into_node_without_bloom()?
+        };
+
+        match node {
+            Node::Branch(branch) => {
+                let children = (0..branch.count())
+                    .map(|idx| branch.page_id(idx))
+                    .collect::<mapping::Result<_>>()?;
+                (children, Vec::new())
+            }
+            Node::Leaf(leaf) => {
+                let records = (0..leaf.count())
+                    .map(|idx| {
+                        let entry = leaf.entry(idx)?;
+                        let value = match entry.value {
+                            Value::Bytes(bytes) => bytes.to_vec(),
+                            Value::Overflowed { page_id } => read_chain(&storage, meta, page_id)?,
+                        };
+                        Ok((entry.key.to_vec(), value))
+                    })
+                    .collect::<mapping::Result<_>>()?;
+                (Vec::new(), records)
+            }
+        }
+    };
+
+    for child in children {
+        copy_node(source, meta, child, put)?;
+    }
+    records
+        .into_iter()
+        .try_for_each(|(key, value)| put(key, value))
+}
+
+/// Reads a page chain written in an older format version, see [`LegacyChunk`].
+fn read_chain(storage: &Storage, meta: &LegacyMeta, mut id: PageId) -> mapping::Result<Vec<u8>> {
+    let mut res = Vec::new();
+    // A chain visits every page at most once, a longer one is looping.
+    for _ in 0..meta.page_count {
+        let chunk = storage.page::<LegacyChunk<_>>(id)?;
+        let body = chunk.body.get(meta.chunk_extra_len..).unwrap_or_default();
+        if *chunk.len != LEGACY_LEN_OVERFLOW_FLAG {
+            let body = body.get(..*chunk.len as usize).ok_or_else(|| {
+                mapping::Error::Size.context(format_args!("chunk page {}", id.raw()))
+            })?;
+            res.extend_from_slice(body);
+            return Ok(res);
+        }
+        res.extend_from_slice(body);
+        id = *chunk.next;
+    }
+    Err(mapping::Error::ChainBroken.context(format_args!("chunk page {}", id.raw())))
+}
+
+/// A chunk of a page chain written before the version 7, which begins with the length of the
+/// payload and the id of the next chunk page. Since the version 6, they are followed by the
+/// fields checked when the chain is walked, which are skipped here, see `chunk_extra_len`.
+struct LegacyChunk<B> {
+    len: Mapping<B, u32>,
+    next: Mapping<B, PageId>,
+    body: B,
+}
+
+/// The length of a legacy chunk whose data continues in the next chunk.
+const LEGACY_LEN_OVERFLOW_FLAG: u32 = u32::MAX;
+
+unsafe impl<B> Page<B> for LegacyChunk<B>
+where
+    B: Bytes,
+{
+    #[inline]
+    fn from_bytes(bytes: B) -> mapping::Result<Self> {
+        let (len, remaining) = unsafe { Mapping::split(bytes)? };
+        let (next, body) = unsafe { Mapping::split(remaining)? };
+        Ok(Self { len, next, body })
+    }
+}
+
+/// The metadata of a file written in an older format version.
+struct LegacyMeta {
+    page_size: u32,
+    txid: u64,
+    root: PageId,
+    page_count: u32,
+    user_version: u32,
+    /// The checksum algorithm stored in the file, which is always CRC32 before the version 4.
+    checksum: Option<ChecksumKind>,
+    /// Whether the leaf pages may have Bloom filters, the header of the node pages has padding
+    /// instead before the version 3.
+    has_bloom: bool,
+    /// The length of the fields between the id of the next chunk page and the payload of a
+    /// chunk, i.e., the id of the chain, the position and the checksum since the version 6.
+    chunk_extra_len: usize,
+}
+
+/// The offsets of the fields of the metadata in an older format version, which is native-endian
+/// like the current one. The checksum covers all the bytes before it.
+struct LegacyLayout {
+    checksum_kind: Option<usize>,
+    txid: Option<usize>,
+    root: usize,
+    next: usize,
+    user_version: Option<usize>,
+    checksum: usize,
+}
+
+impl LegacyLayout {
+    fn of(version: u32) -> Option<Self> {
+        let layout = match version {
+            // There is a single meta page.
+            1 => Self {
+                checksum_kind: None,
+                txid: None,
+                root: 12,
+                next: 20,
+                user_version: None,
+                checksum: 24,
+            },
+            2 | 3 => Self {
+                checksum_kind: None,
+                txid: Some(24),
+                root: 12,
+                next: 20,
+                user_version: None,
+                checksum: 32,
+            },
+            4 => Self {
+                checksum_kind: Some(12),
+                txid: Some(16),
+                root: 24,
+                next: 32,
+                user_version: None,
+                checksum: 36,
+            },
+            5 | 6 => Self {
+                checksum_kind: Some(12),
+                txid: Some(16),
+                root: 24,
+                next: 32,
+                user_version: Some(36),
+                checksum: 40,
+            },
+            _ => return None,
+        };
+        Some(layout)
+    }
+}
+
+impl LegacyMeta {
+    /// The offset of the page size, which is the same in all versions.
+    const PAGE_SIZE: usize = 8;
+
+    /// Reads the latest valid metadata of the file.
+    fn read(path: &Path, version: u32) -> Result<Self> {
+        let layout = LegacyLayout::of(version).ok_or(ValidationError::VersionMismatched)?;
+        let file = fs::File::open(path).map_err(file::Error::from)?;
+        let read = |offset: u64| {
+            let mut bytes = [0; 64];
+            file.read_exact_at(&mut bytes[..layout.checksum + 4], offset)
+                .map(|_| bytes)
+                .map_err(file::Error::from)
+        };
+
+        let first = read(0)?;
+        let page_size = u32_at(&first, Self::PAGE_SIZE);
+        let mut metas = vec![first];
+        if layout.txid.is_some() {
+            // The older meta page is still useful if the latest one is damaged.
+            if let Ok(second) = read(page_size as u64) {
+                metas.push(second);
+            }
+        }
+
+        metas
+            .iter()
+            .filter_map(|bytes| Self::parse(bytes, &layout, version))
+            .filter(|meta| meta.page_size == page_size)
+            .max_by_key(|meta| meta.txid)
+            .ok_or_else(|| ValidationError::ChecksumMismatched.into())
+    }
+
+    fn parse(bytes: &[u8], layout: &LegacyLayout, version: u32) -> Option<Self> {
+        let checksum = match layout.checksum_kind {
+            Some(offset) => Some(ChecksumKind::from_raw(u32_at(bytes, offset))?),
+            None => None,
+        };
+        let expected = checksum
+            .unwrap_or_default()
+            .checksum(&bytes[..layout.checksum]);
+        if expected != u32_at(bytes, layout.checksum) {
+            return None;
+        }
+
+        Some(Self {
+            page_size: u32_at(bytes, Self::PAGE_SIZE),
+            txid: layout.txid.map_or(0, |offset| {
+                u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap())
+            }),
+            root: u32_at(bytes, layout.root).into(),
+            page_count: u32_at(bytes, layout.next),
+            user_version: layout
+                .user_version
+                .map_or(0, |offset| u32_at(bytes, offset)),
+            checksum,
+            has_bloom: version >= 3,
+            chunk_extra_len: if version >= 6 { 12 } else { 0 },
+        })
+    }
+}
+
+#[inline]
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
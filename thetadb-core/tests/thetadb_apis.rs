@@ -1,7 +1,23 @@
-use std::{collections::HashMap, fs, io::Write};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::Write,
+    ops::Bound,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use rand::Rng;
-use thetadb::{ErrorCode, Result, ThetaDB, MAX_KEY_LEN, MAX_VALUE_LEN};
+use thetadb::{
+    ChangeEvent, ChangeLogEntry, ChecksumKind, ErrorCode, IoMode, Metrics, ObserverFn, OpenWarning,
+    Options, PageKind, Preload, Result, SeekResult, ThetaDB, TxMut, TxOutcome, ValueStorage,
+    MAX_KEY_LEN, MAX_VALUE_LEN,
+};
 
 // Here are the highest level APIs tests.
 // Some `mod`s also have their own tests inside.
@@ -63,6 +79,2602 @@ fn test_crud() -> Result<()> {
     })
 }
 
+#[test]
+fn test_get_many() -> Result<()> {
+    test_db("test_get_many.theta", |db| {
+        db.put(b"a", b"1")?;
+        db.put(b"b", b"2")?;
+        db.put(b"c", b"3")?;
+
+        let keys: [&[u8]; 6] = [b"c", b"x", b"a", b"c", b"", b"b"];
+        assert_eq!(
+            db.get_many(&keys)?,
+            vec![
+                Some(b"3".to_vec()),
+                None,
+                Some(b"1".to_vec()),
+                Some(b"3".to_vec()),
+                None,
+                Some(b"2".to_vec()),
+            ]
+        );
+        assert!(db.get_many(&[] as &[&[u8]])?.is_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_put_if_absent() -> Result<()> {
+    test_db("test_put_if_absent.theta", |db| {
+        assert!(db.put_if_absent(b"a", b"1")?);
+        assert!(!db.put_if_absent(b"a", b"2")?);
+        assert_eq!(db.get(b"a")?, Some(b"1".to_vec()));
+
+        let page_size = db.page_size() as usize;
+        db.update(|tx| {
+            assert!(tx.put_if_absent(b"b", vec![1; page_size])?);
+            assert!(!tx.put_if_absent(b"b", vec![2; page_size])?);
+            Ok(())
+        })?;
+        assert_eq!(db.get(b"b")?, Some(vec![1; page_size]));
+        assert_eq!(db.metrics().keys_written, 2);
+
+        // The records are put or kept through the splits of a multi-level tree.
+        db.update(|tx| {
+            for i in 0..2000u32 {
+                assert!(tx.put_if_absent(i.to_be_bytes(), i.to_le_bytes())?);
+            }
+            for i in 0..2000u32 {
+                assert!(!tx.put_if_absent(i.to_be_bytes(), b"other")?);
+            }
+            Ok(())
+        })?;
+        assert!(db.debugger()?.stats()?.height > 1);
+        for i in 0..2000u32 {
+            assert_eq!(db.get(i.to_be_bytes())?, Some(i.to_le_bytes().to_vec()));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_get_into() -> Result<()> {
+    test_db("test_get_into.theta", |db| {
+        let page_size = db.page_size() as usize;
+        db.put(b"large", vec![1; page_size * 3])?;
+        db.put(b"small", b"foo")?;
+
+        let mut buf = Vec::new();
+        assert!(db.get_into(b"large", &mut buf)?);
+        assert_eq!(buf, vec![1; page_size * 3]);
+
+        let capacity = buf.capacity();
+        let tx = db.begin_tx()?;
+        assert!(tx.get_into(b"small", &mut buf)?);
+        assert_eq!(buf, b"foo");
+        assert!(tx.get_into(b"large", &mut buf)?);
+        assert_eq!(buf.len(), page_size * 3);
+        assert_eq!(buf.capacity(), capacity);
+
+        let cursor = db.first_cursor()?;
+        assert!(cursor.value_into(&mut buf)?);
+        assert_eq!(buf, vec![1; page_size * 3]);
+
+        assert!(!tx.get_into(b"missing", &mut buf)?);
+        assert!(buf.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cursor_value_ref() -> Result<()> {
+    test_db("test_cursor_value_ref.theta", |db| {
+        let page_size = db.page_size() as usize;
+        db.update(|tx| {
+            for i in 0..100u32 {
+                tx.put(i.to_be_bytes(), i.to_be_bytes())?;
+            }
+            tx.put(b"large", vec![1; page_size * 2])
+        })?;
+
+        let mut cursor = db.first_cursor()?;
+        let mut sum = 0;
+        while let Some(n) = cursor.value_ref(|v| u32::from_be_bytes(v.try_into().unwrap()))? {
+            sum += n;
+            cursor.next()?;
+            if cursor.key()?.as_deref() == Some(b"large".as_slice()) {
+                break;
+            }
+        }
+        assert_eq!(sum, (0..100).sum());
+
+        assert_eq!(cursor.value_ref(|v| v.len())?, Some(page_size * 2));
+        cursor.next()?;
+        assert_eq!(cursor.value_ref(|v| v.len())?, None);
+
+        // The closure runs without holding the storage, so it can write to the ThetaDB.
+        let cursor = db.first_cursor()?;
+        cursor.value_ref(|v| db.put(b"copied", v))?.transpose()?;
+        assert_eq!(db.get(b"copied")?, Some(0u32.to_be_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_two_phase_commit() -> Result<()> {
+    test_db("test_two_phase_commit.theta", |db| {
+        let page_size = db.page_size() as usize;
+        db.put(b"a", b"1")?;
+
+        let mut tx = db.begin_tx_mut()?;
+        tx.put(b"a", b"2")?;
+        tx.put(b"b", vec![2; page_size * 2])?;
+        let prepared = tx.prepare()?;
+        // The committed state is untouched, and readers are not blocked.
+        assert_eq!(db.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(db.get(b"b")?, None);
+        assert_eq!(
+            db.begin_tx_mut().err().unwrap().code(),
+            ErrorCode::TxOverlapped
+        );
+        prepared.commit()?;
+        assert_eq!(db.get(b"a")?, Some(b"2".to_vec()));
+        assert_eq!(db.get(b"b")?, Some(vec![2; page_size * 2]));
+        assert_eq!(db.metrics().keys_written, 3);
+
+        let mut tx = db.begin_tx_mut()?;
+        tx.put(b"c", vec![3; page_size * 2])?;
+        tx.delete(b"a")?;
+        tx.prepare()?.abort();
+        assert_eq!(db.get(b"a")?, Some(b"2".to_vec()));
+        assert_eq!(db.get(b"c")?, None);
+        assert_eq!(db.metrics().keys_written, 3);
+
+        // The pages written by the aborted transaction are still free.
+        db.put(b"c", vec![4; page_size * 2])?;
+        db.begin_tx_mut()?.prepare()?.commit()?;
+        assert_eq!(db.get(b"c")?, Some(vec![4; page_size * 2]));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_update_scoped() -> Result<()> {
+    test_db("test_update_scoped.theta", |db| {
+        let value = db.update_scoped(|tx| {
+            tx.tx_mut().unwrap().put(b"a", b"1")?;
+            assert!(!tx.is_committed());
+            assert!(tx.tx().is_none());
+            assert_eq!(tx.get(b"a")?, Some(b"1".to_vec()));
+
+            tx.commit_now()?;
+            assert!(tx.is_committed());
+            assert!(tx.tx_mut().is_none());
+
+            // The write lock is released, so another read-write transaction can start.
+            db.put(b"b", b"2")?;
+            // The transaction reads the state right after its commit.
+            assert_eq!(tx.get(b"a")?, Some(b"1".to_vec()));
+            assert!(!tx.contains(b"b")?);
+            assert_eq!(tx.tx().unwrap().first_key()?, Some(b"a".to_vec()));
+
+            // Committing again does nothing.
+            tx.commit_now()?;
+            Ok(42)
+        })?;
+        assert_eq!(value, 42);
+        assert_eq!(db.get(b"b")?, Some(b"2".to_vec()));
+
+        // Committed without calling `commit_now`.
+        db.update_scoped(|tx| tx.tx_mut().unwrap().put(b"c", b"3"))?;
+        assert_eq!(db.get(b"c")?, Some(b"3".to_vec()));
+
+        // The changes committed early stay committed even if the closure fails.
+        let err = db
+            .update_scoped(|tx| {
+                tx.tx_mut().unwrap().put(b"d", b"4")?;
+                tx.commit_now()?;
+                db.put(vec![0; MAX_KEY_LEN + 1], b"")
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!(db.get(b"d")?, Some(b"4".to_vec()));
+
+        // Otherwise, the changes are discarded.
+        let err = db
+            .update_scoped(|tx| {
+                let tx = tx.tx_mut().unwrap();
+                tx.put(b"e", b"5")?;
+                tx.put(vec![0; MAX_KEY_LEN + 1], b"")
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert!(!db.contains(b"e")?);
+
+        // The state right after the commit is read, even if another read-write transaction
+        // waiting for the write lock commits at once.
+        db.update_scoped(|tx| {
+            tx.tx_mut().unwrap().put(b"f", b"6")?;
+            thread::scope(|s| -> Result<()> {
+                let writer = s.spawn(|| db.put(b"g", b"7"));
+                thread::sleep(Duration::from_millis(50));
+                tx.commit_now()?;
+                thread::sleep(Duration::from_millis(50));
+                assert!(tx.contains(b"f")?);
+                assert!(!tx.contains(b"g")?);
+                writer.join().unwrap()
+            })
+        })?;
+        assert!(db.contains(b"g")?);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_increment() -> Result<()> {
+    test_db("test_increment.theta", |db| {
+        // The absent counter counts as 0.
+        assert_eq!(db.increment(b"unread", 3)?, 3);
+        assert_eq!(db.increment(b"unread", -5)?, -2);
+        assert_eq!(db.get(b"unread")?, Some((-2i64).to_be_bytes().to_vec()));
+
+        // The counters are consistent under concurrent increments.
+        let shared = &db;
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(move || {
+                    for _ in 0..50 {
+                        shared.increment(b"views", 1).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(db.increment(b"views", 0)?, 200);
+
+        // Within a transaction, the increments see each other.
+        db.update(|tx| {
+            assert_eq!(tx.increment(b"unread", 10)?, 8);
+            assert_eq!(tx.increment(b"unread", 10)?, 18);
+            Ok(())
+        })?;
+
+        // The invalid values and the overflows are rejected, leaving the values untouched.
+        db.put(b"name", b"theta")?;
+        let err = db.increment(b"name", 1).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!(db.get(b"name")?, Some(b"theta".to_vec()));
+
+        db.put(b"max", i64::MAX.to_be_bytes())?;
+        let err = db.increment(b"max", 1).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!(db.increment(b"max", -1)?, i64::MAX - 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_reads_during_extend() -> Result<()> {
+    const OLD: u32 = 2000;
+    const NEW: u32 = 4000;
+
+    test_db("test_reads_during_extend.theta", |db| {
+        let key = |i: u32| i.to_be_bytes();
+        db.extend((0..OLD).map(|i| (key(i), b"old")))?;
+
+        // Each read sees either all the records before the load or all the ones after it.
+        let is_loaded = |db: &ThetaDB| {
+            db.view(|tx| {
+                let values = [key(0), key(OLD - 1), key(NEW - 1)].map(|k| tx.get(k));
+                match values {
+                    [Ok(Some(first)), Ok(Some(last)), Ok(None)]
+                        if first == b"old" && last == b"old" =>
+                    {
+                        Ok(false)
+                    }
+                    [Ok(Some(first)), Ok(Some(last)), Ok(Some(added))]
+                        if first == b"new" && last == b"new" && added == b"new" =>
+                    {
+                        Ok(true)
+                    }
+                    values => panic!("a partially loaded state is observed: {values:?}"),
+                }
+            })
+        };
+
+        let (loading, reads) = (AtomicBool::new(false), AtomicUsize::new(0));
+        let shared = &db;
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| loop {
+                    let during_load = loading.load(Ordering::Acquire);
+                    if is_loaded(shared).unwrap() {
+                        break;
+                    }
+                    if during_load {
+                        reads.fetch_add(1, Ordering::Release);
+                    }
+                });
+            }
+
+            // Halfway through the load, waits for the readers to read the records before it.
+            let records = (0..NEW).map(|i| {
+                if i == NEW / 2 {
+                    loading.store(true, Ordering::Release);
+                    let start = Instant::now();
+                    while reads.load(Ordering::Acquire) < 30 {
+                        assert!(start.elapsed() < Duration::from_secs(10));
+                        thread::yield_now();
+                    }
+                }
+                (key(i), b"new")
+            });
+            shared.extend(records).unwrap();
+        });
+
+        assert!(reads.into_inner() >= 30);
+        assert!(is_loaded(&db)?);
+        assert_eq!(db.count_range(..)?, NEW as usize);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_append() -> Result<()> {
+    let path = "target/test_append.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().max_value_len(100_000).open(path)?;
+        let page_size = db.page_size() as usize;
+        let mut expected = Vec::new();
+
+        // The absent key is inserted, and the inline value overflows as it grows.
+        for i in 0..100u8 {
+            let suffix = vec![i; 100];
+            db.append(b"log", &suffix)?;
+            expected.extend(suffix);
+            assert_eq!(db.get(b"log")?, Some(expected.clone()));
+        }
+        assert!(matches!(
+            db.debugger()?.value_storage(b"log")?,
+            Some(ValueStorage::Overflow { .. })
+        ));
+
+        // Appending many times within a transaction only allocates the pages needed.
+        db.update(|tx| {
+            for i in 0..200u32 {
+                let suffix = i.to_be_bytes().repeat(10);
+                tx.append(b"log", &suffix)?;
+                expected.extend(suffix);
+            }
+            Ok(())
+        })?;
+        assert_eq!(db.get(b"log")?, Some(expected.clone()));
+        let Some(ValueStorage::Overflow {
+            chunk_count, len, ..
+        }) = db.debugger()?.value_storage(b"log")?
+        else {
+            panic!("the value should overflow");
+        };
+        assert_eq!(len as usize, expected.len());
+        // Each chunk has a 20-byte header before its payload.
+        assert_eq!(
+            chunk_count as usize,
+            expected.len().div_ceil(page_size - 20)
+        );
+
+        // Appending shadows only the last chunk, so the head of the chain stays in place.
+        let head_page = || -> Result<u32> {
+            match db.debugger()?.value_storage(b"log")? {
+                Some(ValueStorage::Overflow { head_page, .. }) => Ok(head_page),
+                _ => panic!("the value should overflow"),
+            }
+        };
+        let head = head_page()?;
+        db.append(b"log", b"!")?;
+        expected.extend(b"!");
+        assert_eq!(head_page()?, head);
+
+        // The value is untouched if it would become too long.
+        let err = db.append(b"log", vec![0; 100_000 - expected.len() + 1]);
+        assert_eq!(err.unwrap_err().code(), ErrorCode::InputInvalid);
+        assert_eq!(db.get(b"log")?, Some(expected.clone()));
+
+        // The snapshot still reads the value before appending.
+        let snapshot = db.snapshot()?;
+        db.append(b"log", b"tail")?;
+        assert_eq!(snapshot.get(b"log")?.map(|v| v.len()), Some(expected.len()));
+        expected.extend(b"tail");
+        assert_eq!(db.get(b"log")?, Some(expected.clone()));
+        drop(snapshot);
+        drop(db);
+
+        let report = ThetaDB::check(path, true)?;
+        assert!(report.is_healthy(), "{:?}", report.problems);
+        assert_eq!(ThetaDB::open(path)?.get(b"log")?, Some(expected));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_transact() -> Result<()> {
+    test_db("test_transact.theta", |db| {
+        // Moves the value from `a` to `b` only if `b` is empty.
+        let move_value = || {
+            db.transact(|tx| {
+                let Some(value) = tx.get(b"a")? else {
+                    return Ok(TxOutcome::Aborted(false));
+                };
+                tx.delete(b"a")?;
+                Ok(match tx.put_if_absent(b"b", value)? {
+                    true => TxOutcome::Committed(true),
+                    false => TxOutcome::Aborted(false),
+                })
+            })
+        };
+
+        db.put(b"a", b"1")?;
+        db.put(b"b", b"2")?;
+        let outcome = move_value()?;
+        assert_eq!(outcome, TxOutcome::Aborted(false));
+        assert_eq!(db.get(b"a")?, Some(b"1".to_vec()));
+
+        db.delete(b"b")?;
+        let outcome = move_value()?;
+        assert!(outcome.is_committed());
+        assert!(outcome.into_inner());
+        assert_eq!(db.get(b"a")?, None);
+        assert_eq!(db.get(b"b")?, Some(b"1".to_vec()));
+
+        // The transaction is rolled back if the closure fails.
+        let err = db
+            .transact(|tx| {
+                tx.put(b"c", b"3")?;
+                tx.put(vec![0; MAX_KEY_LEN + 1], b"")?;
+                Ok(TxOutcome::Committed(()))
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!(db.get(b"c")?, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_bloom_filter() -> Result<()> {
+    let path = "target/test_bloom_filter.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().bloom_bits_per_key(10).open(path)?;
+        let key_value_pairs = obtain_key_value_pairs(2000, 32, 32);
+        let (present, absent) = key_value_pairs.split_at(1000);
+
+        db.extend(present.iter().cloned())?;
+        for (key, value) in present {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        for (key, _) in absent {
+            assert!(!db.contains(key)?);
+        }
+
+        // Merging leaves keeps the filters valid.
+        let (deleted, kept) = present.split_at(900);
+        for (key, _) in deleted {
+            db.delete(key)?;
+        }
+        for (key, value) in kept {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        drop(db);
+
+        // The filters are persisted, and still used without the option.
+        let db = ThetaDB::open(path)?;
+        for (key, value) in kept {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        for (key, _) in deleted.iter().chain(absent) {
+            assert!(!db.contains(key)?);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_first_last() -> Result<()> {
+    test_db("test_first_last.theta", |db| {
+        assert_eq!(db.first_key()?, None);
+        assert_eq!(db.last_key()?, None);
+        assert_eq!(db.first()?, None);
+        assert_eq!(db.last()?, None);
+
+        let page_size = db.debugger()?.page_size()?;
+        let mut key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size as usize);
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        key_value_pairs.sort_by(|l, r| l.0.cmp(&r.0));
+
+        let (first, last) = (&key_value_pairs[0], key_value_pairs.last().unwrap());
+        assert_eq!(db.first_key()?.as_ref(), Some(&first.0));
+        assert_eq!(db.last_key()?.as_ref(), Some(&last.0));
+        assert_eq!(db.first()?.as_ref(), Some(first));
+        assert_eq!(db.last()?.as_ref(), Some(last));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_empty_key() -> Result<()> {
+    test_db("test_empty_key.theta", |db| {
+        db.put(b"", b"empty")?;
+        assert_eq!(db.get(b"")?, Some(b"empty".to_vec()));
+
+        // Grow the tree so that the empty key lives under the internal empty separators.
+        let page_size = db.page_size() as usize;
+        for (key, value) in obtain_key_value_pairs(500, MAX_KEY_LEN, page_size) {
+            if !key.is_empty() {
+                db.put(key, value)?;
+            }
+        }
+        assert!(db.debugger()?.stats()?.height > 1);
+
+        assert_eq!(db.get(b"")?, Some(b"empty".to_vec()));
+        assert_eq!(db.first_key()?, Some(vec![]));
+        assert_eq!(db.first_cursor()?.key()?, Some(vec![]));
+        assert_eq!(db.last_cursor()?.iter().count(), 1);
+
+        db.delete(b"")?;
+        assert!(!db.contains(b"")?);
+        assert_ne!(db.first_key()?, Some(vec![]));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_count_range() -> Result<()> {
+    test_db("test_count_range.theta", |db| {
+        let key = |i: usize| format!("{i:04}").into_bytes();
+
+        // Empty database.
+        assert_eq!(db.count_range(..)?, 0);
+        assert!(db.is_empty()? && db.len()? == 0);
+        assert_eq!(
+            db.count_range((Bound::Included(key(0).as_slice()), Bound::Unbounded))?,
+            0
+        );
+
+        db.update(|tx| {
+            for i in (0..2000).step_by(2) {
+                tx.put(key(i), [0; 100])?;
+            }
+            Ok(())
+        })?;
+
+        // Full ranges.
+        assert_eq!(db.count_range(..)?, 1000);
+        assert!(!db.is_empty()? && db.len()? == 1000);
+        assert_eq!(
+            db.count_range((Bound::Included(key(0).as_slice()), Bound::Unbounded))?,
+            1000
+        );
+
+        // Partial ranges.
+        let count = |start: Bound<&[u8]>, end: Bound<&[u8]>| db.count_range((start, end));
+        let (k100, k101, k1500) = (key(100), key(101), key(1500));
+
+        assert_eq!(count(Bound::Included(&k100), Bound::Excluded(&k1500))?, 700);
+        assert_eq!(count(Bound::Included(&k100), Bound::Included(&k1500))?, 701);
+        assert_eq!(count(Bound::Excluded(&k100), Bound::Included(&k1500))?, 700);
+        assert_eq!(count(Bound::Included(&k101), Bound::Included(&k1500))?, 700);
+        assert_eq!(count(Bound::Unbounded, Bound::Excluded(&k100))?, 50);
+        assert_eq!(count(Bound::Excluded(&k1500), Bound::Unbounded)?, 249);
+
+        // Empty ranges.
+        assert_eq!(count(Bound::Included(&k1500), Bound::Excluded(&k100))?, 0);
+        assert_eq!(count(Bound::Included(&k100), Bound::Excluded(&k100))?, 0);
+        assert_eq!(count(Bound::Included(b"x"), Bound::Unbounded)?, 0);
+        assert_eq!(count(Bound::Unbounded, Bound::Excluded(b""))?, 0);
+
+        // Emptied database.
+        db.update(|tx| {
+            for i in (2..2000).step_by(2) {
+                tx.delete(key(i))?;
+            }
+            Ok(())
+        })?;
+        assert!(db.view(|tx| Ok(!tx.is_empty()? && tx.len()? == 1))?);
+        db.delete(key(0))?;
+        assert!(db.is_empty()? && db.len()? == 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_split_ranges() -> Result<()> {
+    test_db("test_split_ranges.theta", |db| {
+        let key = |i: usize| format!("{i:05}").into_bytes();
+        assert_eq!(db.split_ranges(4)?, vec![(vec![], vec![])]);
+
+        // Too few records to split into as many ranges.
+        db.extend((0..3).map(|i| (key(i), vec![])))?;
+        let ranges = db.split_ranges(8)?;
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[1], (key(1), key(2)));
+
+        db.extend((3..20000).map(|i| (key(i), vec![0; 50])))?;
+        assert_eq!(db.split_ranges(1)?, vec![(vec![], vec![])]);
+        assert_eq!(db.split_ranges(0)?, vec![(vec![], vec![])]);
+
+        let ranges = db.split_ranges(4)?;
+        assert_eq!(ranges.len(), 4);
+        assert!(ranges.first().unwrap().0.is_empty() && ranges.last().unwrap().1.is_empty());
+        assert!(ranges
+            .windows(2)
+            .all(|w| w[0].1 == w[1].0 && w[0].0 < w[0].1));
+
+        // Each range is scanned on its own thread, and they cover all records roughly evenly.
+        let shared = &db;
+        let counts = thread::scope(|scope| {
+            let handles = ranges
+                .iter()
+                .map(|(start, end)| {
+                    scope.spawn(move || {
+                        let end = match end.is_empty() {
+                            true => Bound::Unbounded,
+                            false => Bound::Excluded(end.as_slice()),
+                        };
+                        let range = (Bound::Included(start.as_slice()), end);
+                        shared.range(range).count()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(counts.iter().sum::<usize>(), 20000);
+        assert!(counts.iter().all(|count| *count > 20000 / 8), "{counts:?}");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_page_usage() -> Result<()> {
+    test_db("test_page_usage.theta", |db| {
+        let usage = db.page_usage()?;
+        assert_eq!(usage.live_pages, usage.total_pages - usage.free_pages);
+
+        for (key, value) in obtain_key_value_pairs(1000, 100, 1000) {
+            db.put(key, value)?;
+        }
+        db.delete_range(..)?;
+
+        let usage = db.page_usage()?;
+        let stats = db.debugger()?.stats()?;
+        assert_eq!(usage.total_pages, stats.total_pages);
+        assert_eq!(usage.free_pages, stats.free_pages);
+        assert!(usage.free_pages > 0);
+        assert_eq!(usage.live_pages, usage.total_pages - usage.free_pages);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scan_page() -> Result<()> {
+    test_db("test_scan_page.theta", |db| {
+        assert_eq!(db.scan_page(None, 10)?, (vec![], None));
+
+        for i in 0..1000u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes())?;
+        }
+
+        let mut after = None;
+        let mut scanned = Vec::new();
+        loop {
+            let (page, resume) = db.scan_page(after.as_deref(), 64)?;
+            assert!(page.len() <= 64);
+            if let Some(key) = &resume {
+                assert_eq!(page.len(), 64);
+                assert_eq!(&page.last().unwrap().0, key);
+            }
+            scanned.extend(page);
+            match resume {
+                Some(key) => after = Some(key),
+                None => break,
+            }
+        }
+        assert_eq!(
+            scanned,
+            (0..1000u32)
+                .map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+                .collect::<Vec<_>>()
+        );
+
+        // The resume key does not need to exist.
+        let (page, resume) = db.scan_page(Some(&[0, 0, 3, 231, 0]), 10)?;
+        assert_eq!(page.len(), 0);
+        assert!(resume.is_none());
+
+        let (page, resume) = db.scan_page(Some(&998u32.to_be_bytes()), 1)?;
+        assert_eq!(page[0].0, 999u32.to_be_bytes());
+        assert!(resume.is_none());
+
+        assert_eq!(db.scan_page(None, 0)?, (vec![], None));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_range() -> Result<()> {
+    fn keys(db: &ThetaDB, range: (Bound<u32>, Bound<u32>)) -> Result<Vec<u32>> {
+        let (start, end) = (range.0.map(u32::to_be_bytes), range.1.map(u32::to_be_bytes));
+        db.range((start.as_ref().map(|k| &k[..]), end.as_ref().map(|k| &k[..])))
+            .map(|kv| kv.map(|(k, _)| u32::from_be_bytes(k.try_into().unwrap())))
+            .collect()
+    }
+
+    test_db("test_range.theta", |db| {
+        for i in 0..1000u32 {
+            db.put(i.to_be_bytes(), [0; 100])?;
+        }
+
+        use Bound::*;
+        assert_eq!(
+            keys(&db, (Unbounded, Unbounded))?,
+            (0..1000).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            keys(&db, (Included(100), Excluded(600)))?,
+            (100..600).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            keys(&db, (Excluded(100), Included(600)))?,
+            (101..=600).collect::<Vec<_>>()
+        );
+        assert_eq!(keys(&db, (Included(999), Unbounded))?, [999]);
+        assert!(keys(&db, (Included(600), Excluded(600)))?.is_empty());
+        assert!(keys(&db, (Excluded(600), Included(100)))?.is_empty());
+        assert!(keys(&db, (Excluded(999), Unbounded))?.is_empty());
+        assert_eq!(keys(&db, (Unbounded, Included(2)))?, [0, 1, 2]);
+        assert!(keys(&db, (Unbounded, Excluded(0)))?.is_empty());
+        assert!(keys(&db, (Included(1000), Excluded(2000)))?.is_empty());
+
+        // Consume from both ends alternately until they meet.
+        let mut iter = db.range(..);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some((key, _)) = iter.next().transpose()? {
+            front.push(key);
+            if let Some((key, _)) = iter.next_back().transpose()? {
+                back.push(key);
+            }
+        }
+        assert_eq!(front.len() + back.len(), 1000);
+        front.extend(back.into_iter().rev());
+        assert!(front.windows(2).all(|w| w[0] < w[1]));
+
+        let last = db.range(..).rev().take(2).collect::<Result<Vec<_>>>()?;
+        assert_eq!(last[0].0, 999u32.to_be_bytes());
+        assert_eq!(last[1].0, 998u32.to_be_bytes());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scan_prefix() -> Result<()> {
+    fn keys(db: &ThetaDB, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        db.scan_prefix(prefix)
+            .map(|kv| kv.map(|(k, _)| k))
+            .collect()
+    }
+
+    test_db("test_scan_prefix.theta", |db| {
+        let all: [&[u8]; 8] = [
+            b"a",
+            b"ab",
+            b"abc",
+            b"ab\xFF",
+            b"ac",
+            b"b",
+            b"\xFF",
+            b"\xFF\xFF",
+        ];
+        db.extend(all.map(|key| (key, key)))?;
+
+        assert_eq!(keys(&db, b"ab")?, [&b"ab"[..], b"abc", b"ab\xFF"]);
+        assert_eq!(keys(&db, b"abc")?, [b"abc"]);
+        assert_eq!(keys(&db, b"a")?, all[..5]);
+        assert_eq!(keys(&db, b"")?, all);
+        assert_eq!(keys(&db, b"\xFF")?, all[6..]);
+        assert!(keys(&db, b"abd")?.is_empty());
+        assert!(keys(&db, b"c")?.is_empty());
+
+        // From the other end.
+        let last = db.scan_prefix(b"ab").next_back().transpose()?;
+        assert_eq!(last, Some((b"ab\xFF".to_vec(), b"ab\xFF".to_vec())));
+
+        // The cursor starts at the first key with the prefix, but is not bounded by it.
+        let mut cursor = db.prefix_cursor(b"ab")?;
+        assert_eq!(cursor.key()?, Some(b"ab".to_vec()));
+        assert!(cursor.next()?);
+        assert_eq!(cursor.key()?, Some(b"abc".to_vec()));
+        assert_eq!(db.prefix_cursor(b"abc")?.key()?, Some(b"abc".to_vec()));
+        assert_eq!(db.prefix_cursor(b"")?.key()?, Some(b"a".to_vec()));
+        // There is a key after the prefix, but none starts with it.
+        assert_eq!(db.prefix_cursor(b"abd")?.key()?, None);
+        assert_eq!(db.prefix_cursor(b"c")?.key()?, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_clear() -> Result<()> {
+    test_db("test_clear.theta", |db| {
+        assert_eq!(db.clear()?, 0);
+
+        let page_size = db.debugger()?.page_size()?;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size as usize);
+        db.extend(key_value_pairs.clone())?;
+        let stats = db.debugger()?.stats()?;
+        assert!(stats.height > 1 && stats.overflow_pages > 0);
+
+        // The records written by the same transaction are cleared as well.
+        let deleted = db.update(|tx| {
+            tx.put(b"pending", vec![0; page_size as usize * 3])?;
+            tx.clear()
+        })?;
+        assert_eq!(deleted, key_value_pairs.len() + 1);
+        assert!(db.is_empty()?);
+
+        // All the pages of the tree but the new root leaf are freed.
+        let cleared = db.debugger()?.stats()?;
+        assert_eq!((cleared.height, cleared.leaf_pages), (1, 1));
+        assert_eq!((cleared.branch_pages, cleared.overflow_pages), (0, 0));
+        let tree_pages = stats.branch_pages + stats.leaf_pages + stats.overflow_pages;
+        assert!(cleared.free_pages >= tree_pages);
+
+        db.extend(key_value_pairs.clone())?;
+        for (key, value) in &key_value_pairs {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_retain() -> Result<()> {
+    test_db("test_retain.theta", |db| {
+        assert_eq!(db.retain(|_, _| false)?, 0);
+
+        let page_size = db.debugger()?.page_size()?;
+        let mut key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size as usize);
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        key_value_pairs.sort_by(|l, r| l.0.cmp(&r.0));
+
+        let mut visited = Vec::new();
+        let deleted = db.retain(|key, value| {
+            visited.push((key.to_vec(), value.to_vec()));
+            value.len() % 2 == 0
+        })?;
+
+        // The predicate sees all records in ascending order by key.
+        assert_eq!(visited, key_value_pairs);
+
+        let (retained, removed): (Vec<_>, Vec<_>) = key_value_pairs
+            .into_iter()
+            .partition(|(_, value)| value.len() % 2 == 0);
+        assert_eq!(deleted, removed.len());
+
+        for (key, value) in &retained {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        for (key, _) in &removed {
+            assert!(!db.contains(key)?);
+        }
+
+        // Removing all the remaining records empties the database.
+        assert_eq!(db.retain(|_, _| false)?, retained.len());
+        assert_eq!(db.first_key()?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_delete_range() -> Result<()> {
+    test_db("test_delete_range.theta", |db| {
+        let key = |i: usize| format!("{i:04}").into_bytes();
+        let page_size = db.debugger()?.page_size()? as usize;
+
+        db.update(|tx| {
+            for i in 0..1000 {
+                // Mix inline and overflowed values.
+                let value_len = if i % 10 == 0 { page_size } else { 50 };
+                tx.put(key(i), vec![1; value_len])?;
+            }
+            Ok(())
+        })?;
+
+        let (k100, k200, k900) = (key(100), key(200), key(900));
+
+        assert_eq!(
+            db.delete_range((
+                Bound::Included(k100.as_slice()),
+                Bound::Excluded(k200.as_slice())
+            ))?,
+            100
+        );
+        assert_eq!(
+            db.delete_range((Bound::Excluded(k900.as_slice()), Bound::Unbounded))?,
+            99
+        );
+        assert_eq!(
+            db.delete_range((
+                Bound::Included(k200.as_slice()),
+                Bound::Excluded(k100.as_slice())
+            ))?,
+            0
+        );
+
+        for i in 0..1000 {
+            assert_eq!(db.contains(key(i))?, !(100..200).contains(&i) && i <= 900);
+        }
+        assert_eq!(db.count_range(..)?, 801);
+
+        assert_eq!(db.delete_range(..)?, 801);
+        assert_eq!(db.first_key()?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_begin_tx_mut_timeout() -> Result<()> {
+    test_db("test_begin_tx_mut_timeout.theta", |db| {
+        let (began_tx, began_rx) = mpsc::channel();
+        let (commit_tx, commit_rx) = mpsc::channel();
+        let shared = &db;
+
+        thread::scope(|scope| -> Result<()> {
+            let writer = scope.spawn(move || {
+                let mut tx = shared.begin_tx_mut()?;
+                tx.put(b"foo", b"bar")?;
+                began_tx.send(()).unwrap();
+                commit_rx.recv().unwrap();
+                tx.commit()
+            });
+            began_rx.recv().unwrap();
+
+            let timeout = Duration::from_millis(20);
+            let start = Instant::now();
+            assert!(db.begin_tx_mut_timeout(timeout)?.is_none());
+            assert!(start.elapsed() >= timeout);
+
+            commit_tx.send(()).unwrap();
+            writer.join().unwrap()?;
+
+            let tx = db.begin_tx_mut_timeout(timeout)?.unwrap();
+            assert_eq!(tx.get(b"foo")?, Some(b"bar".to_vec()));
+            Ok(())
+        })?;
+
+        // A sync waiting for the storage held by a read session does so under the coordinator,
+        // so the timeout bounds the whole start rather than only the wait for the coordinator.
+        let (held_tx, held_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let shared = &db;
+
+        thread::scope(|scope| {
+            let reader = scope.spawn(move || {
+                let session = shared.read_session()?;
+                held_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                session.get(b"foo")
+            });
+            held_rx.recv().unwrap();
+            let syncer = scope.spawn(move || shared.sync());
+            thread::sleep(Duration::from_millis(20));
+
+            let timeout = Duration::from_millis(20);
+            let start = Instant::now();
+            drop(db.begin_tx_mut_timeout(timeout)?);
+            assert!(start.elapsed() < Duration::from_secs(10));
+
+            release_tx.send(()).unwrap();
+            assert_eq!(reader.join().unwrap()?, Some(b"bar".to_vec()));
+            syncer.join().unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_send_sync() -> Result<()> {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    test_db("test_send_sync.theta", |db| {
+        assert_send_sync(&db);
+
+        // The ThetaDB can be shared by an `Arc` and moved to other threads.
+        let db = Arc::new(db);
+        let writer = thread::spawn({
+            let db = db.clone();
+            move || db.put(b"foo", b"bar")
+        });
+        writer.join().unwrap()?;
+        assert_eq!(db.get(b"foo")?, Some(b"bar".to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_tx_overlapped() -> Result<()> {
+    test_db("test_tx_overlapped.theta", |db| {
+        db.put(b"foo", b"bar")?;
+
+        // The debugger holds the storage until it is dropped.
+        let debugger = db.debugger()?;
+        let err = db.begin_tx_mut().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+        let err = db.put(b"foo", b"baz").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+        assert_eq!(db.reserve(1).unwrap_err().code(), ErrorCode::TxOverlapped);
+        drop(debugger);
+
+        let err = db.update(|_| db.put(b"foo", b"baz")).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+        let err = db.update(|_| db.debugger().map(|_| ())).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+
+        // Read-only transactions can overlap with anything.
+        db.update(|tx| {
+            tx.put(b"foo", b"baz")?;
+            assert_eq!(db.get(b"foo")?, Some(b"bar".to_vec()));
+            Ok(())
+        })?;
+
+        // Transactions on different threads just wait for each other.
+        let shared = &db;
+        let tx = db.begin_tx_mut()?;
+        thread::scope(|scope| {
+            let reader = scope.spawn(move || shared.get(b"foo"));
+            tx.commit()?;
+            assert_eq!(reader.join().unwrap()?, Some(b"baz".to_vec()));
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn test_reads_during_tx_with_sync() -> Result<()> {
+    test_db("test_reads_during_tx_with_sync.theta", |db| {
+        db.put(b"foo", b"bar")?;
+        let err = db.update(|_| db.sync()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+
+        // The syncs on another thread wait for the read-write transaction instead of queueing
+        // for the storage, which would block the reads made during the transaction forever.
+        let shared = &db;
+        let stopped = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let syncer = scope.spawn(|| {
+                while !stopped.load(Ordering::Relaxed) {
+                    shared.sync()?;
+                }
+                Ok(())
+            });
+
+            for idx in 0..200u32 {
+                db.update(|tx| {
+                    tx.put(b"foo", idx.to_be_bytes())?;
+                    assert!(db.get(b"foo")?.is_some());
+                    Ok(())
+                })?;
+            }
+            stopped.store(true, Ordering::Relaxed);
+            syncer.join().unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_readers_not_blocking_writers() -> Result<()> {
+    test_db("test_readers_not_blocking_writers.theta", |db| {
+        let page_size = db.page_size() as usize;
+        let key_value_pairs = obtain_key_value_pairs(300, MAX_KEY_LEN, page_size);
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+
+        let tx = db.begin_tx()?;
+        let mut cursor = db.first_cursor()?;
+        let mut range = db.range(..);
+        assert!(range.next().is_some());
+
+        // Overwrite and delete everything while the readers are alive, which commit at once.
+        for (idx, (key, value)) in key_value_pairs.iter().enumerate() {
+            if idx % 2 == 0 {
+                db.put(key, value.iter().rev().cloned().collect::<Vec<_>>())?;
+            } else {
+                db.delete(key)?;
+            }
+        }
+        db.put(b"new", b"new")?;
+
+        // The readers still see what it was when they were started.
+        for (key, value) in &key_value_pairs {
+            assert_eq!(tx.get(key)?.as_ref(), Some(value));
+        }
+        assert!(!tx.contains(b"new")?);
+        assert_eq!(range.count(), key_value_pairs.len() - 1);
+
+        let mut count = 0;
+        while let Some((key, value)) = cursor.key_value()? {
+            assert_ne!(key, b"new");
+            assert_eq!(tx.get(key)?, Some(value));
+            count += 1;
+            // Keep writing while scanning.
+            db.put(b"new", count.to_string())?;
+            cursor.next()?;
+        }
+        assert_eq!(count, key_value_pairs.len());
+        drop((tx, cursor));
+
+        assert_eq!(db.get(b"new")?, Some(count.to_string().into_bytes()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_on_commit() -> Result<()> {
+    let path = "target/test_on_commit.theta";
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let observed = events.clone();
+    let db = Options::new()
+        .on_commit(Arc::new(move |changes| {
+            observed.lock().unwrap().push(changes.to_vec());
+        }))
+        .open(path)?;
+
+    with_cleanup(&[path], move || {
+        db.update(|tx| {
+            tx.put(b"a", b"1")?;
+            tx.put(b"b", b"2")?;
+            tx.put(b"c", b"3")?;
+            tx.delete(b"a")?;
+            tx.delete(b"z")
+        })?;
+
+        // Neither aborted nor empty transactions are observed.
+        _ = db.update(|tx| {
+            tx.put(b"d", b"4")?;
+            tx.put(vec![0; MAX_KEY_LEN + 1], b"5")
+        });
+        db.begin_tx_mut()?.commit()?;
+
+        db.delete_range((Bound::Included(b"b".as_slice()), Bound::Unbounded))?;
+
+        let put = |key: &[u8], value: &[u8]| ChangeEvent::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        };
+        let delete = |key: &[u8]| ChangeEvent::Delete { key: key.to_vec() };
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                vec![
+                    put(b"a", b"1"),
+                    put(b"b", b"2"),
+                    put(b"c", b"3"),
+                    delete(b"a")
+                ],
+                vec![delete(b"b"), delete(b"c")],
+            ]
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_changelog() -> Result<()> {
+    let path = "target/test_changelog.theta";
+    let changelog_path = "target/test_changelog.log";
+    let open = || {
+        Options::new()
+            .changelog_path(Some(changelog_path.into()))
+            .open(path)
+    };
+
+    let put = |key: &[u8], value: &[u8]| ChangeEvent::Put {
+        key: key.to_vec(),
+        value: value.to_vec(),
+    };
+    let delete = |key: &[u8]| ChangeEvent::Delete { key: key.to_vec() };
+    let entry = |txid, changes| ChangeLogEntry { txid, changes };
+
+    with_cleanup(&[path, changelog_path], || {
+        let db = open()?;
+        db.update(|tx| {
+            tx.put(b"a", b"1")?;
+            tx.put(b"b", b"2")
+        })?;
+        db.delete(b"a")?;
+        db.delete(b"unknown")?;
+        drop(db);
+
+        // The change log survives restarts.
+        let db = open()?;
+        db.put(b"c", b"3")?;
+        assert_eq!(
+            db.changelog()?,
+            [
+                entry(1, vec![put(b"a", b"1"), put(b"b", b"2")]),
+                entry(2, vec![delete(b"a")]),
+                entry(3, vec![put(b"c", b"3")]),
+            ]
+        );
+
+        db.truncate_changelog(2)?;
+        assert_eq!(db.changelog()?, [entry(3, vec![put(b"c", b"3")])]);
+
+        // Transaction ids keep increasing after the change log is fully truncated.
+        db.truncate_changelog(3)?;
+        assert_eq!(db.changelog()?, []);
+        drop(db);
+
+        let db = open()?;
+        db.delete(b"c")?;
+        assert_eq!(db.changelog()?, [entry(4, vec![delete(b"c")])]);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_snapshot() -> Result<()> {
+    test_db("test_snapshot.theta", |db| {
+        let page_size = db.debugger()?.page_size()?;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size as usize);
+
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+
+        let snapshot = db.snapshot()?;
+
+        // Overwrite and delete everything, the freed pages would be reused without snapshot.
+        for (idx, (key, value)) in key_value_pairs.iter().enumerate() {
+            if idx % 2 == 0 {
+                db.put(key, value.iter().rev().cloned().collect::<Vec<_>>())?;
+            } else {
+                db.delete(key)?;
+            }
+        }
+        db.put(b"new", b"new")?;
+
+        for (key, value) in &key_value_pairs {
+            assert!(snapshot.contains(key)?);
+            assert_eq!(snapshot.get(key)?.as_ref(), Some(value));
+        }
+        assert!(!snapshot.contains(b"new")?);
+
+        let mut count = 0;
+        let mut cursor = snapshot.first_cursor()?;
+        while cursor.key_value()?.is_some() {
+            count += 1;
+            cursor.next()?;
+        }
+        assert_eq!(count, key_value_pairs.len());
+        drop(cursor);
+
+        drop(snapshot);
+        for (idx, (key, value)) in key_value_pairs.iter().enumerate() {
+            let expected = (idx % 2 == 0).then(|| value.iter().rev().cloned().collect());
+            assert_eq!(db.get(key)?, expected);
+        }
+
+        // The retained pages are reusable once the snapshot is dropped.
+        db.put(b"new", b"newer")?;
+        assert!(db.debugger()?.freelist_len()? > 0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_page_size_file_len() -> Result<()> {
+    test_db("test_page_size_file_len.theta", |db| {
+        let page_size = db.page_size();
+        assert_eq!(page_size, db.debugger()?.page_size()?);
+
+        let file_len = db.file_len()?;
+        assert!(file_len > 0);
+        assert_eq!(file_len % page_size as usize, 0);
+
+        for i in 0..100u32 {
+            db.put(i.to_be_bytes(), vec![0; page_size as usize])?;
+        }
+        assert!(db.file_len()? > file_len);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_generation() -> Result<()> {
+    test_db("test_generation.theta", |db| {
+        let generation = db.generation()?;
+
+        db.put(b"foo", b"bar")?;
+        let next = db.generation()?;
+        assert!(next > generation);
+
+        // Reading or committing nothing keeps the generation.
+        db.get(b"foo")?;
+        db.begin_tx_mut()?.commit()?;
+        assert_eq!(db.generation()?, next);
+
+        db.delete(b"foo")?;
+        assert!(db.generation()? > next);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_reserve() -> Result<()> {
+    let path = "target/test_reserve.theta";
+    let capacity = 8 * 1024 * 1024;
+
+    with_cleanup(&[path], || {
+        let db = Options::new().initial_capacity_bytes(capacity).open(path)?;
+        assert!(db.file_len()? >= capacity);
+
+        let pages = (capacity / db.page_size() as usize) as u32;
+        db.reserve(pages)?;
+        let file_len = db.file_len()?;
+        assert!(file_len > capacity);
+
+        // Reserving fewer pages than already available does nothing.
+        db.reserve(1)?;
+        assert_eq!(db.file_len()?, file_len);
+
+        // The file cannot exceed the max length.
+        assert_eq!(
+            db.reserve(u32::MAX).unwrap_err().code(),
+            ErrorCode::DatabaseFull
+        );
+        assert_eq!(db.file_len()?, file_len);
+        drop(db);
+
+        let res = Options::new().initial_capacity_bytes(usize::MAX).open(path);
+        assert_eq!(res.err().map(|e| e.code()), Some(ErrorCode::DatabaseFull));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_io_mode() -> Result<()> {
+    let path = "target/test_io_mode.theta";
+    let open = |io_mode, capacity| {
+        Options::new()
+            .io_mode(io_mode)
+            .page_cache_capacity(capacity)
+            .open(path)
+    };
+
+    with_cleanup(&[path], || {
+        let db = open(IoMode::Syscall, 1)?;
+        let page_size = db.page_size();
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size as usize);
+
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        for (key, _) in &key_value_pairs[..200] {
+            db.delete(key)?;
+        }
+        drop(db);
+
+        // The file written with pread/pwrite is the same as the mapped one, whether the pages
+        // read are cached or evicted.
+        for (io_mode, capacity) in [
+            (IoMode::Mmap, 0),
+            (IoMode::Syscall, 0),
+            (IoMode::Syscall, 256),
+        ] {
+            let db = open(io_mode, capacity)?;
+            for (key, _) in &key_value_pairs[..200] {
+                assert!(!db.contains(key)?);
+            }
+            for (key, value) in &key_value_pairs[200..] {
+                assert_eq!(db.get(key)?.as_ref(), Some(value));
+            }
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_preload() -> Result<()> {
+    let path = "target/test_preload.theta";
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        let page_size = db.page_size() as usize;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size);
+        db.extend(key_value_pairs.clone())?;
+        drop(db);
+
+        for (preload, io_mode) in [
+            (Preload::None, IoMode::Mmap),
+            (Preload::TreeSpine, IoMode::Mmap),
+            (Preload::All, IoMode::Mmap),
+            (Preload::TreeSpine, IoMode::Syscall),
+            (Preload::All, IoMode::Syscall),
+        ] {
+            let db = Options::new()
+                .preload(preload)
+                .io_mode(io_mode)
+                .open(path)?;
+            assert_eq!(db.metrics().read_txs, 0);
+            for (key, value) in &key_value_pairs {
+                assert_eq!(db.get(key)?.as_ref(), Some(value));
+            }
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_options_by_value() -> Result<()> {
+    let path = "target/test_options_by_value.theta";
+
+    with_cleanup(&[path], || {
+        let base = Options::new().with_max_value_len(16).with_force_sync(true);
+        let strict = base.clone().with_max_key_len(4);
+
+        // The variant is derived without touching the base options.
+        let db = strict.open(path)?;
+        let err = db.put(b"long key", b"value").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        let err = db.put(b"key", [0; 17]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        drop(db);
+
+        // The observer can be named by its public alias.
+        let commits = Arc::new(AtomicUsize::new(0));
+        let counter = commits.clone();
+        let observer: Arc<ObserverFn> =
+            Arc::new(move |_| _ = counter.fetch_add(1, Ordering::SeqCst));
+        let db = base.with_on_commit(observer).open(path)?;
+        db.put(b"long key", b"value")?;
+        assert_eq!(db.get(b"long key")?, Some(b"value".to_vec()));
+        assert_eq!(commits.load(Ordering::SeqCst), 1);
+        Ok(())
+    })
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async() -> Result<()> {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+    };
+
+    use thetadb::AsyncThetaDB;
+
+    /// Polls the future on the current thread, parking it until the future is woken.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        struct Unparker(thread::Thread);
+
+        impl Wake for Unparker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(Unparker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    let path = "target/test_async.theta";
+    with_cleanup(&[path], || {
+        block_on(async {
+            let db = AsyncThetaDB::open(path, Options::new()).await?;
+            db.put(b"foo", b"bar").await?;
+            assert_eq!(db.get(b"foo").await?, Some(b"bar".to_vec()));
+
+            // The transactions run within the blocking tasks.
+            let count = db
+                .update(|tx| {
+                    for i in 0..100u32 {
+                        tx.put(i.to_be_bytes(), i.to_le_bytes())?;
+                    }
+                    Ok(100)
+                })
+                .await?;
+            let read = db.view(|tx| Ok(tx.first_cursor()?.iter().count())).await?;
+            assert_eq!(read, count + 1);
+
+            // The operations of clones run concurrently.
+            let futures = (0..8u32)
+                .map(|i| db.clone().put(format!("key{i}"), b"value"))
+                .collect::<Vec<_>>();
+            for future in futures {
+                future.await?;
+            }
+            assert_eq!(db.run(|db| db.count_range(..)).await?, count + 9);
+
+            db.delete(b"foo").await?;
+            assert_eq!(db.get(b"foo").await?, None);
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn test_auto_sync() -> Result<()> {
+    let path = "target/test_auto_sync.theta";
+
+    with_cleanup(&[path], || {
+        let res = Options::new()
+            .auto_sync_interval(Some(Duration::ZERO))
+            .open(path);
+        assert!(matches!(res, Err(err) if err.code() == ErrorCode::InputInvalid));
+
+        // The background thread syncs while the transactions go on.
+        let db = Options::new()
+            .auto_sync_interval(Some(Duration::from_millis(5)))
+            .open(path)?;
+        for i in 0..50u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes())?;
+            thread::sleep(Duration::from_millis(1));
+        }
+        db.sync()?;
+        drop(db);
+
+        // The syncs wait for the read-write transaction instead of blocking the reads made
+        // during it.
+        let db = Options::new()
+            .auto_sync_interval(Some(Duration::from_millis(1)))
+            .open(path)?;
+        for i in 0..50u32 {
+            db.update(|tx| {
+                tx.put(i.to_be_bytes(), i.to_le_bytes())?;
+                thread::sleep(Duration::from_millis(1));
+                assert_eq!(db.get(i.to_be_bytes())?, Some(i.to_le_bytes().to_vec()));
+                Ok(())
+            })?;
+        }
+        drop(db);
+
+        // Dropping stops the thread without waiting for the interval.
+        let db = Options::new()
+            .auto_sync_interval(Some(Duration::from_secs(3600)))
+            .open(path)?;
+        assert_eq!((&db).into_iter().count(), 50);
+        let start = Instant::now();
+        drop(db);
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        let db = Options::new()
+            .auto_sync_interval(Some(Duration::from_secs(3600)))
+            .open(path)?;
+        assert_eq!(
+            db.get(7u32.to_be_bytes())?,
+            Some(7u32.to_le_bytes().to_vec())
+        );
+        db.close()
+    })
+}
+
+#[test]
+fn test_shrink() -> Result<()> {
+    let path = "target/test_shrink.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().shrink_on_close(true).open(path)?;
+        let page_size = db.page_size() as usize;
+        // Nothing is committed if there is nothing to cut off.
+        db.shrink()?;
+        assert_eq!(db.generation()?, 0);
+
+        let key = |i: usize| format!("{i:04}").into_bytes();
+        for i in 0..10 {
+            db.put(key(i), vec![0; page_size])?;
+        }
+        // The pages of the following records are at the end of the file.
+        db.update(|tx| {
+            for i in 10..500 {
+                tx.put(key(i), vec![0; page_size])?;
+            }
+            Ok(())
+        })?;
+        let full_len = db.file_len()?;
+
+        let snapshot = db.snapshot()?;
+        db.delete_range((Bound::Included(key(10).as_slice()), Bound::Unbounded))?;
+        db.shrink()?;
+        // The pages referenced by the snapshot cannot be cut off.
+        assert_eq!(snapshot.get(key(499))?, Some(vec![0; page_size]));
+        drop(snapshot);
+
+        db.shrink()?;
+        let shrunk_len = db.file_len()?;
+        assert!(shrunk_len < full_len / 4);
+        let generation = db.generation()?;
+        db.shrink()?;
+        assert_eq!(db.generation()?, generation);
+        assert_eq!(db.file_len()?, shrunk_len);
+        for i in 0..10 {
+            assert_eq!(db.get(key(i))?, Some(vec![0; page_size]));
+        }
+
+        // The file can grow again, and is shrunk on close.
+        db.put(key(10), vec![0; page_size])?;
+        drop(db);
+        assert!((fs::metadata(path).unwrap().len() as usize) < full_len / 4);
+
+        let db = ThetaDB::open(path)?;
+        assert_eq!((&db).into_iter().count(), 11);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_close() -> Result<()> {
+    let path = "target/test_close.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().shrink_on_close(true).open(path)?;
+        let page_size = db.page_size() as usize;
+        db.put(b"foo", b"bar")?;
+        db.update(|tx| {
+            for i in 0..100u32 {
+                tx.put(i.to_be_bytes(), vec![0; page_size])?;
+            }
+            Ok(())
+        })?;
+        let full_len = db.file_len()?;
+        db.delete_range((Bound::Unbounded, Bound::Excluded(b"foo".as_slice())))?;
+        db.close()?;
+        assert!((fs::metadata(path).unwrap().len() as usize) < full_len / 4);
+
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.get(b"foo")?, Some(b"bar".to_vec()));
+        assert_eq!((&db).into_iter().count(), 1);
+        db.close()
+    })
+}
+
+#[test]
+fn test_path() -> Result<()> {
+    let path = "target/test_path.theta";
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.path(), Path::new(path));
+
+        let reopened = ThetaDB::open(db.path())?;
+        assert_eq!(reopened.path(), db.path());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_needs_compaction() -> Result<()> {
+    let path = "target/test_needs_compaction.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().auto_compact(Some(0.5)).open(path)?;
+        let page_size = db.page_size() as usize;
+        let key = |i: usize| format!("{i:04}").into_bytes();
+        assert!(!db.needs_compaction());
+
+        db.update(|tx| {
+            for i in 0..100 {
+                tx.put(key(i), vec![0; page_size])?;
+            }
+            Ok(())
+        })?;
+        assert!(!db.needs_compaction());
+
+        db.delete_range((Bound::Included(key(10).as_slice()), Bound::Unbounded))?;
+        assert!(db.needs_compaction());
+
+        db.shrink()?;
+        assert!(!db.needs_compaction());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_trim_memory() -> Result<()> {
+    test_db("test_trim_memory.theta", |db| {
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, db.page_size() as usize);
+
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        db.trim_memory()?;
+
+        for (key, value) in &key_value_pairs {
+            assert_eq!(db.get(key)?.as_ref(), Some(value));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_user_version() -> Result<()> {
+    let path = "target/test_user_version.theta";
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.user_version()?, 0);
+        db.set_user_version(1)?;
+        assert_eq!(db.user_version()?, 1);
+
+        // The version is migrated atomically along with the data.
+        let res: Result<()> = db.update(|tx| {
+            tx.put(b"schema", b"v2")?;
+            tx.set_user_version(2);
+            assert_eq!(tx.user_version(), 2);
+            Err(tx.put([0; MAX_KEY_LEN + 1], b"").unwrap_err())
+        });
+        assert!(res.is_err());
+        assert_eq!(db.user_version()?, 1);
+        assert!(!db.contains(b"schema")?);
+
+        db.update(|tx| {
+            tx.put(b"schema", b"v2")?;
+            tx.set_user_version(2);
+            Ok(())
+        })?;
+        assert_eq!(db.user_version()?, 2);
+        drop(db);
+
+        // The version survives reopening, and is untouched by the other commits.
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.user_version()?, 2);
+        db.put(b"a", b"a")?;
+        db.update(|tx| {
+            tx.set_user_version(tx.user_version());
+            Ok(())
+        })?;
+        assert_eq!(db.user_version()?, 2);
+        assert_eq!(db.get(b"schema")?, Some(b"v2".to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_file_format_version() -> Result<()> {
+    let path = "target/test_file_format_version.theta";
+
+    with_cleanup(&[path], || {
+        let err = ThetaDB::file_format_version(path).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IO);
+
+        ThetaDB::open(path)?.put(b"a", b"a")?;
+        assert_eq!(ThetaDB::file_format_version(path)?, 7);
+
+        fs::write(path, b"not a ThetaDB file").unwrap();
+        let err = ThetaDB::file_format_version(path).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::FileUnexpected);
+
+        fs::write(path, b"").unwrap();
+        let err = ThetaDB::file_format_version(path).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::FileUnexpected);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_unexpected_file() -> Result<()> {
+    let dir_path = "target/test_open_unexpected_file_dir.theta";
+    let path = "target/test_open_unexpected_file.theta";
+
+    with_cleanup(&[dir_path, path], || {
+        fs::create_dir_all(dir_path).unwrap();
+        let err = ThetaDB::open(dir_path).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::IO);
+        assert!(err.to_string().contains("not a regular file"), "{err}");
+
+        // The file is too small to be a ThetaDB, and it should be left untouched.
+        fs::write(path, b"tiny").unwrap();
+        let err = ThetaDB::open(path).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::FileUnexpected);
+        assert!(err.to_string().contains("too small"), "{err}");
+        assert_eq!(fs::read(path).unwrap(), b"tiny");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_page_size_mismatched() -> Result<()> {
+    let path = "target/test_page_size_mismatched.theta";
+
+    with_cleanup(&[path], || {
+        Options::new()
+            .page_size(Some(8192))
+            .open(path)?
+            .put(b"a", b"a")?;
+
+        let err = Options::new()
+            .page_size(Some(4096))
+            .open(path)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::FileUnexpected);
+        assert!(err.to_string().contains("page size"), "{err}");
+
+        for page_size in [None, Some(8192)] {
+            let db = Options::new().page_size(page_size).open(path)?;
+            assert_eq!(db.page_size(), 8192);
+            assert_eq!(db.get(b"a")?, Some(b"a".to_vec()));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_options_invalid() {
+    let path = "target/test_options_invalid.theta";
+
+    let mut invalid = Vec::new();
+    for page_size in [1, 256, 4097, 6144] {
+        invalid.push(Options::new().page_size(Some(page_size)).clone());
+    }
+    invalid.push(Options::new().mempool_capacity(0).clone());
+    invalid.push(Options::new().max_key_len(MAX_KEY_LEN + 1).clone());
+    // `u32::MAX` is reserved by the chunk length field.
+    for max_value_len in [u32::MAX as usize, u32::MAX as usize + 1] {
+        invalid.push(Options::new().max_value_len(max_value_len).clone());
+    }
+    for free_ratio in [-0.1, 1.5, f64::NAN] {
+        invalid.push(Options::new().auto_compact(Some(free_ratio)).clone());
+    }
+    for underflow_ratio in [-0.1, 0.5, f64::NAN] {
+        invalid.push(Options::new().underflow_ratio(underflow_ratio).clone());
+    }
+    // The nodes merged at the ratio may not hold the longest keys in small pages.
+    invalid.push(
+        Options::new()
+            .page_size(Some(512))
+            .underflow_ratio(0.4)
+            .clone(),
+    );
+
+    for options in invalid {
+        let err = options.open(path).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid, "{err}");
+        // Nothing should be created for the invalid options.
+        assert!(!Path::new(path).exists());
+    }
+
+    // The largest length below the reserved one is accepted.
+    let db = Options::new()
+        .max_value_len(u32::MAX as usize - 1)
+        .open(path);
+    _ = fs::remove_file(path);
+    assert_eq!(db.unwrap().max_value_len(), u32::MAX as usize - 1);
+}
+
+#[test]
+fn test_underflow_ratio() -> Result<()> {
+    let path = "target/test_underflow_ratio.theta";
+
+    with_cleanup(&[path], || {
+        let mut leaf_pages = Vec::new();
+        for ratio in [0.0, 0.35, 0.45] {
+            _ = fs::remove_file(path);
+            let db = Options::new()
+                .page_size(Some(4096))
+                .underflow_ratio(ratio)
+                .open(path)?;
+            db.extend((0..4000u32).map(|i| (i.to_be_bytes(), [0; 64])))?;
+            db.update(|tx| {
+                for i in (0..4000u32).filter(|i| i % 4 != 0) {
+                    tx.delete(i.to_be_bytes())?;
+                }
+                Ok(())
+            })?;
+
+            let keys = (&db)
+                .into_iter()
+                .map(|res| res.map(|(key, _)| key))
+                .collect::<Result<Vec<_>>>()?;
+            let expected = (0..4000u32).step_by(4).map(|i| i.to_be_bytes().to_vec());
+            assert_eq!(keys, expected.collect::<Vec<_>>());
+            leaf_pages.push(db.debugger()?.stats()?.leaf_pages);
+            drop(db);
+            assert!(ThetaDB::check(path, true)?.is_healthy());
+        }
+        // The higher the ratio, the more leaves are merged.
+        assert!(leaf_pages[0] > leaf_pages[1], "{leaf_pages:?}");
+        assert!(leaf_pages[1] >= leaf_pages[2], "{leaf_pages:?}");
+
+        // The ratio is checked against the page size stored in the file.
+        _ = fs::remove_file(path);
+        drop(Options::new().page_size(Some(512)).open(path)?);
+        let err = Options::new()
+            .underflow_ratio(0.4)
+            .open(path)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid, "{err}");
+        Options::new().underflow_ratio(0.3).open(path).map(drop)
+    })
+}
+
+#[test]
+fn test_recover_from_older_meta() -> Result<()> {
+    let path = "target/test_recover_from_older_meta.theta";
+
+    // Flips a byte of the page index in the given meta page, as if the write was torn.
+    let damage_meta = |index: usize, page_size: usize| {
+        let mut bytes = fs::read(path).unwrap();
+        bytes[index * page_size + 24] ^= 0xFF;
+        fs::write(path, bytes).unwrap();
+    };
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        let page_size = db.page_size() as usize;
+        assert!(!db.recovered_from_older_meta());
+
+        // The two meta pages are written alternately, the second commit goes to the first.
+        db.put(b"a", b"a")?;
+        db.put(b"b", b"b")?;
+        drop(db);
+
+        damage_meta(0, page_size);
+
+        let db = ThetaDB::open(path)?;
+        assert!(db.recovered_from_older_meta());
+        assert!(db.contains(b"a")?);
+        assert!(!db.contains(b"b")?);
+
+        // The damaged meta page is overwritten by the next commit.
+        db.put(b"c", b"c")?;
+        drop(db);
+
+        let db = ThetaDB::open(path)?;
+        assert!(!db.recovered_from_older_meta());
+        assert!(db.contains(b"a")? && db.contains(b"c")?);
+        drop(db);
+
+        damage_meta(0, page_size);
+        damage_meta(1, page_size);
+        assert_eq!(
+            ThetaDB::open(path).err().map(|e| e.code()),
+            Some(ErrorCode::FileUnexpected)
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_newest_meta() -> Result<()> {
+    let path = "target/test_open_newest_meta.theta";
+
+    with_cleanup(&[path], || {
+        let generation = ThetaDB::open(path)?.generation()?;
+
+        // Both meta pages stay valid, and the newest one alternates between them.
+        for i in 1..=2 {
+            ThetaDB::open(path)?.put(b"a", [i])?;
+
+            let db = ThetaDB::open(path)?;
+            assert!(!db.recovered_from_older_meta());
+            assert_eq!(db.generation()?, generation + i as u64);
+            assert_eq!(db.get(b"a")?, Some(vec![i]));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v1() -> Result<()> {
+    let path = "target/test_upgrade_from_v1.theta";
+
+    with_cleanup(&[path], || {
+        // The single meta page is rewritten as the two alternating ones.
+        let db = open_upgraded_fixture(1, path)?;
+        assert!(!db.recovered_from_older_meta());
+        assert!(!Path::new(&format!("{path}.upgrading")).exists());
+
+        db.put(b"key-0005", b"again")?;
+        drop(db);
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.get(b"key-0005")?, Some(b"again".to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v2() -> Result<()> {
+    let path = "target/test_upgrade_from_v2.theta";
+
+    with_cleanup(&[path], || {
+        // The leaf pages of the old file have no Bloom filters, the new ones get them.
+        let db = open_upgraded_fixture(2, path)?;
+        assert!(!db.contains(b"key-0005")?);
+        assert!(db.contains(b"key-0006")?);
+
+        db.put(b"key-0005", b"again")?;
+        drop(db);
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.get(b"key-0005")?, Some(b"again".to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v3() -> Result<()> {
+    let path = "target/test_upgrade_from_v3.theta";
+
+    with_cleanup(&[path], || {
+        // The Bloom filters of the old leaf pages are kept.
+        let db = open_upgraded_fixture(3, path)?;
+        assert!(!db.contains(b"key-0015")?);
+        drop(db);
+
+        // The metadata now stores its checksum algorithm, CRC32 as in the old file.
+        let db = Options::new().checksum(ChecksumKind::XxHash64).open(path)?;
+        assert!(!db.recovered_from_older_meta());
+        assert_eq!(db.get(b"key-0010")?, Some(b"value-10".repeat(4)));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v4() -> Result<()> {
+    let path = "target/test_upgrade_from_v4.theta";
+
+    with_cleanup(&[path], || {
+        let db = open_upgraded_fixture(4, path)?;
+        // The transaction id and the checksum algorithm (XxHash64) of the old file are kept.
+        assert_eq!(db.generation()?, 3);
+        assert!(!Path::new(&format!("{path}.upgrading")).exists());
+
+        db.put(b"key-0005", b"again")?;
+        drop(db);
+        let db = Options::new().checksum(ChecksumKind::XxHash64).open(path)?;
+        assert_eq!(db.get(b"key-0005")?, Some(b"again".to_vec()));
+        assert_eq!(db.generation()?, 4);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v5() -> Result<()> {
+    let path = "target/test_upgrade_from_v5.theta";
+
+    with_cleanup(&[path], || {
+        // The chunks of the overflow values are rewritten with the checksummed header.
+        let db = open_upgraded_fixture(5, path)?;
+        // The user version is set in the fourth transaction.
+        assert_eq!(db.user_version()?, 7);
+        assert_eq!(db.generation()?, 4);
+
+        let mut value = db.get(b"key-0100")?.unwrap();
+        db.append(b"key-0100", b"tail")?;
+        value.extend(b"tail");
+        assert_eq!(db.get(b"key-0100")?, Some(value));
+        assert!(ThetaDB::check(path, true)?.is_healthy());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upgrade_from_v6() -> Result<()> {
+    let path = "target/test_upgrade_from_v6.theta";
+
+    with_cleanup(&[path], || {
+        // The chunks of the overflow values are rewritten to be linked backward.
+        let db = open_upgraded_fixture(6, path)?;
+        assert_eq!(db.user_version()?, 7);
+        assert_eq!(db.generation()?, 4);
+
+        let mut value = db.get(b"key-0200")?.unwrap();
+        db.append(b"key-0200", b"tail")?;
+        value.extend(b"tail");
+        assert_eq!(db.get(b"key-0200")?, Some(value));
+        assert!(ThetaDB::check(path, true)?.is_healthy());
+        Ok(())
+    })
+}
+
+/// Opens a copy of the file in `tests/fixtures` written in the given older format version, which
+/// is upgraded by the open, and checks the records in it.
+///
+/// The file has 4 KB pages and holds 300 keys put in two transactions, then the keys ending
+/// with 5 are deleted in the third one. The values of every 100th key overflow the page.
+fn open_upgraded_fixture(version: u32, path: &str) -> Result<ThetaDB> {
+    fs::copy(format!("tests/fixtures/v{version}.theta"), path).unwrap();
+    assert_eq!(ThetaDB::file_format_version(path)?, version);
+
+    let db = ThetaDB::open(path)?;
+    assert_eq!(ThetaDB::file_format_version(path)?, 7);
+    assert!(ThetaDB::check(path, true)?.is_healthy());
+
+    let value = |i: usize| {
+        if i.is_multiple_of(100) {
+            vec![i as u8; 5000 + i]
+        } else {
+            format!("value-{i}").repeat(i % 7 + 1).into_bytes()
+        }
+    };
+    let expected: Vec<_> = (0..300)
+        .filter(|i| i % 10 != 5)
+        .map(|i| (format!("key-{i:04}").into_bytes(), value(i)))
+        .collect();
+
+    let records = db.into_iter().collect::<Result<Vec<_>>>()?;
+    assert_eq!(records, expected);
+    Ok(db)
+}
+
+#[test]
+fn test_check() -> Result<()> {
+    let path = "target/test_check.theta";
+
+    with_cleanup(&[path], || {
+        // A missing file is not created.
+        assert!(ThetaDB::check(path, true).unwrap_err().is_io());
+        assert!(!Path::new(path).exists());
+
+        let db = ThetaDB::open(path)?;
+        let page_size = db.page_size() as usize;
+        let pairs = obtain_key_value_pairs(2000, 100, 10000);
+        for (key, value) in &pairs {
+            db.put(key, value)?;
+        }
+        for (key, _) in &pairs[1000..] {
+            db.delete(key)?;
+        }
+        let key = pairs[..1000]
+            .iter()
+            .find(|(_, value)| value.len() > page_size)
+            .unwrap()
+            .0
+            .clone();
+        let Some(ValueStorage::Overflow { head_page, .. }) = db.debugger()?.value_storage(&key)?
+        else {
+            panic!("the value should overflow");
+        };
+        let generation = db.generation()?;
+        drop(db);
+
+        let report = ThetaDB::check(path, true)?;
+        assert!(report.is_healthy(), "{:?}", report.problems);
+        assert!(!report.recovered_from_older_meta);
+        let len = fs::metadata(path).unwrap().len();
+
+        // Damages the head of an overflow chain.
+        let mut bytes = fs::read(path).unwrap();
+        let offset = head_page as usize * page_size;
+        bytes[offset..offset + page_size].fill(0xFF);
+        fs::write(path, &bytes).unwrap();
+
+        assert!(ThetaDB::check(path, false)?.is_healthy());
+        let report = ThetaDB::check(path, true)?;
+        assert!(!report.is_healthy());
+        // The damaged page is reached from the end of the chain, and cannot be read.
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].starts_with(&format!("page {head_page} cannot be read")));
+        assert_eq!(fs::metadata(path).unwrap().len(), len);
+
+        // Damages the latest meta page, then the older one as well.
+        let (latest, older) = match generation % 2 {
+            0 => (24, page_size + 24),
+            _ => (page_size + 24, 24),
+        };
+        bytes[latest] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+        let report = ThetaDB::check(path, false)?;
+        assert!(report.is_healthy());
+        assert!(report.recovered_from_older_meta);
+
+        bytes[older] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+        let report = ThetaDB::check(path, true)?;
+        assert_eq!(report.problems.len(), 1);
+
+        // Restores the meta pages, but truncates the file.
+        bytes[24] ^= 0xFF;
+        bytes[page_size + 24] ^= 0xFF;
+        fs::write(path, &bytes[..page_size * 4]).unwrap();
+        let report = ThetaDB::check(path, true)?;
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].starts_with("the file is truncated"));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_checksum_kind() -> Result<()> {
+    let path = "target/test_checksum_kind.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().checksum(ChecksumKind::XxHash64).open(path)?;
+        db.put(b"a", b"a")?;
+        drop(db);
+
+        // The stored algorithm is used regardless of the options.
+        let db = Options::new().checksum(ChecksumKind::Crc32).open(path)?;
+        assert!(!db.recovered_from_older_meta());
+        assert_eq!(db.get(b"a")?, Some(b"a".to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_to_json() -> Result<()> {
+    test_db("test_debugger_to_json.theta", |db| {
+        assert_eq!(
+            db.debugger()?.to_json()?,
+            r#"{"type":"leaf","page_id":2,"count":0,"fill_rate":0,"entries":[]}"#
+        );
+
+        db.put(b"a\"b", b"value")?;
+        let json = db.debugger()?.to_json()?;
+        assert!(json.contains(r#"{"key":"a\"b","value_len":5,"overflowed":false}"#));
+        assert!(!json.contains("value\""));
+
+        let json = db.debugger()?.to_json_with_values()?;
+        assert!(json.contains(r#""value":"value""#));
+
+        // The bytes that are not valid UTF-8 are hex encoded instead of being replaced.
+        db.put(b"k\xff", b"v\xfe\x00")?;
+        let json = db.debugger()?.to_json_with_values()?;
+        assert!(json.contains(
+            r#"{"key":{"hex":"6bff"},"value_len":3,"overflowed":false,"value":{"hex":"76fe00"}}"#
+        ));
+        db.delete(b"k\xff")?;
+
+        let page_size = db.page_size() as usize;
+        for (key, value) in obtain_key_value_pairs(500, MAX_KEY_LEN, page_size) {
+            db.put(key, value)?;
+        }
+        let json = db.debugger()?.to_json()?;
+        assert!(json.starts_with(r#"{"type":"branch""#));
+        assert!(json.contains(r#""children":[{"type":"#));
+        assert!(json.contains(r#""overflowed":true"#));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_dump() -> Result<()> {
+    test_db("test_dump.theta", |db| {
+        let page_size = db.page_size() as usize;
+        for (key, value) in obtain_key_value_pairs(300, MAX_KEY_LEN, page_size) {
+            db.put(key, value)?;
+        }
+
+        let mut dumped = Vec::new();
+        db.dump(&mut dumped)?;
+        let dumped = String::from_utf8(dumped).unwrap();
+        assert!(dumped.starts_with("┓\n┣━━━━━━┓"));
+        assert_eq!(dumped, format!("{:?}", db.debugger()?));
+
+        // The failure of the writer is reported as an I/O error.
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("broken"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let err = db.dump(&mut Broken).unwrap_err();
+        assert!(err.is_io());
+        assert!(err.to_string().contains("broken"), "{err}");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_leaf_fill_histogram() -> Result<()> {
+    test_db("test_debugger_leaf_fill_histogram.theta", |db| {
+        let histogram = db.debugger()?.leaf_fill_histogram()?;
+        assert_eq!(histogram, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        db.update(|tx| {
+            for i in 0..10000u32 {
+                tx.put(i.to_be_bytes(), [0; 32])?;
+            }
+            Ok(())
+        })?;
+        let debugger = db.debugger()?;
+        let histogram = debugger.leaf_fill_histogram()?;
+        let leaf_pages = debugger.stats()?.leaf_pages as usize;
+        assert_eq!(histogram.iter().sum::<usize>(), leaf_pages);
+        // The leaves are split in halves, except for the last one.
+        assert!(
+            histogram[4..].iter().sum::<usize>() >= leaf_pages - 1,
+            "{histogram:?}"
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_value_storage() -> Result<()> {
+    test_db("test_debugger_value_storage.theta", |db| {
+        let page_size = db.page_size() as usize;
+        db.put(b"small", b"value")?;
+        db.put(b"large", vec![7; page_size * 3])?;
+
+        let debugger = db.debugger()?;
+        assert_eq!(
+            debugger.value_storage(b"small")?,
+            Some(ValueStorage::Inline { len: 5 })
+        );
+        assert_eq!(debugger.value_storage(b"missing")?, None);
+
+        let Some(ValueStorage::Overflow {
+            chunk_count, len, ..
+        }) = debugger.value_storage(b"large")?
+        else {
+            panic!("the large value should be overflowed");
+        };
+        assert_eq!(len as usize, page_size * 3);
+        assert_eq!(chunk_count, 4);
+        assert_eq!(debugger.stats()?.overflow_pages, chunk_count);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_freelist_map() -> Result<()> {
+    test_db("test_debugger_freelist_map.theta", |db| {
+        let page_size = db.page_size() as usize;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size);
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        for (key, _) in &key_value_pairs[..250] {
+            db.delete(key)?;
+        }
+
+        let debugger = db.debugger()?;
+        let runs = debugger.freelist_map()?;
+        assert!(!runs.is_empty());
+        assert_eq!(
+            runs.iter().map(|(_, len)| *len as usize).sum::<usize>(),
+            debugger.freelist_len()?
+        );
+        assert!(runs.windows(2).all(|w| w[0].0 + w[0].1 < w[1].0));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_page_info() -> Result<()> {
+    test_db("test_debugger_page_info.theta", |db| {
+        let page_size = db.page_size() as usize;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size * 2);
+        db.extend(key_value_pairs.clone())?;
+        for (key, _) in &key_value_pairs[..100] {
+            db.delete(key)?;
+        }
+
+        let debugger = db.debugger()?;
+        let stats = debugger.stats()?;
+        let infos = (0..stats.total_pages)
+            .map(|id| debugger.page_info(id))
+            .collect::<Result<Vec<_>>>()?;
+        let count = |kind| infos.iter().filter(|info| info.kind == kind).count() as u32;
+
+        assert_eq!(count(PageKind::Meta), 2);
+        assert_eq!(count(PageKind::Branch), stats.branch_pages);
+        assert_eq!(count(PageKind::Leaf), stats.leaf_pages);
+        assert_eq!(count(PageKind::Overflow), stats.overflow_pages);
+        assert_eq!(count(PageKind::Free), stats.free_pages);
+        assert!(count(PageKind::Freelist) > 0);
+        assert_eq!(count(PageKind::Unreferenced), 0);
+
+        let leaf_entries = infos
+            .iter()
+            .filter(|info| info.kind == PageKind::Leaf)
+            .map(|info| info.entry_count as u64)
+            .sum::<u64>();
+        assert_eq!(leaf_entries, stats.entry_count);
+
+        // The links point to the pages of the expected kinds.
+        for info in &infos {
+            for child in &info.children {
+                let kind = infos[*child as usize].kind;
+                assert!(matches!(kind, PageKind::Branch | PageKind::Leaf));
+            }
+            if let Some(prev) = info.prev_page {
+                assert_eq!(infos[prev as usize].kind, info.kind);
+            }
+            if info.kind == PageKind::Branch {
+                assert_eq!(info.children.len() as u32, info.entry_count);
+            }
+        }
+
+        let err = debugger.page_info(stats.total_pages).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_debugger_stats() -> Result<()> {
+    test_db("test_debugger_stats.theta", |db| {
+        let stats = db.debugger()?.stats()?;
+        assert_eq!(
+            (stats.height, stats.leaf_pages, stats.branch_pages),
+            (1, 1, 0)
+        );
+        assert_eq!(stats.entry_count, 0);
+
+        let page_size = db.page_size() as usize;
+        let key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size * 2);
+        for (key, value) in &key_value_pairs {
+            db.put(key, value)?;
+        }
+        for (key, _) in &key_value_pairs[..100] {
+            db.delete(key)?;
+        }
+
+        let stats = db.debugger()?.stats()?;
+        assert!(stats.height > 1 && stats.branch_pages > 0);
+        assert!(stats.overflow_pages > 0 && stats.free_pages > 0);
+        assert_eq!(stats.entry_count as usize, key_value_pairs.len() - 100);
+
+        // Besides, there are two meta pages and at least one freelist page.
+        let used_pages = stats.branch_pages + stats.leaf_pages + stats.overflow_pages;
+        assert!(stats.total_pages >= used_pages + stats.free_pages + 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_metrics() -> Result<()> {
+    test_db("test_metrics.theta", |db| {
+        assert_eq!(db.metrics(), Metrics::default());
+
+        db.put(b"a", b"a")?;
+        db.put(b"b", b"b")?;
+        db.delete(b"a")?;
+        db.delete(b"absent")?;
+        assert_eq!(db.get(b"b")?, Some(b"b".to_vec()));
+        assert!(!db.contains(b"a")?);
+
+        // Uncommitted changes are not counted.
+        let mut tx = db.begin_tx_mut()?;
+        tx.put(b"c", b"c")?;
+        tx.delete(b"b")?;
+        drop(tx);
+
+        let metrics = db.metrics();
+        assert_eq!(metrics.read_txs, 2);
+        assert_eq!(metrics.write_txs, 5);
+        assert_eq!(metrics.keys_read, 2);
+        assert_eq!(metrics.keys_written, 2);
+        assert_eq!(metrics.keys_deleted, 1);
+        assert!(metrics.pages_allocated > 0);
+        assert!(metrics.pages_freed > 0);
+        assert_eq!(metrics.bytes_synced, 0);
+
+        // The pages allocated and freed within a transaction are not counted as freed.
+        db.reset_metrics();
+        db.put(b"d", b"d")?;
+        let freed = db.metrics().pages_freed;
+        db.reset_metrics();
+        let page_size = db.page_size() as usize;
+        db.update(|tx| {
+            tx.put(b"large", vec![0; page_size * 4])?;
+            tx.delete(b"large")?;
+            tx.put(b"e", b"e")
+        })?;
+        assert!(db.metrics().pages_allocated > freed + 4);
+        assert_eq!(db.metrics().pages_freed, freed);
+
+        db.reset_metrics();
+        assert_eq!(db.metrics(), Metrics::default());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_put_delete_many() -> Result<()> {
+    let path = "target/test_put_delete_many.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().max_value_len(100).open(path)?;
+        let key = |i: u32| i.to_be_bytes();
+
+        // An invalid pair leaves the transaction untouched, even the pairs before it.
+        db.update(|tx| {
+            let pairs = (0..100).map(|i| (key(i), vec![0; if i == 50 { 101 } else { 10 }]));
+            let err = tx.put_many(pairs).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::InputInvalid);
+            assert!(!tx.contains(key(0))? && !tx.contains(key(49))?);
+
+            tx.put_many((0..100).map(|i| (key(i), key(i))))?;
+            // The later pairs win.
+            tx.put_many([(key(0), b"a"), (key(0), b"b")])?;
+            Ok(())
+        })?;
+        assert_eq!(db.len()?, 100);
+        assert_eq!(db.get(key(0))?, Some(b"b".to_vec()));
+        assert_eq!(db.get(key(99))?, Some(key(99).to_vec()));
+
+        // The absent and duplicate keys are not counted.
+        let deleted = db.update(|tx| tx.delete_many((90..110).chain([95]).map(key)))?;
+        assert_eq!(deleted, 10);
+        assert_eq!(db.len()?, 90);
+        assert_eq!(db.update(|tx| tx.delete_many(Vec::<Vec<u8>>::new()))?, 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_extend_into_iter() -> Result<()> {
+    test_db("test_extend_into_iter.theta", |db| {
+        assert_eq!((&db).into_iter().count(), 0);
+
+        let page_size = db.page_size() as usize;
+        let mut key_value_pairs = obtain_key_value_pairs(500, MAX_KEY_LEN, page_size);
+        db.extend(key_value_pairs.clone())?;
+
+        // All or nothing.
+        let res = db.extend([(b"a".to_vec(), vec![]), (vec![0; MAX_KEY_LEN + 1], vec![])]);
+        assert_eq!(res.err().map(|e| e.code()), Some(ErrorCode::InputInvalid));
+        assert!(!db.contains(b"a")?);
+
+        key_value_pairs.sort_by(|l, r| l.0.cmp(&r.0));
+        assert_eq!(
+            (&db).into_iter().collect::<Result<Vec<_>>>()?,
+            key_value_pairs
+        );
+
+        let mut values = Vec::new();
+        for key_value in &db {
+            values.push(key_value?.1);
+        }
+        assert_eq!(values.len(), key_value_pairs.len());
+
+        let tail = db.cursor_from_key(&key_value_pairs[100].0)?.iter();
+        assert_eq!(tail.collect::<Result<Vec<_>>>()?, key_value_pairs[100..]);
+
+        // Backward from the last record, or from the middle.
+        let reversed = db.last_cursor()?.iter_rev();
+        assert!(reversed
+            .map(Result::unwrap)
+            .eq(key_value_pairs.iter().rev().cloned()));
+        let head = db.cursor_from_key(&key_value_pairs[100].0)?.iter_rev();
+        assert!(head
+            .take(3)
+            .map(Result::unwrap)
+            .eq(key_value_pairs[98..=100].iter().rev().cloned()));
+        Ok(())
+    })
+}
+
 #[test]
 fn test_cursor() -> Result<()> {
     test_db("test_cursor.theta", |db| {
@@ -112,6 +2724,389 @@ fn test_cursor() -> Result<()> {
     })
 }
 
+#[test]
+fn test_cursor_seek_result() -> Result<()> {
+    test_db("test_cursor_seek_result.theta", |db| {
+        assert_eq!(db.first_cursor()?.seek_result(b"a")?, SeekResult::NotFound);
+
+        // The keys span several leaves, so the next key may be in the next leaf.
+        db.update(|tx| {
+            for i in (0..2000u32).step_by(2) {
+                tx.put(i.to_be_bytes(), [0; 64])?;
+            }
+            Ok(())
+        })?;
+
+        let mut cursor = db.first_cursor()?;
+        for i in 0..2000u32 {
+            let res = cursor.seek_result(&i.to_be_bytes())?;
+            if i % 2 == 0 {
+                assert_eq!(res, SeekResult::Exact);
+                assert_eq!(cursor.key()?, Some(i.to_be_bytes().to_vec()));
+            } else if i < 1999 {
+                let next = (i + 1).to_be_bytes().to_vec();
+                assert_eq!(res, SeekResult::Positioned(next.clone()));
+                assert_eq!(cursor.key()?, Some(next));
+            } else {
+                assert_eq!(res, SeekResult::NotFound);
+                assert_eq!(cursor.key()?, None);
+            }
+        }
+
+        // The cursor keeps moving from where it lands.
+        assert_eq!(cursor.seek_result(&[])?, SeekResult::Positioned(vec![0; 4]));
+        assert!(cursor.next()?);
+        assert_eq!(cursor.key()?, Some(2u32.to_be_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cursor_mut_delete_current() -> Result<()> {
+    test_db("test_cursor_mut_delete_current.theta", |db| {
+        let mut model = obtain_key_value_pairs(3000, 16, 128)
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        db.extend(&model)?;
+
+        // Interleaves the moves and the deletions randomly, so the deletions merge the leaves
+        // under the cursor. The anchor is the key of the current record, or the deleted one.
+        let mut rng = rand::thread_rng();
+        db.update(|tx| {
+            let mut cursor = tx.cursor_mut();
+            assert!(cursor.first()?);
+            let mut anchor = model.keys().next().cloned().unwrap();
+            let mut on_record = true;
+
+            for _ in 0..6000 {
+                let expected = match rng.gen_range(0..20) {
+                    0..=7 => {
+                        assert_eq!(cursor.delete_current()?, on_record);
+                        if on_record {
+                            model.remove(&anchor);
+                            on_record = false;
+                        }
+                        assert_eq!(cursor.key_value()?, None);
+                        continue;
+                    }
+                    8..=16 => {
+                        let bounds = (Bound::Excluded(&anchor), Bound::Unbounded);
+                        let expected = model.range::<Vec<u8>, _>(bounds).next();
+                        assert_eq!(cursor.next()?, expected.is_some());
+                        expected
+                    }
+                    _ => {
+                        let expected = model.range::<Vec<u8>, _>(..&anchor).next_back();
+                        assert_eq!(cursor.prev()?, expected.is_some());
+                        expected
+                    }
+                };
+
+                let expected = expected.map(|(k, v)| (k.clone(), v.clone()));
+                assert_eq!(cursor.key_value()?, expected);
+                match expected {
+                    Some((key, _)) => anchor = key,
+                    // Starts over once the cursor falls off either end.
+                    None => match model.keys().next() {
+                        Some(key) => {
+                            assert!(cursor.first()?);
+                            anchor = key.clone();
+                        }
+                        None => break,
+                    },
+                }
+                on_record = true;
+            }
+            Ok(())
+        })?;
+
+        let records = db.first_cursor()?.iter().collect::<Result<Vec<_>>>()?;
+        assert_eq!(records, model.into_iter().collect::<Vec<_>>());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cursor_before_key() -> Result<()> {
+    test_db("test_cursor_before_key.theta", |db| {
+        assert_eq!(db.cursor_before_key(b"a")?.key()?, None);
+
+        // The keys span several leaves, so the previous key may be in the previous leaf.
+        db.update(|tx| {
+            for i in (2..2000u32).step_by(2) {
+                tx.put(i.to_be_bytes(), [0; 64])?;
+            }
+            Ok(())
+        })?;
+
+        for i in 0..2002u32 {
+            let expected = match i {
+                0 | 1 => None,
+                2000.. => Some(1998u32),
+                i => Some(i & !1),
+            };
+            let key = db.cursor_before_key(&i.to_be_bytes())?.key()?;
+            assert_eq!(key, expected.map(|e| e.to_be_bytes().to_vec()));
+        }
+
+        // Pages through the records before a key in descending order.
+        let mut anchor = 1001u32.to_be_bytes().to_vec();
+        let mut pages = Vec::new();
+        loop {
+            let mut cursor = db.cursor_before_key(&anchor)?;
+            let mut page = Vec::new();
+            while page.len() < 20 {
+                let Some(key) = cursor.key()? else {
+                    break;
+                };
+                page.push(u32::from_be_bytes(key.try_into().unwrap()));
+                cursor.prev()?;
+            }
+            let Some(&last) = page.last() else {
+                break;
+            };
+            anchor = (last - 1).to_be_bytes().to_vec();
+            pages.push(page);
+        }
+        assert_eq!(pages.len(), 25);
+        let keys = pages.concat();
+        assert_eq!(keys, (2..=1000).rev().step_by(2).collect::<Vec<_>>());
+
+        // Also within a transaction.
+        let tx = db.begin_tx()?;
+        assert_eq!(
+            tx.cursor_before_key(&[0xff])?.key()?,
+            Some(1998u32.to_be_bytes().to_vec())
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_read_session() -> Result<()> {
+    test_db("test_read_session.theta", |db| {
+        db.extend((0..1000u32).map(|i| (i.to_be_bytes(), i.to_le_bytes())))?;
+
+        let session = db.read_session()?;
+        assert!(session.contains(7u32.to_be_bytes())?);
+        assert_eq!(
+            session.get(7u32.to_be_bytes())?,
+            Some(7u32.to_le_bytes().to_vec())
+        );
+        let mut buf = Vec::new();
+        assert!(!session.get_into(1000u32.to_be_bytes(), &mut buf)?);
+
+        // Several cursors share the session and move independently.
+        let (mut forward, mut backward) = (session.first_cursor()?, session.last_cursor()?);
+        for i in 0..1000u32 {
+            assert_eq!(forward.key()?, Some(i.to_be_bytes().to_vec()));
+            assert_eq!(backward.key()?, Some((999 - i).to_be_bytes().to_vec()));
+            assert_eq!(forward.next()?, i < 999);
+            assert_eq!(backward.prev()?, i < 999);
+        }
+        let cursor = session.cursor_from_key(&500u32.to_be_bytes())?;
+        assert_eq!(cursor.value()?, Some(500u32.to_le_bytes().to_vec()));
+        drop((forward, backward, cursor));
+
+        // A writer commits only after the session is dropped.
+        let (sender, receiver) = mpsc::channel();
+        let shared = &db;
+        thread::scope(|s| {
+            s.spawn(move || {
+                shared.put(b"a", b"a").unwrap();
+                sender.send(()).unwrap();
+            });
+            thread::sleep(Duration::from_millis(100));
+            assert!(receiver.try_recv().is_err());
+            assert!(!session.contains(b"a").unwrap());
+
+            // The commit queued for the storage would block reading it again, so the other
+            // reads on the thread fail rather than waiting for the session forever.
+            let overlapped = |res: Result<()>| res.unwrap_err().code() == ErrorCode::TxOverlapped;
+            assert!(overlapped(db.get(b"a").map(drop)));
+            assert!(overlapped(db.begin_tx().map(drop)));
+            assert!(overlapped(db.generation().map(drop)));
+            assert!(overlapped(db.file_len().map(drop)));
+            assert!(overlapped(db.read_session().map(drop)));
+            assert!(overlapped(db.debugger().map(drop)));
+            drop(session);
+            receiver.recv().unwrap();
+        });
+        assert!(db.read_session()?.contains(b"a")?);
+
+        // So do the cursors started before the session.
+        let cursor = db.first_cursor()?;
+        let session = db.read_session()?;
+        let err = cursor.key().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TxOverlapped);
+        assert_eq!(
+            session.first_cursor()?.key()?,
+            Some(0u32.to_be_bytes().to_vec())
+        );
+        drop(session);
+        assert_eq!(cursor.key()?, Some(0u32.to_be_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cursor_approx_rank() -> Result<()> {
+    test_db("test_cursor_approx_rank.theta", |db| {
+        assert_eq!(db.first_cursor()?.approx_rank()?, None);
+
+        let count = 2000u32;
+        db.update(|tx| {
+            for i in 0..count {
+                tx.put(i.to_be_bytes(), [0; 64])?;
+            }
+            Ok(())
+        })?;
+        assert!(db.debugger()?.stats()?.height > 1);
+
+        let mut cursor = db.first_cursor()?;
+        assert_eq!(cursor.approx_rank()?, Some(0.0));
+
+        let mut last_rank = 0.0;
+        for i in 0..count {
+            let rank = cursor.approx_rank()?.unwrap();
+            assert!(rank >= last_rank && rank < 1.0);
+            // Sequential keys fill the leaves evenly, so the estimate is close.
+            let exact = i as f64 / count as f64;
+            assert!((rank - exact).abs() < 0.1, "{rank} {exact}");
+            last_rank = rank;
+            cursor.next()?;
+        }
+        assert_eq!(cursor.approx_rank()?, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cursor_fork() -> Result<()> {
+    test_db("test_cursor_fork.theta", |db| {
+        for i in 0..100u32 {
+            db.put(i.to_be_bytes(), i.to_be_bytes())?;
+        }
+
+        let mut cursor = db.cursor_from_key(&10u32.to_be_bytes())?;
+        let mut forked = cursor.fork()?;
+        assert_eq!(forked.key()?, cursor.key()?);
+
+        // The cursors move independently.
+        cursor.next()?;
+        forked.prev()?;
+        assert_eq!(cursor.key()?, Some(11u32.to_be_bytes().to_vec()));
+        assert_eq!(forked.key()?, Some(9u32.to_be_bytes().to_vec()));
+
+        // The forked cursor outlives the original one.
+        drop(cursor);
+        assert_eq!(forked.iter().count(), 91);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_tx_cursor() -> Result<()> {
+    test_db("test_tx_cursor.theta", |db| {
+        for i in 0..10u32 {
+            db.put(i.to_be_bytes(), i.to_be_bytes())?;
+        }
+
+        let tx = db.begin_tx()?;
+        db.put(10u32.to_be_bytes(), 10u32.to_be_bytes())?;
+        db.delete(0u32.to_be_bytes())?;
+
+        // The cursors read the view of the transaction, not the committed changes.
+        assert_eq!(tx.first_cursor()?.key()?, Some(0u32.to_be_bytes().to_vec()));
+        assert_eq!(tx.last_cursor()?.key()?, Some(9u32.to_be_bytes().to_vec()));
+        let cursor = tx.cursor_from_key(&5u32.to_be_bytes())?;
+        assert_eq!(cursor.value()?, Some(5u32.to_be_bytes().to_vec()));
+
+        // The cursor outlives the transaction.
+        drop(tx);
+        assert_eq!(cursor.iter().count(), 5);
+        assert_eq!(db.first_cursor()?.key()?, Some(1u32.to_be_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_iter_corrupted() -> Result<()> {
+    let path = "target/test_iter_corrupted.theta";
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        let page_size = db.page_size() as usize;
+        db.update(|tx| {
+            for i in 0..10u8 {
+                let value = if i == 5 {
+                    vec![0xAB; page_size * 2]
+                } else {
+                    vec![i]
+                };
+                tx.put([i], value)?;
+            }
+            Ok(())
+        })?;
+        drop(db);
+
+        // Break the length of the first chunk of the overflowed value.
+        let mut bytes = fs::read(path).unwrap();
+        for page in bytes.chunks_mut(page_size) {
+            if page[page_size - 16..].iter().all(|&b| b == 0xAB) {
+                page[..4].copy_from_slice(&0x7FFF_FFF0u32.to_ne_bytes());
+            }
+        }
+        fs::write(path, bytes).unwrap();
+
+        let db = ThetaDB::open(path)?;
+        let mut iter = db.first_cursor()?.iter();
+        for i in 0..5u8 {
+            assert_eq!(iter.next().transpose()?, Some((vec![i], vec![i])));
+        }
+        assert!(iter.next().unwrap().unwrap_err().is_corrupted());
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_corrupted_error_context() -> Result<()> {
+    let path = "target/test_corrupted_error_context.theta";
+
+    with_cleanup(&[path], || {
+        let db = ThetaDB::open(path)?;
+        let page_size = db.page_size() as usize;
+        db.extend([(b"a", b"1"), (b"b", b"2"), (b"c", b"3")])?;
+        let debugger = db.debugger()?;
+        let leaf_id = (0..debugger.stats()?.total_pages)
+            .find(|id| debugger.page_info(*id).unwrap().kind == PageKind::Leaf)
+            .unwrap();
+        drop(debugger);
+        drop(db);
+
+        // Point the second slot of the leaf out of the page, after the node header (4 bytes),
+        // the slotted header (8 bytes) and the first slot (8 bytes).
+        let mut bytes = fs::read(path).unwrap();
+        let offset = leaf_id as usize * page_size + 20;
+        bytes[offset..offset + 4].copy_from_slice(&0x7FFF_0000u32.to_ne_bytes());
+        fs::write(path, bytes).unwrap();
+
+        // Searching the leaf reads the broken slot.
+        let db = ThetaDB::open(path)?;
+        let err = db.get(b"b").unwrap_err();
+        assert!(err.is_corrupted());
+        let message = err.to_string();
+        let expected = format!(
+            "leaf page {leaf_id}: slot 1 has the offset {}",
+            0x7FFF_0000u32
+        );
+        assert!(message.contains(&expected), "{message}");
+        Ok(())
+    })
+}
+
 #[test]
 fn test_open_invalid_file() {
     let path = format!("target/invalid.theta");
@@ -130,6 +3125,190 @@ fn test_open_invalid_file() {
     _ = fs::remove_file(&path);
 }
 
+#[test]
+fn test_create_dirs() -> Result<()> {
+    let dir = "target/test_create_dirs";
+    let path = "target/test_create_dirs/db.theta";
+
+    with_cleanup(&[path, dir], || {
+        let err = Options::new().create_dirs(false).open(path).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::IO);
+        assert!(!Path::new(dir).exists());
+
+        Options::new().open(path)?;
+        Options::new().create_dirs(false).open(path)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_dry_run() -> Result<()> {
+    let path = "target/test_dry_run.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new().force_sync(true).open(path)?;
+        db.extend((0..1000u32).map(|i| (i.to_be_bytes(), [1; 200])))?;
+
+        let migrate = |tx: &mut TxMut| {
+            for i in 1000..1500u32 {
+                tx.put(i.to_be_bytes(), [2; 200])?;
+            }
+            for i in 0..300u32 {
+                tx.delete(i.to_be_bytes())?;
+            }
+            tx.put(999u32.to_be_bytes(), vec![3; 10_000])
+        };
+
+        let generation = db.generation()?;
+        let report = db.dry_run(migrate)?;
+        assert_eq!(report.changes.len(), 801);
+        assert_eq!(
+            report.changes[500],
+            ChangeEvent::Delete {
+                key: 0u32.to_be_bytes().to_vec()
+            }
+        );
+        assert_eq!((report.keys_written, report.keys_deleted), (501, 300));
+
+        // Nothing is persisted.
+        assert_eq!(db.generation()?, generation);
+        assert_eq!((&db).into_iter().count(), 1000);
+        assert_eq!(db.get(999u32.to_be_bytes())?, Some(vec![1; 200]));
+
+        // The report matches committing the same changes.
+        db.reset_metrics();
+        db.update(migrate)?;
+        let metrics = db.metrics();
+        let stats = db.debugger()?.stats()?;
+        assert_eq!(
+            metrics.bytes_synced,
+            report.written_pages * db.page_size() as u64
+        );
+        assert_eq!(metrics.pages_allocated, report.allocated_pages);
+        assert_eq!(metrics.pages_freed, report.freed_pages);
+        assert_eq!(stats.total_pages, report.page_count);
+        assert_eq!(stats.free_pages, report.free_pages);
+
+        // A dry run without changes writes nothing, and the error of the closure is returned.
+        let report = db.dry_run(|tx| tx.get(b"a").map(drop))?;
+        assert_eq!(report.written_pages, 0);
+        assert_eq!(report.page_count, stats.total_pages);
+        assert_eq!(report.free_pages, stats.free_pages);
+        let err = db
+            .dry_run(|tx| tx.put(vec![0; MAX_KEY_LEN + 1], b""))
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_create_seeded() -> Result<()> {
+    let path = "target/test_create_seeded.theta";
+
+    with_cleanup(&[path], || {
+        let seed = |tx: &mut TxMut| {
+            for i in 0..100u32 {
+                tx.put(i.to_be_bytes(), b"seed")?;
+            }
+            Ok(())
+        };
+
+        // A failed seed leaves no data, and runs again on the next open.
+        let err = ThetaDB::create_seeded(path, Options::new(), |tx| {
+            seed(tx)?;
+            tx.put(vec![0; MAX_KEY_LEN + 1], b"")
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!((&ThetaDB::open(path)?).into_iter().count(), 0);
+
+        let db = ThetaDB::create_seeded(path, Options::new(), seed)?;
+        assert_eq!((&db).into_iter().count(), 100);
+        db.delete(0u32.to_be_bytes())?;
+        drop(db);
+
+        // The seed is not run again on a ThetaDB that has been written.
+        let db = ThetaDB::create_seeded(path, Options::new(), seed)?;
+        assert_eq!((&db).into_iter().count(), 99);
+        drop(db);
+        fs::remove_file(path).unwrap();
+
+        // Shrinking the file on close commits nothing after a failed seed, which runs again.
+        let options = Options::new().with_shrink_on_close(true);
+        let err = ThetaDB::create_seeded(path, options.clone(), |tx| {
+            seed(tx)?;
+            tx.put(vec![0; MAX_KEY_LEN + 1], b"")
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert_eq!(ThetaDB::open(path)?.generation()?, 0);
+
+        let db = ThetaDB::create_seeded(path, options, seed)?;
+        assert_eq!((&db).into_iter().count(), 100);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_warnings() -> Result<()> {
+    let path = "target/test_open_warnings.theta";
+
+    with_cleanup(&[path], || {
+        assert!(ThetaDB::open(path)?.warnings().is_empty());
+        fs::remove_file(path).unwrap();
+
+        // Small pages limit the keys, and the file is grown in multiples of the OS page size.
+        let db = Options::new()
+            .page_size(Some(512))
+            .initial_capacity_bytes(10_000)
+            .open(path)?;
+        let file_len = db.file_len()?;
+        assert!(file_len > 10_000);
+        assert_eq!(
+            db.warnings(),
+            [
+                OpenWarning::MaxKeyLenLimited {
+                    requested: MAX_KEY_LEN,
+                    actual: db.max_key_len()
+                },
+                OpenWarning::InitialCapacityRounded {
+                    requested: 10_000,
+                    actual: file_len
+                }
+            ]
+        );
+        drop(db);
+
+        // The file already holds the initial capacity, and the keys fit in the pages.
+        let db = Options::new()
+            .max_key_len(64)
+            .initial_capacity_bytes(10_000)
+            .open(path)?;
+        assert!(db.warnings().is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_was_created() -> Result<()> {
+    let path = "target/test_was_created.theta";
+
+    with_cleanup(&[path], || {
+        assert!(ThetaDB::open(path)?.was_created());
+        assert!(!ThetaDB::open(path)?.was_created());
+
+        // An empty file is initialized by the open.
+        fs::write(path, b"").unwrap();
+        assert!(ThetaDB::open(path)?.was_created());
+        assert!(!ThetaDB::open(path)?.was_created());
+        Ok(())
+    })
+}
+
 #[test]
 fn test_put_large_key_value() -> Result<()> {
     test_db("test_error.theta", |db| {
@@ -137,24 +3316,135 @@ fn test_put_large_key_value() -> Result<()> {
         db.put(key, b"")?;
 
         let key = vec![1; MAX_KEY_LEN + 1];
-        assert_eq!(
-            db.put(key, b"").err().unwrap().code(),
-            ErrorCode::InputInvalid
-        );
+        let err = db.put(key, b"").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert!(err.to_string().contains(&format!(
+            "({}) cannot exceed {}",
+            MAX_KEY_LEN + 1,
+            MAX_KEY_LEN
+        )));
 
         let value = vec![1; MAX_VALUE_LEN];
         db.put(b"key", value)?;
 
         let value = vec![1; MAX_VALUE_LEN + 1];
-        assert_eq!(
-            db.put(b"key", value).err().unwrap().code(),
-            ErrorCode::InputInvalid
+        let err = db.put(b"key", value).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert!(!err.is_io() && !err.is_corrupted());
+        assert!(err.to_string().contains(&format!(
+            "({}) cannot exceed {}",
+            MAX_VALUE_LEN + 1,
+            MAX_VALUE_LEN
+        )));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_max_key_value_len() -> Result<()> {
+    let path = "target/test_max_key_value_len.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new()
+            .max_key_len(8)
+            .max_value_len(MAX_VALUE_LEN * 2)
+            .open(path)?;
+        assert_eq!(db.max_key_len(), 8);
+        assert_eq!(db.max_value_len(), MAX_VALUE_LEN * 2);
+        db.put([1; 8], b"")?;
+        let err = db.put([1; 9], b"").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert!(err.to_string().contains("(9) cannot exceed 8"), "{err}");
+
+        let value = vec![1; MAX_VALUE_LEN * 2];
+        db.put(b"key", &value)?;
+        assert_eq!(db.get(b"key")?, Some(value));
+        let err = db
+            .put(b"key", vec![1; MAX_VALUE_LEN * 2 + 1])
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        drop(db);
+
+        // The limits are not persisted.
+        let db = ThetaDB::open(path)?;
+        assert_eq!(db.max_key_len(), MAX_KEY_LEN);
+        assert_eq!(db.max_value_len(), MAX_VALUE_LEN);
+        db.put([1; 9], b"")?;
+        assert!(db.put(b"key", vec![1; MAX_VALUE_LEN + 1]).is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_disable_overflow() -> Result<()> {
+    let path = "target/test_disable_overflow.theta";
+
+    with_cleanup(&[path], || {
+        let db = Options::new()
+            .page_size(Some(4096))
+            .disable_overflow(true)
+            .open(path)?;
+        db.put(b"key", vec![1; 1021])?;
+        assert!(matches!(
+            db.debugger()?.value_storage(b"key")?,
+            Some(ValueStorage::Inline { len: 1021 })
+        ));
+
+        let err = db.put(b"key", vec![1; 1022]).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InputInvalid);
+        assert!(
+            err.to_string().contains("(1022) cannot exceed 1021"),
+            "{err}"
         );
+        drop(db);
 
+        let db = ThetaDB::open(path)?;
+        db.put(b"key", vec![1; 1022])?;
+        assert!(matches!(
+            db.debugger()?.value_storage(b"key")?,
+            Some(ValueStorage::Overflow { len: 1022, .. })
+        ));
         Ok(())
     })
 }
 
+#[test]
+fn test_small_page_size() -> Result<()> {
+    for (page_size, max_key_len) in [(512, 89), (1024, 243)] {
+        let path = format!("target/test_small_page_size_{page_size}.theta");
+
+        with_cleanup(&[&path], || {
+            // Small pages lower the limit below the default one.
+            let db = Options::new().page_size(Some(page_size)).open(&path)?;
+            assert_eq!(db.max_key_len(), max_key_len);
+            let err = db.put(vec![1; max_key_len + 1], b"").err().unwrap();
+            assert_eq!(err.code(), ErrorCode::InputInvalid);
+
+            let key_value_pairs = obtain_key_value_pairs(5000, max_key_len, 1000);
+            db.extend(key_value_pairs.iter().map(|(k, v)| (k, v)))?;
+            for (key, value) in &key_value_pairs {
+                assert_eq!(db.get(key)?.as_ref(), Some(value));
+            }
+
+            // Deleting most of the records merges the nodes.
+            let (deleted, kept) = key_value_pairs.split_at(key_value_pairs.len() * 9 / 10);
+            db.update(|tx| deleted.iter().try_for_each(|(key, _)| tx.delete(key)))?;
+            drop(db);
+
+            let db = ThetaDB::open(&path)?;
+            assert_eq!(db.page_size(), page_size);
+            assert_eq!((&db).into_iter().count(), kept.len());
+            for (key, value) in kept {
+                assert_eq!(db.get(key)?.as_ref(), Some(value));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
 fn test_db(name: &str, test: impl FnOnce(ThetaDB) -> Result<()>) -> Result<()> {
     let path = format!("target/{name}");
     let res = ThetaDB::open(&path).and_then(test);
@@ -162,6 +3452,16 @@ fn test_db(name: &str, test: impl FnOnce(ThetaDB) -> Result<()>) -> Result<()> {
     res
 }
 
+/// Runs the test, then removes the files (or empty directories) it created whether it
+/// succeeded or not.
+fn with_cleanup(paths: &[&str], test: impl FnOnce() -> Result<()>) -> Result<()> {
+    let res = test();
+    for path in paths {
+        _ = fs::remove_file(path).or_else(|_| fs::remove_dir(path));
+    }
+    res
+}
+
 fn obtain_key_value_pairs(
     count: usize,
     key_max_len: usize,
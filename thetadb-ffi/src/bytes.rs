@@ -3,12 +3,18 @@ use std::{ffi::c_void, mem::ManuallyDrop, ptr, slice, str};
 use crate::ffi_call::{ffi_call, FFICallState};
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct FFIBytesRef {
     ptr: *const c_void,
     length: u32,
 }
 
 impl FFIBytesRef {
+    #[inline]
+    pub(crate) fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
     #[inline]
     pub(crate) unsafe fn into_slice<'a>(self) -> &'a [u8] {
         slice::from_raw_parts(self.ptr as *const u8, self.length as usize)
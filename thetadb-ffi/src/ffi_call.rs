@@ -10,6 +10,9 @@ pub enum FFICallCode {
     FFICallErrInputInvalid,
     FFICallErrFileUnexpected,
     FFICallErrDBCorrupted,
+    FFICallErrDBFull,
+    FFICallErrTxOverlapped,
+    FFICallErrAddressSpaceExhausted,
 }
 
 use FFICallCode::*;
@@ -90,6 +93,9 @@ impl FFICallResult for thetadb::Error {
             thetadb::ErrorCode::InputInvalid => FFICallErrInputInvalid,
             thetadb::ErrorCode::FileUnexpected => FFICallErrFileUnexpected,
             thetadb::ErrorCode::DatabaseCorrupted => FFICallErrDBCorrupted,
+            thetadb::ErrorCode::DatabaseFull => FFICallErrDBFull,
+            thetadb::ErrorCode::TxOverlapped => FFICallErrTxOverlapped,
+            thetadb::ErrorCode::AddressSpaceExhausted => FFICallErrAddressSpaceExhausted,
         };
         let err_desc = self.to_string().into();
 
@@ -120,6 +126,27 @@ impl FFIDefault for u8 {
     }
 }
 
+impl FFIDefault for u32 {
+    #[inline]
+    fn default() -> Self {
+        0
+    }
+}
+
+impl FFIDefault for u64 {
+    #[inline]
+    fn default() -> Self {
+        0
+    }
+}
+
+impl FFIDefault for i64 {
+    #[inline]
+    fn default() -> Self {
+        0
+    }
+}
+
 impl FFIDefault for FFIBytes {
     #[inline]
     fn default() -> Self {
@@ -53,6 +53,19 @@ pub mod db {
         })
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_version(call_state: &mut FFICallState) -> FFIBytes {
+        ffi_call(call_state, || Ok(thetadb::VERSION.to_string().into()))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_file_format_version(
+        path: FFIBytesRef,
+        call_state: &mut FFICallState,
+    ) -> u32 {
+        ffi_call(call_state, || ThetaDB::file_format_version(path.into_str()))
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_dealloc(db: *mut c_void, call_state: &mut FFICallState) {
         ffi_call(call_state, || {
@@ -62,6 +75,123 @@ pub mod db {
         })
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_close(db: *mut c_void, call_state: &mut FFICallState) {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            Box::from_raw(db as *mut ThetaDB).close()
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_path(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> FFIBytes {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            Ok(db.path().to_string_lossy().into_owned().into())
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_page_size(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u32 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            Ok(db.page_size())
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_max_key_len(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            Ok(db.max_key_len() as u64)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_max_value_len(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            Ok(db.max_value_len() as u64)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_file_len(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.file_len().map(|len| len as u64)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_generation(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.generation()
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_was_created(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u8 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            Ok(if db.was_created() { 1 } else { 0 })
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_user_version(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u32 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.user_version()
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_set_user_version(
+        db: *const c_void,
+        version: u32,
+        call_state: &mut FFICallState,
+    ) {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.set_user_version(version)
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_contains(
         db: *const c_void,
@@ -103,6 +233,20 @@ pub mod db {
         })
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_increment(
+        db: *const c_void,
+        key: FFIBytesRef,
+        delta: i64,
+        call_state: &mut FFICallState,
+    ) -> i64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.increment(key.into_slice(), delta)
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_delete(
         db: *const c_void,
@@ -115,11 +259,147 @@ pub mod db {
             db.delete(key.into_slice())
         })
     }
+
+    /// Get a page of up to `limit` records whose keys are strictly after `after`, or from the
+    /// first record if `after` is null.
+    ///
+    /// The records are packed into the returned bytes one after another, each as the
+    /// little-endian `u32` length of the key, the key, the little-endian `u32` length of the
+    /// value and the value. The key to resume from is written to `resume`, which is null if
+    /// there are no more records.
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_scan_page(
+        db: *const c_void,
+        after: FFIBytesRef,
+        limit: u64,
+        resume: *mut FFIBytes,
+        call_state: &mut FFICallState,
+    ) -> FFIBytes {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            check_null_ptr!(resume);
+            let db = &*(db as *const ThetaDB);
+            let after = (!after.is_null()).then(|| after.into_slice());
+            let (page, next) = db.scan_page(after, limit.try_into().unwrap_or(usize::MAX))?;
+
+            let mut packed = Vec::new();
+            for (key, value) in page {
+                packed.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                packed.extend_from_slice(&key);
+                packed.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                packed.extend_from_slice(&value);
+            }
+            *resume = next.map(Into::into).unwrap_or_default();
+            Ok(packed.into())
+        })
+    }
 }
 
-pub mod tx {
+pub mod debug {
     use std::ffi::c_void;
 
+    use thetadb::{PageUsage, Stats, ThetaDB};
+
+    use crate::ffi_call::{ffi_call, FFICallState, FFIDefault};
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct ThetaDBStats {
+        height: u32,
+        branch_pages: u32,
+        leaf_pages: u32,
+        overflow_pages: u32,
+        free_pages: u32,
+        total_pages: u32,
+        entry_count: u64,
+    }
+
+    impl From<Stats> for ThetaDBStats {
+        fn from(value: Stats) -> Self {
+            Self {
+                height: value.height,
+                branch_pages: value.branch_pages,
+                leaf_pages: value.leaf_pages,
+                overflow_pages: value.overflow_pages,
+                free_pages: value.free_pages,
+                total_pages: value.total_pages,
+                entry_count: value.entry_count,
+            }
+        }
+    }
+
+    impl FFIDefault for ThetaDBStats {
+        #[inline]
+        fn default() -> Self {
+            Default::default()
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_freelist_len(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> u64 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.debugger()?.freelist_len().map(|len| len as u64)
+        })
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct ThetaDBPageUsage {
+        total_pages: u32,
+        free_pages: u32,
+        live_pages: u32,
+    }
+
+    impl From<PageUsage> for ThetaDBPageUsage {
+        fn from(value: PageUsage) -> Self {
+            Self {
+                total_pages: value.total_pages,
+                free_pages: value.free_pages,
+                live_pages: value.live_pages,
+            }
+        }
+    }
+
+    impl FFIDefault for ThetaDBPageUsage {
+        #[inline]
+        fn default() -> Self {
+            Default::default()
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_page_usage(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> ThetaDBPageUsage {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.page_usage().map(Into::into)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_stats(
+        db: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> ThetaDBStats {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.debugger()?.stats().map(Into::into)
+        })
+    }
+}
+
+pub mod tx {
+    use std::{ffi::c_void, slice};
+
     use thetadb::{ThetaDB, Tx};
 
     use crate::{
@@ -127,6 +407,33 @@ pub mod tx {
         ffi_call::{ffi_call, FFICallState},
     };
 
+    /// The callback of `thetadb_view` and `thetadb_update`, which receives the context and the
+    /// transaction. For `thetadb_update`, returning nonzero commits the transaction.
+    ///
+    /// The transaction is managed by ThetaDB, so it must not be deallocated or committed by the
+    /// callback, nor be used after the callback returns.
+    pub type ThetaDBTxCallback =
+        Option<unsafe extern "C" fn(ctx: *mut c_void, tx: *mut c_void) -> u8>;
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_view(
+        db: *const c_void,
+        ctx: *mut c_void,
+        callback: ThetaDBTxCallback,
+        call_state: &mut FFICallState,
+    ) {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let Some(callback) = callback else {
+                return Ok(());
+            };
+            let db = &*(db as *const ThetaDB);
+            let mut tx = db.begin_tx()?;
+            callback(ctx, &mut tx as *mut Tx as *mut c_void);
+            Ok(())
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_begin_tx(
         db: *const c_void,
@@ -177,18 +484,142 @@ pub mod tx {
                 .map(|value| value.map(FFIBytes::new).unwrap_or_default())
         })
     }
+
+    /// Get the values of `count` keys from the same view, writing them to `values` in the order
+    /// of the keys. The absent ones are written as null bytes, and nothing is written if it
+    /// fails.
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_tx_get_many(
+        tx: *const c_void,
+        keys: *const FFIBytesRef,
+        count: u32,
+        values: *mut FFIBytes,
+        call_state: &mut FFICallState,
+    ) {
+        ffi_call(call_state, || {
+            check_null_ptr!(tx);
+            if count == 0 {
+                return Ok(());
+            }
+            check_null_ptr!(keys);
+            check_null_ptr!(values);
+
+            let tx = &*(tx as *const Tx);
+            let keys = slice::from_raw_parts(keys, count as usize)
+                .iter()
+                .map(|key| key.into_slice())
+                .collect::<Vec<_>>();
+            let found = tx.get_many(&keys)?;
+
+            for (idx, value) in found.into_iter().enumerate() {
+                values
+                    .add(idx)
+                    .write(value.map(FFIBytes::new).unwrap_or_default());
+            }
+            Ok(())
+        })
+    }
+
+    /// The cursors created from the transaction read the same view as it, and must be
+    /// deallocated with `thetadb_cursor_dealloc`. They may outlive the transaction, but not the
+    /// ThetaDB.
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_tx_first_cursor(
+        tx: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(tx);
+            let tx = &*(tx as *const Tx);
+            tx.first_cursor()
+                .map(Box::new)
+                .map(Box::into_raw)
+                .map(|ptr| ptr as *mut c_void)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_tx_last_cursor(
+        tx: *const c_void,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(tx);
+            let tx = &*(tx as *const Tx);
+            tx.last_cursor()
+                .map(Box::new)
+                .map(Box::into_raw)
+                .map(|ptr| ptr as *mut c_void)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_tx_cursor_from_key(
+        tx: *const c_void,
+        key: FFIBytesRef,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(tx);
+            let tx = &*(tx as *const Tx);
+            tx.cursor_from_key(key.into_slice())
+                .map(Box::new)
+                .map(Box::into_raw)
+                .map(|ptr| ptr as *mut c_void)
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_tx_cursor_before_key(
+        tx: *const c_void,
+        key: FFIBytesRef,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(tx);
+            let tx = &*(tx as *const Tx);
+            tx.cursor_before_key(key.into_slice())
+                .map(Box::new)
+                .map(Box::into_raw)
+                .map(|ptr| ptr as *mut c_void)
+        })
+    }
 }
 
 pub mod tx_mut {
-    use std::ffi::c_void;
+    use std::{ffi::c_void, ptr, time::Duration};
 
     use thetadb::{ThetaDB, TxMut};
 
     use crate::{
         bytes::{FFIBytes, FFIBytesRef},
         ffi_call::{ffi_call, FFICallState},
+        tx::ThetaDBTxCallback,
     };
 
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_update(
+        db: *const c_void,
+        ctx: *mut c_void,
+        callback: ThetaDBTxCallback,
+        call_state: &mut FFICallState,
+    ) -> u8 {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let Some(callback) = callback else {
+                return Ok(0);
+            };
+            let db = &*(db as *const ThetaDB);
+            let mut tx = db.begin_tx_mut()?;
+
+            // The transaction is rolled back when it is dropped without committing.
+            if callback(ctx, &mut tx as *mut TxMut as *mut c_void) == 0 {
+                return Ok(0);
+            }
+            tx.commit().map(|_| 1)
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_begin_tx_mut(
         db: *const c_void,
@@ -204,6 +635,24 @@ pub mod tx_mut {
         })
     }
 
+    /// Returns null without an error if another read-write transaction is still in progress
+    /// after the timeout (in milliseconds).
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_begin_tx_mut_timeout(
+        db: *const c_void,
+        timeout_ms: u64,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            let tx = db.begin_tx_mut_timeout(Duration::from_millis(timeout_ms))?;
+            Ok(tx.map_or(ptr::null_mut(), |tx| {
+                Box::into_raw(Box::new(tx)) as *mut c_void
+            }))
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_tx_mut_dealloc(
         tx: *mut c_void,
@@ -336,6 +785,22 @@ pub mod cursor {
         })
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn thetadb_cursor_before_key(
+        db: *const c_void,
+        key: FFIBytesRef,
+        call_state: &mut FFICallState,
+    ) -> *mut c_void {
+        ffi_call(call_state, || {
+            check_null_ptr!(db);
+            let db = &*(db as *const ThetaDB);
+            db.cursor_before_key(key.into_slice())
+                .map(Box::new)
+                .map(Box::into_raw)
+                .map(|ptr| ptr as *mut c_void)
+        })
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn thetadb_cursor_dealloc(
         cursor: *const c_void,